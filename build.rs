@@ -0,0 +1,5 @@
+fn main() {
+    // Requires `protoc`, see https://github.com/protocolbuffers/protobuf#protobuf-compiler-installation
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/control.proto").expect("Unable to compile control.proto");
+}
@@ -92,6 +92,7 @@ fn setup(
         width: 1280,
         height: 720,
         enable_controller: false,
+        ..default()
     };
     
     commands.spawn((
@@ -115,6 +116,7 @@ fn setup(
         width: 1280,
         height: 720,
         enable_controller: false,
+        ..default()
     };
     
     commands.spawn((
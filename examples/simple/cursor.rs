@@ -10,7 +10,6 @@ struct Cursor {}
 impl Plugin for CursorPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, setup)
-            .add_systems(PreUpdate, update_cursor_camera)
             .add_systems(Update, update_cursor_position);
     }
 }
@@ -40,20 +39,6 @@ fn setup(
     ));
 }
 
-fn update_cursor_camera(
-    mut commands: Commands,
-    q_camera: Query<Entity, With<Camera>>,
-    q_cursor: Query<Entity, (With<Cursor>, Without<UiTargetCamera>)>,
-) {
-    if let Some(cursor_entity) = q_cursor.iter().next() {
-        if let Some(camera_entity) = q_camera.iter().next() {
-            commands
-                .entity(cursor_entity)
-                .insert(UiTargetCamera(camera_entity));
-        }
-    }
-}
-
 fn update_cursor_position(
     mut q_cursor: Query<&mut Node, With<Cursor>>,
     mut window_events: EventReader<WindowEvent>,
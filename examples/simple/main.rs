@@ -3,7 +3,7 @@ use bevy::{
     winit::WinitPlugin,
 };
 use bevy_streaming::{
-    gst_webrtc_encoder::GstWebRtcEncoder, CongestionControl, GstWebRtcSettings, SignallingServer, StreamerCameraBuilder, StreamerHelper, StreamerPlugin
+    gst_webrtc_encoder::GstWebRtcEncoder, CongestionControl, DefaultUiStream, GpuSettings, GstWebRtcSettings, SignallingServer, StreamerCameraBuilder, StreamerHelper, StreamerPlugin, VideoCaps, VideoCodec
 };
 use camera_controller::{CameraController, CameraControllerPlugin};
 use cursor::CursorPlugin;
@@ -15,6 +15,18 @@ mod cursor;
 fn main() -> AppExit {
     let mut app = App::new();
 
+    // Select the GPU, see BEVY_STREAMING_GPU
+    let (render_creation, adapter) =
+        match GpuSettings::from_env().and_then(|gpu| gpu.render_creation()) {
+            Ok(created) => created,
+            Err(e) => {
+                eprintln!("Unable to initialize the renderer: {:?}", e);
+                return AppExit::error();
+            }
+        };
+    // Logged by the StreamerPlugin once the logger exists
+    app.insert_resource(adapter);
+
     app.add_plugins((
         DefaultPlugins
             .build()
@@ -22,6 +34,7 @@ fn main() -> AppExit {
             .disable::<WinitPlugin>()
             // Make sure pipelines are ready before rendering
             .set(RenderPlugin {
+                render_creation,
                 synchronous_pipeline_compilation: true,
                 ..default()
             }),
@@ -57,29 +70,34 @@ struct SpectatorCamera;
 
 fn setup_cameras(mut commands: Commands, mut streamer: StreamerHelper<GstWebRtcEncoder>) {
     // camera
-    let main_camera = commands
-        .spawn((
-            Camera3d::default(),
-            Transform::from_xyz(-2.5, 4.5, 9.0).looking_at(Vec3::ZERO, Vec3::Y),
-            streamer.new_streamer_camera(GstWebRtcSettings {
-                signalling_server: SignallingServer::PixelStreaming {
-                    uri: "ws://localhost:8888".to_string(),
-                    streamer_id: Some("player".to_string()),
-                },
-                // signalling_server: SignallingServer::GstWebRtc {
-                //     uri: "ws://127.0.0.1:8443".to_string(),
-                //     peer_id: None,
-                // },
-                width: 1920,
-                height: 1080,
-                video_caps: Some("video/x-h264".to_string()),
-                congestion_control: Some(CongestionControl::Disabled),
-                enable_controller: true,
-            }),
-            CameraController::default(),
-            PlayerCamera,
-        ))
-        .id();
+    commands.spawn((
+        Camera3d::default(),
+        Transform::from_xyz(-2.5, 4.5, 9.0).looking_at(Vec3::ZERO, Vec3::Y),
+        streamer.new_streamer_camera(GstWebRtcSettings {
+            signalling_server: SignallingServer::PixelStreaming {
+                uri: "ws://localhost:8888".to_string(),
+                streamer_id: Some("player".to_string()),
+                proxy: None,
+                headers: default(),
+                cafile: None,
+                insecure_tls: false,
+            },
+            // signalling_server: SignallingServer::GstWebRtc {
+            //     uri: "ws://127.0.0.1:8443".to_string(),
+            //     peer_id: None,
+            // },
+            width: 1920,
+            height: 1080,
+            video_caps: Some(VideoCaps::codec(VideoCodec::H264)),
+            congestion_control: Some(CongestionControl::Disabled),
+            enable_controller: true,
+            ..default()
+        }),
+        CameraController::default(),
+        PlayerCamera,
+        // Displays the UI, as there is no window
+        DefaultUiStream,
+    ));
 
     commands.spawn((
         Camera3d::default(),
@@ -88,25 +106,29 @@ fn setup_cameras(mut commands: Commands, mut streamer: StreamerHelper<GstWebRtcE
             signalling_server: SignallingServer::PixelStreaming {
                 uri: "ws://localhost:8888".to_string(),
                 streamer_id: Some("spectator".to_string()),
+                proxy: None,
+                headers: default(),
+                cafile: None,
+                insecure_tls: false,
             },
             width: 1920,
             height: 1080,
-            video_caps: Some("video/x-h264".to_string()),
+            video_caps: Some(VideoCaps::codec(VideoCodec::H264)),
             congestion_control: Some(CongestionControl::Disabled),
             enable_controller: false,
+            ..default()
         }),
         SpectatorCamera,
     ));
 
     commands.spawn((
-        Text::new("You need to specify TargetCamera to display UI elements, because there is no main window."),
+        Text::new("The UI is displayed by the camera with DefaultUiStream."),
         Node {
             position_type: PositionType::Absolute,
             top: Val::Px(12.0),
             left: Val::Px(12.0),
             ..default()
         },
-        UiTargetCamera(main_camera),
     ));
 }
 
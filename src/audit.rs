@@ -0,0 +1,217 @@
+use anyhow::Result;
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use bevy_platform::collections::HashMap;
+use bevy_render::prelude::*;
+use serde::Serialize;
+use std::{
+    fs::OpenOptions,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{StreamLabels, capture::Capture, viewers::ViewerTracker};
+
+/// An event of the lifecycle of the streams
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// The capture of the stream started, including when it resumes from standby
+    StreamStarted {
+        stream: String,
+    },
+    /// The capture of the stream stopped, or the stream was removed
+    StreamStopped {
+        stream: String,
+    },
+    PeerJoined {
+        stream: String,
+        peer_id: String,
+    },
+    PeerLeft {
+        stream: String,
+        peer_id: String,
+    },
+    /// The peer controlling the input of the stream changed.
+    ///
+    /// The input of every peer is handled, this event is recorded by the apps restricting
+    /// the input to one peer with `AuditLog::record`.
+    InputOwnerChanged {
+        stream: String,
+        previous: Option<String>,
+        owner: Option<String>,
+    },
+}
+
+/// An `AuditEvent` and when it happened
+#[derive(Clone, Debug, Serialize)]
+pub struct AuditRecord {
+    /// Milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+}
+
+impl AuditRecord {
+    fn new(timestamp: SystemTime, event: AuditEvent) -> Self {
+        Self {
+            timestamp_ms: timestamp
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_millis() as u64)
+                .unwrap_or_default(),
+            event,
+        }
+    }
+}
+
+/// Destination of the audit records, e.g. a file or an OpenTelemetry (OTLP) logs exporter.
+///
+/// It is implemented for closures, to forward the records to any other system.
+pub trait AuditSink: Send + Sync + 'static {
+    fn write(&self, record: &AuditRecord) -> Result<()>;
+}
+
+impl<F> AuditSink for F
+where
+    F: Fn(&AuditRecord) -> Result<()> + Send + Sync + 'static,
+{
+    fn write(&self, record: &AuditRecord) -> Result<()> {
+        self(record)
+    }
+}
+
+/// Writes the records as JSON lines, e.g.
+/// `{"timestamp_ms":1700000000000,"event":"peer_joined","stream":"main","peer_id":"..."}`
+pub struct JsonLinesAuditSink {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl JsonLinesAuditSink {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Mutex::new(Box::new(writer)),
+        }
+    }
+
+    /// Appends the records to the file at `path`, created if needed
+    pub fn append(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+}
+
+impl AuditSink for JsonLinesAuditSink {
+    fn write(&self, record: &AuditRecord) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        // Flushed for each record, so that none is lost if the app is killed
+        let mut writer = self.writer.lock().unwrap();
+        writer.write_all(&line)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Audit log of the streams, for compliance in multi-user deployments.
+///
+/// When this resource is inserted, the streams started and stopped and the peers joining and
+/// leaving are recorded to its sink. The streams are identified by the name of their
+/// `StreamLabels`.
+#[derive(Resource, Clone)]
+pub struct AuditLog {
+    sink: Arc<dyn AuditSink>,
+}
+
+impl AuditLog {
+    pub fn new(sink: impl AuditSink) -> Self {
+        Self {
+            sink: Arc::new(sink),
+        }
+    }
+
+    /// Records an event which happened now
+    pub fn record(&self, event: AuditEvent) {
+        self.record_at(SystemTime::now(), event);
+    }
+
+    fn record_at(&self, timestamp: SystemTime, event: AuditEvent) {
+        let record = AuditRecord::new(timestamp, event);
+        if let Err(e) = self.sink.write(&record) {
+            error!("Unable to write the audit record {:?}: {:?}", record, e);
+        }
+    }
+}
+
+fn stream_name(entity: Entity, labels: Option<&StreamLabels>) -> String {
+    labels
+        .map(|labels| labels.name.clone())
+        .unwrap_or_else(|| entity.to_string())
+}
+
+/// This system records the peers which joined or left the streams since the last update
+pub(crate) fn record_viewer_changes(
+    log: Option<Res<AuditLog>>,
+    viewers: Query<(Entity, &ViewerTracker, Option<&StreamLabels>)>,
+) {
+    for (entity, tracker, labels) in viewers.iter() {
+        // Drained even without a log, so that the changes do not pile up
+        let changes = tracker.take_changes();
+        let Some(log) = &log else {
+            continue;
+        };
+
+        for change in changes {
+            let stream = stream_name(entity, labels);
+            let peer_id = change.peer_id;
+            let event = if change.joined {
+                AuditEvent::PeerJoined { stream, peer_id }
+            } else {
+                AuditEvent::PeerLeft { stream, peer_id }
+            };
+            log.record_at(change.timestamp, event);
+        }
+    }
+}
+
+/// This system records the streams started and stopped since the last update.
+///
+/// The streams without a camera run as long as they exist.
+pub(crate) fn record_stream_states(
+    log: Option<Res<AuditLog>>,
+    streams: Query<(Entity, &StreamLabels, Option<&Camera>)>,
+    captures: Query<&Capture>,
+    mut running: Local<HashMap<Entity, String>>,
+) {
+    let Some(log) = log else {
+        return;
+    };
+
+    let mut stopped = running.keys().copied().collect::<Vec<_>>();
+    for (entity, labels, camera) in streams.iter() {
+        let is_running = match camera.map(|camera| camera.target.as_image()) {
+            Some(Some(image)) => captures
+                .iter()
+                .any(|capture| capture.src_image() == image && capture.enabled()),
+            Some(None) => false,
+            None => true,
+        };
+
+        if is_running {
+            stopped.retain(|stopped| *stopped != entity);
+            if running.insert(entity, labels.name.clone()).is_none() {
+                log.record(AuditEvent::StreamStarted {
+                    stream: labels.name.clone(),
+                });
+            }
+        }
+    }
+
+    for entity in stopped {
+        if let Some(stream) = running.remove(&entity) {
+            log.record(AuditEvent::StreamStopped { stream });
+        }
+    }
+}
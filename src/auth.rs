@@ -0,0 +1,478 @@
+use bevy_log::prelude::*;
+use bevy_platform::collections::HashMap;
+use gst::prelude::*;
+use gstrswebrtc::{
+    RUNTIME,
+    signaller::{Signallable, SignallableExt},
+    webrtcsink::BaseWebRTCSink,
+};
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "cuda")]
+use crate::nvenc::{NvencSession, hardware_disabled, try_acquire, watch_webrtc_encoder};
+#[cfg(feature = "pixelstreaming")]
+use crate::pixelstreaming::signaller::UePsSignaller;
+use crate::{GstWebRtcSettings, SessionThrottle};
+
+/// A peer asking to open a session with a streamer
+#[derive(Clone, Debug)]
+pub struct SessionRequest {
+    /// Name of the stream, see `StreamLabels`
+    pub stream: String,
+    pub peer_id: String,
+    /// Metadata sent by the signalling server along with the request, e.g. the extra fields
+    /// of the Pixel Streaming `playerConnected` message (non-string values are JSON encoded)
+    pub metadata: HashMap<String, String>,
+}
+
+/// Whether a session is accepted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SessionDecision {
+    Allow,
+    /// Rejects the session, the reason is sent to the peer if the signalling protocol supports it
+    Deny {
+        reason: Option<String>,
+    },
+}
+
+type SyncAuthorizer = dyn Fn(&SessionRequest) -> SessionDecision + Send + Sync;
+type AsyncAuthorizer =
+    dyn Fn(SessionRequest) -> Pin<Box<dyn Future<Output = SessionDecision> + Send>> + Send + Sync;
+
+/// Metadata fields always treated as credentials, compared case insensitively
+#[cfg(feature = "pixelstreaming")]
+const CREDENTIAL_FIELDS: [&str; 7] = [
+    "token",
+    "jwt",
+    "password",
+    "secret",
+    "authorization",
+    "accessToken",
+    "access_token",
+];
+
+#[derive(Clone)]
+enum Authorize {
+    Sync(Arc<SyncAuthorizer>),
+    Async(Arc<AsyncAuthorizer>),
+}
+
+/// Decides which peers may open a session with a streamer, e.g. by validating a token.
+///
+/// With Pixel Streaming, it is called when the `playerConnected` message is received and
+/// denied players are disconnected before any negotiation. With the GStreamer signaller,
+/// it is called on `session-requested` and denied sessions are ended right away.
+///
+/// The credentials are removed from the metadata exposed in `PeerMetadata`: the fields read
+/// by `shared_secret` and `JwtAuthorizer`, the ones added with `with_credential_field` and
+/// the usual names, e.g. `token` or `password`.
+///
+/// The callbacks are run on the webrtc plugin runtime, sync callbacks must return quickly.
+#[derive(Clone)]
+pub struct SessionAuthorizer {
+    authorize: Authorize,
+    credential_fields: Vec<String>,
+}
+
+impl SessionAuthorizer {
+    pub fn new(
+        authorize: impl Fn(&SessionRequest) -> SessionDecision + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            authorize: Authorize::Sync(Arc::new(authorize)),
+            credential_fields: Vec::new(),
+        }
+    }
+
+    pub fn new_async<F>(authorize: impl Fn(SessionRequest) -> F + Send + Sync + 'static) -> Self
+    where
+        F: Future<Output = SessionDecision> + Send + 'static,
+    {
+        Self {
+            authorize: Authorize::Async(Arc::new(move |request| Box::pin(authorize(request)))),
+            credential_fields: Vec::new(),
+        }
+    }
+
+    /// Removes this metadata field from `PeerMetadata`, as it holds a credential
+    pub fn with_credential_field(mut self, field: impl Into<String>) -> Self {
+        self.credential_fields.push(field.into());
+        self
+    }
+
+    /// Allows the peers sending `secret` in the `field` metadata, e.g. `{"token": "..."}` in
+    /// the Pixel Streaming `playerConnected` message. Other signallers send no metadata, every
+    /// session is denied.
+    pub fn shared_secret(field: impl Into<String>, secret: impl Into<String>) -> Self {
+        let field = field.into();
+        let secret = secret.into();
+        Self::new({
+            let field = field.clone();
+            move |request| match request.metadata.get(&field) {
+                None => SessionDecision::Deny {
+                    reason: Some("Missing token".to_string()),
+                },
+                Some(token) if constant_time_eq(token.as_bytes(), secret.as_bytes()) => {
+                    SessionDecision::Allow
+                }
+                Some(_) => SessionDecision::Deny {
+                    reason: Some("Invalid token".to_string()),
+                },
+            }
+        })
+        .with_credential_field(field)
+    }
+
+    /// Allows the peers sending a valid JWT, see `JwtAuthorizer`
+    #[cfg(feature = "jwt")]
+    pub fn jwt(authorizer: JwtAuthorizer) -> Self {
+        let field = authorizer.field.clone();
+        Self::new(move |request| authorizer.authorize(request)).with_credential_field(field)
+    }
+
+    pub(crate) async fn authorize(&self, request: SessionRequest) -> SessionDecision {
+        let stream = request.stream.clone();
+        let peer_id = request.peer_id.clone();
+
+        let decision = match &self.authorize {
+            Authorize::Sync(authorize) => authorize(&request),
+            Authorize::Async(authorize) => authorize(request).await,
+        };
+
+        if let SessionDecision::Deny { reason } = &decision {
+            info!(%stream, "Session of {} denied: {:?}", peer_id, reason);
+        }
+
+        decision
+    }
+
+    #[cfg(feature = "pixelstreaming")]
+    fn is_credential(&self, field: &str) -> bool {
+        self.credential_fields
+            .iter()
+            .any(|credential| credential == field)
+    }
+}
+
+/// Returns true if the metadata `field` holds a credential, which must not be exposed, see
+/// `SessionAuthorizer`
+#[cfg(feature = "pixelstreaming")]
+pub(crate) fn is_credential_field(authorizer: Option<&SessionAuthorizer>, field: &str) -> bool {
+    CREDENTIAL_FIELDS
+        .iter()
+        .any(|credential| credential.eq_ignore_ascii_case(field))
+        || authorizer.is_some_and(|authorizer| authorizer.is_credential(field))
+}
+
+/// Compares the tokens in a time independent of their content, so that they can't be guessed
+/// from the response times
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Validates the JWT sent by the peers in their metadata, e.g. `{"token": "eyJ..."}` in the
+/// Pixel Streaming `playerConnected` message.
+///
+/// The signature and the `exp` claim are always checked. When the token has a `stream`
+/// claim, a name or a list of names, it must grant the requested stream.
+#[cfg(feature = "jwt")]
+#[derive(Clone)]
+pub struct JwtAuthorizer {
+    field: String,
+    key: jsonwebtoken::DecodingKey,
+    validation: jsonwebtoken::Validation,
+}
+
+#[cfg(feature = "jwt")]
+impl JwtAuthorizer {
+    /// Tokens signed with HMAC SHA-256 and a shared secret
+    pub fn hs256(secret: impl AsRef<[u8]>) -> Self {
+        Self::new(
+            jsonwebtoken::DecodingKey::from_secret(secret.as_ref()),
+            jsonwebtoken::Algorithm::HS256,
+        )
+    }
+
+    /// Tokens signed with RSA SHA-256, verified with a PEM encoded public key
+    pub fn rs256_pem(public_key: &[u8]) -> anyhow::Result<Self> {
+        Ok(Self::new(
+            jsonwebtoken::DecodingKey::from_rsa_pem(public_key)?,
+            jsonwebtoken::Algorithm::RS256,
+        ))
+    }
+
+    fn new(key: jsonwebtoken::DecodingKey, algorithm: jsonwebtoken::Algorithm) -> Self {
+        let mut validation = jsonwebtoken::Validation::new(algorithm);
+        // The audience is only checked if set with `with_audience`
+        validation.validate_aud = false;
+        Self {
+            field: "token".to_string(),
+            key,
+            validation,
+        }
+    }
+
+    /// Reads the token from another metadata field than `token`
+    pub fn with_field(mut self, field: impl Into<String>) -> Self {
+        self.field = field.into();
+        self
+    }
+
+    pub fn with_audience(mut self, audience: &str) -> Self {
+        self.validation.set_audience(&[audience]);
+        self
+    }
+
+    pub fn with_issuer(mut self, issuer: &str) -> Self {
+        self.validation.set_issuer(&[issuer]);
+        self
+    }
+
+    fn authorize(&self, request: &SessionRequest) -> SessionDecision {
+        let deny = |reason: &str| SessionDecision::Deny {
+            reason: Some(reason.to_string()),
+        };
+        let Some(token) = request.metadata.get(&self.field) else {
+            return deny("Missing token");
+        };
+
+        let claims = match jsonwebtoken::decode::<HashMap<String, serde_json::Value>>(
+            token,
+            &self.key,
+            &self.validation,
+        ) {
+            Ok(data) => data.claims,
+            Err(e) => {
+                debug!(stream = %request.stream, "Invalid token of {}: {}", request.peer_id, e);
+                return deny("Invalid token");
+            }
+        };
+
+        let grants_stream = match claims.get("stream") {
+            None => true,
+            Some(serde_json::Value::String(stream)) => *stream == request.stream,
+            Some(serde_json::Value::Array(streams)) => streams
+                .iter()
+                .any(|stream| stream.as_str() == Some(request.stream.as_str())),
+            Some(_) => false,
+        };
+        if !grants_stream {
+            return deny("Token not valid for this stream");
+        }
+
+        SessionDecision::Allow
+    }
+}
+
+/// Outcome of a session throttling check
+pub(crate) enum Admission {
+    Admitted,
+    /// The session is queued and must be checked again after this delay
+    Wait(Duration),
+    Rejected,
+}
+
+#[derive(Default)]
+struct ThrottleState {
+    /// Times of the sessions admitted during the last second
+    admitted: VecDeque<Instant>,
+    queued: usize,
+}
+
+/// Limits the number of sessions negotiated per second, see `SessionThrottle`
+pub(crate) struct SessionThrottler {
+    settings: SessionThrottle,
+    state: Mutex<ThrottleState>,
+}
+
+impl SessionThrottler {
+    pub(crate) fn new(settings: SessionThrottle) -> Self {
+        Self {
+            settings,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Admits a session if the rate allows it, `queued` is true if the session was waiting
+    pub(crate) fn admit(&self, queued: bool) -> Admission {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        while state
+            .admitted
+            .front()
+            .is_some_and(|admitted| now.duration_since(*admitted) >= Duration::from_secs(1))
+        {
+            state.admitted.pop_front();
+        }
+        if queued {
+            state.queued -= 1;
+        }
+
+        if state.admitted.len() < self.settings.max_sessions_per_second as usize {
+            state.admitted.push_back(now);
+            Admission::Admitted
+        } else if state.queued < self.settings.max_queued {
+            // No session is admitted with a rate of 0, waiting would not change it
+            let Some(oldest) = state.admitted.front().copied() else {
+                return Admission::Rejected;
+            };
+            state.queued += 1;
+            Admission::Wait(Duration::from_secs(1).saturating_sub(now.duration_since(oldest)))
+        } else {
+            Admission::Rejected
+        }
+    }
+}
+
+/// Checks applied to the sessions requested to a streamer before they are negotiated
+#[derive(Clone)]
+pub(crate) struct SessionGate {
+    /// Name of the stream
+    pub(crate) stream: String,
+    pub(crate) authorizer: Option<SessionAuthorizer>,
+    pub(crate) throttler: Option<Arc<SessionThrottler>>,
+    /// The NVENC sessions of the peers, by session id
+    #[cfg(feature = "cuda")]
+    encoders: Arc<Mutex<HashMap<String, NvencSession>>>,
+}
+
+impl SessionGate {
+    /// Returns the gate configured by the settings, if any.
+    ///
+    /// With the `cuda` feature there is always a gate, as every peer needs an NVENC session.
+    pub(crate) fn from_settings(settings: &GstWebRtcSettings) -> Option<Self> {
+        if !cfg!(feature = "cuda")
+            && settings.session_authorizer.is_none()
+            && settings.session_throttle.is_none()
+        {
+            return None;
+        }
+
+        Some(Self {
+            stream: settings.stream_name(),
+            authorizer: settings.session_authorizer.clone(),
+            throttler: settings
+                .session_throttle
+                .clone()
+                .map(|throttle| Arc::new(SessionThrottler::new(throttle))),
+            #[cfg(feature = "cuda")]
+            encoders: Arc::default(),
+        })
+    }
+
+    /// Takes the encoding session of a peer, returns false if the GPU has no session left
+    #[cfg(feature = "cuda")]
+    pub(crate) fn acquire_encoder(&self, session_id: &str) -> bool {
+        // The sessions use software encoders once a hardware encoder failed
+        if hardware_disabled() {
+            return true;
+        }
+        let Some(session) = try_acquire(&self.stream) else {
+            return false;
+        };
+        self.encoders
+            .lock()
+            .unwrap()
+            .insert(session_id.to_string(), session);
+        true
+    }
+
+    #[cfg(not(feature = "cuda"))]
+    pub(crate) fn acquire_encoder(&self, _session_id: &str) -> bool {
+        true
+    }
+
+    /// Checks the sessions requested to the signaller of `webrtcsink`
+    pub(crate) fn connect(self, webrtcsink: &BaseWebRTCSink) {
+        let signaller = webrtcsink.property::<Signallable>("signaller");
+
+        #[cfg(feature = "cuda")]
+        signaller.connect_closure("session-ended", false, {
+            let encoders = self.encoders.clone();
+            glib::closure!(move |_signaller: &Signallable, session_id: &str| -> bool {
+                encoders.lock().unwrap().remove(session_id);
+                false
+            })
+        });
+
+        // A failing NVENC encoder releases the NVENC session of its peer
+        #[cfg(feature = "cuda")]
+        webrtcsink.connect_closure("encoder-setup", false, {
+            let stream = self.stream.clone();
+            let encoders = self.encoders.clone();
+            glib::closure!(move |_sink: &BaseWebRTCSink,
+                                 consumer_id: Option<&str>,
+                                 _pad_name: &str,
+                                 encoder: &gst::Element|
+                  -> bool {
+                if let Some(session_id) = consumer_id {
+                    watch_webrtc_encoder(&stream, session_id, encoder, &encoders);
+                }
+                false
+            })
+        });
+
+        // The Pixel Streaming signaller only requests the sessions once they pass the gate
+        #[cfg(feature = "pixelstreaming")]
+        if let Some(signaller) = signaller.downcast_ref::<UePsSignaller>() {
+            signaller.set_session_gate(self);
+            return;
+        }
+
+        // Other signallers request the sessions right away, they are ended if they don't pass
+        signaller.connect_closure(
+            "session-requested",
+            false,
+            glib::closure!(
+                move |signaller: &Signallable,
+                      session_id: &str,
+                      peer_id: &str,
+                      _offer: Option<&gst_webrtc::WebRTCSessionDescription>| {
+                    let end_session = {
+                        let signaller = signaller.clone();
+                        let session_id = session_id.to_string();
+                        move || {
+                            signaller.end_session(&session_id);
+                            signaller.emit_by_name::<bool>("session-ended", &[&session_id]);
+                        }
+                    };
+
+                    // Queueing is not possible once the session is requested
+                    if let Some(throttler) = &self.throttler {
+                        if !matches!(throttler.admit(false), Admission::Admitted) {
+                            warn!(stream = %self.stream, "Too many sessions, rejecting {}", peer_id);
+                            end_session();
+                            return;
+                        }
+                    }
+
+                    if let Some(authorizer) = self.authorizer.clone() {
+                        let request = SessionRequest {
+                            stream: self.stream.clone(),
+                            peer_id: peer_id.to_string(),
+                            metadata: HashMap::new(),
+                        };
+                        let gate = self.clone();
+                        let session_id = session_id.to_string();
+                        RUNTIME.spawn(async move {
+                            if authorizer.authorize(request).await != SessionDecision::Allow
+                                || !gate.acquire_encoder(&session_id)
+                            {
+                                end_session();
+                            }
+                        });
+                    } else if !self.acquire_encoder(session_id) {
+                        end_session();
+                    }
+                }
+            ),
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use bevy_ecs::prelude::*;
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+
+use crate::capture::capture_memory_size;
+
+/// What happens to a new stream which doesn't fit in the `GpuMemoryBudget`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GpuBudgetAction {
+    /// The camera renders to a placeholder and no frame is streamed
+    Refuse,
+    /// The stream is created with the largest size fitting in the budget, keeping its aspect
+    /// ratio, or refused if it would be smaller than `GpuMemoryBudget::min_size`
+    #[default]
+    Downscale,
+}
+
+/// Limits the GPU memory used by the render targets and readback buffers of the streamer
+/// cameras, so that spawning too many cameras is reported by `GpuMemoryBudgetExceeded`
+/// rather than by a device lost error.
+///
+/// Insert it as a resource to enable it, it applies to the cameras created afterwards.
+#[derive(Resource, Clone, Debug)]
+pub struct GpuMemoryBudget {
+    /// Memory available to the streamer cameras, in bytes
+    pub max_bytes: u64,
+    pub action: GpuBudgetAction,
+    /// Streams are never downscaled below this width and height
+    pub min_size: (u32, u32),
+    used: Arc<AtomicU64>,
+}
+
+impl GpuMemoryBudget {
+    pub fn new(max_bytes: u64) -> Self {
+        Self {
+            max_bytes,
+            action: GpuBudgetAction::default(),
+            min_size: (320, 180),
+            used: Arc::default(),
+        }
+    }
+
+    pub fn with_action(mut self, action: GpuBudgetAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    pub fn with_min_size(mut self, width: u32, height: u32) -> Self {
+        self.min_size = (width, height);
+        self
+    }
+
+    /// Returns the memory used by the streamer cameras, in bytes
+    pub fn used_bytes(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    fn available_bytes(&self) -> u64 {
+        self.max_bytes.saturating_sub(self.used_bytes())
+    }
+
+    /// Reserves the memory of a new stream, downscaled or refused if it doesn't fit
+    pub(crate) fn reserve(&self, width: u32, height: u32) -> BudgetedSize {
+        let available = self.available_bytes();
+        let (width, height) = if capture_memory_size(width, height) <= available {
+            (width, height)
+        } else {
+            match self.action {
+                GpuBudgetAction::Refuse => return BudgetedSize::refused(width, height),
+                GpuBudgetAction::Downscale => match self.downscale(width, height, available) {
+                    Some(size) => size,
+                    None => return BudgetedSize::refused(width, height),
+                },
+            }
+        };
+
+        let bytes = capture_memory_size(width, height);
+        self.used.fetch_add(bytes, Ordering::Relaxed);
+        BudgetedSize {
+            width,
+            height,
+            reservation: Some(Arc::new(GpuMemoryReservation {
+                bytes: AtomicU64::new(bytes),
+                used: self.used.clone(),
+            })),
+            refused: false,
+        }
+    }
+
+    /// Returns the largest size with the aspect ratio of `width` x `height` fitting in
+    /// `available` bytes, with even dimensions as required by the encoders
+    fn downscale(&self, width: u32, height: u32, available: u64) -> Option<(u32, u32)> {
+        let mut scale = (available as f64 / capture_memory_size(width, height) as f64).sqrt();
+
+        loop {
+            let scaled_width = ((width as f64 * scale) as u32) & !1;
+            let scaled_height = ((height as f64 * scale) as u32) & !1;
+            if scaled_width < self.min_size.0 || scaled_height < self.min_size.1 {
+                return None;
+            }
+            if capture_memory_size(scaled_width, scaled_height) <= available {
+                return Some((scaled_width, scaled_height));
+            }
+            // The readback buffers rows are padded, so the memory is not exactly proportional
+            scale *= 0.98;
+        }
+    }
+
+    /// Updates a reservation for a new size, returns false if it doesn't fit
+    pub(crate) fn resize(
+        &self,
+        reservation: &GpuMemoryReservation,
+        width: u32,
+        height: u32,
+    ) -> bool {
+        let current = reservation.bytes.load(Ordering::Relaxed);
+        let bytes = capture_memory_size(width, height);
+        if bytes > current && bytes - current > self.available_bytes() {
+            return false;
+        }
+
+        reservation.bytes.store(bytes, Ordering::Relaxed);
+        if bytes > current {
+            self.used.fetch_add(bytes - current, Ordering::Relaxed);
+        } else {
+            self.used.fetch_sub(current - bytes, Ordering::Relaxed);
+        }
+        true
+    }
+}
+
+/// Memory of a stream counted in the `GpuMemoryBudget`, released when the last capture
+/// holding it is dropped
+#[derive(Debug)]
+pub(crate) struct GpuMemoryReservation {
+    bytes: AtomicU64,
+    used: Arc<AtomicU64>,
+}
+
+impl Drop for GpuMemoryReservation {
+    fn drop(&mut self) {
+        self.used
+            .fetch_sub(self.bytes.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+}
+
+/// Size given to a new stream by the `GpuMemoryBudget`
+pub(crate) struct BudgetedSize {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) reservation: Option<Arc<GpuMemoryReservation>>,
+    /// The camera must not be rendered
+    pub(crate) refused: bool,
+}
+
+impl BudgetedSize {
+    /// The size of a stream when there is no budget
+    pub(crate) fn unlimited(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            reservation: None,
+            refused: false,
+        }
+    }
+
+    pub(crate) fn refused(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            reservation: None,
+            refused: true,
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use anyhow::Result;
+use bevy_ecs::prelude::*;
+use std::sync::{
+    Arc, Mutex, OnceLock,
+    atomic::{AtomicBool, Ordering},
+};
+
+use crate::encoder::{EncoderStats, Frame, StreamEncoder};
+
+/// A frame handed to the callback of a `CallbackEncoder`
+#[derive(Clone, Copy, Debug)]
+pub struct CallbackFrame<'a> {
+    /// The streamer camera the frame was captured from, `None` for the cameras not created
+    /// with `StreamerHelper::new_streamer_camera`
+    pub camera: Option<Entity>,
+    /// RGBA pixels with their size and capture time, see `Frame::packed_data` for the pixels
+    /// without the padding of the rows
+    pub frame: Frame<'a>,
+}
+
+/// Called with each frame of a `CallbackEncoder`
+pub type FrameCallback = Arc<dyn Fn(&CallbackFrame) + Send + Sync>;
+
+/// Settings of a `CallbackEncoder`
+#[derive(Clone)]
+pub struct CallbackSettings {
+    /// Name of the stream
+    pub name: String,
+    /// Labels attached to the stream, see `StreamLabels`
+    pub labels: Vec<(String, String)>,
+    pub width: u32,
+    pub height: u32,
+    pub callback: FrameCallback,
+}
+
+impl CallbackSettings {
+    /// Returns the settings of a stream handing its frames to `callback`, with the default
+    /// size
+    pub fn new(callback: impl Fn(&CallbackFrame) + Send + Sync + 'static) -> Self {
+        Self {
+            name: "callback".to_string(),
+            labels: Vec::new(),
+            width: 1280,
+            height: 720,
+            callback: Arc::new(callback),
+        }
+    }
+}
+
+/// An encoder handing the raw frames to a closure, to consume them in the app without
+/// GStreamer, e.g. for computer vision or a screenshot service.
+///
+/// The callback is called on the capture worker, which pushes the frames of every stream: it
+/// must not block, the frames to process at length are copied and sent to another thread.
+/// The frames are only handed over while the encoder is started.
+pub struct CallbackEncoder {
+    callback: FrameCallback,
+    camera: OnceLock<Entity>,
+    started: AtomicBool,
+    stats: Mutex<EncoderStats>,
+}
+
+impl CallbackEncoder {
+    pub fn new(settings: CallbackSettings) -> Arc<Self> {
+        Arc::new(Self {
+            callback: settings.callback,
+            camera: OnceLock::new(),
+            started: AtomicBool::new(false),
+            stats: Mutex::new(EncoderStats {
+                width: settings.width,
+                height: settings.height,
+                ..Default::default()
+            }),
+        })
+    }
+
+    /// Sets the camera of the frames, once it is spawned
+    pub(crate) fn set_camera(&self, camera: Entity) {
+        let _ = self.camera.set(camera);
+    }
+}
+
+impl StreamEncoder for CallbackEncoder {
+    fn push_frame(&self, frame: &Frame) -> Result<()> {
+        if !self.started.load(Ordering::Acquire) {
+            return Ok(());
+        }
+
+        (self.callback)(&CallbackFrame {
+            camera: self.camera.get().copied(),
+            frame: *frame,
+        });
+        self.stats.lock().unwrap().frames_pushed += 1;
+
+        Ok(())
+    }
+
+    fn start(&self) -> Result<()> {
+        self.started.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<()> {
+        self.started.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    /// The frames carry their size
+    fn resize(&self, width: u32, height: u32) -> Result<()> {
+        let mut stats = self.stats.lock().unwrap();
+        stats.width = width;
+        stats.height = height;
+
+        Ok(())
+    }
+
+    /// Every frame is a keyframe
+    fn request_keyframe(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn stats(&self) -> Option<EncoderStats> {
+        Some(self.stats.lock().unwrap().clone())
+    }
+}
+
+/// The `CallbackEncoder` of a streamer camera, told about the camera once it is spawned
+#[derive(Component)]
+pub(crate) struct CallbackCamera(pub(crate) Arc<CallbackEncoder>);
+
+/// This system sets the camera of the frames of the `CallbackEncoder`s
+pub(crate) fn assign_callback_cameras(
+    cameras: Query<(Entity, &CallbackCamera), Added<CallbackCamera>>,
+) {
+    for (entity, callback) in cameras.iter() {
+        callback.0.set_camera(entity);
+    }
+}
@@ -1,7 +1,7 @@
-use std::sync::atomic::Ordering;
+use std::{sync::atomic::Ordering, time::Duration};
 
 use bevy_ecs::prelude::*;
-use bevy_log::prelude::*;
+use bevy_log::{info_span, prelude::*};
 use bevy_render::{
     render_asset::RenderAssets,
     render_graph::{self, NodeRunError, RenderGraphContext, RenderLabel},
@@ -13,7 +13,11 @@ use bevy_render::{
 
 use crate::capture::{ReleaseBufferSignal, SendBufferJob, WorkerSendBuffer};
 
-use super::Captures;
+use super::{
+    Captures,
+    grading::{self, GradedTargets, GradingPipeline},
+    letterbox::{self, LetterboxTargets},
+};
 
 /// `RenderGraph` label for `CaptureNode`
 #[derive(Debug, PartialEq, Eq, Clone, Hash, RenderLabel)]
@@ -35,13 +39,17 @@ impl render_graph::Node for CaptureDriver {
         let gpu_images = world
             .get_resource::<RenderAssets<bevy_render::texture::GpuImage>>()
             .unwrap();
+        let graded_targets = world.get_resource::<GradedTargets>().unwrap();
+        let grading_pipeline = world.get_resource::<GradingPipeline>().unwrap();
+        let letterbox_targets = world.get_resource::<LetterboxTargets>().unwrap();
+        let render_queue = world.get_resource::<RenderQueue>().unwrap();
 
         let mut encoder = render_context
             .render_device()
             .create_command_encoder(&CommandEncoderDescriptor::default());
 
         for capture in captures.iter() {
-            if !capture.enabled() {
+            if !capture.capturing() {
                 continue;
             }
 
@@ -55,7 +63,7 @@ impl render_graph::Node for CaptureDriver {
             // That's why image in buffer can be little bit wider
             // This should be taken into account at copy from buffer stage
             let padded_bytes_per_row = RenderDevice::align_copy_bytes_per_row(
-                (src_image.size.width as usize / block_dimensions.0 as usize) * block_size as usize,
+                (capture.size.width as usize / block_dimensions.0 as usize) * block_size as usize,
             );
 
             // Choose an available buffer
@@ -73,6 +81,14 @@ impl render_graph::Node for CaptureDriver {
                 }
             }
 
+            // The newest frame replaces the oldest one in flight, see `FrameSkipPolicy`
+            let chosen = chosen.or_else(|| {
+                if capture.drop_oldest.load(Ordering::Relaxed) {
+                    capture.reclaim_oldest_buffer()
+                } else {
+                    None
+                }
+            });
             let Some((idx, buf)) = chosen else {
                 info!("All buffers busy, skipping frame");
                 capture.skip.store(true, Ordering::Release);
@@ -84,8 +100,41 @@ impl render_graph::Node for CaptureDriver {
 
             buf.in_use.store(true, Ordering::Release);
 
+            // The frame id is set before `cancelled` is cleared, so that the worker tells the
+            // frames dropped with a reclaimed buffer from this one
+            let frame_id = capture.next_frame_id.fetch_add(1, Ordering::Relaxed);
+            buf.frame_id.store(frame_id, Ordering::SeqCst);
+            buf.cancelled.store(false, Ordering::SeqCst);
+            buf.pts.store(
+                capture.started.elapsed().as_nanos() as u64,
+                Ordering::Release,
+            );
+            let _span = info_span!("capture_submit", frame_id).entered();
+
+            // The graded copy of the render target is read back instead, see `StreamGrading`
+            let texture = match graded_targets.get(&capture.src_image) {
+                Some(graded) => {
+                    grading::grade(&mut encoder, grading_pipeline, graded);
+                    &graded.texture
+                }
+                None => &src_image.texture,
+            };
+            // A render target smaller than the frames is centered on black bars, see
+            // `AspectPolicy::Letterbox`
+            let texture = match letterbox_targets.get(&capture.src_image) {
+                Some(target) => {
+                    letterbox::letterbox(&mut encoder, texture, target);
+                    &target.texture
+                }
+                None => texture,
+            };
+
+            let timed = capture
+                .timing
+                .as_ref()
+                .filter(|timing| timing.begin(&mut encoder, render_queue));
             encoder.copy_texture_to_buffer(
-                src_image.texture.as_image_copy(),
+                texture.as_image_copy(),
                 TexelCopyBufferInfo {
                     buffer: &buf.buffer,
                     layout: TexelCopyBufferLayout {
@@ -98,11 +147,13 @@ impl render_graph::Node for CaptureDriver {
                         rows_per_image: None,
                     },
                 },
-                src_image.size,
+                capture.size,
             );
+            if let Some(timing) = timed {
+                timing.end(&mut encoder);
+            }
         }
 
-        let render_queue = world.get_resource::<RenderQueue>().unwrap();
         render_queue.submit(std::iter::once(encoder.finish()));
 
         Ok(())
@@ -114,8 +165,13 @@ pub fn receive_image_from_buffer(
     render_device: Res<RenderDevice>,
     worker: Res<WorkerSendBuffer>,
 ) {
-    for (capture_idx, capture) in captures.0.iter_mut().enumerate() {
-        if !capture.enabled() {
+    for capture in captures.0.iter_mut() {
+        // The timestamps of the copy submitted by `CaptureDriver`
+        if let Some(timing) = &capture.timing {
+            timing.read_back();
+        }
+
+        if !capture.capturing() {
             continue;
         }
 
@@ -127,26 +183,51 @@ pub fn receive_image_from_buffer(
 
         let current = capture.current.load(Ordering::Acquire);
         let buf = &capture.buffers[current];
+        let frame_id = buf.frame_id.load(Ordering::Acquire);
+        let pts = Duration::from_nanos(buf.pts.load(Ordering::Acquire));
+        let _span = info_span!("capture_map", frame_id).entered();
+
+        // The pending frames are released without being pushed, so that this one is next
+        if capture.drop_oldest.load(Ordering::Relaxed) {
+            capture.cancel_frames_before(frame_id);
+        }
 
         let slice = buf.buffer.slice(..);
 
         slice.map_async(MapMode::Read, {
             let buffer = buf.buffer.clone();
-            let encoder = capture.encoder.clone();
+            let encoders = capture.encoders();
+            let held = capture.held_frame();
+            let inspector = capture.inspector();
+            let markers = capture.markers.clone();
             let in_use = buf.in_use.clone();
+            let cancelled = buf.cancelled.clone();
+            let reading = buf.reading.clone();
+            let current_frame_id = buf.frame_id.clone();
+            let size = capture.size;
             let worker_tx = worker.tx.clone();
             move |result| match result {
                 Ok(_) => {
                     let job = SendBufferJob {
                         buffer,
-                        encoder,
-                        capture_idx,
-                        buffer_idx: current,
+                        encoders,
+                        in_use,
+                        frame_id,
+                        pts,
+                        size,
+                        held,
+                        inspector,
+                        markers,
+                        cancelled,
+                        reading,
+                        current_frame_id,
                     };
                     if let Err(e) = worker_tx.send(job) {
                         error!("Worker channel closed: {:?}", e);
                     }
                 }
+                // The mappings aborted by `Capture::reclaim_oldest_buffer` are expected
+                Err(_) if current_frame_id.load(Ordering::Acquire) != frame_id => {}
                 Err(err) => {
                     error!("Failed to map buffer: {err}");
                     in_use.store(false, Ordering::Release);
@@ -158,14 +239,12 @@ pub fn receive_image_from_buffer(
     }
 }
 
-pub fn release_mapped_buffers(
-    captures: Res<Captures>,
-    release_buffer_signal: Res<ReleaseBufferSignal>,
-) {
+pub fn release_mapped_buffers(release_buffer_signal: Res<ReleaseBufferSignal>) {
     while let Ok(signal) = release_buffer_signal.rx.try_recv() {
-        let capture = &captures[signal.capture_idx];
-        let buf = &capture.buffers[signal.buffer_idx];
-        buf.buffer.unmap();
-        buf.in_use.store(false, Ordering::Release);
+        if signal.is_stale() {
+            continue;
+        }
+        signal.buffer.unmap();
+        signal.in_use.store(false, Ordering::Release);
     }
 }
@@ -0,0 +1,288 @@
+use bevy_asset::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_image::prelude::*;
+use bevy_log::prelude::*;
+use bevy_platform::collections::HashMap;
+use bevy_render::{
+    camera::Camera,
+    render_asset::RenderAssets,
+    render_resource::{
+        BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+        BufferDescriptor, BufferUsages, CommandEncoder, ComputePassDescriptor, ComputePipeline,
+        Extent3d, FilterMode, PipelineLayoutDescriptor, RawComputePipelineDescriptor, Sampler,
+        SamplerBindingType, SamplerDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages,
+        StorageTextureAccess, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+        TextureSampleType, TextureUsages, TextureView,
+        binding_types::{
+            sampler, texture_2d, texture_3d, texture_storage_2d, uniform_buffer_sized,
+        },
+    },
+    renderer::{RenderDevice, RenderQueue},
+    texture::GpuImage,
+};
+use std::num::NonZero;
+
+use super::{Capture, Captures};
+
+/// Size of the uniform buffer of the shader parameters
+const PARAMS_SIZE: u64 = 32;
+
+/// Color adjustments applied on the GPU to the frames of a streamer camera before they are
+/// read back, so that a broadcast feed can be graded differently from the game view.
+///
+/// The adjustments apply to the sRGB encoded colors, in this order: `brightness` and
+/// `contrast`, `saturation`, `gamma` and the `lut`.
+#[derive(Component, Clone, Debug)]
+pub struct StreamGrading {
+    pub gamma: f32,
+    pub contrast: f32,
+    pub saturation: f32,
+    /// Offset added to the colors, between -1 and 1
+    pub brightness: f32,
+    /// 3D lookup table, e.g. converted from a `.cube` file, with a linear format such as
+    /// `Rgba8Unorm` so that its values are the output sRGB colors
+    pub lut: Option<Handle<Image>>,
+}
+
+impl Default for StreamGrading {
+    fn default() -> Self {
+        Self {
+            gamma: 1.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            brightness: 0.0,
+            lut: None,
+        }
+    }
+}
+
+impl StreamGrading {
+    fn params(&self, use_lut: bool) -> [u8; PARAMS_SIZE as usize] {
+        let mut params = [0; PARAMS_SIZE as usize];
+        let values = [
+            self.gamma.max(0.01).to_ne_bytes(),
+            self.contrast.to_ne_bytes(),
+            self.saturation.to_ne_bytes(),
+            self.brightness.to_ne_bytes(),
+            (use_lut as u32).to_ne_bytes(),
+        ];
+        for (chunk, value) in params.chunks_exact_mut(4).zip(values) {
+            chunk.copy_from_slice(&value);
+        }
+        params
+    }
+}
+
+/// This system copies the `StreamGrading` of the cameras to their captures
+pub(crate) fn apply_stream_grading(
+    cameras: Query<(&Camera, Option<&StreamGrading>)>,
+    captures: Query<&Capture>,
+) {
+    for (camera, grading) in cameras.iter() {
+        let Some(image) = camera.target.as_image() else {
+            continue;
+        };
+        for capture in captures.iter().filter(|c| c.src_image() == image) {
+            *capture.grading.lock().unwrap() = grading.cloned();
+        }
+    }
+}
+
+/// Compute pipeline grading the render targets
+#[derive(Resource)]
+pub(crate) struct GradingPipeline {
+    layout: BindGroupLayout,
+    pipeline: ComputePipeline,
+    /// Bound when there is no lookup table
+    default_lut: TextureView,
+    sampler: Sampler,
+}
+
+impl FromWorld for GradingPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        let layout = render_device.create_bind_group_layout(
+            "stream_grading_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    texture_storage_2d(TextureFormat::Rgba8Unorm, StorageTextureAccess::WriteOnly),
+                    uniform_buffer_sized(false, NonZero::new(PARAMS_SIZE)),
+                    texture_3d(TextureSampleType::Float { filterable: true }),
+                    sampler(SamplerBindingType::Filtering),
+                ),
+            ),
+        );
+
+        let module = render_device.create_and_validate_shader_module(ShaderModuleDescriptor {
+            label: Some("stream_grading"),
+            source: ShaderSource::Wgsl(include_str!("grading.wgsl").into()),
+        });
+        let pipeline_layout = render_device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("stream_grading"),
+            bind_group_layouts: &[&*layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = render_device.create_compute_pipeline(&RawComputePipelineDescriptor {
+            label: Some("stream_grading"),
+            layout: Some(&pipeline_layout),
+            module: &module,
+            entry_point: Some("grade"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let default_lut = render_device
+            .create_texture(&TextureDescriptor {
+                label: Some("stream_grading_default_lut"),
+                size: Extent3d::default(),
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D3,
+                format: TextureFormat::Rgba8Unorm,
+                usage: TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&Default::default());
+        let sampler = render_device.create_sampler(&SamplerDescriptor {
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            layout,
+            pipeline,
+            default_lut,
+            sampler,
+        }
+    }
+}
+
+/// Graded copy of a render target, read back instead of the render target
+pub(crate) struct GradedTarget {
+    pub(crate) texture: Texture,
+    view: TextureView,
+    params: Buffer,
+    bind_group: Option<BindGroup>,
+}
+
+/// `GradedTarget`s of the captures having a `StreamGrading`, by render target
+#[derive(Resource, Default)]
+pub(crate) struct GradedTargets(HashMap<AssetId<Image>, GradedTarget>);
+
+impl GradedTargets {
+    pub(crate) fn get(&self, image: &Handle<Image>) -> Option<&GradedTarget> {
+        self.0
+            .get(&image.id())
+            .filter(|target| target.bind_group.is_some())
+    }
+}
+
+/// This system creates the `GradedTarget`s and their bind groups
+pub(crate) fn prepare_graded_targets(
+    captures: Res<Captures>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    pipeline: Res<GradingPipeline>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut targets: ResMut<GradedTargets>,
+) {
+    let graded = captures
+        .iter()
+        .filter_map(|capture| Some((capture, capture.grading.lock().unwrap().clone()?)))
+        .collect::<Vec<_>>();
+    targets.0.retain(|id, _| {
+        graded
+            .iter()
+            .any(|(capture, _)| capture.src_image.id() == *id)
+    });
+
+    for (capture, grading) in graded {
+        let Some(src_image) = gpu_images.get(&capture.src_image) else {
+            continue;
+        };
+
+        let target = targets
+            .0
+            .entry(capture.src_image.id())
+            .or_insert_with(|| create_graded_target(&render_device, src_image.size));
+        if target.texture.size() != src_image.size {
+            *target = create_graded_target(&render_device, src_image.size);
+        }
+
+        let lut = grading
+            .lut
+            .as_ref()
+            .and_then(|lut| gpu_images.get(lut))
+            .filter(|lut| {
+                let is_3d = lut.texture.dimension() == TextureDimension::D3;
+                if !is_3d {
+                    warn!("The lookup table of a StreamGrading must be a 3D image");
+                }
+                is_3d
+            });
+
+        render_queue.write_buffer(&target.params, 0, &grading.params(lut.is_some()));
+        target.bind_group = Some(render_device.create_bind_group(
+            "stream_grading",
+            &pipeline.layout,
+            &BindGroupEntries::sequential((
+                &src_image.texture_view,
+                &target.view,
+                target.params.as_entire_binding(),
+                lut.map_or(&pipeline.default_lut, |lut| &lut.texture_view),
+                &pipeline.sampler,
+            )),
+        ));
+    }
+}
+
+fn create_graded_target(render_device: &RenderDevice, size: Extent3d) -> GradedTarget {
+    let texture = render_device.create_texture(&TextureDescriptor {
+        label: Some("stream_grading_target"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Rgba8Unorm,
+        usage: TextureUsages::STORAGE_BINDING | TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&Default::default());
+    let params = render_device.create_buffer(&BufferDescriptor {
+        label: Some("stream_grading_params"),
+        size: PARAMS_SIZE,
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    GradedTarget {
+        texture,
+        view,
+        params,
+        bind_group: None,
+    }
+}
+
+/// Grades a render target into its `GradedTarget`
+pub(crate) fn grade(
+    encoder: &mut CommandEncoder,
+    pipeline: &GradingPipeline,
+    target: &GradedTarget,
+) {
+    let Some(bind_group) = &target.bind_group else {
+        return;
+    };
+    let size = target.texture.size();
+
+    let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+        label: Some("stream_grading"),
+        timestamp_writes: None,
+    });
+    pass.set_pipeline(&pipeline.pipeline);
+    pass.set_bind_group(0, &**bind_group, &[]);
+    pass.dispatch_workgroups(size.width.div_ceil(8), size.height.div_ceil(8), 1);
+}
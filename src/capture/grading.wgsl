@@ -0,0 +1,49 @@
+// Color grading of the frames of a stream, see `StreamGrading`
+
+struct Params {
+    gamma: f32,
+    contrast: f32,
+    saturation: f32,
+    brightness: f32,
+    use_lut: u32,
+}
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var target: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(2) var<uniform> params: Params;
+@group(0) @binding(3) var lut: texture_3d<f32>;
+@group(0) @binding(4) var lut_sampler: sampler;
+
+// The render target is sRGB, the target is written with the same encoding
+fn to_srgb(color: vec3<f32>) -> vec3<f32> {
+    return select(
+        1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055,
+        color * 12.92,
+        color <= vec3(0.0031308),
+    );
+}
+
+@compute @workgroup_size(8, 8)
+fn grade(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(source);
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+
+    let texel = textureLoad(source, vec2<i32>(id.xy), 0);
+    var color = to_srgb(clamp(texel.rgb, vec3(0.0), vec3(1.0)));
+
+    color = (color - 0.5) * params.contrast + 0.5 + params.brightness;
+    let luma = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    color = mix(vec3(luma), color, params.saturation);
+    color = pow(clamp(color, vec3(0.0), vec3(1.0)), vec3(1.0 / params.gamma));
+
+    if params.use_lut != 0u {
+        // Sample the centers of the border texels
+        let dimensions = vec3<f32>(textureDimensions(lut));
+        let coords = color * (dimensions - 1.0) / dimensions + 0.5 / dimensions;
+        color = textureSampleLevel(lut, lut_sampler, coords, 0.0).rgb;
+    }
+
+    textureStore(target, vec2<i32>(id.xy), vec4(color, texel.a));
+}
@@ -0,0 +1,128 @@
+use bevy_asset::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_image::prelude::*;
+use bevy_platform::collections::HashMap;
+use bevy_render::{
+    render_asset::RenderAssets,
+    render_resource::{
+        CommandEncoder, Extent3d, LoadOp, Operations, Origin3d, RenderPassColorAttachment,
+        RenderPassDescriptor, StoreOp, TexelCopyTextureInfo, Texture, TextureAspect,
+        TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
+    },
+    renderer::RenderDevice,
+    texture::GpuImage,
+};
+
+use super::Captures;
+
+/// Frame of a capture whose render target is smaller than the stream, read back instead of
+/// the render target, see `AspectPolicy::Letterbox`
+pub(crate) struct LetterboxTarget {
+    pub(crate) texture: Texture,
+    view: TextureView,
+}
+
+/// `LetterboxTarget`s of the captures, by render target
+#[derive(Resource, Default)]
+pub(crate) struct LetterboxTargets(HashMap<AssetId<Image>, LetterboxTarget>);
+
+impl LetterboxTargets {
+    pub(crate) fn get(&self, image: &Handle<Image>) -> Option<&LetterboxTarget> {
+        self.0.get(&image.id())
+    }
+}
+
+/// This system creates the `LetterboxTarget`s of the captures whose render target is not the
+/// size of their frames
+pub(crate) fn prepare_letterbox_targets(
+    captures: Res<Captures>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    render_device: Res<RenderDevice>,
+    mut targets: ResMut<LetterboxTargets>,
+) {
+    let letterboxed = captures
+        .iter()
+        .filter_map(|capture| {
+            let src_image = gpu_images.get(&capture.src_image)?;
+            (src_image.size != capture.size).then_some((capture, src_image))
+        })
+        .collect::<Vec<_>>();
+    targets.0.retain(|id, _| {
+        letterboxed
+            .iter()
+            .any(|(capture, _)| capture.src_image.id() == *id)
+    });
+
+    for (capture, src_image) in letterboxed {
+        let format = src_image.texture_format;
+        let target = targets
+            .0
+            .entry(capture.src_image.id())
+            .or_insert_with(|| create_letterbox_target(&render_device, capture.size, format));
+        if target.texture.size() != capture.size || target.texture.format() != format {
+            *target = create_letterbox_target(&render_device, capture.size, format);
+        }
+    }
+}
+
+fn create_letterbox_target(
+    render_device: &RenderDevice,
+    size: Extent3d,
+    format: TextureFormat,
+) -> LetterboxTarget {
+    let texture = render_device.create_texture(&TextureDescriptor {
+        label: Some("stream_letterbox_target"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_DST | TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&Default::default());
+
+    LetterboxTarget { texture, view }
+}
+
+/// Centers `source` on black bars in its `LetterboxTarget`, it is cropped if it is larger,
+/// e.g. for the frame rendered before a resize
+pub(crate) fn letterbox(encoder: &mut CommandEncoder, source: &Texture, target: &LetterboxTarget) {
+    // The bars are cleared, the copy covers the rest
+    encoder.begin_render_pass(&RenderPassDescriptor {
+        label: Some("stream_letterbox"),
+        color_attachments: &[Some(RenderPassColorAttachment {
+            view: &target.view,
+            resolve_target: None,
+            ops: Operations {
+                load: LoadOp::Clear(wgpu::Color::BLACK),
+                store: StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+
+    // The formats only differ by their sRGB encoding when the source is graded, the bytes are
+    // copied as they are
+    let size = Extent3d {
+        width: source.width().min(target.texture.width()),
+        height: source.height().min(target.texture.height()),
+        ..Default::default()
+    };
+    encoder.copy_texture_to_texture(
+        source.as_image_copy(),
+        TexelCopyTextureInfo {
+            texture: &target.texture,
+            mip_level: 0,
+            origin: Origin3d {
+                x: (target.texture.width() - size.width) / 2,
+                y: (target.texture.height() - size.height) / 2,
+                z: 0,
+            },
+            aspect: TextureAspect::All,
+        },
+        size,
+    );
+}
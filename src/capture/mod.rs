@@ -2,24 +2,55 @@ use bevy_asset::{RenderAssetUsages, prelude::*};
 use bevy_derive::{Deref, DerefMut};
 use bevy_ecs::prelude::*;
 use bevy_image::prelude::*;
-use bevy_log::prelude::*;
+use bevy_log::{info_span, prelude::*};
 use bevy_render::{
     Extract,
-    camera::RenderTarget,
+    camera::{Camera, RenderTarget},
     render_resource::{
-        Buffer, BufferDescriptor, BufferUsages, Extent3d, TextureDimension, TextureFormat,
-        TextureUsages,
+        Buffer, BufferDescriptor, BufferUsages, Extent3d, MapState, TextureDimension,
+        TextureFormat, TextureUsages,
     },
     renderer::RenderDevice,
 };
 use crossbeam_channel::{Receiver, Sender, unbounded};
-use std::sync::{
-    Arc,
-    atomic::{AtomicBool, AtomicUsize, Ordering},
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
-use crate::encoder::EncoderHandle;
+use crate::{
+    FrameSkipPolicy, HoldLastFrame, PlaceholderFrame, PlaceholderSource,
+    budget::GpuMemoryReservation,
+    encoder::{EncoderHandle, Frame},
+};
 pub mod driver;
+pub(crate) mod grading;
+pub(crate) mod letterbox;
+pub(crate) mod timing;
+
+use grading::StreamGrading;
+use timing::GpuTiming;
+
+/// Number of readback buffers of a capture
+const BUFFER_COUNT: usize = 3; // triple buffering
+
+/// Returns the bytes per row of the captured frames, the rows copied from the GPU are padded
+fn frame_stride(width: u32) -> usize {
+    RenderDevice::align_copy_bytes_per_row(width as usize * 4)
+}
+
+/// Called with each captured frame before it is pushed to the encoder, on the capture worker
+pub(crate) type FrameInspector = Arc<dyn Fn(&Frame) + Send + Sync>;
+
+/// Markers waiting for their frame to be pushed, with the id of the frame, see `StreamMarker`
+type PendingMarkers = Arc<Mutex<Vec<(u64, String)>>>;
+
+/// Encoders the frames of a capture are pushed to, shared by the extracted captures so that
+/// the encoders added later receive the frames, see `StreamerHelper::add_encoder`
+type CaptureEncoders = Arc<Mutex<Vec<EncoderHandle>>>;
 
 /// `Captures` aggregator in `RenderWorld`
 #[derive(Clone, Default, Resource, Deref, DerefMut)]
@@ -34,6 +65,14 @@ pub fn capture_extract(mut commands: Commands, captures: Extract<Query<&Capture>
 struct CaptureBuffer {
     buffer: Buffer,
     in_use: Arc<AtomicBool>,
+    // id of the frame copied in this buffer
+    frame_id: Arc<AtomicU64>,
+    /// Capture time of the frame copied in this buffer, in nanoseconds from `Capture::started`
+    pts: Arc<AtomicU64>,
+    /// The frame is released without being pushed, see `FrameSkipPolicy::DropOldest`
+    cancelled: Arc<AtomicBool>,
+    /// The worker is reading the frame, the buffer can't be reclaimed for a newer one
+    reading: Arc<AtomicBool>,
 }
 
 /// Used by `CaptureDriver` for copying from render target to buffer
@@ -42,20 +81,155 @@ pub struct Capture {
     buffers: Vec<CaptureBuffer>,
     current: Arc<AtomicUsize>,
     skip: Arc<AtomicBool>,
+    /// The pending frames are cancelled when a newer one is captured, see `FrameSkipPolicy`
+    drop_oldest: Arc<AtomicBool>,
+    next_frame_id: Arc<AtomicU64>,
+    /// Origin of the pts of the frames
+    started: Instant,
 
     enabled: Arc<AtomicBool>,
+    /// The camera is inactive and a placeholder is streamed instead, see `PlaceholderFrame`
+    placeholder: Arc<AtomicBool>,
+    /// A `TestPattern` is streamed instead
+    test_pattern: Arc<AtomicBool>,
     src_image: Handle<Image>,
+    /// Size of the frames, the render target is smaller when it is letterboxed, see
+    /// `AspectPolicy`
+    size: Extent3d,
+    /// Aspect ratio of the camera when it was created, kept by `AspectPolicy::Letterbox`
+    aspect: (u32, u32),
+    /// The first encoder, whose statistics and pipeline are the ones of the stream
     encoder: EncoderHandle,
+    /// Every encoder, `encoder` first
+    encoders: CaptureEncoders,
+    /// Memory counted in the `GpuMemoryBudget`, if any
+    reservation: Option<Arc<GpuMemoryReservation>>,
+    /// Frame pushed again while no frame is captured, see `HoldLastFrame`
+    held: Arc<Mutex<Option<Arc<HeldFrame>>>>,
+    grading: Arc<Mutex<Option<StreamGrading>>>,
+    inspector: Arc<Mutex<Option<FrameInspector>>>,
+    markers: PendingMarkers,
+    /// Measures the GPU time of the copies, if the device supports timestamp queries
+    timing: Option<Arc<GpuTiming>>,
+}
+
+/// Last frame of a capture or placeholder, pushed again by a background thread when no frame
+/// was pushed for `interval`, which also happens when the app doesn't render
+pub(crate) struct HeldFrame {
+    interval: Duration,
+    /// The captured frames are kept, otherwise only the placeholders are pushed again
+    keep_captured: bool,
+    encoders: CaptureEncoders,
+    width: u32,
+    height: u32,
+    next_frame_id: Arc<AtomicU64>,
+    started: Instant,
+    frame: Mutex<Option<Vec<u8>>>,
+    last_push: Mutex<Instant>,
+}
+
+impl HeldFrame {
+    fn spawn(
+        interval: Duration,
+        keep_captured: bool,
+        capture: &Capture,
+        frame: Option<Vec<u8>>,
+    ) -> Arc<Self> {
+        let held = Arc::new(Self {
+            interval,
+            keep_captured,
+            encoders: capture.encoders.clone(),
+            width: capture.size.width,
+            height: capture.size.height,
+            next_frame_id: capture.next_frame_id.clone(),
+            started: capture.started,
+            frame: Mutex::new(frame),
+            last_push: Mutex::new(Instant::now()),
+        });
+
+        // The thread stops once the capture is dropped or holds another frame
+        let weak = Arc::downgrade(&held);
+        std::thread::spawn(move || {
+            while let Some(held) = weak.upgrade() {
+                let wait = held.push_if_stale();
+                drop(held);
+                std::thread::sleep(wait);
+            }
+        });
+
+        held
+    }
+
+    /// Records the frame just pushed by the capture
+    fn captured(&self, frame: Vec<u8>) {
+        if self.keep_captured {
+            *self.frame.lock().unwrap() = Some(frame);
+        }
+        *self.last_push.lock().unwrap() = Instant::now();
+    }
+
+    /// Replaces the held frame, pushed immediately
+    fn replace(&self, frame: Option<Vec<u8>>) {
+        let mut last_push = self.last_push.lock().unwrap();
+        let mut held = self.frame.lock().unwrap();
+        *held = frame;
+        if let Some(frame) = held.as_ref() {
+            self.push(frame);
+            *last_push = Instant::now();
+        }
+    }
+
+    /// Pushes the frame again if no frame was pushed for `interval`, returns the delay before
+    /// the next check
+    fn push_if_stale(&self) -> Duration {
+        let mut last_push = self.last_push.lock().unwrap();
+        let elapsed = last_push.elapsed();
+        if elapsed < self.interval {
+            return self.interval - elapsed;
+        }
+
+        if let Some(frame) = self.frame.lock().unwrap().as_ref() {
+            self.push(frame);
+        }
+        *last_push = Instant::now();
+
+        self.interval
+    }
+
+    fn push(&self, data: &[u8]) {
+        let frame = Frame {
+            data,
+            width: self.width,
+            height: self.height,
+            stride: frame_stride(self.width),
+            pts: self.started.elapsed(),
+            id: self.next_frame_id.fetch_add(1, Ordering::Relaxed),
+        };
+        for encoder in self.encoders.lock().unwrap().iter() {
+            if let Err(e) = encoder.push_frame(&frame) {
+                debug!("Unable to push held frame {}: {:?}", frame.id, e);
+            }
+        }
+    }
 }
 
 pub struct SendBufferJob {
     // slice: BufferSlice<'static>,
     buffer: Buffer,
     // len: usize,
-    encoder: EncoderHandle,
-    // in_use: Arc<AtomicBool>,
-    capture_idx: usize,
-    buffer_idx: usize,
+    encoders: Vec<EncoderHandle>,
+    in_use: Arc<AtomicBool>,
+    frame_id: u64,
+    pts: Duration,
+    size: Extent3d,
+    held: Option<Arc<HeldFrame>>,
+    inspector: Option<FrameInspector>,
+    markers: PendingMarkers,
+    cancelled: Arc<AtomicBool>,
+    reading: Arc<AtomicBool>,
+    /// Id of the frame currently in the buffer, it differs from `frame_id` once the buffer
+    /// is reclaimed for a newer frame
+    current_frame_id: Arc<AtomicU64>,
 }
 
 #[derive(Resource, Clone)]
@@ -68,11 +242,29 @@ pub struct ReleaseBufferSignal {
     pub rx: Receiver<ReleaseSignal>,
 }
 
+/// Buffer to release once its content has been sent.
+///
+/// The buffer itself is carried rather than indices into `Captures`, because the captures are
+/// re-extracted every frame and their order changes when cameras are spawned or despawned.
 pub struct ReleaseSignal {
-    // index of the capture
-    capture_idx: usize,
-    // index of the buffer to release
-    buffer_idx: usize,
+    buffer: Buffer,
+    in_use: Arc<AtomicBool>,
+    frame_id: u64,
+    current_frame_id: Arc<AtomicU64>,
+}
+
+impl ReleaseSignal {
+    /// Returns true if the buffer was reclaimed for a newer frame, and is already unmapped
+    fn is_stale(&self) -> bool {
+        self.current_frame_id.load(Ordering::Acquire) != self.frame_id
+    }
+}
+
+/// Returns the GPU memory used by the render target and the readback buffers of a capture
+pub(crate) fn capture_memory_size(width: u32, height: u32) -> u64 {
+    let buffer_size = frame_stride(width) as u64 * height as u64;
+
+    width as u64 * height as u64 * 4 + buffer_size * BUFFER_COUNT as u64
 }
 
 impl Capture {
@@ -82,10 +274,9 @@ impl Capture {
         render_device: &RenderDevice,
         encoder: EncoderHandle,
     ) -> Self {
-        let padded_bytes_per_row =
-            RenderDevice::align_copy_bytes_per_row((size.width) as usize) * 4;
+        let padded_bytes_per_row = frame_stride(size.width);
 
-        let buffers = (0..3) // triple buffering
+        let buffers = (0..BUFFER_COUNT)
             .map(|_| {
                 let buffer = render_device.create_buffer(&BufferDescriptor {
                     label: Some("Capture buffer"),
@@ -96,6 +287,10 @@ impl Capture {
                 CaptureBuffer {
                     buffer,
                     in_use: Arc::new(AtomicBool::new(false)),
+                    frame_id: Arc::new(AtomicU64::new(0)),
+                    pts: Arc::new(AtomicU64::new(0)),
+                    cancelled: Arc::new(AtomicBool::new(false)),
+                    reading: Arc::new(AtomicBool::new(false)),
                 }
             })
             .collect();
@@ -104,15 +299,378 @@ impl Capture {
             buffers,
             current: Arc::new(AtomicUsize::new(0)),
             skip: Arc::new(AtomicBool::new(false)),
+            drop_oldest: Arc::new(AtomicBool::new(false)),
+            next_frame_id: Arc::new(AtomicU64::new(0)),
+            started: Instant::now(),
             enabled: Arc::new(AtomicBool::new(true)),
+            placeholder: Arc::new(AtomicBool::new(false)),
+            test_pattern: Arc::new(AtomicBool::new(false)),
             src_image,
+            size,
+            aspect: (size.width, size.height),
+            encoders: Arc::new(Mutex::new(vec![encoder.clone()])),
             encoder,
+            reservation: None,
+            held: Arc::default(),
+            grading: Arc::default(),
+            inspector: Arc::default(),
+            markers: Arc::default(),
+            timing: GpuTiming::new(render_device).map(Arc::new),
         }
     }
 
+    /// Counts the memory of this capture in the `GpuMemoryBudget` until it is dropped
+    pub(crate) fn with_reservation(
+        mut self,
+        reservation: Option<Arc<GpuMemoryReservation>>,
+    ) -> Self {
+        self.reservation = reservation;
+        self
+    }
+
+    pub(crate) fn reservation(&self) -> Option<&Arc<GpuMemoryReservation>> {
+        self.reservation.as_ref()
+    }
+
+    /// Pushes the frames to the encoders of `capture`, the one being replaced, or lets its
+    /// `TestPattern` do it
+    pub(crate) fn with_encoders_of(mut self, capture: &Capture) -> Self {
+        self.encoder = capture.encoder.clone();
+        self.encoders = capture.encoders.clone();
+        self.set_test_pattern(capture.test_pattern.load(Ordering::Relaxed));
+        self
+    }
+
+    /// Keeps the aspect ratio of the capture being replaced
+    pub(crate) fn with_aspect_of(mut self, capture: &Capture) -> Self {
+        self.aspect = capture.aspect;
+        self
+    }
+
     pub fn enabled(&self) -> bool {
         self.enabled.load(Ordering::Relaxed)
     }
+
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns true if frames are copied from the render target and pushed to the encoder
+    pub(crate) fn capturing(&self) -> bool {
+        self.enabled()
+            && !self.placeholder.load(Ordering::Relaxed)
+            && !self.test_pattern.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of frames copied from the GPU and not pushed to the encoder yet
+    pub(crate) fn buffers_in_use(&self) -> usize {
+        self.buffers
+            .iter()
+            .filter(|buf| buf.in_use.load(Ordering::Acquire))
+            .count()
+    }
+
+    pub(crate) fn buffer_count(&self) -> usize {
+        self.buffers.len()
+    }
+
+    fn set_frame_skip_policy(&self, policy: FrameSkipPolicy) {
+        self.drop_oldest
+            .store(policy == FrameSkipPolicy::DropOldest, Ordering::Relaxed);
+    }
+
+    /// Cancels the frames older than `frame_id` which are not pushed yet
+    fn cancel_frames_before(&self, frame_id: u64) {
+        for buf in &self.buffers {
+            if buf.in_use.load(Ordering::Acquire) && buf.frame_id.load(Ordering::Acquire) < frame_id
+            {
+                buf.cancelled.store(true, Ordering::Release);
+            }
+        }
+    }
+
+    /// Takes back the buffer of the oldest frame which the worker is not reading, when all the
+    /// buffers are in use, the frame is dropped. See `FrameSkipPolicy::DropOldest`
+    fn reclaim_oldest_buffer(&self) -> Option<(usize, CaptureBuffer)> {
+        let (idx, buf) = self
+            .buffers
+            .iter()
+            .enumerate()
+            .filter(|(_, buf)| !buf.reading.load(Ordering::SeqCst))
+            .min_by_key(|(_, buf)| buf.frame_id.load(Ordering::Acquire))?;
+
+        // The worker checks `cancelled` once it marks the buffer as read, either it drops the
+        // frame or the buffer is not reclaimed
+        buf.cancelled.store(true, Ordering::SeqCst);
+        if buf.reading.load(Ordering::SeqCst) {
+            return None;
+        }
+        // The callback of an aborted mapping and the release of the dropped frame are ignored
+        // once the buffer no longer holds it, the callback can be called by `unmap`
+        buf.frame_id.store(u64::MAX, Ordering::SeqCst);
+        if buf.buffer.map_state() != MapState::Unmapped {
+            buf.buffer.unmap();
+        }
+
+        Some((idx, buf.clone()))
+    }
+
+    pub(crate) fn set_test_pattern(&self, test_pattern: bool) {
+        self.test_pattern.store(test_pattern, Ordering::Relaxed);
+    }
+
+    /// Returns the width and height of the captured frames
+    pub(crate) fn size(&self) -> (u32, u32) {
+        (self.size.width, self.size.height)
+    }
+
+    /// Returns the aspect ratio of the camera when it was created
+    pub(crate) fn aspect(&self) -> (u32, u32) {
+        self.aspect
+    }
+
+    /// Returns the render target image copied by this capture
+    pub(crate) fn src_image(&self) -> &Handle<Image> {
+        &self.src_image
+    }
+
+    /// Returns the first encoder the frames are pushed to
+    pub(crate) fn encoder(&self) -> &EncoderHandle {
+        &self.encoder
+    }
+
+    /// Returns every encoder the frames are pushed to, the first one first
+    pub(crate) fn encoders(&self) -> Vec<EncoderHandle> {
+        self.encoders.lock().unwrap().clone()
+    }
+
+    /// Pushes the next frames to `encoder` too
+    pub(crate) fn add_encoder(&self, encoder: EncoderHandle) {
+        self.encoders.lock().unwrap().push(encoder);
+    }
+
+    /// Sets the inspector of the captured frames, or removes it if `None`
+    pub(crate) fn set_inspector(&self, inspector: Option<FrameInspector>) {
+        *self.inspector.lock().unwrap() = inspector;
+    }
+
+    fn inspector(&self) -> Option<FrameInspector> {
+        self.inspector.lock().unwrap().clone()
+    }
+
+    /// Adds a marker to the next captured frame, passed to the encoder with it, and returns
+    /// the id of this frame
+    pub(crate) fn add_marker(&self, name: String) -> u64 {
+        let frame_id = self.next_frame_id.load(Ordering::Relaxed);
+        self.markers.lock().unwrap().push((frame_id, name));
+        frame_id
+    }
+
+    /// Pushes the held frame again every `interval` while no frame is captured, or stops
+    /// holding frames if `None`
+    fn set_hold_interval(&self, interval: Option<Duration>, keep_captured: bool) {
+        let mut held = self.held.lock().unwrap();
+        let current = held
+            .as_ref()
+            .map(|held| (held.interval, held.keep_captured));
+        if current == interval.map(|interval| (interval, keep_captured)) {
+            return;
+        }
+
+        // The placeholder being streamed is held by the new frame too
+        let placeholder = held
+            .as_ref()
+            .filter(|_| self.showing_placeholder())
+            .and_then(|held| held.frame.lock().unwrap().clone());
+        *held =
+            interval.map(|interval| HeldFrame::spawn(interval, keep_captured, self, placeholder));
+    }
+
+    pub(crate) fn held_frame(&self) -> Option<Arc<HeldFrame>> {
+        self.held.lock().unwrap().clone()
+    }
+
+    /// Stops capturing and holds `frame` instead, or resumes capturing if `None`
+    fn set_placeholder(&self, frame: Option<Vec<u8>>) {
+        self.placeholder.store(frame.is_some(), Ordering::Relaxed);
+        if let Some(held) = self.held_frame() {
+            held.replace(frame);
+        }
+    }
+
+    fn showing_placeholder(&self) -> bool {
+        self.placeholder.load(Ordering::Relaxed)
+    }
+}
+
+/// Returns the frame of a placeholder with the layout of the captured frames, `None` if its
+/// image is not loaded yet
+fn placeholder_frame(
+    source: &PlaceholderSource,
+    width: u32,
+    height: u32,
+    images: &Assets<Image>,
+) -> Option<Vec<u8>> {
+    let row_size = frame_stride(width);
+    let mut frame = vec![0; row_size * height as usize];
+
+    match source {
+        PlaceholderSource::Color(color) => {
+            for row in frame.chunks_exact_mut(row_size) {
+                for pixel in row[..width as usize * 4].chunks_exact_mut(4) {
+                    pixel.copy_from_slice(color);
+                }
+            }
+        }
+        PlaceholderSource::Image(handle) => {
+            let image = images.get(handle)?;
+            let format = image.texture_descriptor.format;
+            let data = match &image.data {
+                Some(data)
+                    if matches!(
+                        format,
+                        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb
+                    ) =>
+                {
+                    data
+                }
+                _ => {
+                    warn!("Unsupported placeholder image format {:?}", format);
+                    return Some(frame);
+                }
+            };
+
+            // Nearest neighbor scaling to the size of the stream
+            let (image_width, image_height) = (image.width() as usize, image.height() as usize);
+            for (y, row) in frame.chunks_exact_mut(row_size).enumerate() {
+                let image_y = y * image_height / height as usize;
+                for (x, pixel) in row[..width as usize * 4].chunks_exact_mut(4).enumerate() {
+                    let image_x = x * image_width / width as usize;
+                    let offset = (image_y * image_width + image_x) * 4;
+                    pixel.copy_from_slice(&data[offset..offset + 4]);
+                }
+            }
+        }
+    }
+
+    Some(frame)
+}
+
+/// This system copies the `FrameSkipPolicy` of the cameras to their captures
+pub(crate) fn apply_frame_skip_policies(
+    cameras: Query<(&Camera, Option<&FrameSkipPolicy>)>,
+    captures: Query<&Capture>,
+) {
+    for (camera, policy) in cameras.iter() {
+        let Some(image) = camera.target.as_image() else {
+            continue;
+        };
+        for capture in captures.iter().filter(|c| c.src_image() == image) {
+            capture.set_frame_skip_policy(policy.copied().unwrap_or_default());
+        }
+    }
+}
+
+/// This system applies the `HoldLastFrame` and `PlaceholderFrame` of the cameras to their
+/// captures
+pub(crate) fn apply_held_frames(
+    cameras: Query<(
+        &Camera,
+        Option<&HoldLastFrame>,
+        Option<Ref<PlaceholderFrame>>,
+    )>,
+    captures: Query<&Capture>,
+    images: Res<Assets<Image>>,
+) {
+    for (camera, hold, placeholder) in cameras.iter() {
+        let Some(image) = camera.target.as_image() else {
+            continue;
+        };
+        let interval = placeholder
+            .as_ref()
+            .map(|placeholder| placeholder.interval)
+            .or(hold.map(|hold| hold.interval));
+
+        for capture in captures.iter().filter(|c| c.src_image() == image) {
+            capture.set_hold_interval(interval, hold.is_some());
+
+            let inactive = !camera.is_active || !capture.enabled();
+            match &placeholder {
+                Some(placeholder) if inactive => {
+                    if capture.showing_placeholder() && !placeholder.is_changed() {
+                        continue;
+                    }
+                    let frame = placeholder_frame(
+                        &placeholder.source,
+                        capture.size.width,
+                        capture.size.height,
+                        &images,
+                    );
+                    if frame.is_some() {
+                        capture.set_placeholder(frame);
+                    }
+                }
+                _ if capture.showing_placeholder() => capture.set_placeholder(None),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Encoders added to a streamer camera, added to its capture once it is spawned, see
+/// `StreamerHelper::add_encoder`
+#[derive(Component)]
+pub(crate) struct PendingEncoders(pub(crate) Vec<EncoderHandle>);
+
+/// This system adds the `PendingEncoders` of the cameras to their captures, resized to the
+/// captured frames
+pub(crate) fn attach_pending_encoders(
+    mut commands: Commands,
+    cameras: Query<(Entity, &Camera, &PendingEncoders)>,
+    captures: Query<&Capture>,
+) {
+    for (entity, camera, pending) in cameras.iter() {
+        let Some(capture) = camera
+            .target
+            .as_image()
+            .and_then(|image| captures.iter().find(|c| c.src_image() == image))
+        else {
+            continue;
+        };
+
+        let (width, height) = capture.size();
+        for encoder in &pending.0 {
+            let size = encoder.stats().map(|stats| (stats.width, stats.height));
+            if size.is_some_and(|size| size != (width, height)) {
+                if let Err(e) = encoder.resize(width, height) {
+                    warn!(
+                        "Unable to resize the added encoder to {}x{}: {:?}",
+                        width, height, e
+                    );
+                }
+            }
+            capture.add_encoder(encoder.clone());
+        }
+        commands.entity(entity).remove::<PendingEncoders>();
+    }
+}
+
+/// Returns the image a streamer camera renders to, which can be copied
+pub(crate) fn render_target_image(
+    images: &mut ResMut<Assets<Image>>,
+    size: Extent3d,
+) -> Handle<Image> {
+    // This is the texture that will be rendered to.
+    let mut render_target_image = Image::new_fill(
+        size,
+        TextureDimension::D2,
+        &[0; 4],
+        TextureFormat::bevy_default(),
+        RenderAssetUsages::default(),
+    );
+    render_target_image.texture_descriptor.usage |=
+        TextureUsages::COPY_SRC | TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING;
+    images.add(render_target_image)
 }
 
 /// Setups render target and cpu image for saving, changes scene state into render mode
@@ -124,6 +682,7 @@ pub fn setup_render_target(
     width: u32,
     height: u32,
     encoder: EncoderHandle,
+    reservation: Option<Arc<GpuMemoryReservation>>,
 ) -> RenderTarget {
     let size = Extent3d {
         width,
@@ -131,28 +690,36 @@ pub fn setup_render_target(
         ..Default::default()
     };
 
-    // This is the texture that will be rendered to.
-    let mut render_target_image = Image::new_fill(
-        size,
+    let render_target_image_handle = render_target_image(images, size);
+
+    commands.spawn(
+        Capture::new(
+            render_target_image_handle.clone(),
+            size,
+            render_device,
+            encoder,
+        )
+        .with_reservation(reservation),
+    );
+
+    // commands.spawn(ImageToSave(cpu_image_handle));
+
+    RenderTarget::Image(render_target_image_handle.into())
+}
+
+/// Returns the placeholder render target of a camera refused by the `GpuMemoryBudget`,
+/// which is not captured
+pub(crate) fn placeholder_render_target(images: &mut ResMut<Assets<Image>>) -> RenderTarget {
+    let mut image = Image::new_fill(
+        Extent3d::default(),
         TextureDimension::D2,
         &[0; 4],
         TextureFormat::bevy_default(),
         RenderAssetUsages::default(),
     );
-    render_target_image.texture_descriptor.usage |=
-        TextureUsages::COPY_SRC | TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING;
-    let render_target_image_handle = images.add(render_target_image);
-
-    commands.spawn(Capture::new(
-        render_target_image_handle.clone(),
-        size,
-        render_device,
-        encoder,
-    ));
-
-    // commands.spawn(ImageToSave(cpu_image_handle));
+    image.texture_descriptor.usage |= TextureUsages::RENDER_ATTACHMENT;
 
-    RenderTarget::Image(render_target_image_handle.into())
+    RenderTarget::Image(images.add(image).into())
 }
 
 pub fn spawn_worker() -> (Sender<SendBufferJob>, Receiver<ReleaseSignal>) {
@@ -161,15 +728,79 @@ pub fn spawn_worker() -> (Sender<SendBufferJob>, Receiver<ReleaseSignal>) {
 
     std::thread::spawn(move || {
         while let Ok(job) = rx_job.recv() {
+            let _span = info_span!("capture_worker", frame_id = job.frame_id).entered();
+
+            // Marked as read before checking `cancelled`, see `Capture::reclaim_oldest_buffer`
+            job.reading.store(true, Ordering::SeqCst);
+            if job.current_frame_id.load(Ordering::SeqCst) != job.frame_id {
+                debug!("Dropping frame {}, its buffer is reclaimed", job.frame_id);
+                job.reading.store(false, Ordering::SeqCst);
+                continue;
+            }
+            if job.cancelled.load(Ordering::SeqCst) {
+                debug!("Dropping frame {}, a newer frame is captured", job.frame_id);
+                job.reading.store(false, Ordering::SeqCst);
+                if let Err(e) = tx_release.send(ReleaseSignal {
+                    buffer: job.buffer,
+                    in_use: job.in_use,
+                    frame_id: job.frame_id,
+                    current_frame_id: job.current_frame_id,
+                }) {
+                    error!("Release channel closed: {:?}", e);
+                }
+                continue;
+            }
+
             let slice = job.buffer.slice(..);
             let data = slice.get_mapped_range().to_vec();
 
+            {
+                let _span = info_span!("encoder_push", frame_id = job.frame_id).entered();
+                let frame = Frame {
+                    data: &data,
+                    width: job.size.width,
+                    height: job.size.height,
+                    stride: frame_stride(job.size.width),
+                    pts: job.pts,
+                    id: job.frame_id,
+                };
+                if let Some(inspector) = &job.inspector {
+                    inspector(&frame);
+                }
+                // The markers of the skipped frames go to the next pushed one
+                let markers: Vec<(u64, String)> = {
+                    let mut pending = job.markers.lock().unwrap();
+                    let (due, later) = std::mem::take(&mut *pending)
+                        .into_iter()
+                        .partition(|(frame_id, _)| *frame_id <= job.frame_id);
+                    *pending = later;
+                    due
+                };
+                // An encoder failing does not hold back the others
+                for encoder in &job.encoders {
+                    for (_, name) in &markers {
+                        if let Err(e) = encoder.add_marker(name, &frame) {
+                            debug!(
+                                "Unable to add marker {:?} to frame {}: {:?}",
+                                name, job.frame_id, e
+                            );
+                        }
+                    }
+                    if let Err(e) = encoder.push_frame(&frame) {
+                        debug!("Unable to push frame {}: {:?}", job.frame_id, e);
+                    }
+                }
+            }
+            if let Some(held) = job.held {
+                held.captured(data);
+            }
 
-            let _ = job.encoder.push_frame(&data);
-
+            job.reading.store(false, Ordering::SeqCst);
             if let Err(e) = tx_release.send(ReleaseSignal {
-                capture_idx: job.capture_idx,
-                buffer_idx: job.buffer_idx,
+                buffer: job.buffer,
+                in_use: job.in_use,
+                frame_id: job.frame_id,
+                current_frame_id: job.current_frame_id,
             }) {
                 error!("Release channel closed: {:?}", e);
             }
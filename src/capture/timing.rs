@@ -0,0 +1,205 @@
+use bevy_diagnostic::{Diagnostic, DiagnosticMeasurement, DiagnosticPath, DiagnosticsStore};
+use bevy_ecs::prelude::*;
+use bevy_render::{
+    camera::Camera,
+    render_resource::{
+        Buffer, BufferDescriptor, BufferUsages, CommandEncoder, MapMode, QuerySet,
+        QuerySetDescriptor, QueryType,
+    },
+    renderer::{RenderDevice, RenderQueue},
+    settings::WgpuFeatures,
+};
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU8, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use super::Capture;
+use crate::{CaptureGpuTime, StreamLabels};
+
+/// Size of the start and end timestamps
+const TIMESTAMPS_SIZE: u64 = 2 * 8;
+
+/// States of the timestamps of a measured copy
+const IDLE: u8 = 0;
+const WRITTEN: u8 = 1;
+const MAPPING: u8 = 2;
+const MAPPED: u8 = 3;
+
+/// Timestamp queries measuring the GPU time of the copies of a capture.
+///
+/// One copy is measured at a time, the next copies are not measured until its timestamps are
+/// read back.
+pub(crate) struct GpuTiming {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readback_buffer: Buffer,
+    state: Arc<AtomicU8>,
+    latest: Mutex<Option<Duration>>,
+}
+
+impl GpuTiming {
+    /// Returns `None` if the device doesn't support timestamp queries in command encoders
+    pub(crate) fn new(render_device: &RenderDevice) -> Option<Self> {
+        let features =
+            WgpuFeatures::TIMESTAMP_QUERY | WgpuFeatures::TIMESTAMP_QUERY_INSIDE_ENCODERS;
+        if !render_device.features().contains(features) {
+            return None;
+        }
+
+        let query_set = render_device
+            .wgpu_device()
+            .create_query_set(&QuerySetDescriptor {
+                label: Some("Capture timestamps"),
+                ty: QueryType::Timestamp,
+                count: 2,
+            });
+        let resolve_buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("Capture timestamps resolve buffer"),
+            size: TIMESTAMPS_SIZE,
+            usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("Capture timestamps readback buffer"),
+            size: TIMESTAMPS_SIZE,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            readback_buffer,
+            state: Arc::new(AtomicU8::new(IDLE)),
+            latest: Mutex::default(),
+        })
+    }
+
+    /// Reads the timestamps of the last measured copy if they are mapped, then writes the
+    /// start timestamp of the next copy. Returns false if the last copy is not read back yet,
+    /// `end` must not be called then.
+    pub(crate) fn begin(&self, encoder: &mut CommandEncoder, queue: &RenderQueue) -> bool {
+        if self.state.load(Ordering::Acquire) == MAPPED {
+            {
+                let data = self.readback_buffer.slice(..).get_mapped_range();
+                let timestamp = |offset: usize| {
+                    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+                };
+                let ticks = timestamp(8).saturating_sub(timestamp(0));
+                let nanos = ticks as f64 * queue.get_timestamp_period() as f64;
+                *self.latest.lock().unwrap() = Some(Duration::from_nanos(nanos as u64));
+            }
+            self.readback_buffer.unmap();
+            self.state.store(IDLE, Ordering::Release);
+        }
+
+        if self.state.load(Ordering::Acquire) != IDLE {
+            return false;
+        }
+        encoder.write_timestamp(&self.query_set, 0);
+        true
+    }
+
+    /// Writes the end timestamp of the copy and copies both timestamps to the readback buffer
+    pub(crate) fn end(&self, encoder: &mut CommandEncoder) {
+        encoder.write_timestamp(&self.query_set, 1);
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            TIMESTAMPS_SIZE,
+        );
+        self.state.store(WRITTEN, Ordering::Release);
+    }
+
+    /// Maps the timestamps of the measured copy, once its commands are submitted
+    pub(crate) fn read_back(&self) {
+        if self
+            .state
+            .compare_exchange(WRITTEN, MAPPING, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return;
+        }
+
+        let state = self.state.clone();
+        self.readback_buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                state.store(
+                    if result.is_ok() { MAPPED } else { IDLE },
+                    Ordering::Release,
+                );
+            });
+    }
+
+    /// Returns the GPU time of the copy measured since the last call, if any
+    fn take(&self) -> Option<Duration> {
+        self.latest.lock().unwrap().take()
+    }
+}
+
+/// Returns the diagnostic path of the copy time of a stream
+fn diagnostic_path(stream: &str) -> DiagnosticPath {
+    // Each `/` starts a new component of the path, which must not be empty
+    DiagnosticPath::new(format!(
+        "streaming/{}/capture_gpu_time",
+        stream.replace('/', "_")
+    ))
+}
+
+/// This system copies the GPU time of the copies of the captures to the `CaptureGpuTime` of
+/// their camera and to the diagnostics, if the `DiagnosticsStore` exists
+pub(crate) fn update_capture_gpu_times(
+    mut commands: Commands,
+    mut cameras: Query<(
+        Entity,
+        &Camera,
+        Option<&StreamLabels>,
+        Option<&mut CaptureGpuTime>,
+    )>,
+    captures: Query<&Capture>,
+    mut diagnostics: Option<ResMut<DiagnosticsStore>>,
+) {
+    for (entity, camera, labels, gpu_time) in cameras.iter_mut() {
+        let Some(image) = camera.target.as_image() else {
+            continue;
+        };
+        let Some(copy_time) = captures
+            .iter()
+            .find(|capture| capture.src_image() == image)
+            .and_then(|capture| capture.timing.as_ref())
+            .and_then(|timing| timing.take())
+        else {
+            continue;
+        };
+
+        match gpu_time {
+            Some(mut gpu_time) => gpu_time.0 = Some(copy_time),
+            None => {
+                commands
+                    .entity(entity)
+                    .insert(CaptureGpuTime(Some(copy_time)));
+            }
+        }
+
+        if let (Some(diagnostics), Some(labels)) = (diagnostics.as_mut(), labels) {
+            let path = diagnostic_path(&labels.name);
+            if diagnostics.get(&path).is_none() {
+                diagnostics.add(Diagnostic::new(path.clone()).with_suffix("ms"));
+            }
+            if let Some(diagnostic) = diagnostics.get_mut(&path) {
+                diagnostic.add_measurement(DiagnosticMeasurement {
+                    time: Instant::now(),
+                    value: copy_time.as_secs_f64() * 1000.0,
+                });
+            }
+        }
+    }
+}
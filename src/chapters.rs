@@ -0,0 +1,162 @@
+use anyhow::{Result, bail};
+use bevy_log::prelude::*;
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+    time::Duration,
+};
+
+/// Most chapters of the Nero chapter box, their count is a single byte
+const MAX_MP4_CHAPTERS: usize = 255;
+
+/// Returns the table of contents written as chapters by matroskamux, one chapter per marker
+/// lasting until the next one. `chapters` are sorted by start time.
+pub(crate) fn matroska_toc(chapters: &[(gst::ClockTime, String)]) -> gst::Toc {
+    let mut toc = gst::Toc::new(gst::TocScope::Global);
+    let mut edition = gst::TocEntry::new(gst::TocEntryType::Edition, "markers");
+    for (index, (start, name)) in chapters.iter().enumerate() {
+        let stop = chapters
+            .get(index + 1)
+            .map(|(next, _)| next.nseconds() as i64)
+            .unwrap_or(-1);
+        let mut chapter = gst::TocEntry::new(gst::TocEntryType::Chapter, &format!("{index}"));
+        let mut tags = gst::TagList::new();
+        tags.get_mut()
+            .unwrap()
+            .add::<gst::tags::Title>(&name.as_str(), gst::TagMergeMode::Replace);
+        {
+            let chapter = chapter.get_mut().unwrap();
+            chapter.set_start_stop_times(start.nseconds() as i64, stop);
+            chapter.set_tags(tags);
+        }
+        edition.get_mut().unwrap().append_sub_entry(chapter);
+    }
+    toc.get_mut().unwrap().append_entry(edition);
+    toc
+}
+
+/// Reads the header of the box at `offset`, returns its type, size and header size
+fn read_box_header(file: &mut File, offset: u64, end: u64) -> Result<([u8; 4], u64, u64)> {
+    let mut header = [0u8; 8];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut header)?;
+    let kind = [header[4], header[5], header[6], header[7]];
+    match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+        // The box extends to the end of its parent
+        0 => Ok((kind, end - offset, 8)),
+        1 => {
+            let mut size = [0u8; 8];
+            file.read_exact(&mut size)?;
+            Ok((kind, u64::from_be_bytes(size), 16))
+        }
+        size => Ok((kind, size as u64, 8)),
+    }
+}
+
+/// Returns the offset, size and header size of the last box of type `kind` between `start`
+/// and `end`, and whether it is the last box of this range
+fn find_box(
+    file: &mut File,
+    start: u64,
+    end: u64,
+    kind: &[u8; 4],
+) -> Result<Option<(u64, u64, u64, bool)>> {
+    let mut found = None;
+    let mut offset = start;
+    while offset + 8 <= end {
+        let (box_kind, size, header_size) = read_box_header(file, offset, end)?;
+        if size < header_size || offset + size > end {
+            bail!("Invalid MP4 box at {}", offset);
+        }
+        if &box_kind == kind {
+            found = Some((offset, size, header_size));
+        }
+        offset += size;
+    }
+
+    Ok(found.map(|(offset, size, header_size)| (offset, size, header_size, offset + size == end)))
+}
+
+/// Returns the Nero chapter box (`chpl`), read by FFmpeg, VLC and mpv
+fn chpl_box(chapters: &[(Duration, String)]) -> Vec<u8> {
+    let mut data = vec![0u8; 8];
+    data.extend_from_slice(&[1, 0, 0, 0]); // Version 1, no flags
+    data.extend_from_slice(&[0; 4]);
+    data.push(chapters.len() as u8);
+    for (start, name) in chapters {
+        // In units of 100 ns
+        data.extend_from_slice(&((start.as_nanos() / 100) as u64).to_be_bytes());
+        let mut len = name.len().min(u8::MAX as usize);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        data.push(len as u8);
+        data.extend_from_slice(&name.as_bytes()[..len]);
+    }
+    let size = data.len() as u32;
+    data[..4].copy_from_slice(&size.to_be_bytes());
+    data[4..8].copy_from_slice(b"chpl");
+    data
+}
+
+/// Grows the 32 bits size of the box at `offset` by `added` bytes
+fn grow_box(file: &mut File, offset: u64, size: u64, header_size: u64, added: u64) -> Result<()> {
+    if header_size != 8 || size + added > u32::MAX as u64 {
+        bail!("The MP4 box at {} has a 64 bits size", offset);
+    }
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(&((size + added) as u32).to_be_bytes())?;
+    Ok(())
+}
+
+/// Adds `chapters` to a MP4 file finalized by mp4mux, whose `moov` box is written at the end
+/// of the file so that it is extended without moving the media data. `chapters` are sorted,
+/// their start is relative to the start of the file.
+pub(crate) fn write_mp4_chapters(path: &Path, chapters: &[(Duration, String)]) -> Result<()> {
+    let chapters = if chapters.len() > MAX_MP4_CHAPTERS {
+        warn!(
+            "{} markers in {}, only the first {} are written as chapters",
+            chapters.len(),
+            path.display(),
+            MAX_MP4_CHAPTERS
+        );
+        &chapters[..MAX_MP4_CHAPTERS]
+    } else {
+        chapters
+    };
+
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let len = file.metadata()?.len();
+    let Some((moov, moov_size, moov_header, true)) = find_box(&mut file, 0, len, b"moov")? else {
+        bail!("No moov box at the end of {}", path.display());
+    };
+    if moov_header != 8 {
+        bail!("The moov box of {} has a 64 bits size", path.display());
+    }
+
+    let chpl = chpl_box(chapters);
+    let udta = find_box(&mut file, moov + moov_header, len, b"udta")?;
+    let added = match udta {
+        // Extends the user data box when it is the last box of moov
+        Some((udta, udta_size, udta_header, true)) => {
+            grow_box(&mut file, udta, udta_size, udta_header, chpl.len() as u64)?;
+            file.seek(SeekFrom::End(0))?;
+            file.write_all(&chpl)?;
+            chpl.len() as u64
+        }
+        Some(_) => bail!("The udta box is not the last box of the moov box"),
+        None => {
+            let size = chpl.len() as u32 + 8;
+            file.seek(SeekFrom::End(0))?;
+            file.write_all(&size.to_be_bytes())?;
+            file.write_all(b"udta")?;
+            file.write_all(&chpl)?;
+            size as u64
+        }
+    };
+    grow_box(&mut file, moov, moov_size, moov_header, added)?;
+    file.sync_all()?;
+
+    Ok(())
+}
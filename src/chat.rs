@@ -0,0 +1,293 @@
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use bevy_platform::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::{ControllerState, StreamerUiInteraction, pixelstreaming::message::PSOutgoingMessage};
+
+/// Name of the chat messages in the data channel envelopes
+const CHAT_EVENT: &str = "chat";
+/// Name of the notices sent to a peer whose message was rejected
+const CHAT_REJECTED_EVENT: &str = "chat_rejected";
+
+/// Text chat between the peers connected to the streamer cameras with `enable_controller` set.
+///
+/// Peers send `{"event": "chat", "data": {"text": "...", "to": "<peer id>"}}` in a
+/// `UiInteraction` message (`emitUIInteraction` in the Pixel Streaming frontend), `to` being
+/// omitted for the messages to every peer of the stream. The messages are relayed as
+/// `{"event": "chat", "data": {"from": "...", "name": "...", "text": "...", "to": ...}}` in
+/// `Response` messages, and sent in the app as `ChatMessageReceived`.
+#[derive(Clone, Debug)]
+pub struct ChatPlugin {
+    /// Longest message, in characters
+    pub max_length: usize,
+    /// Most messages a peer can send during `rate_window`
+    pub max_messages: usize,
+    pub rate_window: Duration,
+    /// Relays the messages of the peers, otherwise the app relays them with `SendChatMessage`,
+    /// e.g. after moderating them
+    pub relay: bool,
+}
+
+impl Default for ChatPlugin {
+    fn default() -> Self {
+        Self {
+            max_length: 500,
+            max_messages: 5,
+            rate_window: Duration::from_secs(10),
+            relay: true,
+        }
+    }
+}
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Chat {
+            settings: self.clone(),
+            names: HashMap::default(),
+            sent: HashMap::default(),
+        });
+        app.add_event::<ChatMessageReceived>();
+        app.add_event::<SendChatMessage>();
+        app.add_systems(
+            PreUpdate,
+            receive_chat_messages.after(crate::handle_controller_messages),
+        );
+        app.add_systems(PostUpdate, send_chat_messages);
+    }
+}
+
+/// State of the `ChatPlugin`
+#[derive(Resource)]
+pub struct Chat {
+    settings: ChatPlugin,
+    names: HashMap<String, String>,
+    /// When the last messages of each peer were sent, for the rate limiting
+    sent: HashMap<String, VecDeque<Instant>>,
+}
+
+impl Chat {
+    /// Sets the name displayed for the messages of a peer, e.g. once authenticated. The peer
+    /// id is displayed otherwise.
+    pub fn set_name(&mut self, peer_id: impl Into<String>, name: impl Into<String>) {
+        self.names.insert(peer_id.into(), name.into());
+    }
+
+    pub fn name(&self, peer_id: &str) -> Option<&str> {
+        self.names.get(peer_id).map(String::as_str)
+    }
+
+    /// Returns true if the peer can send a message now, and counts it
+    fn allow(&mut self, peer_id: &str, now: Instant) -> bool {
+        let window = self.settings.rate_window;
+        let sent = self.sent.entry(peer_id.to_string()).or_default();
+        while sent
+            .front()
+            .is_some_and(|time| now.duration_since(*time) >= window)
+        {
+            sent.pop_front();
+        }
+
+        if sent.len() >= self.settings.max_messages {
+            return false;
+        }
+        sent.push_back(now);
+        true
+    }
+
+    /// Forgets the peers which sent no message during the last window
+    fn prune(&mut self, now: Instant) {
+        let window = self.settings.rate_window;
+        self.sent.retain(|_, sent| {
+            sent.back()
+                .is_some_and(|time| now.duration_since(*time) < window)
+        });
+    }
+}
+
+/// Sender of a chat message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChatSender {
+    Peer(String),
+    /// The app, e.g. for announcements
+    Server,
+}
+
+/// A chat message received from a peer, after the rate limiting
+#[derive(Event, Clone, Debug)]
+pub struct ChatMessageReceived {
+    /// The streamer camera the peer is connected to
+    pub camera: Entity,
+    pub peer_id: String,
+    /// Name of the peer, see `Chat::set_name`
+    pub name: Option<String>,
+    pub text: String,
+    /// The recipient of a private message, `None` for the messages to every peer
+    pub to: Option<String>,
+}
+
+/// Sends a chat message to the peers of a streamer camera
+#[derive(Event, Clone, Debug)]
+pub struct SendChatMessage {
+    pub camera: Entity,
+    pub from: ChatSender,
+    pub text: String,
+    /// The recipient of a private message, `None` to send it to every peer
+    pub to: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IncomingEnvelope {
+    event: String,
+    data: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct IncomingChatMessage {
+    text: String,
+    #[serde(default)]
+    to: Option<String>,
+}
+
+#[derive(Serialize)]
+struct OutgoingEnvelope<'a, D> {
+    event: &'a str,
+    data: D,
+}
+
+#[derive(Serialize)]
+struct OutgoingChatMessage<'a> {
+    /// Peer id of the sender, `None` for the app
+    from: Option<&'a str>,
+    name: Option<&'a str>,
+    text: &'a str,
+    to: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct ChatRejected<'a> {
+    reason: &'a str,
+}
+
+fn response<D: Serialize>(event: &str, data: D) -> PSOutgoingMessage {
+    PSOutgoingMessage::Response(serde_json::to_string(&OutgoingEnvelope { event, data }).unwrap())
+}
+
+/// This system checks the chat messages received from the peers, sends them in the app and
+/// relays them if enabled
+fn receive_chat_messages(
+    mut chat: ResMut<Chat>,
+    mut interactions: EventReader<StreamerUiInteraction>,
+    mut received: EventWriter<ChatMessageReceived>,
+    mut relayed: EventWriter<SendChatMessage>,
+    controllers: Query<&ControllerState>,
+) {
+    let now = Instant::now();
+    chat.prune(now);
+
+    for interaction in interactions.read() {
+        let Ok(envelope) = serde_json::from_str::<IncomingEnvelope>(&interaction.message) else {
+            continue;
+        };
+        if envelope.event != CHAT_EVENT {
+            continue;
+        }
+        let Ok(controller) = controllers.get(interaction.camera) else {
+            continue;
+        };
+        let reject = |reason: &str| {
+            controller.send_to(
+                &interaction.peer_id,
+                &response(CHAT_REJECTED_EVENT, ChatRejected { reason }),
+            );
+        };
+
+        let message = match serde_json::from_value::<IncomingChatMessage>(envelope.data) {
+            Ok(message) => message,
+            Err(error) => {
+                warn!(
+                    "Invalid chat message from {}: {}",
+                    interaction.peer_id, error
+                );
+                reject("invalid");
+                continue;
+            }
+        };
+        let text = message.text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        if text.chars().count() > chat.settings.max_length {
+            reject("too_long");
+            continue;
+        }
+        if !chat.allow(&interaction.peer_id, now) {
+            debug!("Chat message of {} rate limited", interaction.peer_id);
+            reject("rate_limited");
+            continue;
+        }
+
+        received.write(ChatMessageReceived {
+            camera: interaction.camera,
+            peer_id: interaction.peer_id.clone(),
+            name: chat.name(&interaction.peer_id).map(str::to_string),
+            text: text.to_string(),
+            to: message.to.clone(),
+        });
+        if chat.settings.relay {
+            relayed.write(SendChatMessage {
+                camera: interaction.camera,
+                from: ChatSender::Peer(interaction.peer_id.clone()),
+                text: text.to_string(),
+                to: message.to,
+            });
+        }
+    }
+}
+
+/// This system sends the chat messages to the peers. The private messages are also sent
+/// back to their sender.
+fn send_chat_messages(
+    chat: Res<Chat>,
+    mut messages: EventReader<SendChatMessage>,
+    controllers: Query<&ControllerState>,
+) {
+    for message in messages.read() {
+        let Ok(controller) = controllers.get(message.camera) else {
+            warn!(
+                "Unable to send a chat message to {}, not a streamer camera with a controller",
+                message.camera
+            );
+            continue;
+        };
+
+        let from = match &message.from {
+            ChatSender::Peer(peer_id) => Some(peer_id.as_str()),
+            ChatSender::Server => None,
+        };
+        let outgoing = response(
+            CHAT_EVENT,
+            OutgoingChatMessage {
+                from,
+                name: from.and_then(|peer_id| chat.name(peer_id)),
+                text: &message.text,
+                to: message.to.as_deref(),
+            },
+        );
+
+        match &message.to {
+            None => controller.broadcast(&outgoing),
+            Some(to) => {
+                controller.send_to(to, &outgoing);
+                if let Some(from) = from.filter(|from| from != to) {
+                    controller.send_to(from, &outgoing);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,446 @@
+use anyhow::{Context, Result, anyhow};
+use bevy_color::Color;
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use bevy_render::camera::Camera;
+use bevy_ui::{BackgroundColor, GlobalZIndex, Node, PositionType, UiTargetCamera, Val};
+use gst::prelude::*;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicU64, Ordering},
+};
+
+use crate::{capture::Capture, encoder::Frame};
+
+/// Gray levels of the ramp of the validation pattern, with the limits of the limited range
+const GRAY_RAMP: [u8; 8] = [0, 16, 32, 64, 128, 192, 235, 255];
+/// Red, green and blue patches following the ramp, to catch swapped channels
+const PRIMARIES: [[u8; 4]; 3] = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+/// Height of the strip of patches, in percent of the height of the stream
+const STRIP_HEIGHT: f32 = 10.0;
+/// Size of the patches converted by the round-trip, in pixels
+const ROUND_TRIP_PATCH: u32 = 8;
+
+/// Returns the RGBA colors of the patches of the validation pattern, from left to right
+fn patches() -> Vec<[u8; 4]> {
+    GRAY_RAMP
+        .iter()
+        .map(|gray| [*gray, *gray, *gray, 255])
+        .chain(PRIMARIES)
+        .collect()
+}
+
+/// Checks that the colors of a streamer camera reach its encoder unchanged, to catch sRGB and
+/// limited range issues in CI.
+///
+/// A strip of patches (a gray ramp and the primaries) is displayed with `bevy_ui` on top of
+/// the camera, and sampled in one captured frame every `every` frames, before it is pushed to
+/// the encoder. The UI is rendered after tonemapping, so the patches are captured as is
+/// when the render target, the capture and the `StreamGrading` preserve the colors. See
+/// `ColorValidation::report`.
+#[derive(Component, Clone, Debug)]
+pub struct ColorValidation {
+    /// Checks one frame every `every` captured frames
+    pub every: u64,
+    /// Difference allowed on each channel
+    pub tolerance: u8,
+    /// Also converts the sampled colors to I420 and back with GStreamer, as the encoders do,
+    /// to catch the range issues of the conversion
+    pub round_trip: bool,
+    report: Arc<Mutex<Option<ColorValidationReport>>>,
+}
+
+impl Default for ColorValidation {
+    fn default() -> Self {
+        Self {
+            every: 30,
+            tolerance: 3,
+            round_trip: true,
+            report: Arc::default(),
+        }
+    }
+}
+
+impl ColorValidation {
+    /// Returns the report of the last checked frame, `None` until a frame is checked
+    pub fn report(&self) -> Option<ColorValidationReport> {
+        self.report.lock().unwrap().clone()
+    }
+
+    /// Returns true if the last checked frame had no issue, `None` until a frame is checked
+    pub fn passed(&self) -> Option<bool> {
+        self.report().map(|report| report.issues.is_empty())
+    }
+}
+
+/// Where a color issue was found
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorStage {
+    /// In the captured frame, before the encoder
+    Capture,
+    /// After the conversion to I420 and back, see `ColorValidation::round_trip`
+    RoundTrip,
+}
+
+/// A color issue found by a `ColorValidation`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorIssue {
+    /// Black and white are 16 and 235, full range values were squeezed in the limited range
+    LimitedRange,
+    /// The mid grays are too bright, the sRGB transfer function was applied twice
+    DoubleSrgbEncoding,
+    /// The mid grays are too dark, linear values were streamed as sRGB
+    MissingSrgbEncoding,
+    /// Red and blue are swapped, e.g. BGRA frames handled as RGBA
+    ChannelsSwapped,
+    /// The colors differ in another way, by up to `max_error` on a channel
+    Mismatch { max_error: u8 },
+}
+
+/// Result of the check of a frame by a `ColorValidation`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColorValidationReport {
+    pub frame_id: u64,
+    /// Colors of the patches, see `ColorValidation`
+    pub expected: Vec<[u8; 4]>,
+    /// Colors sampled in the captured frame
+    pub captured: Vec<[u8; 4]>,
+    /// Captured colors after the conversion to I420 and back, if enabled
+    pub round_trip: Option<Vec<[u8; 4]>>,
+    /// Issues found, empty if the colors are preserved
+    pub issues: Vec<(ColorStage, ColorIssue)>,
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Transformations of the colors explaining a mismatch of the gray ramp
+const GRAY_MODELS: [(Option<ColorIssue>, fn(u8) -> f32); 4] = [
+    (None, |gray| gray as f32),
+    (Some(ColorIssue::LimitedRange), |gray| {
+        16.0 + gray as f32 * 219.0 / 255.0
+    }),
+    (Some(ColorIssue::DoubleSrgbEncoding), |gray| {
+        linear_to_srgb(gray as f32 / 255.0) * 255.0
+    }),
+    (Some(ColorIssue::MissingSrgbEncoding), |gray| {
+        srgb_to_linear(gray as f32 / 255.0) * 255.0
+    }),
+];
+
+fn max_error(expected: &[[u8; 4]], actual: &[[u8; 4]]) -> u8 {
+    expected
+        .iter()
+        .zip(actual)
+        .flat_map(|(expected, actual)| {
+            expected[..3]
+                .iter()
+                .zip(&actual[..3])
+                .map(|(expected, actual)| expected.abs_diff(*actual))
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Returns the issues explaining the differences between the patches and the sampled colors
+fn classify(expected: &[[u8; 4]], actual: &[[u8; 4]], tolerance: u8) -> Vec<ColorIssue> {
+    let max_error = max_error(expected, actual);
+    if max_error <= tolerance {
+        return Vec::new();
+    }
+
+    let mut issues = Vec::new();
+    let primaries = &actual[GRAY_RAMP.len()..];
+    let (red, blue) = (primaries[0], primaries[2]);
+    if red[2] > red[0] && blue[0] > blue[2] {
+        issues.push(ColorIssue::ChannelsSwapped);
+    }
+
+    // The model of the gray ramp closest to the sampled grays
+    let grays = actual[..GRAY_RAMP.len()]
+        .iter()
+        .map(|color| color[..3].iter().map(|c| *c as f32).sum::<f32>() / 3.0);
+    let best = GRAY_MODELS
+        .iter()
+        .map(|(issue, model)| {
+            let error = GRAY_RAMP
+                .iter()
+                .zip(grays.clone())
+                .map(|(gray, actual)| (model(*gray) - actual).abs())
+                .fold(0.0, f32::max);
+            (issue, error)
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b));
+    match best {
+        Some((Some(issue), error)) if error <= tolerance as f32 => issues.push(*issue),
+        _ if issues.is_empty() => issues.push(ColorIssue::Mismatch { max_error }),
+        _ => {}
+    }
+
+    issues
+}
+
+/// Converts colors to I420 and back with the `videoconvert` of GStreamer
+struct RoundTrip {
+    pipeline: gst::Pipeline,
+    appsrc: gst_app::AppSrc,
+    appsink: gst_app::AppSink,
+    width: u32,
+}
+
+impl RoundTrip {
+    fn new(patches: usize) -> Result<Self> {
+        gst::init()?;
+
+        let width = patches as u32 * ROUND_TRIP_PATCH;
+        let pipeline = gst::parse::launch(&format!(
+            "appsrc name=src format=time \
+                caps=\"video/x-raw,format=RGBA,width={width},height={ROUND_TRIP_PATCH},\
+                framerate=0/1\" ! \
+            videoconvert ! \
+            video/x-raw,format=I420 ! \
+            videoconvert ! \
+            video/x-raw,format=RGBA ! \
+            appsink name=sink sync=false"
+        ))
+        .context("Unable to create the round-trip pipeline")?
+        .downcast::<gst::Pipeline>()
+        .map_err(|_| anyhow!("Failed to cast to pipeline"))?;
+
+        let appsrc = pipeline
+            .by_name("src")
+            .ok_or_else(|| anyhow!("Could not get appsrc element"))?
+            .downcast::<gst_app::AppSrc>()
+            .map_err(|_| anyhow!("Not an appsrc"))?;
+        let appsink = pipeline
+            .by_name("sink")
+            .ok_or_else(|| anyhow!("Could not get appsink element"))?
+            .downcast::<gst_app::AppSink>()
+            .map_err(|_| anyhow!("Not an appsink"))?;
+        pipeline.set_state(gst::State::Playing)?;
+
+        Ok(Self {
+            pipeline,
+            appsrc,
+            appsink,
+            width,
+        })
+    }
+
+    fn convert(&self, colors: &[[u8; 4]]) -> Result<Vec<[u8; 4]>> {
+        let mut data = Vec::with_capacity((self.width * ROUND_TRIP_PATCH * 4) as usize);
+        for _ in 0..ROUND_TRIP_PATCH {
+            for color in colors {
+                for _ in 0..ROUND_TRIP_PATCH {
+                    data.extend_from_slice(color);
+                }
+            }
+        }
+        self.appsrc
+            .push_buffer(gst::Buffer::from_mut_slice(data))
+            .map_err(|e| anyhow!("Failed to push buffer: {:?}", e))?;
+
+        let sample = self
+            .appsink
+            .try_pull_sample(gst::ClockTime::from_seconds(1))
+            .ok_or_else(|| anyhow!("No sample converted"))?;
+        let buffer = sample
+            .buffer()
+            .ok_or_else(|| anyhow!("The sample has no buffer"))?
+            .map_readable()?;
+        let row = (ROUND_TRIP_PATCH / 2 * self.width * 4) as usize;
+        Ok((0..colors.len())
+            .map(|patch| {
+                let x = patch as u32 * ROUND_TRIP_PATCH + ROUND_TRIP_PATCH / 2;
+                let offset = row + (x * 4) as usize;
+                buffer[offset..offset + 4].try_into().unwrap()
+            })
+            .collect())
+    }
+}
+
+impl Drop for RoundTrip {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
+
+/// Checks the captured frames of a camera, on the capture worker
+struct ColorValidator {
+    every: u64,
+    tolerance: u8,
+    round_trip: Option<Mutex<RoundTrip>>,
+    report: Arc<Mutex<Option<ColorValidationReport>>>,
+    frames: AtomicU64,
+    stream: String,
+}
+
+impl ColorValidator {
+    fn inspect(&self, frame: &Frame) {
+        if self.frames.fetch_add(1, Ordering::Relaxed) % self.every.max(1) != 0 {
+            return;
+        }
+
+        let expected = patches();
+        let y = (frame.height as f32 * STRIP_HEIGHT / 200.0) as usize;
+        let captured: Vec<[u8; 4]> = (0..expected.len())
+            .map(|patch| {
+                let x =
+                    ((patch as f32 + 0.5) * frame.width as f32 / expected.len() as f32) as usize;
+                let offset = y * frame.stride + x * 4;
+                frame.data[offset..offset + 4].try_into().unwrap()
+            })
+            .collect();
+
+        let mut issues: Vec<_> = classify(&expected, &captured, self.tolerance)
+            .into_iter()
+            .map(|issue| (ColorStage::Capture, issue))
+            .collect();
+        let round_trip = self.round_trip.as_ref().and_then(|round_trip| {
+            match round_trip.lock().unwrap().convert(&captured) {
+                Ok(converted) => Some(converted),
+                Err(e) => {
+                    warn!(stream = %self.stream, "Color round-trip failed: {:?}", e);
+                    None
+                }
+            }
+        });
+        if let Some(converted) = &round_trip {
+            // Only the issues added by the conversion
+            issues.extend(
+                classify(&captured, converted, self.tolerance)
+                    .into_iter()
+                    .map(|issue| (ColorStage::RoundTrip, issue)),
+            );
+        }
+
+        let mut report = self.report.lock().unwrap();
+        let previous = report.as_ref().map(|report| report.issues.clone());
+        if previous.as_ref() != Some(&issues) {
+            if issues.is_empty() {
+                info!(stream = %self.stream, "Color validation passed");
+            } else {
+                warn!(stream = %self.stream, "Color validation failed: {:?}", issues);
+            }
+        }
+        *report = Some(ColorValidationReport {
+            frame_id: frame.id,
+            expected,
+            captured,
+            round_trip,
+            issues,
+        });
+    }
+}
+
+/// Validation pattern displayed on a camera
+#[derive(Component)]
+pub(crate) struct RunningColorValidation {
+    overlay: Entity,
+}
+
+type ValidationItem<'a> = (
+    Entity,
+    &'a Camera,
+    Option<&'a ColorValidation>,
+    Option<&'a RunningColorValidation>,
+    Option<&'a crate::StreamLabels>,
+);
+
+/// This system displays the validation pattern on the cameras with a `ColorValidation` and
+/// inspects their frames
+pub(crate) fn apply_color_validations(
+    mut commands: Commands,
+    cameras: Query<ValidationItem>,
+    captures: Query<&Capture>,
+) {
+    for (entity, camera, validation, running, labels) in cameras.iter() {
+        if validation.is_some() == running.is_some() {
+            continue;
+        }
+        let Some(capture) = camera
+            .target
+            .as_image()
+            .and_then(|image| captures.iter().find(|c| c.src_image() == image))
+        else {
+            continue;
+        };
+
+        let Some(validation) = validation else {
+            capture.set_inspector(None);
+            if let Some(running) = running {
+                commands.entity(running.overlay).despawn();
+            }
+            commands.entity(entity).remove::<RunningColorValidation>();
+            continue;
+        };
+
+        let stream = labels.map(|labels| labels.name.clone()).unwrap_or_default();
+        let patches = patches();
+        let round_trip = if validation.round_trip {
+            match RoundTrip::new(patches.len()) {
+                Ok(round_trip) => Some(Mutex::new(round_trip)),
+                Err(e) => {
+                    warn!(%stream, "Color round-trip disabled: {:?}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let validator = Arc::new(ColorValidator {
+            every: validation.every,
+            tolerance: validation.tolerance,
+            round_trip,
+            report: validation.report.clone(),
+            frames: AtomicU64::new(0),
+            stream,
+        });
+        capture.set_inspector(Some(Arc::new(move |frame: &Frame| {
+            validator.inspect(frame)
+        })));
+
+        let overlay = commands
+            .spawn((
+                Node {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(0.0),
+                    left: Val::Px(0.0),
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(STRIP_HEIGHT),
+                    ..Default::default()
+                },
+                UiTargetCamera(entity),
+                GlobalZIndex(i32::MAX),
+            ))
+            .with_children(|strip| {
+                for [r, g, b, a] in patches.iter().copied() {
+                    strip.spawn((
+                        Node {
+                            width: Val::Percent(100.0 / patches.len() as f32),
+                            height: Val::Percent(100.0),
+                            ..Default::default()
+                        },
+                        BackgroundColor(Color::srgba_u8(r, g, b, a)),
+                    ));
+                }
+            })
+            .id();
+        commands
+            .entity(entity)
+            .insert(RunningColorValidation { overlay });
+    }
+}
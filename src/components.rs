@@ -0,0 +1,369 @@
+use bevy_asset::Handle;
+use bevy_ecs::{component::HookContext, prelude::*, world::DeferredWorld};
+use bevy_image::Image;
+use bevy_log::prelude::*;
+use bevy_platform::collections::{HashMap, HashSet};
+use crossbeam_channel::Receiver;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    encoder::{EncoderHandle, StreamEncoder},
+    gst_webrtc_encoder::GstWebRtcEncoder,
+};
+
+/// Name and labels of the stream of a streamer camera.
+///
+/// The name is used in pipeline element names, as the `stream` field of the logs and in
+/// stats, so that streams can be told apart.
+#[derive(Component, Clone, Debug, Default)]
+pub struct StreamLabels {
+    pub name: String,
+    pub labels: Vec<(String, String)>,
+}
+
+/// A streamer without any camera, created by `StreamerHelper::new_audio_only_streamer`.
+///
+/// The session lives as long as this component.
+#[derive(Component, Clone)]
+pub struct AudioOnlyStreamer {
+    pub encoder: EncoderHandle,
+}
+
+/// A streamer without any camera, created by `StreamerHelper::new_pre_encoded_streamer`.
+///
+/// The application pushes its encoded frames to `encoder`. The session lives as long as this
+/// component, its pipeline is stopped when it is removed.
+#[derive(Component, Clone)]
+#[component(on_remove = stop_pre_encoded_streamer)]
+pub struct PreEncodedStreamer {
+    pub encoder: Arc<GstWebRtcEncoder>,
+}
+
+fn stop_pre_encoded_streamer(world: DeferredWorld, context: HookContext) {
+    let Some(streamer) = world.get::<PreEncodedStreamer>(context.entity) else {
+        return;
+    };
+    if let Err(e) = streamer.encoder.stop() {
+        warn!("Unable to stop the pre-encoded streamer: {:?}", e);
+    }
+}
+
+/// A streamer camera whose pipeline is being created in the background.
+///
+/// Removed once `StreamerCameraReady` is sent.
+#[derive(Component)]
+pub struct PendingStreamer {
+    pub(crate) receiver: Receiver<Result<(), String>>,
+}
+
+/// Number of peers currently watching a streamer camera.
+///
+/// With LiveKit, the peers are the sessions of the signaller with the SFU rather than the room
+/// subscribers, so LiveKit cameras have no `StandbyPolicy`.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ViewerCount(pub u32);
+
+/// Connection details of a peer, sent by the signalling server when it connects
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PeerInfo {
+    /// The peer asked for a data channel, e.g. to send inputs
+    pub data_channel: bool,
+    /// The peer is a SFU, forwarding the stream to other peers
+    pub sfu: bool,
+    /// User agent of the player, from the `userAgent` field
+    pub user_agent: Option<String>,
+    /// Region of the player, e.g. `eu-west`, from the `region` field
+    pub region: Option<String>,
+    /// Country of the player, e.g. `FR`, from the `country` field
+    pub country: Option<String>,
+    /// Any other field sent by the signalling server (non-string values are JSON encoded),
+    /// except the credentials, see `SessionAuthorizer`
+    pub metadata: HashMap<String, String>,
+}
+
+impl PeerInfo {
+    /// Guesses the kind of device of the player from its user agent, e.g. to adapt the
+    /// default quality
+    pub fn client_kind(&self) -> ClientKind {
+        let Some(user_agent) = &self.user_agent else {
+            return ClientKind::Unknown;
+        };
+        let has = |tokens: &[&str]| tokens.iter().any(|token| user_agent.contains(token));
+
+        if has(&[
+            "SmartTV", "SMART-TV", "Tizen", "Web0S", "AppleTV", "CrKey", "BRAVIA",
+        ]) {
+            ClientKind::Tv
+        } else if has(&["iPad", "Tablet"]) || (has(&["Android"]) && !has(&["Mobile"])) {
+            ClientKind::Tablet
+        } else if has(&["Mobile", "iPhone", "Android"]) {
+            ClientKind::Mobile
+        } else if has(&["Windows", "Macintosh", "X11", "Linux", "CrOS"]) {
+            ClientKind::Desktop
+        } else {
+            ClientKind::Unknown
+        }
+    }
+}
+
+/// Kind of device of a player, see `PeerInfo::client_kind`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClientKind {
+    Desktop,
+    Mobile,
+    Tablet,
+    Tv,
+    Unknown,
+}
+
+/// Connection details of the peers of a streamer camera, by peer id.
+///
+/// Only filled with Pixel Streaming, from the `playerConnected` messages, so that apps can
+/// tell SFUs, bots and real players apart, or pick a region and localize the UI when the
+/// signalling server forwards the client info.
+#[derive(Component, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PeerMetadata(pub HashMap<String, PeerInfo>);
+
+/// Round-trip time of each peer of a streamer camera, by session id (the peer id with
+/// Pixel Streaming), measured from the RTCP reports and updated every second.
+///
+/// Peers are missing until their first report is received.
+#[derive(Component, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PeerLatency(pub HashMap<String, Duration>);
+
+/// GPU time of the last measured copy of the frames of a streamer camera to the readback
+/// buffers, the capture cost of the camera.
+///
+/// Inserted once a copy is measured, which needs a device supporting timestamp queries in
+/// command encoders. Also reported in the `streaming/<stream>/capture_gpu_time` diagnostic
+/// (milliseconds).
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CaptureGpuTime(pub Option<Duration>);
+
+/// Puts a streamer camera in standby when nobody watches it.
+///
+/// `after` the last viewer left, frames are no longer captured and the encoders are put in
+/// standby, see `StreamEncoder::set_standby`, only the signalling connection stays alive.
+/// Capture restarts when a viewer connects.
+/// Sends `StreamerStandby` and `StreamerResumed` on transitions.
+#[derive(Component, Clone, Debug, Default)]
+pub struct StandbyPolicy {
+    /// Delay before going in standby, `None` disables standby
+    pub after: Option<Duration>,
+    pub(crate) idle_since: Option<Instant>,
+    pub(crate) standby: bool,
+}
+
+impl StandbyPolicy {
+    pub fn new(after: Option<Duration>) -> Self {
+        Self {
+            after,
+            ..Default::default()
+        }
+    }
+
+    /// Returns true if the camera is in standby
+    pub fn is_standby(&self) -> bool {
+        self.standby
+    }
+}
+
+/// Limits the sessions of the peers of a streamer camera, insert it on the camera.
+///
+/// A peer is warned `warning_before` it is disconnected, with a
+/// `{"event": "session_warning", "data": {"reason": "idle", "remaining_secs": 30}}` Pixel
+/// Streaming `Response` message, and `PeerSessionExpired` is sent once it is disconnected.
+#[derive(Component, Clone, Debug)]
+pub struct SessionPolicy {
+    /// Longest session of a peer
+    pub max_duration: Option<Duration>,
+    /// Disconnects the peers which sent no input for this long. Only applies to the cameras
+    /// receiving the input of the peers, see `enable_controller`.
+    pub idle_timeout: Option<Duration>,
+    /// Disconnects the peers which sent no RTCP packet for this long, e.g. the viewers which
+    /// stopped receiving the stream without leaving. Also applies to the view-only cameras.
+    pub rtcp_timeout: Option<Duration>,
+    pub warning_before: Duration,
+    pub(crate) warned: HashSet<String>,
+    pub(crate) expired: HashSet<String>,
+}
+
+impl Default for SessionPolicy {
+    fn default() -> Self {
+        Self {
+            max_duration: None,
+            idle_timeout: None,
+            rtcp_timeout: None,
+            warning_before: Duration::from_secs(30),
+            warned: HashSet::new(),
+            expired: HashSet::new(),
+        }
+    }
+}
+
+impl SessionPolicy {
+    pub fn new(max_duration: Option<Duration>, idle_timeout: Option<Duration>) -> Self {
+        Self {
+            max_duration,
+            idle_timeout,
+            ..Default::default()
+        }
+    }
+}
+
+/// What a streamer camera does with its frames when all its readback buffers are busy, e.g.
+/// because the encoder is slower than the rendering
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FrameSkipPolicy {
+    /// The new frames are skipped until a buffer is released, the frames already captured
+    /// are delivered
+    #[default]
+    SkipNewest,
+    /// The frames waiting to be pushed are dropped as soon as a newer one is captured, so that
+    /// the newest frame is always delivered, which lowers the latency
+    DropOldest,
+}
+
+/// How a streamer camera applies the resolutions requested by its peers, see
+/// `StreamerResolutionRequest`, so that operators stay in control of the GPU cost.
+///
+/// The requests are ignored by the cameras without this component.
+#[derive(Component, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ResolutionPolicy {
+    #[default]
+    Ignore,
+    /// The stream is resized to the largest of these resolutions fitting in the requested
+    /// one, or to the smallest of them if none fits
+    Clamp(Vec<(u32, u32)>),
+    /// The stream is resized to the requested resolution, the requests larger than this
+    /// maximum are rejected
+    Honor { max_width: u32, max_height: u32 },
+}
+
+impl ResolutionPolicy {
+    /// Returns the resolution applied for a request of `width` x `height`, `None` if the
+    /// request is ignored
+    pub fn resolve(&self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let pixels = |(width, height): &(u32, u32)| *width as u64 * *height as u64;
+        match self {
+            ResolutionPolicy::Ignore => None,
+            ResolutionPolicy::Clamp(resolutions) => resolutions
+                .iter()
+                .filter(|(w, h)| *w <= width && *h <= height)
+                .max_by_key(|resolution| pixels(resolution))
+                .or_else(|| {
+                    resolutions
+                        .iter()
+                        .min_by_key(|resolution| pixels(resolution))
+                })
+                .copied(),
+            ResolutionPolicy::Honor {
+                max_width,
+                max_height,
+            } => (width <= *max_width && height <= *max_height).then_some((width, height)),
+        }
+    }
+}
+
+/// How a streamer camera is resized to a resolution with another aspect ratio than the one it
+/// was created with, by a `ResolutionPolicy` or the control API, e.g. for the viewers of a
+/// portrait phone.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AspectPolicy {
+    /// The render target takes the resolution, the camera renders more or less of the scene
+    #[default]
+    Stretch,
+    /// The render target keeps the aspect ratio of the camera, it is fitted in the resolution
+    /// and centered on black bars on the GPU (letterbox or pillarbox)
+    Letterbox,
+}
+
+impl AspectPolicy {
+    /// Returns the size of the render target of a camera with the aspect ratio `aspect`,
+    /// streamed in `width` x `height`
+    pub fn render_size(&self, aspect: (u32, u32), width: u32, height: u32) -> (u32, u32) {
+        if *self == AspectPolicy::Stretch {
+            return (width, height);
+        }
+
+        // Rounded to the nearest pixel, within the resolution
+        let scale = |length: u32, numerator: u32, denominator: u32| {
+            let (numerator, denominator) = (numerator.max(1) as u64, denominator.max(1) as u64);
+            let scaled = (length as u64 * numerator + denominator / 2) / denominator;
+            scaled.max(1) as u32
+        };
+        let fitted_height = scale(width, aspect.1, aspect.0);
+        if fitted_height <= height {
+            (width, fitted_height)
+        } else {
+            (scale(height, aspect.0, aspect.1).min(width), height)
+        }
+    }
+}
+
+/// Pushes the last captured frame again every `interval` while no frame is captured, e.g.
+/// when the app is paused and doesn't render, in standby or when the capture is stopped, so
+/// that viewers see a frozen image rather than a dead connection.
+#[derive(Component, Clone, Debug)]
+pub struct HoldLastFrame {
+    pub interval: Duration,
+}
+
+impl Default for HoldLastFrame {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Content of a `PlaceholderFrame`
+#[derive(Clone, Debug)]
+pub enum PlaceholderSource {
+    /// RGBA color
+    Color([u8; 4]),
+    /// RGBA image, scaled to the size of the stream, e.g. a "Be right back" banner
+    Image(Handle<Image>),
+}
+
+/// Streams a placeholder every `interval` instead of the camera while it is inactive
+/// (`Camera::is_active` is false, e.g. when its scene is unavailable) or its capture is
+/// stopped, so that recordings and SFU tracks stay continuous.
+///
+/// Its interval takes precedence over the one of `HoldLastFrame`.
+#[derive(Component, Clone, Debug)]
+pub struct PlaceholderFrame {
+    pub source: PlaceholderSource,
+    pub interval: Duration,
+}
+
+impl Default for PlaceholderFrame {
+    fn default() -> Self {
+        Self {
+            source: PlaceholderSource::Color([0, 0, 0, 255]),
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Effective connection details of a streamer camera, to display a joinable link to viewers.
+///
+/// Updated once the signalling completes, `ready` is set when the details are final.
+#[derive(Component, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// URL of the signalling server
+    pub signalling_url: String,
+    /// Streamer id committed or renamed by the signalling server, which the viewers must
+    /// subscribe to, the peer id for GstWebRtc or the feed id for Janus
+    pub streamer_id: Option<String>,
+    /// LiveKit or Janus VideoRoom room
+    pub room: Option<String>,
+    /// LiveKit participant identity
+    pub participant: Option<String>,
+    /// WHEP endpoint the stream can be played from, for backends serving one
+    pub whep_url: Option<String>,
+    pub ready: bool,
+}
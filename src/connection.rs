@@ -0,0 +1,98 @@
+use bevy_ecs::prelude::*;
+use gst::prelude::*;
+use gstrswebrtc::{signaller::Signallable, webrtcsink::BaseWebRTCSink};
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "livekit")]
+use crate::livekit::LiveKitSettings;
+use crate::{ConnectionInfo, GstWebRtcSettings, SignallingServer};
+
+/// Collects the connection details of a streamer camera, updated from the signaller
+#[derive(Component, Clone, Default)]
+pub(crate) struct ConnectionInfoSource {
+    info: Arc<Mutex<ConnectionInfo>>,
+}
+
+impl ConnectionInfoSource {
+    pub(crate) fn from_settings(settings: &GstWebRtcSettings) -> Self {
+        let info = match &settings.signalling_server {
+            // The peer id is not confirmed by the server, the configured one is used
+            SignallingServer::GstWebRtc { uri, peer_id, .. } => ConnectionInfo {
+                signalling_url: uri.clone(),
+                streamer_id: peer_id.clone(),
+                ready: true,
+                ..Default::default()
+            },
+            // The streamer id is committed once the streamer is registered
+            #[cfg(feature = "pixelstreaming")]
+            SignallingServer::PixelStreaming { uri, .. } => ConnectionInfo {
+                signalling_url: uri.clone(),
+                ..Default::default()
+            },
+            #[cfg(feature = "janus")]
+            SignallingServer::Janus {
+                uri,
+                room_id,
+                feed_id,
+                ..
+            } => ConnectionInfo {
+                signalling_url: uri.clone(),
+                room: Some(room_id.to_string()),
+                streamer_id: feed_id.map(|feed_id| feed_id.to_string()),
+                ready: true,
+                ..Default::default()
+            },
+            #[cfg(feature = "whip")]
+            SignallingServer::Whip { endpoint, .. } => ConnectionInfo {
+                signalling_url: endpoint.clone(),
+                ready: true,
+                ..Default::default()
+            },
+        };
+
+        Self {
+            info: Arc::new(Mutex::new(info)),
+        }
+    }
+
+    #[cfg(feature = "livekit")]
+    pub(crate) fn from_livekit_settings(settings: &LiveKitSettings) -> Self {
+        Self {
+            info: Arc::new(Mutex::new(ConnectionInfo {
+                signalling_url: settings.url.clone(),
+                room: Some(settings.room_name.clone()),
+                participant: Some(settings.participant_identity.clone()),
+                ready: true,
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Updates the streamer id when the signaller of `webrtcsink` commits it
+    pub(crate) fn connect(&self, webrtcsink: &BaseWebRTCSink) {
+        let signaller = webrtcsink.property::<Signallable>("signaller");
+        if signaller.find_property("streamer-id").is_none() {
+            return;
+        }
+
+        signaller.connect_notify(Some("streamer-id"), {
+            let info = self.info.clone();
+            move |signaller, _| {
+                let streamer_id = signaller.property::<Option<String>>("streamer-id");
+                let mut info = info.lock().unwrap();
+                info.ready = streamer_id.is_some();
+                info.streamer_id = streamer_id;
+            }
+        });
+    }
+}
+
+/// This system copies the connection details collected from the signallers to `ConnectionInfo`
+pub(crate) fn update_connection_infos(
+    mut connections: Query<(&ConnectionInfoSource, &mut ConnectionInfo)>,
+) {
+    for (source, mut info) in connections.iter_mut() {
+        let current = source.info.lock().unwrap().clone();
+        info.set_if_neq(current);
+    }
+}
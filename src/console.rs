@@ -0,0 +1,238 @@
+use anyhow::Result;
+use bevy_app::prelude::*;
+use bevy_ecs::{event::EventCursor, prelude::*};
+use bevy_log::prelude::*;
+use bevy_platform::collections::{HashMap, HashSet};
+use serde::Serialize;
+
+use crate::{ControllerState, StreamerCommand, pixelstreaming::message::PSOutgoingMessage};
+
+type CommandHandler = Box<dyn Fn(&mut World, &[String]) -> Result<String> + Send + Sync>;
+
+/// Peers allowed to run a remote command
+#[derive(Clone, Debug, Default)]
+pub enum PeerAllowList {
+    /// No peer is allowed until added with `RemoteConsole::allow_peer`
+    #[default]
+    None,
+    /// Only the listed peers are allowed
+    Peers(HashSet<String>),
+    /// Any connected peer is allowed
+    Any,
+}
+
+impl PeerAllowList {
+    fn allows(&self, peer_id: &str) -> bool {
+        match self {
+            PeerAllowList::None => false,
+            PeerAllowList::Peers(peers) => peers.contains(peer_id),
+            PeerAllowList::Any => true,
+        }
+    }
+}
+
+struct RemoteCommand {
+    allowed_peers: PeerAllowList,
+    handler: CommandHandler,
+}
+
+/// Registry of the commands peers can run through Pixel Streaming `Command` messages.
+///
+/// A command is either sent as `{"ConsoleCommand": "name arg1 arg2"}` (as the Pixel Streaming
+/// frontend does) or as a plain `name arg1 arg2` string. The result is sent back to the
+/// peer in a `Response` message.
+#[derive(Resource, Default)]
+pub struct RemoteConsole {
+    commands: HashMap<String, RemoteCommand>,
+}
+
+impl RemoteConsole {
+    /// Registers a command, replacing any existing command with the same name
+    pub fn register<F>(&mut self, name: impl Into<String>, allowed_peers: PeerAllowList, handler: F)
+    where
+        F: Fn(&mut World, &[String]) -> Result<String> + Send + Sync + 'static,
+    {
+        self.commands.insert(
+            name.into(),
+            RemoteCommand {
+                allowed_peers,
+                handler: Box::new(handler),
+            },
+        );
+    }
+
+    /// Allows a peer to run the given command
+    pub fn allow_peer(&mut self, name: &str, peer_id: impl Into<String>) {
+        let Some(command) = self.commands.get_mut(name) else {
+            warn!("Unknown remote command {}", name);
+            return;
+        };
+        match &mut command.allowed_peers {
+            PeerAllowList::None => {
+                command.allowed_peers = PeerAllowList::Peers(HashSet::from_iter([peer_id.into()]))
+            }
+            PeerAllowList::Peers(peers) => {
+                peers.insert(peer_id.into());
+            }
+            PeerAllowList::Any => {}
+        }
+    }
+
+    /// Revokes the permission of a peer to run the given command
+    pub fn deny_peer(&mut self, name: &str, peer_id: &str) {
+        if let Some(RemoteCommand {
+            allowed_peers: PeerAllowList::Peers(peers),
+            ..
+        }) = self.commands.get_mut(name)
+        {
+            peers.remove(peer_id);
+        }
+    }
+}
+
+pub trait RemoteConsoleAppExt {
+    /// Registers a command in the `RemoteConsole`
+    fn register_remote_command<F>(
+        &mut self,
+        name: impl Into<String>,
+        allowed_peers: PeerAllowList,
+        handler: F,
+    ) -> &mut Self
+    where
+        F: Fn(&mut World, &[String]) -> Result<String> + Send + Sync + 'static;
+}
+
+impl RemoteConsoleAppExt for App {
+    fn register_remote_command<F>(
+        &mut self,
+        name: impl Into<String>,
+        allowed_peers: PeerAllowList,
+        handler: F,
+    ) -> &mut Self
+    where
+        F: Fn(&mut World, &[String]) -> Result<String> + Send + Sync + 'static,
+    {
+        self.world_mut()
+            .get_resource_or_init::<RemoteConsole>()
+            .register(name, allowed_peers, handler);
+        self
+    }
+}
+
+#[derive(Serialize)]
+struct CommandResponse<'a> {
+    command: &'a str,
+    ok: bool,
+    result: String,
+}
+
+/// Extracts the command line from a Pixel Streaming `Command` payload, decoded from UTF-16
+fn parse_command_line(command: &str) -> String {
+    match serde_json::from_str::<serde_json::Value>(command) {
+        Ok(serde_json::Value::Object(object)) => object
+            .get("ConsoleCommand")
+            .and_then(|value| value.as_str())
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+        Ok(serde_json::Value::String(command)) => command.trim().to_string(),
+        _ => command.trim().to_string(),
+    }
+}
+
+/// This system runs the commands received from peers and sends back the results
+pub(crate) fn dispatch_remote_commands(
+    world: &mut World,
+    mut cursor: Local<EventCursor<StreamerCommand>>,
+) {
+    let events = world.resource::<Events<StreamerCommand>>();
+    let received = cursor.read(events).cloned().collect::<Vec<_>>();
+    if received.is_empty() {
+        return;
+    }
+
+    world.resource_scope(|world, console: Mut<RemoteConsole>| {
+        for event in received {
+            let command_line = parse_command_line(&event.command);
+            let mut parts = command_line.split_whitespace().map(str::to_string);
+            let Some(name) = parts.next() else {
+                continue;
+            };
+            let args = parts.collect::<Vec<_>>();
+
+            let (ok, result) = match console.commands.get(&name) {
+                None => (false, format!("Unknown command {}", name)),
+                Some(command) if !command.allowed_peers.allows(&event.peer_id) => {
+                    warn!(
+                        "Peer {} is not allowed to run command {}",
+                        event.peer_id, name
+                    );
+                    (false, format!("Not allowed to run {}", name))
+                }
+                Some(command) => match (command.handler)(world, &args) {
+                    Ok(result) => (true, result),
+                    Err(error) => (false, error.to_string()),
+                },
+            };
+
+            let response = CommandResponse {
+                command: &name,
+                ok,
+                result,
+            };
+            let Some(controller) = world.get::<ControllerState>(event.camera) else {
+                continue;
+            };
+            controller.send_to(
+                &event.peer_id,
+                &PSOutgoingMessage::Response(serde_json::to_string(&response).unwrap()),
+            );
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixelstreaming::message::PSMessage;
+
+    /// Encodes a `Command` message as the Pixel Streaming frontend does: the message id, the
+    /// length of the string in UTF-16 code units, then the UTF-16LE code units
+    fn frontend_command(command: &str) -> Vec<u8> {
+        let units = command.encode_utf16().collect::<Vec<_>>();
+        let mut data = vec![51];
+        data.extend_from_slice(&(units.len() as u16).to_le_bytes());
+        for unit in units {
+            data.extend_from_slice(&unit.to_le_bytes());
+        }
+        data
+    }
+
+    fn command_line(data: &[u8]) -> String {
+        match PSMessage::try_from(data).unwrap() {
+            PSMessage::Command(command) => parse_command_line(&command.command),
+            message => panic!("Unexpected message {:?}", message),
+        }
+    }
+
+    #[test]
+    fn parses_frontend_console_commands() {
+        let data = frontend_command(r#"{"ConsoleCommand":"stat fps"}"#);
+        assert_eq!(command_line(&data), "stat fps");
+
+        let data = frontend_command(r#"{"ConsoleCommand":"say été 🎮"}"#);
+        assert_eq!(command_line(&data), "say été 🎮");
+    }
+
+    #[test]
+    fn parses_plain_commands() {
+        assert_eq!(command_line(&frontend_command("stat fps ")), "stat fps");
+        assert_eq!(command_line(&frontend_command(r#""stat fps""#)), "stat fps");
+    }
+
+    #[test]
+    fn ignores_other_commands() {
+        let data = frontend_command(r#"{"Resolution.Width":1280,"Resolution.Height":720}"#);
+        assert_eq!(command_line(&data), "");
+    }
+}
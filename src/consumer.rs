@@ -0,0 +1,85 @@
+use gst::prelude::*;
+use gst_webrtc::WebRTCDataChannel;
+use gstrswebrtc::webrtcsink::BaseWebRTCSink;
+use std::sync::Arc;
+
+/// A peer consuming a stream, handed to the callbacks of a `ConsumerHook`
+#[derive(Clone, Copy, Debug)]
+pub struct ConsumerContext<'a> {
+    /// Name of the stream, see `StreamLabels`
+    pub stream: &'a str,
+    pub peer_id: &'a str,
+    /// `webrtcbin` of the session with the peer
+    pub webrtcbin: &'a gst::Element,
+}
+
+type AddedFn = dyn Fn(&ConsumerContext) + Send + Sync;
+type DataChannelFn = dyn Fn(&ConsumerContext, &WebRTCDataChannel) + Send + Sync;
+
+/// Hands the `webrtcbin` of each peer to user callbacks, to add transceivers, stats callbacks
+/// or custom data channels beyond what this crate wraps.
+///
+/// The callbacks are called from GStreamer threads and must not block. The session is
+/// negotiated once the `added` callback returns, so that transceivers and data channels created
+/// there are part of the offer.
+#[derive(Clone)]
+pub struct ConsumerHook {
+    added: Arc<AddedFn>,
+    data_channel: Option<Arc<DataChannelFn>>,
+}
+
+impl ConsumerHook {
+    /// Calls `added` with every new peer
+    pub fn new(added: impl Fn(&ConsumerContext) + Send + Sync + 'static) -> Self {
+        Self {
+            added: Arc::new(added),
+            data_channel: None,
+        }
+    }
+
+    /// Also calls `data_channel` with every data channel opened by a peer
+    pub fn with_data_channel(
+        mut self,
+        data_channel: impl Fn(&ConsumerContext, &WebRTCDataChannel) + Send + Sync + 'static,
+    ) -> Self {
+        self.data_channel = Some(Arc::new(data_channel));
+        self
+    }
+
+    /// Calls the callbacks with the consumers of `webrtcsink`
+    pub(crate) fn connect(self, stream: String, webrtcsink: &BaseWebRTCSink) {
+        webrtcsink.connect_closure(
+            "consumer-added",
+            false,
+            glib::closure!(move |_sink: &BaseWebRTCSink,
+                                 peer_id: &str,
+                                 webrtcbin: &gst::Element| {
+                let context = ConsumerContext {
+                    stream: &stream,
+                    peer_id,
+                    webrtcbin,
+                };
+                (self.added)(&context);
+
+                if let Some(data_channel) = self.data_channel.clone() {
+                    let stream = stream.clone();
+                    let peer_id = peer_id.to_string();
+                    webrtcbin.connect_closure(
+                        "on-data-channel",
+                        false,
+                        glib::closure!(
+                            move |webrtcbin: &gst::Element, channel: &WebRTCDataChannel| {
+                                let context = ConsumerContext {
+                                    stream: &stream,
+                                    peer_id: &peer_id,
+                                    webrtcbin,
+                                };
+                                data_channel(&context, channel);
+                            }
+                        ),
+                    );
+                }
+            }),
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use bevy_log::prelude::*;
+use std::{net::SocketAddr, pin::Pin, time::Duration};
+use tokio_stream::{Stream, wrappers::ReceiverStream};
+use tonic::{Request, Response, Status, metadata::MetadataValue, transport::Server};
+
+use super::{
+    ControlClient, ControlError, ControlRequest, ControlResponse, ControlResult, StatsInfo,
+    StreamInfo,
+};
+
+mod proto {
+    tonic::include_proto!("bevy_streaming.control.v1");
+}
+
+use proto::{
+    Empty, ListStreamsRequest, ListStreamsResponse, ScreenshotResponse, SetBitrateRequest,
+    SetResolutionRequest, StreamRequest, WatchStatsRequest,
+    stream_control_server::{StreamControl, StreamControlServer},
+};
+
+impl From<StatsInfo> for proto::Stats {
+    fn from(stats: StatsInfo) -> Self {
+        Self {
+            frames_pushed: stats.frames_pushed,
+            width: stats.width,
+            height: stats.height,
+            bitrate: stats.bitrate,
+            bytes_sent: stats.bytes_sent,
+            peer_bytes_sent: stats.peer_bytes_sent.into_iter().collect(),
+            latency_ms: stats.latency_ms,
+        }
+    }
+}
+
+impl From<StreamInfo> for proto::Stream {
+    fn from(stream: StreamInfo) -> Self {
+        Self {
+            name: stream.name,
+            labels: stream.labels.into_iter().collect(),
+            running: stream.running,
+            standby: stream.standby,
+            viewers: stream.viewers,
+            stats: stream.stats.map(proto::Stats::from),
+        }
+    }
+}
+
+impl From<ControlError> for Status {
+    fn from(e: ControlError) -> Self {
+        match e {
+            ControlError::NotFound(_) => Status::not_found(e.to_string()),
+            ControlError::Failed(_) => Status::internal(e.to_string()),
+        }
+    }
+}
+
+fn unexpected(response: ControlResponse) -> Status {
+    Status::internal(format!("Unexpected response {:?}", response))
+}
+
+fn done(result: ControlResult) -> Result<Response<Empty>, Status> {
+    match result? {
+        ControlResponse::Done => Ok(Response::new(Empty {})),
+        response => Err(unexpected(response)),
+    }
+}
+
+struct GrpcControl {
+    client: ControlClient,
+}
+
+impl GrpcControl {
+    async fn streams(&self) -> Result<ListStreamsResponse, Status> {
+        match self.client.request(ControlRequest::ListStreams).await? {
+            ControlResponse::Streams(streams) => Ok(ListStreamsResponse {
+                streams: streams.into_iter().map(proto::Stream::from).collect(),
+            }),
+            response => Err(unexpected(response)),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl StreamControl for GrpcControl {
+    type WatchStatsStream =
+        Pin<Box<dyn Stream<Item = Result<ListStreamsResponse, Status>> + Send + 'static>>;
+
+    async fn list_streams(
+        &self,
+        _request: Request<ListStreamsRequest>,
+    ) -> Result<Response<ListStreamsResponse>, Status> {
+        Ok(Response::new(self.streams().await?))
+    }
+
+    async fn get_stream(
+        &self,
+        request: Request<StreamRequest>,
+    ) -> Result<Response<proto::Stream>, Status> {
+        let name = request.into_inner().name;
+        match self
+            .client
+            .request(ControlRequest::GetStream { name })
+            .await?
+        {
+            ControlResponse::Stream(stream) => Ok(Response::new(stream.into())),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    async fn start_stream(
+        &self,
+        request: Request<StreamRequest>,
+    ) -> Result<Response<Empty>, Status> {
+        let name = request.into_inner().name;
+        done(self.client.request(ControlRequest::Start { name }).await)
+    }
+
+    async fn stop_stream(
+        &self,
+        request: Request<StreamRequest>,
+    ) -> Result<Response<Empty>, Status> {
+        let name = request.into_inner().name;
+        done(self.client.request(ControlRequest::Stop { name }).await)
+    }
+
+    async fn set_bitrate(
+        &self,
+        request: Request<SetBitrateRequest>,
+    ) -> Result<Response<Empty>, Status> {
+        let SetBitrateRequest { name, bitrate } = request.into_inner();
+        done(
+            self.client
+                .request(ControlRequest::SetBitrate { name, bitrate })
+                .await,
+        )
+    }
+
+    async fn set_resolution(
+        &self,
+        request: Request<SetResolutionRequest>,
+    ) -> Result<Response<Empty>, Status> {
+        let SetResolutionRequest {
+            name,
+            width,
+            height,
+        } = request.into_inner();
+        done(
+            self.client
+                .request(ControlRequest::Resize {
+                    name,
+                    width,
+                    height,
+                })
+                .await,
+        )
+    }
+
+    async fn screenshot(
+        &self,
+        request: Request<StreamRequest>,
+    ) -> Result<Response<ScreenshotResponse>, Status> {
+        let name = request.into_inner().name;
+        match self
+            .client
+            .request(ControlRequest::Screenshot { name })
+            .await?
+        {
+            ControlResponse::Screenshot(png) => Ok(Response::new(ScreenshotResponse { png })),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    async fn watch_stats(
+        &self,
+        request: Request<WatchStatsRequest>,
+    ) -> Result<Response<Self::WatchStatsStream>, Status> {
+        let interval = match request.into_inner().interval_ms {
+            0 => Duration::from_secs(1),
+            interval_ms => Duration::from_millis(interval_ms as u64),
+        };
+
+        let (sender, receiver) = tokio::sync::mpsc::channel(4);
+        let control = GrpcControl {
+            client: self.client.clone(),
+        };
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                // Stops once the client is gone
+                if sender.send(control.streams().await).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+}
+
+/// Starts the gRPC server on the webrtc plugin runtime
+pub(super) fn spawn_server(address: SocketAddr, token: Option<String>, client: ControlClient) {
+    let expected = token.map(|token| {
+        format!("Bearer {}", token)
+            .parse::<MetadataValue<_>>()
+            .expect("Invalid control API token")
+    });
+    let authorize = move |request: Request<()>| match &expected {
+        Some(expected) if request.metadata().get("authorization") != Some(expected) => {
+            Err(Status::unauthenticated("Invalid token"))
+        }
+        _ => Ok(request),
+    };
+
+    let service = StreamControlServer::with_interceptor(GrpcControl { client }, authorize);
+
+    gstrswebrtc::RUNTIME.spawn(async move {
+        info!("gRPC control API listening on {}", address);
+        if let Err(e) = Server::builder().add_service(service).serve(address).await {
+            error!("gRPC control API server error: {}", e);
+        }
+    });
+}
@@ -0,0 +1,177 @@
+use axum::{
+    Json, Router,
+    extract::{
+        Path, Request, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+};
+use bevy_log::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+use std::net::SocketAddr;
+
+use super::{ControlClient, ControlError, ControlRequest, ControlResponse, ControlResult};
+
+#[derive(Deserialize)]
+struct BitrateBody {
+    bitrate: u32,
+}
+
+#[derive(Deserialize)]
+struct ResolutionBody {
+    width: u32,
+    height: u32,
+}
+
+/// Starts the HTTP server on the webrtc plugin runtime
+pub(super) fn spawn_server(address: SocketAddr, token: Option<String>, client: ControlClient) {
+    let router = Router::new()
+        .route("/streams", get(list_streams))
+        .route("/streams/{name}", get(get_stream))
+        .route("/streams/{name}/stats", get(stats))
+        .route("/streams/{name}/start", post(start))
+        .route("/streams/{name}/stop", post(stop))
+        .route("/streams/{name}/bitrate", put(set_bitrate))
+        .route("/streams/{name}/resolution", put(resize))
+        .route("/streams/{name}/screenshot", get(screenshot))
+        .route("/ws", get(websocket))
+        .layer(middleware::from_fn_with_state(token, authorize))
+        .with_state(client);
+
+    gstrswebrtc::RUNTIME.spawn(async move {
+        let listener = match tokio::net::TcpListener::bind(address).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Unable to bind the control API to {}: {}", address, e);
+                return;
+            }
+        };
+
+        info!("Control API listening on {}", address);
+        if let Err(e) = axum::serve(listener, router).await {
+            error!("Control API server error: {}", e);
+        }
+    });
+}
+
+async fn authorize(State(token): State<Option<String>>, request: Request, next: Next) -> Response {
+    if let Some(token) = token {
+        let authorized = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|value| value == token);
+        if !authorized {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    }
+
+    next.run(request).await
+}
+
+fn respond(result: ControlResult) -> Response {
+    match result {
+        Ok(ControlResponse::Done) => StatusCode::NO_CONTENT.into_response(),
+        Ok(ControlResponse::Streams(streams)) => Json(streams).into_response(),
+        Ok(ControlResponse::Stream(stream)) => Json(stream).into_response(),
+        Ok(ControlResponse::Stats(stats)) => Json(stats).into_response(),
+        Ok(ControlResponse::Screenshot(png)) => {
+            ([(header::CONTENT_TYPE, "image/png")], png).into_response()
+        }
+        Err(e @ ControlError::NotFound(_)) => {
+            (StatusCode::NOT_FOUND, e.to_string()).into_response()
+        }
+        Err(e @ ControlError::Failed(_)) => {
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
+
+async fn list_streams(State(client): State<ControlClient>) -> Response {
+    respond(client.request(ControlRequest::ListStreams).await)
+}
+
+async fn get_stream(State(client): State<ControlClient>, Path(name): Path<String>) -> Response {
+    respond(client.request(ControlRequest::GetStream { name }).await)
+}
+
+async fn stats(State(client): State<ControlClient>, Path(name): Path<String>) -> Response {
+    respond(client.request(ControlRequest::Stats { name }).await)
+}
+
+async fn start(State(client): State<ControlClient>, Path(name): Path<String>) -> Response {
+    respond(client.request(ControlRequest::Start { name }).await)
+}
+
+async fn stop(State(client): State<ControlClient>, Path(name): Path<String>) -> Response {
+    respond(client.request(ControlRequest::Stop { name }).await)
+}
+
+async fn set_bitrate(
+    State(client): State<ControlClient>,
+    Path(name): Path<String>,
+    Json(body): Json<BitrateBody>,
+) -> Response {
+    let request = ControlRequest::SetBitrate {
+        name,
+        bitrate: body.bitrate,
+    };
+    respond(client.request(request).await)
+}
+
+async fn resize(
+    State(client): State<ControlClient>,
+    Path(name): Path<String>,
+    Json(body): Json<ResolutionBody>,
+) -> Response {
+    let request = ControlRequest::Resize {
+        name,
+        width: body.width,
+        height: body.height,
+    };
+    respond(client.request(request).await)
+}
+
+async fn screenshot(State(client): State<ControlClient>, Path(name): Path<String>) -> Response {
+    respond(client.request(ControlRequest::Screenshot { name }).await)
+}
+
+async fn websocket(State(client): State<ControlClient>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(socket, client))
+}
+
+/// Handles the requests sent as text messages, screenshots are replied as binary messages
+async fn handle_socket(mut socket: WebSocket, client: ControlClient) {
+    while let Some(Ok(message)) = socket.recv().await {
+        let Message::Text(text) = message else {
+            continue;
+        };
+
+        let reply = match serde_json::from_str::<ControlRequest>(text.as_str()) {
+            Ok(request) => socket_message(client.request(request).await),
+            Err(e) => socket_message(Err(ControlError::Failed(e.to_string()))),
+        };
+
+        if socket.send(reply).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn socket_message(result: ControlResult) -> Message {
+    let json = match result {
+        Ok(ControlResponse::Screenshot(png)) => return Message::Binary(png.into()),
+        Ok(ControlResponse::Done) => json!({ "ok": true }),
+        Ok(ControlResponse::Streams(streams)) => json!({ "ok": true, "streams": streams }),
+        Ok(ControlResponse::Stream(stream)) => json!({ "ok": true, "stream": stream }),
+        Ok(ControlResponse::Stats(stats)) => json!({ "ok": true, "stats": stats }),
+        Err(e) => json!({ "ok": false, "error": e.to_string() }),
+    };
+
+    Message::Text(json.to_string().into())
+}
@@ -0,0 +1,359 @@
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_image::prelude::*;
+use bevy_log::prelude::*;
+use bevy_platform::collections::HashMap;
+use bevy_render::{
+    prelude::*,
+    view::screenshot::{Screenshot, ScreenshotCaptured},
+};
+use crossbeam_channel::{Receiver, Sender};
+use serde::{Deserialize, Serialize};
+use std::{io::Cursor, net::SocketAddr};
+use tokio::sync::oneshot;
+
+use crate::{
+    AspectPolicy, StandbyPolicy, StreamLabels, ViewerCount, capture::Capture,
+    encoder::EncoderStats, resolution::CaptureResizer,
+};
+
+#[cfg(feature = "grpc")]
+mod grpc;
+mod http;
+
+/// Embedded server exposing the streams of the app to orchestration systems.
+///
+/// Serves a JSON HTTP API and a WebSocket accepting the same requests:
+/// - `GET /streams`: lists the streams
+/// - `GET /streams/{name}`: describes a stream
+/// - `GET /streams/{name}/stats`: statistics of the encoder of a stream
+/// - `POST /streams/{name}/start` and `POST /streams/{name}/stop`
+/// - `PUT /streams/{name}/bitrate` with `{"bitrate": 4000000}` (bit/s)
+/// - `PUT /streams/{name}/resolution` with `{"width": 1280, "height": 720}`
+/// - `GET /streams/{name}/screenshot`: PNG of the next rendered frame
+/// - `GET /ws`: WebSocket, each text message is a `ControlRequest`, e.g.
+///   `{"request": "set_bitrate", "name": "main", "bitrate": 4000000}`
+///
+/// Streams are the cameras with a `StreamLabels`, identified by their name.
+///
+/// With the `grpc` feature, the same requests are served over gRPC on `grpc_address`, see
+/// `proto/control.proto`.
+pub struct ControlApiPlugin {
+    /// Address the server listens on
+    pub address: SocketAddr,
+    /// Address the gRPC server listens on, if set
+    #[cfg(feature = "grpc")]
+    pub grpc_address: Option<SocketAddr>,
+    /// Token the clients must send as `Authorization: Bearer <token>`, if set
+    pub token: Option<String>,
+}
+
+impl Default for ControlApiPlugin {
+    fn default() -> Self {
+        Self {
+            address: SocketAddr::from(([127, 0, 0, 1], 8090)),
+            #[cfg(feature = "grpc")]
+            grpc_address: None,
+            token: None,
+        }
+    }
+}
+
+impl Plugin for ControlApiPlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let client = ControlClient { sender };
+        #[cfg(feature = "grpc")]
+        if let Some(grpc_address) = self.grpc_address {
+            grpc::spawn_server(grpc_address, self.token.clone(), client.clone());
+        }
+        http::spawn_server(self.address, self.token.clone(), client);
+
+        app.insert_resource(ControlRequests { receiver });
+        app.add_systems(Update, handle_control_requests);
+    }
+}
+
+/// A request to the control API
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub(crate) enum ControlRequest {
+    ListStreams,
+    GetStream {
+        name: String,
+    },
+    Stats {
+        name: String,
+    },
+    Start {
+        name: String,
+    },
+    Stop {
+        name: String,
+    },
+    SetBitrate {
+        name: String,
+        bitrate: u32,
+    },
+    Resize {
+        name: String,
+        width: u32,
+        height: u32,
+    },
+    Screenshot {
+        name: String,
+    },
+}
+
+/// Description of a stream returned by the control API
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct StreamInfo {
+    pub name: String,
+    pub labels: HashMap<String, String>,
+    /// False if capture is stopped, by the API or by the standby policy
+    pub running: bool,
+    pub standby: bool,
+    pub viewers: u32,
+    pub stats: Option<StatsInfo>,
+}
+
+/// Statistics of the encoder of a stream
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct StatsInfo {
+    pub frames_pushed: u64,
+    pub width: u32,
+    pub height: u32,
+    pub bitrate: Option<u32>,
+    pub bytes_sent: u64,
+    pub peer_bytes_sent: HashMap<String, u64>,
+    pub latency_ms: Option<f64>,
+}
+
+impl From<EncoderStats> for StatsInfo {
+    fn from(stats: EncoderStats) -> Self {
+        Self {
+            frames_pushed: stats.frames_pushed,
+            width: stats.width,
+            height: stats.height,
+            bitrate: stats.bitrate,
+            bytes_sent: stats.bytes_sent,
+            peer_bytes_sent: stats.peer_bytes_sent,
+            latency_ms: stats.latency.map(|latency| latency.as_secs_f64() * 1000.0),
+        }
+    }
+}
+
+/// The result of a `ControlRequest`
+#[derive(Debug)]
+pub(crate) enum ControlResponse {
+    Done,
+    Streams(Vec<StreamInfo>),
+    Stream(StreamInfo),
+    Stats(Option<StatsInfo>),
+    /// A PNG image
+    Screenshot(Vec<u8>),
+}
+
+#[derive(Debug)]
+pub(crate) enum ControlError {
+    NotFound(String),
+    Failed(String),
+}
+
+impl std::fmt::Display for ControlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ControlError::NotFound(name) => write!(f, "No stream named {}", name),
+            ControlError::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+pub(crate) type ControlResult = Result<ControlResponse, ControlError>;
+
+struct PendingRequest {
+    request: ControlRequest,
+    reply: oneshot::Sender<ControlResult>,
+}
+
+/// Sends requests to the app, from the server tasks
+#[derive(Clone)]
+pub(crate) struct ControlClient {
+    sender: Sender<PendingRequest>,
+}
+
+impl ControlClient {
+    /// Sends a request to the app and waits for its result, handled during the next update
+    pub(crate) async fn request(&self, request: ControlRequest) -> ControlResult {
+        let (reply, response) = oneshot::channel();
+        self.sender
+            .send(PendingRequest { request, reply })
+            .map_err(|_| ControlError::Failed("The app is not running".to_string()))?;
+
+        response
+            .await
+            .map_err(|_| ControlError::Failed("The request was dropped".to_string()))?
+    }
+}
+
+#[derive(Resource)]
+struct ControlRequests {
+    receiver: Receiver<PendingRequest>,
+}
+
+type StreamItem<'a> = (
+    &'a StreamLabels,
+    &'a Camera,
+    Option<&'a ViewerCount>,
+    Option<&'a StandbyPolicy>,
+    Option<&'a AspectPolicy>,
+);
+
+/// Returns the capture of a camera, and the entity holding it
+fn find_capture<'a>(
+    camera: &Camera,
+    captures: &'a Query<(Entity, &Capture)>,
+) -> Option<(Entity, &'a Capture)> {
+    let image = camera.target.as_image()?;
+    captures
+        .iter()
+        .find(|(_, capture)| capture.src_image() == image)
+}
+
+fn stream_info(stream: StreamItem, capture: Option<&Capture>) -> StreamInfo {
+    let (labels, _, viewers, standby, _) = stream;
+    StreamInfo {
+        name: labels.name.clone(),
+        labels: labels.labels.iter().cloned().collect(),
+        running: capture.is_some_and(Capture::enabled),
+        standby: standby.is_some_and(StandbyPolicy::is_standby),
+        viewers: viewers.map(|viewers| viewers.0).unwrap_or_default(),
+        stats: capture
+            .and_then(|capture| capture.encoder().stats())
+            .map(StatsInfo::from),
+    }
+}
+
+/// This system handles the requests received by the control server
+fn handle_control_requests(
+    mut commands: Commands,
+    requests: Res<ControlRequests>,
+    streams: Query<StreamItem>,
+    captures: Query<(Entity, &Capture)>,
+    mut resizer: CaptureResizer,
+) {
+    for PendingRequest { request, reply } in requests.receiver.try_iter() {
+        debug!("Control request: {:?}", request);
+
+        let name = match &request {
+            ControlRequest::ListStreams => {
+                let streams = streams
+                    .iter()
+                    .map(|stream| {
+                        let capture = find_capture(stream.1, &captures).map(|(_, c)| c);
+                        stream_info(stream, capture)
+                    })
+                    .collect();
+                let _ = reply.send(Ok(ControlResponse::Streams(streams)));
+                continue;
+            }
+            ControlRequest::GetStream { name }
+            | ControlRequest::Stats { name }
+            | ControlRequest::Start { name }
+            | ControlRequest::Stop { name }
+            | ControlRequest::SetBitrate { name, .. }
+            | ControlRequest::Resize { name, .. }
+            | ControlRequest::Screenshot { name } => name.clone(),
+        };
+
+        let Some(stream) = streams.iter().find(|(labels, ..)| labels.name == name) else {
+            let _ = reply.send(Err(ControlError::NotFound(name.clone())));
+            continue;
+        };
+        let camera = stream.1;
+        let Some((capture_entity, capture)) = find_capture(camera, &captures) else {
+            let _ = reply.send(Err(ControlError::Failed(format!(
+                "Stream {} has no capture",
+                name
+            ))));
+            continue;
+        };
+        let encoder = capture.encoder();
+        let failed = |e: anyhow::Error| ControlError::Failed(e.to_string());
+
+        let result = match request {
+            ControlRequest::ListStreams => unreachable!(),
+            ControlRequest::GetStream { .. } => {
+                Ok(ControlResponse::Stream(stream_info(stream, Some(capture))))
+            }
+            ControlRequest::Stats { .. } => {
+                Ok(ControlResponse::Stats(encoder.stats().map(StatsInfo::from)))
+            }
+            // The encoders added to the stream are started and stopped with it
+            ControlRequest::Start { .. } => capture
+                .encoders()
+                .iter()
+                .try_for_each(|encoder| encoder.start())
+                .map_err(failed)
+                .map(|_| {
+                    info!(stream = %name, "Stream started by the control API");
+                    capture.set_enabled(true);
+                    ControlResponse::Done
+                }),
+            ControlRequest::Stop { .. } => {
+                capture.set_enabled(false);
+                info!(stream = %name, "Stream stopped by the control API");
+                capture
+                    .encoders()
+                    .iter()
+                    .map(|encoder| encoder.stop())
+                    .fold(Ok(()), |result, stopped| result.and(stopped))
+                    .map_err(failed)
+                    .map(|_| ControlResponse::Done)
+            }
+            ControlRequest::SetBitrate { bitrate, .. } => encoder
+                .set_bitrate(bitrate)
+                .map_err(failed)
+                .map(|_| ControlResponse::Done),
+            ControlRequest::Resize { width, height, .. } => resizer
+                .resize(
+                    capture_entity,
+                    capture,
+                    width,
+                    height,
+                    stream.4.copied().unwrap_or_default(),
+                )
+                .map_err(failed)
+                .map(|_| ControlResponse::Done),
+            ControlRequest::Screenshot { .. } => {
+                // Replied to once the frame is rendered
+                let mut reply = Some(reply);
+                commands
+                    .spawn(Screenshot::image(capture.src_image().clone()))
+                    .observe(move |trigger: Trigger<ScreenshotCaptured>| {
+                        if let Some(reply) = reply.take() {
+                            let _ = reply.send(encode_png(&trigger.event().0));
+                        }
+                    });
+                continue;
+            }
+        };
+
+        let _ = reply.send(result);
+    }
+}
+
+fn encode_png(frame: &Image) -> ControlResult {
+    let failed = |e: &dyn std::fmt::Display| ControlError::Failed(e.to_string());
+
+    let mut png = Vec::new();
+    frame
+        .clone()
+        .try_into_dynamic()
+        .map_err(|e| failed(&e))?
+        .to_rgba8()
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| failed(&e))?;
+
+    Ok(ControlResponse::Screenshot(png))
+}
@@ -0,0 +1,177 @@
+use anyhow::{Context, Result, anyhow};
+use bevy_log::prelude::*;
+use gst::prelude::*;
+use gst_video::{VideoFormat, VideoInfo};
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    PipelineLogLevel,
+    encoder::{
+        EncoderStats, Frame, FrameTimestamps, StreamEncoder, pipeline_latency,
+        request_appsrc_keyframe, resize_appsrc,
+    },
+    pipeline_log::log_bus_message,
+};
+
+/// Settings of a `CustomPipelineEncoder`
+#[derive(Clone)]
+pub struct CustomPipelineSettings {
+    /// Name of the stream
+    pub name: String,
+    /// Labels attached to the stream, see `StreamLabels`
+    pub labels: Vec<(String, String)>,
+    /// A gst-launch description of the pipeline, containing an appsrc named `appsrc_name`,
+    /// e.g. `appsrc name=src ! videoconvert ! vp8enc ! webmmux ! shout2send ...`
+    pub pipeline: String,
+    /// Name of the appsrc receiving the frames
+    pub appsrc_name: String,
+    pub width: u32,
+    pub height: u32,
+    /// Verbosity of the pipeline messages logs
+    pub log_level: PipelineLogLevel,
+}
+
+impl Default for CustomPipelineSettings {
+    fn default() -> Self {
+        Self {
+            name: "custom".to_string(),
+            labels: Vec::new(),
+            pipeline: String::new(),
+            appsrc_name: "src".to_string(),
+            width: 1920,
+            height: 1080,
+            log_level: PipelineLogLevel::default(),
+        }
+    }
+}
+
+impl CustomPipelineSettings {
+    /// Returns the settings of a gst-launch `pipeline` receiving the frames in the appsrc named
+    /// `appsrc_name`, with the default size
+    pub fn new(pipeline: impl Into<String>, appsrc_name: impl Into<String>) -> Self {
+        Self {
+            pipeline: pipeline.into(),
+            appsrc_name: appsrc_name.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// An encoder pushing the frames into a user-provided pipeline, for deliveries not covered
+/// by the other encoders (Icecast, custom RTP topologies...).
+///
+/// The frames are RGBA, the caps of the appsrc are set by the encoder. Any sink supported by
+/// GStreamer can be used, see `CustomPipelineSettings::new`.
+pub struct CustomPipelineEncoder {
+    pipeline: gst::Pipeline,
+    appsrc: gst_app::AppSrc,
+    timestamps: FrameTimestamps,
+    stats: Mutex<EncoderStats>,
+}
+
+impl CustomPipelineEncoder {
+    pub fn new(settings: CustomPipelineSettings) -> Result<Arc<Self>> {
+        gst::init()?;
+
+        let pipeline = gst::parse::launch(&settings.pipeline)
+            .with_context(|| format!("Unable to parse pipeline: {}", settings.pipeline))?
+            .downcast::<gst::Pipeline>()
+            .map_err(|_| anyhow!("The description is not a pipeline"))?;
+        pipeline.set_property("name", &settings.name);
+
+        let appsrc = pipeline
+            .by_name(&settings.appsrc_name)
+            .ok_or_else(|| anyhow!("No element named {} in the pipeline", settings.appsrc_name))?
+            .downcast::<gst_app::AppSrc>()
+            .map_err(|_| anyhow!("{} is not an appsrc", settings.appsrc_name))?;
+
+        let video_info = VideoInfo::builder(VideoFormat::Rgba, settings.width, settings.height)
+            .build()
+            .context("Failed to create video info")?;
+        appsrc.set_caps(Some(&video_info.to_caps()?));
+        appsrc.set_format(gst::Format::Time);
+        appsrc.set_is_live(true);
+        appsrc.set_do_timestamp(true);
+
+        let bus = pipeline
+            .bus()
+            .ok_or_else(|| anyhow!("Pipeline has no bus"))?;
+        let stream = settings.name.clone();
+        let log_level = settings.log_level;
+        std::thread::spawn(move || {
+            for msg in bus.iter_timed(gst::ClockTime::NONE) {
+                log_bus_message(&stream, log_level, &msg);
+                if let gst::MessageView::Eos(_) = msg.view() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Arc::new(Self {
+            pipeline,
+            appsrc,
+            timestamps: FrameTimestamps::default(),
+            stats: Mutex::new(EncoderStats {
+                width: settings.width,
+                height: settings.height,
+                ..Default::default()
+            }),
+        }))
+    }
+}
+
+impl Drop for CustomPipelineEncoder {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
+
+impl StreamEncoder for CustomPipelineEncoder {
+    fn push_frame(&self, frame: &Frame) -> Result<()> {
+        let buffer = self.timestamps.video_buffer(&self.appsrc, frame)?;
+        self.appsrc
+            .push_buffer(buffer)
+            .map_err(|e| anyhow!("Failed to push buffer: {:?}", e))?;
+        self.stats.lock().unwrap().frames_pushed += 1;
+
+        Ok(())
+    }
+
+    fn start(&self) -> Result<()> {
+        info!(stream = %self.pipeline.name(), "Start pipeline");
+        self.pipeline.set_state(gst::State::Playing)?;
+
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<()> {
+        info!(stream = %self.pipeline.name(), "Stop pipeline");
+        self.pipeline.set_state(gst::State::Null)?;
+
+        Ok(())
+    }
+
+    fn resize(&self, width: u32, height: u32) -> Result<()> {
+        resize_appsrc(&self.appsrc, width, height)?;
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.width = width;
+        stats.height = height;
+
+        Ok(())
+    }
+
+    fn request_keyframe(&self) -> Result<()> {
+        request_appsrc_keyframe(&self.appsrc)
+    }
+
+    fn stats(&self) -> Option<EncoderStats> {
+        let mut stats = self.stats.lock().unwrap().clone();
+        stats.latency = pipeline_latency(&self.pipeline);
+        Some(stats)
+    }
+
+    fn pipeline_state(&self) -> Option<gst::State> {
+        Some(self.pipeline.current_state())
+    }
+}
@@ -0,0 +1,297 @@
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+
+/// GStreamer elements needed by a capability, all of them or any of them
+#[derive(Clone, Copy, Debug)]
+enum Elements {
+    All(&'static [&'static str]),
+    Any(&'static [&'static str]),
+}
+
+struct Check {
+    name: &'static str,
+    required: bool,
+    elements: Elements,
+    hint: &'static str,
+}
+
+const CHECKS: &[Check] = &[
+    Check {
+        name: "webrtc",
+        required: true,
+        elements: Elements::All(&[
+            "webrtcbin",
+            "nicesrc",
+            "nicesink",
+            "dtlssrtpenc",
+            "srtpenc",
+            "rtpbin",
+        ]),
+        hint: "install gst-plugins-bad (webrtc, dtls, srtp), gst-plugins-good (rtpmanager) \
+            and the GStreamer plugin of libnice",
+    },
+    Check {
+        name: "conversion",
+        required: true,
+        elements: Elements::All(&[
+            "appsrc",
+            "videoconvert",
+            "audioconvert",
+            "audioresample",
+            "audiomixer",
+        ]),
+        hint: "install gst-plugins-base",
+    },
+    Check {
+        name: "video-encoder",
+        required: true,
+        elements: Elements::Any(&["x264enc", "vp8enc", "vp9enc", "nvh264enc", "nvcudah264enc"]),
+        hint: "install gst-plugins-ugly (x264enc) or gst-plugins-good (vp8enc, vp9enc)",
+    },
+    Check {
+        name: "hardware-encoder",
+        required: false,
+        elements: Elements::Any(HARDWARE_ENCODERS),
+        hint: "install the nvcodec, va or qsv plugins of gst-plugins-bad and the GPU drivers",
+    },
+    Check {
+        name: "recording",
+        required: false,
+        elements: Elements::All(&["h264parse", "mp4mux", "matroskamux", "splitmuxsink"]),
+        hint: "install gst-plugins-bad (videoparsers) and gst-plugins-good (isomp4, matroska, \
+            multifile)",
+    },
+    Check {
+        name: "rtmp",
+        required: false,
+        elements: Elements::All(&["flvmux", "rtmpsink"]),
+        hint: "install gst-plugins-good (flv) and gst-plugins-bad (rtmp)",
+    },
+    #[cfg(target_os = "linux")]
+    Check {
+        name: "v4l2",
+        required: false,
+        elements: Elements::All(&["v4l2sink"]),
+        hint: "install gst-plugins-good (video4linux2) and v4l2loopback",
+    },
+    #[cfg(unix)]
+    Check {
+        name: "shm",
+        required: false,
+        elements: Elements::All(&["shmsink"]),
+        hint: "install gst-plugins-bad (shm)",
+    },
+    Check {
+        name: "rtp-udp",
+        required: false,
+        elements: Elements::All(&["rtph264pay", "rtpvp8pay", "rtpbin", "udpsink"]),
+        hint: "install gst-plugins-good (rtp, rtpmanager, udp)",
+    },
+    #[cfg(feature = "rtsp")]
+    Check {
+        name: "rtsp",
+        required: true,
+        elements: Elements::All(&["h264parse", "rtph264pay"]),
+        hint: "install gst-plugins-bad (videoparsers), gst-plugins-good (rtp) and \
+            gst-rtsp-server",
+    },
+    #[cfg(feature = "moq")]
+    Check {
+        name: "moq",
+        required: true,
+        elements: Elements::All(&["h264parse", "isofmp4mux", "moqsink"]),
+        hint: "install gst-plugins-bad (videoparsers), build gst-plugins-rs with `cargo build \
+            --release -p gst-plugin-fmp4` and moq-gst, then add them to GST_PLUGIN_PATH",
+    },
+    #[cfg(feature = "ndi")]
+    Check {
+        name: "ndi",
+        required: true,
+        elements: Elements::All(&["ndisink"]),
+        hint: "install the NDI SDK and build gst-plugins-rs with `cargo build --release -p \
+            gst-plugin-ndi`, then add it to GST_PLUGIN_PATH",
+    },
+    #[cfg(feature = "livekit")]
+    Check {
+        name: "livekit",
+        required: true,
+        elements: Elements::All(&["livekitwebrtcsink"]),
+        hint: "build gst-plugins-rs with `cargo build --release -p gst-plugin-webrtc \
+            --features livekit` and add it to GST_PLUGIN_PATH",
+    },
+];
+
+const HARDWARE_ENCODERS: &[&str] = &[
+    "nvh264enc",
+    "nvcudah264enc",
+    "nvh265enc",
+    "nvcudah265enc",
+    "nvav1enc",
+    "vah264enc",
+    "vaapih264enc",
+    "qsvh264enc",
+    "vtenc_h264",
+];
+
+/// Result of the check of a capability
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapabilityStatus {
+    pub name: &'static str,
+    /// Streaming does not work without this capability
+    pub required: bool,
+    pub available: bool,
+    /// Missing elements, only the ones preventing the capability
+    pub missing: Vec<&'static str>,
+    /// How to make the capability available
+    pub hint: &'static str,
+}
+
+/// Capabilities of the runtime environment, found by `doctor`
+#[derive(Resource, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StreamingCapabilities {
+    /// `None` if GStreamer could not be initialized
+    pub gstreamer_version: Option<String>,
+    /// Cargo features of the crate enabling signallers and integrations
+    pub features: Vec<&'static str>,
+    pub capabilities: Vec<CapabilityStatus>,
+    /// Hardware encoders found, e.g. `nvh264enc`
+    pub hardware_encoders: Vec<&'static str>,
+}
+
+impl StreamingCapabilities {
+    /// True if every required capability is available
+    pub fn is_ready(&self) -> bool {
+        self.gstreamer_version.is_some()
+            && self
+                .capabilities
+                .iter()
+                .all(|capability| capability.available || !capability.required)
+    }
+
+    pub fn capability(&self, name: &str) -> Option<&CapabilityStatus> {
+        self.capabilities
+            .iter()
+            .find(|capability| capability.name == name)
+    }
+
+    /// Logs the capabilities, with a warning or an error for each missing one
+    pub fn log(&self) {
+        let Some(version) = &self.gstreamer_version else {
+            error!("GStreamer could not be initialized, check its installation");
+            return;
+        };
+        info!(
+            "{}, features {:?}, hardware encoders {:?}",
+            version, self.features, self.hardware_encoders
+        );
+
+        for capability in self.capabilities.iter().filter(|c| !c.available) {
+            if capability.required {
+                error!(
+                    "Missing {} (elements {:?}): {}",
+                    capability.name, capability.missing, capability.hint
+                );
+            } else {
+                warn!(
+                    "{} unavailable (elements {:?}): {}",
+                    capability.name, capability.missing, capability.hint
+                );
+            }
+        }
+    }
+}
+
+/// Checks which GStreamer plugins, hardware encoders and signallers are available, so that
+/// missing dependencies are reported at startup rather than when a pipeline is created.
+///
+/// `StreamerPlugin` runs it at startup, logs the result and inserts it as a resource.
+pub fn doctor() -> StreamingCapabilities {
+    let features = [
+        #[cfg(feature = "pixelstreaming")]
+        "pixelstreaming",
+        #[cfg(feature = "livekit")]
+        "livekit",
+        #[cfg(feature = "janus")]
+        "janus",
+        #[cfg(feature = "whip")]
+        "whip",
+        #[cfg(feature = "rtsp")]
+        "rtsp",
+        #[cfg(feature = "moq")]
+        "moq",
+        #[cfg(feature = "ndi")]
+        "ndi",
+        #[cfg(feature = "cuda")]
+        "cuda",
+        #[cfg(feature = "control-api")]
+        "control-api",
+        #[cfg(feature = "grpc")]
+        "grpc",
+    ]
+    .to_vec();
+
+    if let Err(e) = gst::init() {
+        warn!("Unable to initialize GStreamer: {}", e);
+        return StreamingCapabilities {
+            features,
+            ..Default::default()
+        };
+    }
+    let has_element = |name: &&str| gst::ElementFactory::find(name).is_some();
+
+    let capabilities = CHECKS
+        .iter()
+        .map(|check| {
+            let (available, missing) = match check.elements {
+                Elements::All(elements) => {
+                    let missing = elements
+                        .iter()
+                        .filter(|name| !has_element(name))
+                        .copied()
+                        .collect::<Vec<_>>();
+                    (missing.is_empty(), missing)
+                }
+                Elements::Any(elements) => {
+                    if elements.iter().any(has_element) {
+                        (true, Vec::new())
+                    } else {
+                        (false, elements.to_vec())
+                    }
+                }
+            };
+
+            CapabilityStatus {
+                name: check.name,
+                required: check.required,
+                available,
+                missing,
+                hint: check.hint,
+            }
+        })
+        .collect();
+
+    StreamingCapabilities {
+        gstreamer_version: Some(gst::version_string().to_string()),
+        features,
+        capabilities,
+        hardware_encoders: HARDWARE_ENCODERS
+            .iter()
+            .filter(|name| has_element(name))
+            .copied()
+            .collect(),
+    }
+}
+
+/// This system checks the capabilities with `doctor` and logs them, unless the
+/// `StreamingCapabilities` resource was inserted by the app
+pub(crate) fn report_capabilities(
+    mut commands: Commands,
+    capabilities: Option<Res<StreamingCapabilities>>,
+) {
+    if capabilities.is_some() {
+        return;
+    }
+    let capabilities = doctor();
+    capabilities.log();
+    commands.insert_resource(capabilities);
+}
@@ -1,9 +1,332 @@
-use anyhow::Result;
-use std::sync::Arc;
+use anyhow::{Result, anyhow};
+use bevy_log::prelude::*;
+use bevy_platform::collections::HashMap;
+use gst::prelude::*;
+use std::{
+    borrow::Cow,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+
+/// Statistics of an encoder
+#[derive(Clone, Debug, Default)]
+pub struct EncoderStats {
+    /// Number of frames pushed to the encoder
+    pub frames_pushed: u64,
+    pub width: u32,
+    pub height: u32,
+    /// Target bitrate in bits per second, if known
+    pub bitrate: Option<u32>,
+    /// Bytes sent to the peers since the stream started, including the peers which left
+    pub bytes_sent: u64,
+    /// Bytes sent to each connected peer, by session id
+    pub peer_bytes_sent: HashMap<String, u64>,
+    /// Latency of the pipeline, from a frame pushed to the encoder to its sending, if the
+    /// pipeline is live and running
+    pub latency: Option<Duration>,
+}
+
+/// A frame pushed to a `StreamEncoder`
+#[derive(Clone, Copy, Debug)]
+pub struct Frame<'a> {
+    /// RGBA pixels, `stride` bytes per row, or an access unit for pre-encoded streams
+    pub data: &'a [u8],
+    pub width: u32,
+    pub height: u32,
+    /// Bytes per row of `data`, the rows copied from the GPU are padded
+    pub stride: usize,
+    /// Time at which the frame was captured, from the start of the capture
+    pub pts: Duration,
+    /// Id assigned when the frame was captured.
+    ///
+    /// Frame ids are monotonically increasing per camera, backends can propagate them
+    /// downstream so logs and stats refer to the same frame.
+    pub id: u64,
+}
+
+impl<'a> Frame<'a> {
+    /// Returns a frame of tightly packed RGBA pixels
+    pub fn packed(data: &'a [u8], width: u32, height: u32, pts: Duration, id: u64) -> Self {
+        Self {
+            data,
+            width,
+            height,
+            stride: width as usize * 4,
+            pts,
+            id,
+        }
+    }
+
+    /// Returns the pixels without the padding of the rows
+    pub fn packed_data(&self) -> Cow<'a, [u8]> {
+        let row_size = self.width as usize * 4;
+        if self.stride == row_size {
+            return Cow::Borrowed(&self.data[..row_size * self.height as usize]);
+        }
+        Cow::Owned(
+            self.data
+                .chunks(self.stride)
+                .take(self.height as usize)
+                .flat_map(|row| &row[..row_size])
+                .copied()
+                .collect(),
+        )
+    }
+}
 
 pub trait StreamEncoder: Send + Sync {
-    fn push_frame(&self, frame_data: &[u8]) -> Result<()>;
+    fn push_frame(&self, frame: &Frame) -> Result<()>;
     fn start(&self) -> Result<()>;
+
+    /// Stops the encoder, frames pushed afterwards are dropped
+    fn stop(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Puts the encoder in standby while nobody watches the stream, see `StandbyPolicy`.
+    /// Does nothing by default, the encoder only stops receiving frames
+    fn set_standby(&self, standby: bool) -> Result<()> {
+        let _ = standby;
+        Ok(())
+    }
+
+    /// Changes the size of the frames pushed to the encoder
+    fn resize(&self, width: u32, height: u32) -> Result<()> {
+        let _ = (width, height);
+        Err(anyhow!("Resizing is not supported by this encoder"))
+    }
+
+    /// Sets the target bitrate, in bits per second
+    fn set_bitrate(&self, bitrate: u32) -> Result<()> {
+        let _ = bitrate;
+        Err(anyhow!(
+            "Setting the bitrate is not supported by this encoder"
+        ))
+    }
+
+    /// Requests the encoder to produce a keyframe as soon as possible
+    fn request_keyframe(&self) -> Result<()> {
+        Err(anyhow!(
+            "Requesting keyframes is not supported by this encoder"
+        ))
+    }
+
+    /// Adds a named marker to `frame`, called just before it is pushed, see `StreamMarker`.
+    /// Ignored by the encoders which do not record the markers
+    fn add_marker(&self, name: &str, frame: &Frame) -> Result<()> {
+        let _ = (name, frame);
+        Ok(())
+    }
+
+    /// Returns the statistics of the encoder, if it provides any
+    fn stats(&self) -> Option<EncoderStats> {
+        None
+    }
+
+    /// Returns the current state of the pipeline of the encoder, if it runs one
+    fn pipeline_state(&self) -> Option<gst::State> {
+        None
+    }
+}
+
+pub type EncoderHandle = Arc<dyn StreamEncoder>;
+
+/// An encoder created in the background, frames pushed before it is ready are dropped
+#[derive(Default)]
+pub struct DeferredEncoder {
+    inner: OnceLock<EncoderHandle>,
+}
+
+impl DeferredEncoder {
+    /// Sets the encoder, once it is created and started
+    pub fn set(&self, encoder: EncoderHandle) {
+        if self.inner.set(encoder).is_err() {
+            warn!("Deferred encoder already set");
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.inner.get().is_some()
+    }
+
+    fn ready(&self) -> Result<&EncoderHandle> {
+        self.inner
+            .get()
+            .ok_or_else(|| anyhow!("The encoder is not ready yet"))
+    }
 }
 
-pub type EncoderHandle = Arc<dyn StreamEncoder>;
\ No newline at end of file
+impl StreamEncoder for DeferredEncoder {
+    fn push_frame(&self, frame: &Frame) -> Result<()> {
+        match self.inner.get() {
+            Some(encoder) => encoder.push_frame(frame),
+            None => Ok(()),
+        }
+    }
+
+    fn start(&self) -> Result<()> {
+        // Started in the background
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<()> {
+        match self.inner.get() {
+            Some(encoder) => encoder.stop(),
+            None => Ok(()),
+        }
+    }
+
+    fn set_standby(&self, standby: bool) -> Result<()> {
+        match self.inner.get() {
+            Some(encoder) => encoder.set_standby(standby),
+            None => Ok(()),
+        }
+    }
+
+    fn resize(&self, width: u32, height: u32) -> Result<()> {
+        self.ready()?.resize(width, height)
+    }
+
+    fn set_bitrate(&self, bitrate: u32) -> Result<()> {
+        self.ready()?.set_bitrate(bitrate)
+    }
+
+    fn request_keyframe(&self) -> Result<()> {
+        self.ready()?.request_keyframe()
+    }
+
+    fn add_marker(&self, name: &str, frame: &Frame) -> Result<()> {
+        match self.inner.get() {
+            Some(encoder) => encoder.add_marker(name, frame),
+            None => Ok(()),
+        }
+    }
+
+    fn stats(&self) -> Option<EncoderStats> {
+        self.inner.get().and_then(|encoder| encoder.stats())
+    }
+
+    fn pipeline_state(&self) -> Option<gst::State> {
+        self.inner
+            .get()
+            .and_then(|encoder| encoder.pipeline_state())
+    }
+}
+
+/// Frames pushed later than this after their capture are timestamped again from the running
+/// time, e.g. once the pipeline is restarted
+const MAX_FRAME_DELAY: Duration = Duration::from_secs(1);
+
+/// Timestamps the buffers pushed to an appsrc with the pts of the frames, mapped to the running
+/// time of the pipeline from the first frame pushed
+#[derive(Default)]
+pub(crate) struct FrameTimestamps {
+    /// Running time minus pts, in nanoseconds
+    offset: Mutex<Option<i128>>,
+}
+
+impl FrameTimestamps {
+    /// Returns the running time of a frame captured at `pts`, `None` if the pipeline is not
+    /// running
+    fn running_time(&self, appsrc: &gst_app::AppSrc, pts: Duration) -> Option<gst::ClockTime> {
+        let now = appsrc.current_running_time()?.nseconds() as i128;
+        let pts = pts.as_nanos() as i128;
+
+        let mut offset = self.offset.lock().unwrap();
+        let running_time = offset.map(|offset| pts + offset);
+        let running_time = match running_time {
+            Some(running_time)
+                if running_time <= now
+                    && now - running_time <= MAX_FRAME_DELAY.as_nanos() as i128 =>
+            {
+                running_time
+            }
+            _ => {
+                *offset = Some(now - pts);
+                now
+            }
+        };
+        Some(gst::ClockTime::from_nseconds(running_time as u64))
+    }
+
+    /// Returns a buffer holding the data of `frame`, with the frame id as offset, to push to
+    /// `appsrc`. It is timestamped by `appsrc` if the pipeline is not running.
+    pub(crate) fn buffer(&self, appsrc: &gst_app::AppSrc, frame: &Frame) -> gst::Buffer {
+        let mut buffer = gst::Buffer::from_slice(frame.data.to_vec());
+        {
+            let buffer = buffer.get_mut().unwrap();
+            buffer.set_offset(frame.id);
+            if let Some(running_time) = self.running_time(appsrc, frame.pts) {
+                buffer.set_pts(running_time);
+                buffer.set_dts(running_time);
+            }
+        }
+        buffer
+    }
+
+    /// Returns a buffer holding the RGBA pixels of `frame`, with a video meta describing
+    /// their stride
+    pub(crate) fn video_buffer(
+        &self,
+        appsrc: &gst_app::AppSrc,
+        frame: &Frame,
+    ) -> Result<gst::Buffer> {
+        let mut buffer = self.buffer(appsrc, frame);
+        gst_video::VideoMeta::add_full(
+            buffer.get_mut().unwrap(),
+            gst_video::VideoFrameFlags::empty(),
+            gst_video::VideoFormat::Rgba,
+            frame.width,
+            frame.height,
+            &[0],
+            &[frame.stride as i32],
+        )?;
+        Ok(buffer)
+    }
+}
+
+/// Returns the latency of a live pipeline, the configured one or else the one computed by
+/// a latency query
+pub(crate) fn pipeline_latency(pipeline: &gst::Pipeline) -> Option<Duration> {
+    if pipeline.current_state() != gst::State::Playing {
+        return None;
+    }
+    if let Some(latency) = pipeline.latency() {
+        return Some(Duration::from_nanos(latency.nseconds()));
+    }
+
+    let mut query = gst::query::Latency::new();
+    if !pipeline.query(&mut query) {
+        return None;
+    }
+    let (live, min, _max) = query.result();
+    live.then(|| Duration::from_nanos(min.nseconds()))
+}
+
+/// Changes the size in the caps of an appsrc receiving RGBA frames
+pub(crate) fn resize_appsrc(appsrc: &gst_app::AppSrc, width: u32, height: u32) -> Result<()> {
+    let mut caps = appsrc
+        .caps()
+        .ok_or_else(|| anyhow!("The appsrc has no caps"))?;
+    {
+        let caps = caps.make_mut();
+        caps.set("width", width as i32);
+        caps.set("height", height as i32);
+    }
+    appsrc.set_caps(Some(&caps));
+
+    Ok(())
+}
+
+/// Sends a force key unit event downstream of an appsrc, to the encoders
+pub(crate) fn request_appsrc_keyframe(appsrc: &gst_app::AppSrc) -> Result<()> {
+    let event = gst_video::DownstreamForceKeyUnitEvent::builder()
+        .all_headers(true)
+        .build();
+
+    if appsrc.send_event(event) {
+        Ok(())
+    } else {
+        Err(anyhow!("The keyframe request was not handled"))
+    }
+}
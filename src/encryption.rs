@@ -0,0 +1,78 @@
+use age::{Encryptor, secrecy::SecretString, x25519};
+use anyhow::{Result, anyhow};
+use bevy_log::prelude::*;
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+/// Encryption of the finalized recordings with age (<https://age-encryption.org>), e.g. for
+/// session recordings containing user interactions.
+///
+/// Each file is encrypted to `<path>.age` once finalized, then the plaintext file is removed.
+/// It is written in plaintext while it is recorded.
+#[derive(Clone, Debug)]
+pub enum RecordEncryption {
+    /// Encrypts to these X25519 recipients (`age1...` public keys), so that only the holders
+    /// of the matching identities can decrypt the recordings
+    Recipients(Vec<String>),
+    /// Encrypts with a passphrase
+    Passphrase(String),
+}
+
+impl RecordEncryption {
+    /// Checks the recipients, so that invalid settings are reported before recording
+    pub(crate) fn validate(&self) -> Result<()> {
+        self.encryptor().map(|_| ())
+    }
+
+    fn encryptor(&self) -> Result<Encryptor> {
+        Ok(match self {
+            RecordEncryption::Recipients(recipients) => {
+                let recipients = recipients
+                    .iter()
+                    .map(|recipient| {
+                        recipient
+                            .parse::<x25519::Recipient>()
+                            .map_err(|e| anyhow!("Invalid age recipient {}: {}", recipient, e))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Encryptor::with_recipients(
+                    recipients
+                        .iter()
+                        .map(|recipient| recipient as &dyn age::Recipient),
+                )?
+            }
+            RecordEncryption::Passphrase(passphrase) => {
+                Encryptor::with_user_passphrase(SecretString::from(passphrase.clone()))
+            }
+        })
+    }
+}
+
+/// Encrypts a finalized file to `<path>.age` and removes it, returns the path of the
+/// encrypted file
+pub(crate) fn encrypt_file(encryption: &RecordEncryption, path: &Path) -> Result<PathBuf> {
+    let mut encrypted_path = path.as_os_str().to_owned();
+    encrypted_path.push(".age");
+    let encrypted_path = PathBuf::from(encrypted_path);
+
+    let encrypt = || -> Result<()> {
+        let mut input = File::open(path)?;
+        let output = BufWriter::new(File::create(&encrypted_path)?);
+        let mut writer = encryption.encryptor()?.wrap_output(output)?;
+        std::io::copy(&mut input, &mut writer)?;
+        writer.finish()?.into_inner()?.sync_all()?;
+        Ok(())
+    };
+
+    debug!("Encrypting {}", path.display());
+    if let Err(e) = encrypt() {
+        let _ = std::fs::remove_file(&encrypted_path);
+        return Err(e);
+    }
+    std::fs::remove_file(path)?;
+
+    Ok(encrypted_path)
+}
@@ -0,0 +1,148 @@
+use bevy_ecs::prelude::*;
+use std::path::PathBuf;
+
+use crate::record::RecordingLimit;
+
+/// A `UiInteraction` message received from a peer through the data channel
+#[derive(Event, Clone, Debug)]
+pub struct StreamerUiInteraction {
+    /// The streamer camera the peer is connected to
+    pub camera: Entity,
+    pub peer_id: String,
+    pub message: String,
+}
+
+/// A `Command` message received from a peer through the data channel
+#[derive(Event, Clone, Debug)]
+pub struct StreamerCommand {
+    /// The streamer camera the peer is connected to
+    pub camera: Entity,
+    pub peer_id: String,
+    pub command: String,
+}
+
+/// A resolution requested by a peer, applied according to the `ResolutionPolicy` of the
+/// streamer camera.
+///
+/// Sent for the Pixel Streaming `{"Resolution.Width": 1280, "Resolution.Height": 720}`
+/// commands. `livekitwebrtcsink` doesn't report the layer preferences of the LiveKit
+/// subscribers, apps receiving them by other means can send this event.
+#[derive(Event, Clone, Debug)]
+pub struct StreamerResolutionRequest {
+    pub camera: Entity,
+    pub peer_id: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Sent once the pipeline of a camera created with `new_streamer_camera_async` is started,
+/// or failed to start
+#[derive(Event, Clone, Debug)]
+pub struct StreamerCameraReady {
+    pub camera: Entity,
+    pub result: Result<(), String>,
+}
+
+/// Sent when a streamer camera goes in standby, see `StandbyPolicy`
+#[derive(Event, Clone, Debug)]
+pub struct StreamerStandby {
+    pub camera: Entity,
+}
+
+/// Sent when a streamer camera in standby restarts because a viewer connected
+#[derive(Event, Clone, Debug)]
+pub struct StreamerResumed {
+    pub camera: Entity,
+}
+
+/// Sent when a recording file, or a segment of a segmented recording, is finalized and
+/// can be read
+#[derive(Event, Clone, Debug)]
+pub struct RecordingFinalized {
+    pub camera: Entity,
+    pub path: PathBuf,
+}
+
+/// Sent when a recording reaches one of its `RecordLimits`, before it is stopped or rotated
+#[derive(Event, Clone, Debug)]
+pub struct RecordingLimitReached {
+    pub camera: Entity,
+    pub limit: RecordingLimit,
+}
+
+/// Drops a named marker on the frame a streamer camera renders in this update, e.g. a
+/// chapter point or "round started".
+///
+/// The recordings to a file write the markers as chapters, at the time of this frame. The
+/// Pixel Streaming peers receive `{"event": "marker", "data": {"name": "...", "frame_id": 42}}`
+/// in a `Response` message. The markers of the frames which are not captured, e.g. skipped
+/// or replaced by a placeholder, go to the next captured frame.
+#[derive(Event, Clone, Debug)]
+pub struct StreamMarker {
+    pub camera: Entity,
+    pub name: String,
+}
+
+/// Why the session of a peer ended, see `SessionPolicy`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionExpiry {
+    MaxDuration,
+    Idle,
+    /// No RTCP packet was received from the peer, see `SessionPolicy::rtcp_timeout`
+    RtcpTimeout,
+}
+
+/// Sent when a peer is disconnected by the `SessionPolicy` of its camera
+#[derive(Event, Clone, Debug)]
+pub struct PeerSessionExpired {
+    pub camera: Entity,
+    pub peer_id: String,
+    pub reason: SessionExpiry,
+}
+
+/// Sent when a new stream doesn't fit in the `GpuMemoryBudget`
+#[derive(Event, Clone, Debug)]
+pub struct GpuMemoryBudgetExceeded {
+    /// Name of the stream, see `StreamLabels`, empty for the cameras created by
+    /// `StreamerHelper::new_streamer_camera_with_encoder`
+    pub stream: String,
+    pub requested_size: (u32, u32),
+    /// The downscaled size of the stream, `None` if it is refused
+    pub granted_size: Option<(u32, u32)>,
+}
+
+/// Sent when the hardware encoder of a stream failed while streaming, e.g. after a driver
+/// reset or when its NVENC session was taken by another process.
+///
+/// The encoder is replaced by a software encoder and the pipeline restarted, rather than the
+/// stream stopping with the error. Only for the RTMP, RTP, MoQ and LiveKit streams, and the
+/// recordings to a RTMP server: restarting a file recording would overwrite it.
+///
+/// With the WebRTC streams, `webrtcsink` creates an encoder per session and the session of a
+/// failed encoder ends: its NVENC session is released and the NVENC encoders are disabled, so
+/// that the peer reconnects, and the next sessions start, with a software encoder.
+#[cfg(feature = "cuda")]
+#[derive(Event, Clone, Debug)]
+pub struct EncoderFallback {
+    /// Name of the stream, see `StreamLabels`
+    pub stream: String,
+    /// Element of the failed encoder, e.g. `nvh264enc`
+    pub hardware: String,
+    /// Element of the software encoder replacing it
+    pub software: String,
+    /// Error posted by the failed encoder
+    pub error: String,
+}
+
+/// Sent when a stream needs a hardware encoder while all the NVENC sessions of the GPU are
+/// in use, see `NvencCapabilities`.
+///
+/// The peer is disconnected, recordings fall back to a software encoder.
+#[cfg(feature = "cuda")]
+#[derive(Event, Clone, Debug)]
+pub struct NvencSessionLimitReached {
+    /// Name of the stream, see `StreamLabels`
+    pub stream: String,
+    pub active: u32,
+    pub max: u32,
+}
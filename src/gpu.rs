@@ -0,0 +1,262 @@
+use anyhow::{Context, Result, anyhow, bail};
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use bevy_render::{
+    renderer::{RenderInstance, WgpuWrapper, initialize_renderer},
+    settings::{Backends, RenderCreation, RenderResources, WgpuSettings},
+};
+use std::sync::Arc;
+use wgpu::{AdapterInfo, DeviceType};
+
+/// Environment variable read by `GpuSettings::from_env`
+const GPU_ENV: &str = "BEVY_STREAMING_GPU";
+
+/// Adapter rendering the streamer cameras, see `GpuSettings`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum GpuSelection {
+    /// A discrete GPU, otherwise an integrated then a virtual one
+    #[default]
+    PreferDiscrete,
+    /// The GPU with this PCI vendor id, and device id if set, e.g. `0x10de` for NVIDIA
+    Pci { vendor: u32, device: Option<u32> },
+    /// The first GPU whose name contains this one, case insensitive
+    Name(String),
+    /// A software rasterizer, lavapipe with Vulkan or llvmpipe with OpenGL
+    Software,
+}
+
+impl GpuSelection {
+    /// Parses `discrete`, `software`, `pci:<vendor>[:<device>]` with hexadecimal ids, e.g.
+    /// `pci:10de:2204`, or `name:<name>`
+    pub fn parse(value: &str) -> Result<Self> {
+        let parse_id = |id: &str| {
+            u32::from_str_radix(id.trim_start_matches("0x"), 16)
+                .with_context(|| format!("Invalid PCI id {id}"))
+        };
+
+        match value.split_once(':') {
+            None if value == "discrete" => Ok(Self::PreferDiscrete),
+            None if value == "software" => Ok(Self::Software),
+            Some(("pci", ids)) => match ids.split_once(':') {
+                Some((vendor, device)) => Ok(Self::Pci {
+                    vendor: parse_id(vendor)?,
+                    device: Some(parse_id(device)?),
+                }),
+                None => Ok(Self::Pci {
+                    vendor: parse_id(ids)?,
+                    device: None,
+                }),
+            },
+            Some(("name", name)) => Ok(Self::Name(name.to_string())),
+            _ => bail!(
+                "Invalid GPU selection {value}, expected discrete, software, \
+                    pci:<vendor>[:<device>] or name:<name>"
+            ),
+        }
+    }
+
+    fn matches(&self, info: &AdapterInfo) -> bool {
+        let hardware = info.device_type != DeviceType::Cpu;
+        match self {
+            GpuSelection::PreferDiscrete => hardware,
+            GpuSelection::Pci { vendor, device } => {
+                info.vendor == *vendor && device.is_none_or(|device| info.device == device)
+            }
+            GpuSelection::Name(name) => info.name.to_lowercase().contains(&name.to_lowercase()),
+            GpuSelection::Software => !hardware,
+        }
+    }
+}
+
+/// Selection of the adapter of a headless app, whose default choice by wgpu is often wrong
+/// on cloud instances, e.g. the software rasterizer next to an NVIDIA GPU, and only shows up
+/// as a wgpu panic.
+///
+/// `GpuSettings::render_creation` is set on the `RenderPlugin`, it fails with the list of
+/// the adapters when none matches. The selected adapter is logged by the `StreamerPlugin`
+/// once inserted, the logger does not exist yet when the renderer is created:
+///
+/// ```ignore
+/// let (render_creation, adapter) = GpuSettings::from_env()?.render_creation()?;
+/// app.add_plugins(DefaultPlugins.set(RenderPlugin {
+///     render_creation,
+///     ..default()
+/// }))
+/// .insert_resource(adapter);
+/// ```
+#[derive(Clone)]
+pub struct GpuSettings {
+    pub selection: GpuSelection,
+    /// Falls back to a software rasterizer when no GPU matches, at a fraction of the
+    /// framerate, e.g. on the instances without GPU
+    pub software_fallback: bool,
+    /// Settings of the renderer, their `backends` are the ones searched
+    pub wgpu: WgpuSettings,
+}
+
+impl Default for GpuSettings {
+    fn default() -> Self {
+        Self {
+            selection: GpuSelection::default(),
+            software_fallback: true,
+            wgpu: WgpuSettings::default(),
+        }
+    }
+}
+
+/// Adapter selected by `GpuSettings`
+#[derive(Resource, Clone, Debug)]
+pub struct SelectedAdapter {
+    pub info: AdapterInfo,
+    /// Whether no GPU matched the selection and the software rasterizer is used instead
+    pub software_fallback: bool,
+    /// The selection that did not match, when falling back
+    pub selection: GpuSelection,
+}
+
+impl GpuSettings {
+    /// Reads the selection from `BEVY_STREAMING_GPU`, see `GpuSelection::parse`. A GPU
+    /// selected this way does not fall back to a software rasterizer, the default settings
+    /// are used when it is not set.
+    pub fn from_env() -> Result<Self> {
+        match std::env::var(GPU_ENV) {
+            Ok(value) => Ok(Self {
+                selection: GpuSelection::parse(&value)
+                    .with_context(|| format!("Invalid {GPU_ENV}"))?,
+                software_fallback: false,
+                ..Default::default()
+            }),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Returns the adapter matching the selection
+    pub fn select_adapter(&self) -> Result<SelectedAdapter> {
+        let backends = self.backends();
+        let mut adapters: Vec<AdapterInfo> = self
+            .instance()
+            .enumerate_adapters(backends)
+            .iter()
+            .map(|adapter| adapter.get_info())
+            .collect();
+        // Discrete first, software last
+        adapters.sort_by_key(|info| match info.device_type {
+            DeviceType::DiscreteGpu => 0,
+            DeviceType::IntegratedGpu => 1,
+            DeviceType::VirtualGpu => 2,
+            DeviceType::Other => 3,
+            DeviceType::Cpu => 4,
+        });
+
+        let selected = |info: &AdapterInfo, software_fallback| SelectedAdapter {
+            info: info.clone(),
+            software_fallback,
+            selection: self.selection.clone(),
+        };
+        if let Some(info) = adapters.iter().find(|info| self.selection.matches(info)) {
+            return Ok(selected(info, false));
+        }
+        if self.software_fallback {
+            if let Some(info) = adapters
+                .iter()
+                .find(|info| GpuSelection::Software.matches(info))
+            {
+                return Ok(selected(info, true));
+            }
+        }
+
+        let available = if adapters.is_empty() {
+            "none".to_string()
+        } else {
+            adapters
+                .iter()
+                .map(describe_adapter)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        Err(anyhow!(
+            "No GPU adapter matching {:?} with the backends {:?}, available: {}. In a \
+                container, give it the GPU (--gpus all with the NVIDIA Container Toolkit, or \
+                --device /dev/dri), or install Mesa for a software rasterizer (lavapipe in \
+                mesa-vulkan-drivers, llvmpipe in libgl1-mesa-dri)",
+            self.selection,
+            backends,
+            available
+        ))
+    }
+
+    /// Creates the renderer on the selected adapter, to be set on the `RenderPlugin`, and
+    /// returns the adapter to be inserted as a resource
+    pub fn render_creation(&self) -> Result<(RenderCreation, SelectedAdapter)> {
+        let adapter = self.select_adapter()?;
+        let selected = &adapter.info;
+
+        let instance = self.instance();
+        let wgpu_settings = WgpuSettings {
+            backends: Some(selected.backend.into()),
+            ..self.wgpu.clone()
+        };
+        let request_adapter_options = wgpu::RequestAdapterOptions {
+            power_preference: wgpu_settings.power_preference,
+            force_fallback_adapter: selected.device_type == DeviceType::Cpu,
+            compatible_surface: None,
+        };
+        // The adapter is found again by its name, on the backend of the selected one
+        let (device, queue, info, render_adapter) = bevy_tasks::block_on(initialize_renderer(
+            &instance,
+            &wgpu_settings,
+            &request_adapter_options,
+            Some(selected.name.to_lowercase()),
+        ));
+        if info.name != selected.name {
+            bail!(
+                "The renderer was created on {} instead of the selected {}",
+                describe_adapter(&info),
+                describe_adapter(selected)
+            );
+        }
+
+        let render_creation = RenderCreation::Manual(RenderResources(
+            device,
+            queue,
+            info,
+            render_adapter,
+            RenderInstance(Arc::new(WgpuWrapper::new(instance))),
+        ));
+        Ok((render_creation, adapter))
+    }
+
+    fn backends(&self) -> Backends {
+        self.wgpu.backends.unwrap_or(Backends::all())
+    }
+
+    fn instance(&self) -> wgpu::Instance {
+        wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: self.backends(),
+            flags: self.wgpu.instance_flags,
+            ..Default::default()
+        })
+    }
+}
+
+/// This system logs the adapter selected by `GpuSettings`, when inserted by the app
+pub(crate) fn log_selected_adapter(adapter: Option<Res<SelectedAdapter>>) {
+    let Some(adapter) = adapter else {
+        return;
+    };
+    if adapter.software_fallback {
+        warn!(
+            "No GPU matching {:?}, falling back to the software rasterizer {}",
+            adapter.selection, adapter.info.name
+        );
+    }
+    info!("Rendering with the GPU {}", describe_adapter(&adapter.info));
+}
+
+/// Returns the name, PCI ids, type and backend of an adapter, for the logs
+fn describe_adapter(info: &AdapterInfo) -> String {
+    format!(
+        "{} [{:04x}:{:04x}] ({:?}, {:?})",
+        info.name, info.vendor, info.device, info.device_type, info.backend
+    )
+}
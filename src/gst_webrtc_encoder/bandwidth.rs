@@ -0,0 +1,69 @@
+use bevy_platform::collections::HashMap;
+use gst::prelude::*;
+use gstrswebrtc::webrtcsink::BaseWebRTCSink;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::encoder::EncoderStats;
+
+/// Interval between two reads of the sink stats
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Returns the bytes sent by the outbound RTP streams found in the stats of a consumer
+fn outbound_bytes_sent(structure: &gst::StructureRef) -> u64 {
+    structure
+        .iter()
+        .filter_map(|(_, value)| value.get::<gst::Structure>().ok())
+        .map(|stats| {
+            if stats.name() == "rtp-outbound-stream-stats" {
+                stats.get::<u64>("bytes-sent").unwrap_or_default()
+            } else {
+                outbound_bytes_sent(&stats)
+            }
+        })
+        .sum()
+}
+
+/// Updates the bytes sent by `webrtcsink` in `stats` until the sink is disposed.
+///
+/// The bytes sent to the peers which left are kept in the total, up to the last read of the
+/// stats before they left.
+pub(crate) fn track_bandwidth(webrtcsink: &BaseWebRTCSink, stats: &Arc<Mutex<EncoderStats>>) {
+    let sink = webrtcsink.downgrade();
+    let stats = Arc::downgrade(stats);
+    std::thread::spawn(move || {
+        let mut departed = 0;
+        let mut peers = HashMap::<String, u64>::new();
+
+        loop {
+            std::thread::sleep(STATS_INTERVAL);
+
+            let (Some(sink), Some(stats)) = (sink.upgrade(), stats.upgrade()) else {
+                break;
+            };
+
+            // One field per session
+            let sink_stats = sink.property::<gst::Structure>("stats");
+            let current = sink_stats
+                .iter()
+                .filter_map(|(session_id, value)| {
+                    let consumer_stats = value.get::<gst::Structure>().ok()?;
+                    Some((session_id.to_string(), outbound_bytes_sent(&consumer_stats)))
+                })
+                .collect::<HashMap<_, _>>();
+
+            departed += peers
+                .iter()
+                .filter(|(session_id, _)| !current.contains_key(*session_id))
+                .map(|(_, bytes)| bytes)
+                .sum::<u64>();
+            peers = current;
+
+            let mut stats = stats.lock().unwrap();
+            stats.bytes_sent = departed + peers.values().sum::<u64>();
+            stats.peer_bytes_sent = peers.clone();
+        }
+    });
+}
@@ -0,0 +1,87 @@
+use anyhow::{Context, Result, bail};
+use bevy_log::prelude::*;
+use gst::prelude::*;
+use gstrswebrtc::webrtcsink::BaseWebRTCSink;
+use std::{fs, path::Path};
+
+use crate::DtlsCertificate;
+
+/// Returns the PEM of the certificate and its private key, generating the file if needed
+pub(crate) fn certificate_pem(certificate: &DtlsCertificate) -> Result<String> {
+    match certificate {
+        DtlsCertificate::Pem(pem) => check_pem(pem.clone()),
+        DtlsCertificate::File(path) if path.exists() => fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(check_pem)
+            .with_context(|| format!("Unable to read the DTLS certificate {}", path.display())),
+        DtlsCertificate::File(path) => {
+            let pem = generate_pem()?;
+            write_private(path, &pem).with_context(|| {
+                format!("Unable to write the DTLS certificate {}", path.display())
+            })?;
+            info!("Generated the DTLS certificate {}", path.display());
+            Ok(pem)
+        }
+    }
+}
+
+/// Checks that the PEM has the private key of the certificate, which the DTLS elements
+/// need too
+fn check_pem(pem: String) -> Result<String> {
+    if !pem.contains("-----BEGIN CERTIFICATE-----") {
+        bail!("No certificate in the PEM");
+    }
+    if !pem.contains("PRIVATE KEY-----") {
+        bail!("No private key in the PEM, it must contain the certificate and its key");
+    }
+    Ok(pem)
+}
+
+/// Generates a self-signed certificate and its ECDSA P-256 private key
+fn generate_pem() -> Result<String> {
+    let rcgen::CertifiedKey { cert, key_pair } =
+        rcgen::generate_simple_self_signed(vec!["bevy_streaming".to_string()])
+            .context("Unable to generate the DTLS certificate")?;
+    Ok(format!("{}{}", cert.pem(), key_pair.serialize_pem()))
+}
+
+/// Writes the private key readable only by the user
+fn write_private(path: &Path, pem: &str) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    std::io::Write::write_all(&mut options.open(path)?, pem.as_bytes())
+}
+
+/// Sets the DTLS certificate of the transports of the sessions of `webrtcsink`
+pub(crate) fn use_certificate(webrtcsink: &BaseWebRTCSink, pem: String) {
+    // The transports are created with the transceivers, the certificate must be set before
+    // the DTLS elements start
+    webrtcsink.connect_closure(
+        "consumer-added",
+        false,
+        glib::closure!(
+            move |_sink: &BaseWebRTCSink, _peer_id: &str, webrtcbin: &gst::Element| {
+                let Some(bin) = webrtcbin.downcast_ref::<gst::Bin>() else {
+                    return;
+                };
+                for element in bin.iterate_recurse().into_iter().flatten() {
+                    set_pem(&element, &pem);
+                }
+                let pem = pem.clone();
+                bin.connect_deep_element_added(move |_, _, element| set_pem(element, &pem));
+            }
+        ),
+    );
+}
+
+fn set_pem(element: &gst::Element, pem: &str) {
+    let factory = element.factory().map(|factory| factory.name());
+    if factory.as_deref() == Some("dtlssrtpdec") {
+        element.set_property("pem", pem);
+    }
+}
@@ -0,0 +1,41 @@
+use anyhow::{Context, Result};
+use gst::prelude::*;
+use gstrswebrtc::signaller::Signallable;
+
+/// Element owning the signaller of the Janus VideoRoom plugin
+const JANUS_SINK: &str = "janusvrwebrtcsink";
+
+/// Returns a signaller publishing to a room of the Janus VideoRoom plugin.
+///
+/// The Janus signaller is private to gstrswebrtc, so it is taken from a `janusvrwebrtcsink`
+/// element. The statically linked plugin is registered if GStreamer doesn't find the element.
+pub(crate) fn signaller(
+    uri: &str,
+    room_id: u64,
+    feed_id: Option<u64>,
+    display_name: Option<&str>,
+    secret_key: Option<&str>,
+) -> Result<Signallable> {
+    if gst::ElementFactory::find(JANUS_SINK).is_none() {
+        gstrswebrtc::plugin_register_static().context("Unable to register the webrtc plugin")?;
+    }
+    let sink = gst::ElementFactory::make(JANUS_SINK)
+        .build()
+        .context("The janusvrwebrtcsink element is not available")?;
+    let signaller = sink.property::<Signallable>("signaller");
+
+    // The ids are strings or integers depending on the version of the signaller
+    signaller.set_property_from_str("janus-endpoint", uri);
+    signaller.set_property_from_str("room-id", &room_id.to_string());
+    if let Some(feed_id) = feed_id {
+        signaller.set_property_from_str("feed-id", &feed_id.to_string());
+    }
+    if let Some(display_name) = display_name {
+        signaller.set_property("display-name", display_name);
+    }
+    if let Some(secret_key) = secret_key {
+        signaller.set_property("secret-key", secret_key);
+    }
+
+    Ok(signaller)
+}
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{Result, bail};
 use bevy_log::prelude::*;
 use derive_more::derive::{Display, Error};
 use gst::prelude::*;
@@ -6,10 +6,27 @@ use gstrswebrtc::{
     signaller::{Signallable, Signaller},
     webrtcsink::{self, BaseWebRTCSink, WebRTCSinkCongestionControl},
 };
+use std::sync::{Arc, Mutex};
 
 #[cfg(feature = "pixelstreaming")]
 use crate::pixelstreaming::signaller::UePsSignaller;
-use crate::{CongestionControl, SignallingServer, GstWebRtcSettings, encoder::StreamEncoder};
+use crate::{
+    CongestionControl, EncodedCodec, GstWebRtcSettings, HostAudio, SignallingServer, VideoFilters,
+    auth::SessionGate,
+    encoder::{
+        EncoderStats, Frame, FrameTimestamps, StreamEncoder, pipeline_latency,
+        request_appsrc_keyframe, resize_appsrc,
+    },
+};
+
+mod bandwidth;
+mod dtls;
+#[cfg(feature = "janus")]
+mod janus;
+mod playout_delay;
+mod rtp;
+#[cfg(feature = "whip")]
+mod whip;
 
 #[derive(Debug, Display, Error)]
 #[display("Received error from {src}: {error} (debug: {debug:?})")]
@@ -19,75 +36,375 @@ struct ErrorMessage {
     debug: Option<glib::GString>,
 }
 
-impl Into<Signallable> for &SignallingServer {
-    fn into(self) -> Signallable {
-        match self {
-            SignallingServer::GstWebRtc { uri, peer_id } => {
+impl TryFrom<&SignallingServer> for Signallable {
+    type Error = anyhow::Error;
+
+    fn try_from(signalling_server: &SignallingServer) -> Result<Self> {
+        Ok(match signalling_server {
+            SignallingServer::GstWebRtc {
+                uri,
+                peer_id,
+                cafile,
+                insecure_tls,
+            } => {
                 let signaller = Signaller::default();
                 signaller.set_property_from_str("uri", uri);
                 if let Some(peer_id) = peer_id {
                     signaller.set_property_from_str("peer-id", peer_id);
                 }
+                signaller.set_property("cafile", cafile.to_value());
+                signaller.set_property("insecure-tls", *insecure_tls);
                 signaller.upcast()
             }
             #[cfg(feature = "pixelstreaming")]
-            SignallingServer::PixelStreaming { uri, streamer_id } => {
+            SignallingServer::PixelStreaming {
+                uri,
+                streamer_id,
+                proxy,
+                headers,
+                cafile,
+                insecure_tls,
+            } => {
                 let signaller = UePsSignaller::default();
                 signaller.set_property_from_str("uri", uri);
                 if let Some(streamer_id) = streamer_id {
                     signaller.set_property_from_str("streamer-id", streamer_id);
                 }
+                if let Some(proxy) = proxy {
+                    signaller.set_property_from_str("proxy", proxy);
+                }
+                crate::settings::check_headers(headers)?;
+                if !headers.is_empty() {
+                    let headers = headers
+                        .iter()
+                        .fold(
+                            gst::Structure::builder("headers"),
+                            |builder, (name, value)| builder.field(name.as_str(), value.as_str()),
+                        )
+                        .build();
+                    signaller.set_property("headers", headers);
+                }
+                signaller.set_property("cafile", cafile.to_value());
+                signaller.set_property("insecure-tls", *insecure_tls);
                 signaller.upcast()
             }
-        }
+            #[cfg(feature = "janus")]
+            SignallingServer::Janus {
+                uri,
+                room_id,
+                feed_id,
+                display_name,
+                secret_key,
+            } => janus::signaller(
+                uri,
+                *room_id,
+                *feed_id,
+                display_name.as_deref(),
+                secret_key.as_deref(),
+            )?,
+            #[cfg(feature = "whip")]
+            SignallingServer::Whip { endpoint, token } => {
+                whip::signaller(endpoint, token.as_deref())?
+            }
+        })
     }
 }
 
+/// Frames pushed to the main video track of a `GstWebRtcEncoder`
+#[derive(Clone, Copy)]
+enum VideoInput {
+    /// RGBA frames, encoded by `webrtcsink`
+    Raw,
+    Encoded(EncodedCodec),
+}
+
 #[derive(Clone)]
 pub struct GstWebRtcEncoder {
     #[allow(dead_code)]
     settings: GstWebRtcSettings,
     pipeline: gst::Pipeline,
-    pub appsrc: gst_app::AppSrc,
+    /// Source of the main video track, `None` for audio-only streamers
+    pub appsrc: Option<gst_app::AppSrc>,
+    /// Codec of the frames pushed to `appsrc`, `None` for raw RGBA frames
+    codec: Option<EncodedCodec>,
+    timestamps: Arc<FrameTimestamps>,
     pub webrtcsink: BaseWebRTCSink,
+    stats: Arc<Mutex<EncoderStats>>,
 }
 
-impl GstWebRtcEncoder {
-    pub fn with_settings(settings: GstWebRtcSettings) -> Result<Self> {
-        gst::init()?;
+/// Returns the `gaussianblur` elements applying `filters`, a positive sigma blurs and a
+/// negative one sharpens
+fn video_filter_elements(filters: &VideoFilters) -> Result<Vec<gst::Element>> {
+    [filters.denoise, filters.sharpen.map(|sharpen| -sharpen)]
+        .into_iter()
+        .flatten()
+        .map(|sigma| {
+            Ok(gst::ElementFactory::make("gaussianblur")
+                .property("sigma", sigma.clamp(-20.0, 20.0) as f64)
+                .build()?)
+        })
+        .collect()
+}
 
-        let pipeline = gst::Pipeline::default();
+/// Enables the intra refresh of the encoders created by `webrtcsink`, spread over `period`
+/// frames
+fn configure_intra_refresh(webrtcsink: &BaseWebRTCSink, period: u32) {
+    // The encoders are set up by webrtcsink before this signal is emitted, false lets it
+    // complete their setup
+    webrtcsink.connect_closure(
+        "encoder-setup",
+        false,
+        glib::closure!(move |_sink: &BaseWebRTCSink,
+                             _consumer_id: Option<&str>,
+                             _pad_name: &str,
+                             encoder: &gst::Element|
+              -> bool {
+            let factory = encoder.factory().map(|factory| factory.name());
+            match factory.as_deref() {
+                Some("x264enc") => {
+                    encoder.set_property("intra-refresh", true);
+                    encoder.set_property("key-int-max", period);
+                }
+                Some("x265enc") => {
+                    let options = encoder.property::<Option<String>>("option-string");
+                    let refresh = format!("intra-refresh=1:keyint={period}");
+                    encoder.set_property(
+                        "option-string",
+                        match options.filter(|options| !options.is_empty()) {
+                            Some(options) => format!("{options}:{refresh}"),
+                            None => refresh,
+                        },
+                    );
+                }
+                _ => debug!(
+                    "Intra refresh is not supported by the encoder {:?}",
+                    factory
+                ),
+            }
+            false
+        }),
+    );
+}
 
-        // Specify the format we want to provide as application into the pipeline
-        // by creating a video info with the given format and creating caps from it for the appsrc element.
-        let video_info = gst_video::VideoInfo::builder(
-            gst_video::VideoFormat::Rgba,
-            settings.width,
-            settings.height,
-        )
+/// Adds an `appsrc ! videoconvert` branch to the pipeline, followed by the `filters`, linked to
+/// a new video pad of `webrtcsink`
+fn add_video_source(
+    pipeline: &gst::Pipeline,
+    webrtcsink: &BaseWebRTCSink,
+    name: &str,
+    width: u32,
+    height: u32,
+    filters: &VideoFilters,
+) -> Result<gst_app::AppSrc> {
+    // Specify the format we want to provide as application into the pipeline
+    // by creating a video info with the given format and creating caps from it for the appsrc element.
+    let video_info = gst_video::VideoInfo::builder(gst_video::VideoFormat::Rgba, width, height)
         .build()
         .expect("Failed to create video info");
 
-        let appsrc = gst_app::AppSrc::builder()
-            .name("appsrc")
-            .do_timestamp(true)
-            .is_live(true)
-            .caps(&video_info.to_caps().unwrap())
-            .format(gst::Format::Bytes)
-            // Allocate space for 1 buffer
-            .max_bytes((settings.width * settings.height * 4).into())
-            .build();
+    let appsrc = gst_app::AppSrc::builder()
+        .name(name)
+        .do_timestamp(true)
+        .is_live(true)
+        .caps(&video_info.to_caps().unwrap())
+        .format(gst::Format::Time)
+        // Allocate space for 1 buffer
+        .max_bytes((width * height * 4).into())
+        .build();
+
+    // let queue = gst::ElementFactory::make("queue").build()?;
+    // queue.set_property_from_str("leaky", "downstream");
+
+    let videoconvert = gst::ElementFactory::make("videoconvert").build()?;
+    let filters = video_filter_elements(filters)?;
+
+    pipeline.add_many([
+        appsrc.upcast_ref(),
+        // &queue,
+        &videoconvert,
+    ])?;
+    pipeline.add_many(&filters)?;
+    gst::Element::link_many(
+        [
+            appsrc.upcast_ref(),
+            // &queue,
+            &videoconvert,
+        ]
+        .into_iter()
+        .chain(&filters)
+        .chain([webrtcsink.upcast_ref()]),
+    )?;
 
-        // let queue = gst::ElementFactory::make("queue").build()?;
-        // queue.set_property_from_str("leaky", "downstream");
+    Ok(appsrc)
+}
 
-        let videoconvert = gst::ElementFactory::make("videoconvert").build()?;
+impl EncodedCodec {
+    /// Caps of the access units pushed to the appsrc
+    pub(crate) fn caps(&self) -> gst::Caps {
+        let media_type = match self {
+            EncodedCodec::H264 => "video/x-h264",
+            EncodedCodec::H265 => "video/x-h265",
+        };
+        gst::Caps::builder(media_type)
+            .field("stream-format", "byte-stream")
+            .field("alignment", "au")
+            .build()
+    }
 
-        let webrtcsink =
-            webrtcsink::BaseWebRTCSink::with_signaller(settings.signalling_server.as_ref().into());
+    /// Name of the parser element of the codec
+    pub(crate) fn parser(&self) -> &'static str {
+        match self {
+            EncodedCodec::H264 => "h264parse",
+            EncodedCodec::H265 => "h265parse",
+        }
+    }
+}
+
+/// Adds an `appsrc ! parser` branch for pre-encoded access units to the pipeline, linked to
+/// a new video pad of `webrtcsink`
+fn add_encoded_source(
+    pipeline: &gst::Pipeline,
+    webrtcsink: &BaseWebRTCSink,
+    name: &str,
+    codec: EncodedCodec,
+) -> Result<gst_app::AppSrc> {
+    let appsrc = gst_app::AppSrc::builder()
+        .name(name)
+        .do_timestamp(true)
+        .is_live(true)
+        .caps(&codec.caps())
+        .format(gst::Format::Time)
+        .build();
+
+    // Repeat the parameter sets before each keyframe, for the peers joining later
+    let parser = gst::ElementFactory::make(codec.parser())
+        .property("config-interval", -1i32)
+        .build()?;
+
+    pipeline.add_many([appsrc.upcast_ref(), &parser])?;
+    gst::Element::link_many([appsrc.upcast_ref(), &parser, webrtcsink.upcast_ref()])?;
+
+    Ok(appsrc)
+}
 
+/// Adds audio branches built from GStreamer descriptions, mixed together if there are several,
+/// linked to a new audio pad of `webrtcsink`
+fn add_audio_sources(
+    pipeline: &gst::Pipeline,
+    webrtcsink: &BaseWebRTCSink,
+    descriptions: &[String],
+) -> Result<()> {
+    let sink = match descriptions.len() {
+        0 => return Ok(()),
+        1 => webrtcsink.clone().upcast::<gst::Element>(),
+        _ => {
+            let audiomixer = gst::ElementFactory::make("audiomixer").build()?;
+            let audioconvert = gst::ElementFactory::make("audioconvert").build()?;
+
+            pipeline.add_many([&audiomixer, &audioconvert])?;
+            gst::Element::link_many([&audiomixer, &audioconvert, webrtcsink.upcast_ref()])?;
+
+            audiomixer
+        }
+    };
+
+    for description in descriptions {
+        let source = gst::parse::bin_from_description(description, true)?;
+        let audioconvert = gst::ElementFactory::make("audioconvert").build()?;
+        let audioresample = gst::ElementFactory::make("audioresample").build()?;
+
+        pipeline.add_many([source.upcast_ref(), &audioconvert, &audioresample])?;
+        gst::Element::link_many([source.upcast_ref(), &audioconvert, &audioresample, &sink])?;
+    }
+
+    Ok(())
+}
+
+impl GstWebRtcEncoder {
+    pub fn with_settings(settings: GstWebRtcSettings) -> Result<Self> {
+        Self::with_extra_tracks(settings, &[]).map(|(encoder, _)| encoder)
+    }
+
+    /// Creates an encoder sending additional video tracks (with the given sizes) in the same
+    /// WebRTC session, so the viewer can switch between them without renegotiating.
+    ///
+    /// The returned tracks are encoders for the additional cameras, they are started along
+    /// with this encoder.
+    pub fn with_extra_tracks(
+        settings: GstWebRtcSettings,
+        extra_tracks: &[(u32, u32)],
+    ) -> Result<(Self, Vec<GstWebRtcTrack>)> {
+        Self::build(settings, Some(VideoInput::Raw), extra_tracks)
+    }
+
+    /// Creates an encoder without any video, streaming only the `audio_source` and `host_audio`
+    /// of the settings (if any) and the data channels.
+    pub fn audio_only(settings: GstWebRtcSettings) -> Result<Self> {
+        Self::build(settings, None, &[]).map(|(encoder, _)| encoder)
+    }
+
+    /// Creates an encoder streaming frames already encoded with `codec`, e.g. by an external
+    /// encoder, instead of RGBA frames.
+    ///
+    /// Each pushed frame is an access unit in byte-stream format. The `video_caps` of the
+    /// settings must accept the codec, and the bitrate is not adapted by the congestion
+    /// control. Keyframe requests of the peers are reported by `connect_keyframe_requested`.
+    pub fn pre_encoded(settings: GstWebRtcSettings, codec: EncodedCodec) -> Result<Self> {
+        gst::init()?;
         if let Some(video_caps) = &settings.video_caps {
-            webrtcsink.set_property_from_str("video-caps", video_caps);
+            if !video_caps.to_caps().can_intersect(&codec.caps()) {
+                bail!("The video caps {:?} do not accept {:?}", video_caps, codec);
+            }
+        }
+        Self::build(settings, Some(VideoInput::Encoded(codec)), &[]).map(|(encoder, _)| encoder)
+    }
+
+    fn build(
+        settings: GstWebRtcSettings,
+        video: Option<VideoInput>,
+        extra_tracks: &[(u32, u32)],
+    ) -> Result<(Self, Vec<GstWebRtcTrack>)> {
+        gst::init()?;
+
+        let name = settings.stream_name();
+        let pipeline = gst::Pipeline::with_name(&name);
+
+        let signaller: Signallable = settings.signalling_server.as_ref().try_into()?;
+        let bind_addresses = settings
+            .bind_address
+            .as_ref()
+            .map(|bind_address| bind_address.addresses())
+            .transpose()?;
+        if let Some(addresses) = &bind_addresses {
+            if signaller.find_property("bind-address").is_some() {
+                let addresses: Vec<String> = addresses.iter().map(ToString::to_string).collect();
+                signaller.set_property("bind-address", addresses.join(","));
+            } else {
+                warn!(stream = %name, "The signalling connection is not bound to {:?}", addresses);
+            }
+        }
+        let webrtcsink = webrtcsink::BaseWebRTCSink::with_signaller(signaller);
+        webrtcsink.set_property("name", format!("{name}-webrtcsink"));
+
+        if let Some(gate) = SessionGate::from_settings(&settings) {
+            gate.connect(&webrtcsink);
+        }
+        if let Some(munger) = settings.sdp_munger.clone() {
+            munger.connect(name.clone(), &webrtcsink);
+        }
+        if let Some(hook) = settings.consumer_hook.clone() {
+            hook.connect(name.clone(), &webrtcsink);
+        }
+
+        // Expose the name and labels to the consumers
+        let mut meta = gst::Structure::builder("meta").field("name", name.as_str());
+        for (key, value) in &settings.labels {
+            meta = meta.field(key.as_str(), value.as_str());
+        }
+        webrtcsink.set_property("meta", meta.build());
+
+        if let Some(video_caps) = &settings.video_caps {
+            webrtcsink.set_property("video-caps", video_caps.to_caps());
         }
         if let Some(congestion_control) = &settings.congestion_control {
             webrtcsink.set_property(
@@ -102,29 +419,100 @@ impl GstWebRtcEncoder {
             );
         }
 
-        pipeline.add_many([
-            appsrc.upcast_ref(),
-            // &queue,
-            &videoconvert,
-            webrtcsink.upcast_ref(),
-        ])?;
-        gst::Element::link_many([
-            appsrc.upcast_ref(),
-            // &queue,
-            &videoconvert,
-            webrtcsink.upcast_ref(),
-        ])?;
-
-        Ok(Self {
-            settings,
-            pipeline,
-            appsrc,
-            webrtcsink,
-        })
+        rtp::configure_rtp_transport(&webrtcsink, &settings.rtp_transport);
+        if let Some(addresses) = bind_addresses {
+            rtp::bind_ice_candidates(&webrtcsink, addresses);
+        }
+        if let Some(certificate) = &settings.dtls_certificate {
+            dtls::use_certificate(&webrtcsink, dtls::certificate_pem(certificate)?);
+        }
+        if let Some(period) = settings.intra_refresh {
+            configure_intra_refresh(&webrtcsink, period);
+        }
+
+        pipeline.add(&webrtcsink)?;
+
+        let appsrc = match video {
+            Some(VideoInput::Raw) => Some(add_video_source(
+                &pipeline,
+                &webrtcsink,
+                "appsrc",
+                settings.width,
+                settings.height,
+                &settings.video_filters,
+            )?),
+            Some(VideoInput::Encoded(codec)) => {
+                Some(add_encoded_source(&pipeline, &webrtcsink, "appsrc", codec)?)
+            }
+            None => None,
+        };
+        let codec = match video {
+            Some(VideoInput::Encoded(codec)) => Some(codec),
+            _ => None,
+        };
+
+        let audio_sources = settings
+            .audio_source
+            .iter()
+            .cloned()
+            .chain(settings.host_audio.iter().map(HostAudio::description))
+            .collect::<Vec<_>>();
+        add_audio_sources(&pipeline, &webrtcsink, &audio_sources)?;
+
+        let tracks = extra_tracks
+            .iter()
+            .enumerate()
+            .map(|(i, (width, height))| {
+                add_video_source(
+                    &pipeline,
+                    &webrtcsink,
+                    &format!("appsrc{}", i + 1),
+                    *width,
+                    *height,
+                    &settings.video_filters,
+                )
+                .map(|appsrc| GstWebRtcTrack {
+                    appsrc,
+                    timestamps: Arc::default(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if let Some(min_latency) = settings.latency.source_min_latency {
+            let sources = appsrc
+                .iter()
+                .chain(tracks.iter().map(|track| &track.appsrc));
+            for appsrc in sources {
+                appsrc.set_property("min-latency", min_latency.as_nanos() as i64);
+            }
+        }
+        if let Some(sink_latency) = settings.latency.sink_latency {
+            pipeline.set_latency(gst::ClockTime::from_nseconds(sink_latency.as_nanos() as u64));
+        }
+
+        let stats = Arc::new(Mutex::new(EncoderStats {
+            width: settings.width,
+            height: settings.height,
+            ..Default::default()
+        }));
+        bandwidth::track_bandwidth(&webrtcsink, &stats);
+
+        Ok((
+            Self {
+                stats,
+                settings,
+                pipeline,
+                appsrc,
+                codec,
+                timestamps: Arc::default(),
+                webrtcsink,
+            },
+            tracks,
+        ))
     }
 
     pub fn start(&self) -> Result<()> {
-        info!("Start pipeline");
+        info!(stream = %self.pipeline.name(), "Start pipeline");
         self.pipeline.set_state(gst::State::Playing)?;
 
         Ok(())
@@ -161,28 +549,167 @@ impl GstWebRtcEncoder {
         Ok(())
     }
 
+    /// Pushes a buffer timestamped by the appsrc
     pub fn push_buffer(&self, data: &Vec<u8>) -> anyhow::Result<()> {
-        let mut buffer = gst::Buffer::with_size(data.len()).unwrap();
-        {
-            let buffer = buffer.get_mut().unwrap();
-            buffer.copy_from_slice(0, data).unwrap();
-        }
+        let Some(appsrc) = &self.appsrc else {
+            return Err(anyhow::anyhow!("Audio-only stream has no video source"));
+        };
 
-        let _ = self.appsrc.push_buffer(buffer);
+        let _ = appsrc.push_buffer(gst::Buffer::from_slice(data.clone()));
+        self.stats.lock().unwrap().frames_pushed += 1;
+
+        Ok(())
+    }
+
+    /// Pushes a frame, with the frame id stored as the buffer offset (the frame number for
+    /// video) and its pts mapped to the running time
+    fn push_frame_buffer(&self, frame: &Frame) -> anyhow::Result<()> {
+        let Some(appsrc) = &self.appsrc else {
+            return Err(anyhow::anyhow!("Audio-only stream has no video source"));
+        };
+
+        // The access units of pre-encoded streams have no rows
+        let buffer = match self.codec {
+            Some(_) => self.timestamps.buffer(appsrc, frame),
+            None => self.timestamps.video_buffer(appsrc, frame)?,
+        };
+        let _ = appsrc.push_buffer(buffer);
+        self.stats.lock().unwrap().frames_pushed += 1;
 
         Ok(())
     }
+
+    /// Calls `callback` when a keyframe is requested, e.g. when a peer joins, so that a
+    /// pre-encoded stream can produce one
+    pub fn connect_keyframe_requested<F: Fn() + Send + Sync + 'static>(&self, callback: F) {
+        let Some(pad) = self
+            .appsrc
+            .as_ref()
+            .and_then(|appsrc| appsrc.static_pad("src"))
+        else {
+            return;
+        };
+        pad.add_probe(gst::PadProbeType::EVENT_UPSTREAM, move |_pad, info| {
+            if let Some(gst::PadProbeData::Event(event)) = &info.data {
+                if gst_video::UpstreamForceKeyUnitEvent::parse(event).is_ok() {
+                    callback();
+                }
+            }
+            gst::PadProbeReturn::Ok
+        });
+    }
+
     pub fn finish(self: Box<Self>) {
         self.pipeline.set_state(gst::State::Null).unwrap();
     }
 }
 
 impl StreamEncoder for GstWebRtcEncoder {
-    fn push_frame(&self, frame_data: &[u8]) -> Result<()> {
-        self.push_buffer(&frame_data.to_vec())
+    fn push_frame(&self, frame: &Frame) -> Result<()> {
+        self.push_frame_buffer(frame)
     }
 
     fn start(&self) -> Result<()> {
         GstWebRtcEncoder::start(self)
     }
+
+    fn stop(&self) -> Result<()> {
+        info!(stream = %self.pipeline.name(), "Stop pipeline");
+        self.pipeline.set_state(gst::State::Null)?;
+
+        Ok(())
+    }
+
+    fn set_standby(&self, standby: bool) -> Result<()> {
+        // Paused, the signaller stays connected and the next viewer ends the standby
+        let state = if standby {
+            gst::State::Paused
+        } else {
+            gst::State::Playing
+        };
+        debug!(stream = %self.pipeline.name(), "Set pipeline to {:?}", state);
+        self.pipeline.set_state(state)?;
+
+        Ok(())
+    }
+
+    fn resize(&self, width: u32, height: u32) -> Result<()> {
+        let appsrc = self
+            .appsrc
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Audio-only stream has no video source"))?;
+        if self.codec.is_some() {
+            return Err(anyhow::anyhow!(
+                "The size of a pre-encoded stream is set by its encoder"
+            ));
+        }
+        resize_appsrc(appsrc, width, height)?;
+        appsrc.set_max_bytes((width * height * 4).into());
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.width = width;
+        stats.height = height;
+
+        Ok(())
+    }
+
+    /// Sets the start and maximum bitrates of `webrtcsink`, the congestion control (if any)
+    /// adapts the bitrate of each session below the maximum
+    fn set_bitrate(&self, bitrate: u32) -> Result<()> {
+        self.webrtcsink.set_property("start-bitrate", bitrate);
+        self.webrtcsink.set_property("max-bitrate", bitrate);
+        self.stats.lock().unwrap().bitrate = Some(bitrate);
+
+        Ok(())
+    }
+
+    fn request_keyframe(&self) -> Result<()> {
+        let appsrc = self
+            .appsrc
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Audio-only stream has no video source"))?;
+        request_appsrc_keyframe(appsrc)
+    }
+
+    fn stats(&self) -> Option<EncoderStats> {
+        let mut stats = self.stats.lock().unwrap().clone();
+        stats.latency = pipeline_latency(&self.pipeline);
+        Some(stats)
+    }
+
+    fn pipeline_state(&self) -> Option<gst::State> {
+        Some(self.pipeline.current_state())
+    }
+}
+
+/// An additional video track of a `GstWebRtcEncoder` session
+#[derive(Clone)]
+pub struct GstWebRtcTrack {
+    pub appsrc: gst_app::AppSrc,
+    timestamps: Arc<FrameTimestamps>,
+}
+
+impl StreamEncoder for GstWebRtcTrack {
+    fn push_frame(&self, frame: &Frame) -> Result<()> {
+        let buffer = self.timestamps.video_buffer(&self.appsrc, frame)?;
+        let _ = self.appsrc.push_buffer(buffer);
+
+        Ok(())
+    }
+
+    fn start(&self) -> Result<()> {
+        // Started with the session
+        Ok(())
+    }
+
+    fn resize(&self, width: u32, height: u32) -> Result<()> {
+        resize_appsrc(&self.appsrc, width, height)?;
+        self.appsrc.set_max_bytes((width * height * 4).into());
+
+        Ok(())
+    }
+
+    fn request_keyframe(&self) -> Result<()> {
+        request_appsrc_keyframe(&self.appsrc)
+    }
 }
@@ -0,0 +1,123 @@
+use gst::{glib, prelude::*, subclass::prelude::*};
+use gst_rtp::subclass::prelude::*;
+use std::sync::{
+    LazyLock,
+    atomic::{AtomicU32, Ordering},
+};
+
+/// Largest delay of the extension in milliseconds, 12 bits in units of 10 ms
+pub(super) const MAX_DELAY: u32 = 0xfff * 10;
+
+#[derive(Default)]
+pub struct PlayoutDelayExtension {
+    /// Delays in milliseconds, 0 renders the frames as soon as they are decoded
+    min_delay: AtomicU32,
+    max_delay: AtomicU32,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for PlayoutDelayExtension {
+    const NAME: &'static str = "GstRTPHeaderExtensionPlayoutDelay";
+    type Type = super::PlayoutDelayExtension;
+    type ParentType = gst_rtp::RTPHeaderExtension;
+}
+
+impl ObjectImpl for PlayoutDelayExtension {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPS: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecUInt::builder("min-delay")
+                    .nick("Minimum delay")
+                    .blurb("Minimum delay before rendering the frames in milliseconds")
+                    .maximum(MAX_DELAY)
+                    .build(),
+                glib::ParamSpecUInt::builder("max-delay")
+                    .nick("Maximum delay")
+                    .blurb("Maximum delay before rendering the frames in milliseconds")
+                    .maximum(MAX_DELAY)
+                    .build(),
+            ]
+        });
+
+        PROPS.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let delay = value.get::<u32>().expect("type checked upstream");
+        match pspec.name() {
+            "min-delay" => self.min_delay.store(delay, Ordering::Relaxed),
+            "max-delay" => self.max_delay.store(delay, Ordering::Relaxed),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            "min-delay" => self.min_delay.load(Ordering::Relaxed).to_value(),
+            "max-delay" => self.max_delay.load(Ordering::Relaxed).to_value(),
+            _ => unimplemented!(),
+        }
+    }
+}
+
+impl GstObjectImpl for PlayoutDelayExtension {}
+
+impl ElementImpl for PlayoutDelayExtension {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "RTP playout delay header extension",
+                "Network/Extension/RTPHeader",
+                "Asks the receivers to render the frames within a delay",
+                "Romain Lamarche",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+}
+
+impl RTPHeaderExtensionImpl for PlayoutDelayExtension {
+    const URI: &'static str = crate::RtpHeaderExtension::PLAYOUT_DELAY;
+
+    fn supported_flags(&self) -> gst_rtp::RTPHeaderExtensionFlags {
+        gst_rtp::RTPHeaderExtensionFlags::ONE_BYTE | gst_rtp::RTPHeaderExtensionFlags::TWO_BYTE
+    }
+
+    fn max_size(&self, _input: &gst::BufferRef) -> usize {
+        3
+    }
+
+    /// Writes the delays in units of 10 ms, on 12 bits each
+    fn write(
+        &self,
+        _input: &gst::BufferRef,
+        _write_flags: gst_rtp::RTPHeaderExtensionFlags,
+        _output: &gst::BufferRef,
+        output_data: &mut [u8],
+    ) -> Result<usize, gst::LoggableError> {
+        if output_data.len() < 3 {
+            return Err(gst::loggable_error!(
+                gst::CAT_RUST,
+                "No room for the playout delay"
+            ));
+        }
+        let min = (self.min_delay.load(Ordering::Relaxed) / 10).min(0xfff);
+        let max = (self.max_delay.load(Ordering::Relaxed) / 10).min(0xfff);
+        output_data[0] = (min >> 4) as u8;
+        output_data[1] = (((min & 0xf) << 4) | (max >> 8)) as u8;
+        output_data[2] = (max & 0xff) as u8;
+
+        Ok(3)
+    }
+
+    /// The delays are only sent, the received ones are ignored
+    fn read(
+        &self,
+        _read_flags: gst_rtp::RTPHeaderExtensionFlags,
+        _input_data: &[u8],
+        _output: &mut gst::BufferRef,
+    ) -> Result<(), gst::LoggableError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+use gst::{glib, prelude::*};
+use gst_rtp::prelude::*;
+use std::{sync::LazyLock, time::Duration};
+
+mod imp;
+
+glib::wrapper! {
+    /// The playout-delay RTP header extension, asking the receivers to render the frames
+    /// within a delay, registered as `rtphdrextplayoutdelay`
+    pub(crate) struct PlayoutDelayExtension(ObjectSubclass<imp::PlayoutDelayExtension>)
+        @extends gst_rtp::RTPHeaderExtension, gst::Element, gst::Object;
+}
+
+/// Registers the element once, so that it is found by its URI
+pub(crate) fn register() -> Result<(), glib::BoolError> {
+    static REGISTERED: LazyLock<Result<(), glib::BoolError>> = LazyLock::new(|| {
+        gst::Element::register(
+            None,
+            "rtphdrextplayoutdelay",
+            gst::Rank::MARGINAL,
+            PlayoutDelayExtension::static_type(),
+        )
+    });
+    REGISTERED.clone()
+}
+
+impl PlayoutDelayExtension {
+    /// Returns an extension with the id `id`, asking to render the frames after at most
+    /// `max_delay`, clamped to the 40.95 s the extension can carry
+    pub(crate) fn new(id: u32, max_delay: Duration) -> Self {
+        let max_delay = max_delay.as_millis().min(imp::MAX_DELAY as u128) as u32;
+        let extension: Self = glib::Object::builder()
+            .property("max-delay", max_delay)
+            .build();
+        extension.set_id(id);
+        extension
+    }
+}
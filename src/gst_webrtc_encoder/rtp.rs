@@ -0,0 +1,254 @@
+use bevy_log::prelude::*;
+use gst::prelude::*;
+use gst_rtp::prelude::*;
+use gstrswebrtc::webrtcsink::BaseWebRTCSink;
+use std::{net::IpAddr, time::Duration};
+
+use super::playout_delay::{self, PlayoutDelayExtension};
+use crate::{RtpHeaderExtension, RtpPriority, RtpTransportSettings, VideoCodec};
+
+impl From<RtpPriority> for gst_webrtc::WebRTCPriorityType {
+    fn from(priority: RtpPriority) -> Self {
+        match priority {
+            RtpPriority::VeryLow => gst_webrtc::WebRTCPriorityType::VeryLow,
+            RtpPriority::Low => gst_webrtc::WebRTCPriorityType::Low,
+            RtpPriority::Medium => gst_webrtc::WebRTCPriorityType::Medium,
+            RtpPriority::High => gst_webrtc::WebRTCPriorityType::High,
+        }
+    }
+}
+
+/// Sets the priority of the senders of a transceiver, which marks their packets with the
+/// matching DSCP value
+fn set_transceiver_priority(transceiver: &gst_webrtc::WebRTCRTPTransceiver, priority: RtpPriority) {
+    if let Some(sender) = transceiver.sender() {
+        sender.set_priority(priority.into());
+    }
+}
+
+/// Limits the rate control buffer of an encoder to `pacing`, which bounds the bursts of
+/// packets sent after keyframes
+fn set_rate_control_buffer(encoder: &gst::Element, pacing: Duration) {
+    let millis = pacing.as_millis() as u32;
+    let factory = encoder.factory().map(|factory| factory.name());
+
+    match factory.as_deref() {
+        Some("x264enc") => encoder.set_property("vbv-buf-capacity", millis),
+        Some("vp8enc" | "vp9enc") => encoder.set_property("buffer-size", millis as i32),
+        Some("nvh264enc" | "nvh265enc") => {
+            // The buffer size is in kbits, from the bitrate in kbit/s
+            let bitrate = encoder.property::<u32>("bitrate");
+            encoder.set_property("vbv-buffer-size", bitrate * millis / 1000);
+        }
+        _ => debug!("Pacing is not supported by the encoder {:?}", factory),
+    }
+}
+
+/// Returns the codec of the packets of a video payloader
+fn payloader_codec(payloader: &gst::Element) -> Option<VideoCodec> {
+    let factory = payloader.factory().map(|factory| factory.name());
+    match factory.as_deref() {
+        Some("rtph264pay") => Some(VideoCodec::H264),
+        Some("rtph265pay") => Some(VideoCodec::H265),
+        Some("rtpvp8pay") => Some(VideoCodec::Vp8),
+        Some("rtpvp9pay") => Some(VideoCodec::Vp9),
+        Some("rtpav1pay") => Some(VideoCodec::Av1),
+        _ => None,
+    }
+}
+
+/// Replaces the header extensions of a payloader by `extensions`
+fn set_header_extensions(payloader: &gst::Element, extensions: &[RtpHeaderExtension]) {
+    payloader.emit_by_name::<()>("clear-extensions", &[]);
+    for extension in extensions {
+        let Some(header_extension) = gst_rtp::RTPHeaderExtension::create_from_uri(&extension.uri)
+        else {
+            warn!(
+                "No element implements the RTP header extension {}",
+                extension.uri
+            );
+            continue;
+        };
+        header_extension.set_id(extension.id as u32);
+        payloader.emit_by_name::<()>("add-extension", &[&header_extension]);
+    }
+}
+
+/// Adds the playout-delay header extension to a payloader, with the first id not used by its
+/// other extensions
+fn add_playout_delay(payloader: &gst::Element, max_delay: Duration) {
+    let extensions = payloader.property::<gst::Array>("extensions");
+    let used_ids: Vec<u32> = extensions
+        .iter()
+        .filter_map(|extension| extension.get::<gst_rtp::RTPHeaderExtension>().ok())
+        .map(|extension| extension.id())
+        .collect();
+    // The ids of the one-byte headers, supported by every receiver
+    let Some(id) = (1..=14).find(|id| !used_ids.contains(id)) else {
+        warn!("No RTP header extension id left for the playout delay");
+        return;
+    };
+    let extension = PlayoutDelayExtension::new(id, max_delay);
+    payloader.emit_by_name::<()>("add-extension", &[&extension]);
+}
+
+/// Applies the `RtpTransportSettings` to the sessions of `webrtcsink`
+pub(crate) fn configure_rtp_transport(
+    webrtcsink: &BaseWebRTCSink,
+    settings: &RtpTransportSettings,
+) {
+    // The ICE agent gathers the candidates once the offer is created, after this signal
+    if let Some(port_range) = settings.port_range.clone() {
+        webrtcsink.connect_closure(
+            "consumer-added",
+            false,
+            glib::closure!(move |_sink: &BaseWebRTCSink,
+                                 _peer_id: &str,
+                                 webrtcbin: &gst::Element| {
+                let ice = webrtcbin.property::<gst_webrtc::WebRTCICE>("ice-agent");
+                ice.set_property("min-rtp-port", *port_range.start() as u32);
+                ice.set_property("max-rtp-port", *port_range.end() as u32);
+            }),
+        );
+    }
+
+    if let Some(priority) = settings.priority {
+        webrtcsink.connect_closure(
+            "consumer-added",
+            false,
+            glib::closure!(move |_sink: &BaseWebRTCSink,
+                                 _peer_id: &str,
+                                 webrtcbin: &gst::Element| {
+                let mut index = 0;
+                while let Some(transceiver) = webrtcbin
+                    .emit_by_name::<Option<gst_webrtc::WebRTCRTPTransceiver>>(
+                        "get-transceiver",
+                        &[&index],
+                    )
+                {
+                    set_transceiver_priority(&transceiver, priority);
+                    index += 1;
+                }
+
+                webrtcbin.connect_closure(
+                    "on-new-transceiver",
+                    false,
+                    glib::closure!(move |_webrtcbin: &gst::Element,
+                                         transceiver: &gst_webrtc::WebRTCRTPTransceiver| {
+                        set_transceiver_priority(transceiver, priority);
+                    }),
+                );
+            }),
+        );
+    }
+
+    // Lets the extensions find the playout-delay element by its URI
+    if settings.header_extensions.is_some() || settings.playout_delay.is_some() {
+        if let Err(err) = playout_delay::register() {
+            warn!(
+                "Unable to register the playout delay header extension: {}",
+                err
+            );
+        }
+    }
+
+    // The payloaders and encoders are set up by webrtcsink before these signals are emitted,
+    // false lets it complete their setup
+    if settings.header_extensions.is_some() || !settings.payload_types.is_empty() {
+        let header_extensions = settings.header_extensions.clone();
+        let payload_types = settings.payload_types.clone();
+        webrtcsink.connect_closure(
+            "payloader-setup",
+            false,
+            glib::closure!(move |_sink: &BaseWebRTCSink,
+                                 _consumer_id: Option<&str>,
+                                 _pad_name: &str,
+                                 payloader: &gst::Element|
+                  -> bool {
+                let codec = payloader_codec(payloader);
+                if let Some((_, pt)) = payload_types.iter().find(|(c, _)| Some(*c) == codec) {
+                    payloader.set_property("pt", *pt as u32);
+                }
+                // Only the video payloaders, the audio packets keep the extensions of webrtcsink
+                if let (Some(extensions), Some(_)) = (&header_extensions, codec) {
+                    set_header_extensions(payloader, extensions);
+                }
+                false
+            }),
+        );
+    }
+
+    // After the header extensions of the settings, which would remove it
+    if let Some(playout_delay) = settings.playout_delay {
+        webrtcsink.connect_closure(
+            "payloader-setup",
+            false,
+            glib::closure!(move |_sink: &BaseWebRTCSink,
+                                 _consumer_id: Option<&str>,
+                                 _pad_name: &str,
+                                 payloader: &gst::Element|
+                  -> bool {
+                if payloader_codec(payloader).is_some() {
+                    add_playout_delay(payloader, playout_delay);
+                }
+                false
+            }),
+        );
+    }
+
+    if let Some(max_packet_size) = settings.max_packet_size {
+        webrtcsink.connect_closure(
+            "payloader-setup",
+            false,
+            glib::closure!(move |_sink: &BaseWebRTCSink,
+                                 _consumer_id: Option<&str>,
+                                 _pad_name: &str,
+                                 payloader: &gst::Element|
+                  -> bool {
+                payloader.set_property("mtu", max_packet_size);
+                false
+            }),
+        );
+    }
+
+    if let Some(pacing) = settings.pacing {
+        webrtcsink.connect_closure(
+            "encoder-setup",
+            false,
+            glib::closure!(move |_sink: &BaseWebRTCSink,
+                                 _consumer_id: Option<&str>,
+                                 _pad_name: &str,
+                                 encoder: &gst::Element|
+                  -> bool {
+                set_rate_control_buffer(encoder, pacing);
+                false
+            }),
+        );
+    }
+}
+
+/// Restricts the ICE candidates of the media of the sessions of `webrtcsink` to the local
+/// `addresses`
+pub(crate) fn bind_ice_candidates(webrtcsink: &BaseWebRTCSink, addresses: Vec<IpAddr>) {
+    // The ICE agent gathers the candidates once the offer is created, after this signal
+    webrtcsink.connect_closure(
+        "consumer-added",
+        false,
+        glib::closure!(
+            move |_sink: &BaseWebRTCSink, peer_id: &str, webrtcbin: &gst::Element| {
+                let ice = webrtcbin.property::<gst_webrtc::WebRTCICE>("ice-agent");
+                if glib::subclass::signal::SignalId::lookup("add-local-ip-address", ice.type_())
+                    .is_none()
+                {
+                    warn!(%peer_id, "The ICE agent can not be bound to a local address");
+                    return;
+                }
+                for address in &addresses {
+                    if !ice.emit_by_name::<bool>("add-local-ip-address", &[&address.to_string()]) {
+                        warn!(%peer_id, "Unable to gather the ICE candidates on {}", address);
+                    }
+                }
+            }
+        ),
+    );
+}
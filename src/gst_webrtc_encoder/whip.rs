@@ -0,0 +1,30 @@
+use anyhow::{Context, Result};
+use gst::prelude::*;
+use gstrswebrtc::signaller::Signallable;
+
+/// Element owning the signaller of the WHIP client
+const WHIP_SINK: &str = "whipclientsink";
+
+/// Returns a signaller publishing to a WHIP endpoint, e.g. of Cloudflare Stream, Janus or
+/// MediaMTX.
+///
+/// The WHIP client signaller is private to gstrswebrtc, so it is taken from a
+/// `whipclientsink` element. The statically linked plugin is registered if GStreamer doesn't
+/// find the element.
+pub(crate) fn signaller(endpoint: &str, token: Option<&str>) -> Result<Signallable> {
+    if gst::ElementFactory::find(WHIP_SINK).is_none() {
+        gstrswebrtc::plugin_register_static().context("Unable to register the webrtc plugin")?;
+    }
+    let sink = gst::ElementFactory::make(WHIP_SINK)
+        .build()
+        .context("The whipclientsink element is not available")?;
+    let signaller = sink.property::<Signallable>("signaller");
+
+    signaller.set_property("whip-endpoint", endpoint);
+    if let Some(token) = token {
+        // Sent as a bearer token in the `Authorization` header
+        signaller.set_property("auth-token", token);
+    }
+
+    Ok(signaller)
+}
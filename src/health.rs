@@ -0,0 +1,247 @@
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use bevy_render::prelude::*;
+use std::time::{Duration, Instant};
+
+use crate::{
+    AudioOnlyStreamer, ConnectionInfo, PreEncodedStreamer, StandbyPolicy, StreamLabels,
+    capture::Capture, encoder::EncoderHandle,
+};
+
+/// Health of a stream, from the best to the worst
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HealthState {
+    Healthy,
+    /// The stream works but viewers may not be able to watch it, see `StreamHealth::issues`
+    Degraded,
+    /// The pipeline is not running
+    Unhealthy,
+    /// Not checked yet
+    #[default]
+    Starting,
+}
+
+/// A problem found by the health checks of a stream
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HealthIssue {
+    /// The pipeline of the encoder is not playing
+    PipelineNotPlaying(gst::State),
+    /// The stream is not registered to the signalling server yet, see `ConnectionInfo`
+    SignallingNotReady,
+    /// No frame was pushed to the encoder for `StreamHealthSettings::stall_after`, while
+    /// the camera is captured
+    FramesStalled,
+}
+
+impl HealthIssue {
+    fn state(&self) -> HealthState {
+        match self {
+            HealthIssue::PipelineNotPlaying(_) => HealthState::Unhealthy,
+            HealthIssue::SignallingNotReady | HealthIssue::FramesStalled => HealthState::Degraded,
+        }
+    }
+}
+
+/// Thresholds of the health checks, the defaults are used if this resource is not inserted
+#[derive(Resource, Clone, Debug)]
+pub struct StreamHealthSettings {
+    /// A stream whose encoder received no frame for this long is stalled
+    pub stall_after: Duration,
+    /// How long a worse state must last before the health changes
+    pub degrade_after: Duration,
+    /// How long a better state must last before the health changes, longer than
+    /// `degrade_after` so that the health doesn't flap
+    pub recover_after: Duration,
+}
+
+impl Default for StreamHealthSettings {
+    fn default() -> Self {
+        Self {
+            stall_after: Duration::from_secs(2),
+            degrade_after: Duration::from_secs(2),
+            recover_after: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Health of the stream of a streamer camera, aggregating the state of its pipeline, of its
+/// signalling and the flow of its frames.
+///
+/// Inserted on the entities with `StreamLabels`, a change is only committed once the new
+/// state lasted for `StreamHealthSettings::degrade_after` (or `recover_after` when it gets
+/// better). `StreamHealthChanged` is sent on each change.
+#[derive(Component, Clone, Debug)]
+pub struct StreamHealth {
+    pub state: HealthState,
+    /// Issues found by the last check, they may not be reflected in `state` yet
+    pub issues: Vec<HealthIssue>,
+    /// When `state` last changed
+    pub since: Instant,
+    /// The state observed and when it was first observed, if it differs from `state`
+    pending: Option<(HealthState, Instant)>,
+    frames_pushed: u64,
+    last_frame: Instant,
+}
+
+impl StreamHealth {
+    fn new(now: Instant) -> Self {
+        Self {
+            state: HealthState::Starting,
+            issues: Vec::new(),
+            since: now,
+            pending: None,
+            frames_pushed: 0,
+            last_frame: now,
+        }
+    }
+
+    /// Commits the observed state once it lasted long enough, returns the previous state if
+    /// it changed
+    fn observe(
+        &mut self,
+        observed: HealthState,
+        now: Instant,
+        settings: &StreamHealthSettings,
+    ) -> Option<HealthState> {
+        if observed == self.state {
+            self.pending = None;
+            return None;
+        }
+
+        let first_observed = match self.pending {
+            Some((pending, since)) if pending == observed => since,
+            _ => {
+                self.pending = Some((observed, now));
+                now
+            }
+        };
+        // Leaving the starting state is not delayed
+        let delay = if self.state == HealthState::Starting {
+            Duration::ZERO
+        } else if observed > self.state {
+            settings.degrade_after
+        } else {
+            settings.recover_after
+        };
+        if now.duration_since(first_observed) < delay {
+            return None;
+        }
+
+        let previous = self.state;
+        self.state = observed;
+        self.since = now;
+        self.pending = None;
+        Some(previous)
+    }
+}
+
+/// Sent when the `StreamHealth` of a stream changes
+#[derive(Event, Clone, Debug)]
+pub struct StreamHealthChanged {
+    pub entity: Entity,
+    /// Name of the stream, see `StreamLabels`
+    pub stream: String,
+    pub previous: HealthState,
+    pub current: HealthState,
+    pub issues: Vec<HealthIssue>,
+}
+
+type HealthItem<'a> = (
+    Entity,
+    &'a StreamLabels,
+    Option<&'a Camera>,
+    Option<&'a AudioOnlyStreamer>,
+    Option<&'a PreEncodedStreamer>,
+    Option<&'a ConnectionInfo>,
+    Option<&'a StandbyPolicy>,
+    Option<&'a mut StreamHealth>,
+);
+
+/// This system checks the health of the streams, inserts their `StreamHealth` and sends
+/// `StreamHealthChanged`
+pub(crate) fn update_stream_health(
+    mut commands: Commands,
+    mut streams: Query<HealthItem>,
+    captures: Query<&Capture>,
+    settings: Option<Res<StreamHealthSettings>>,
+    mut changed_events: EventWriter<StreamHealthChanged>,
+) {
+    let settings = settings
+        .map(|settings| settings.clone())
+        .unwrap_or_default();
+    let now = Instant::now();
+
+    for (entity, labels, camera, audio_only, pre_encoded, connection, standby, health) in
+        streams.iter_mut()
+    {
+        // The frames are only expected while the camera is captured
+        let (encoder, capturing): (Option<&EncoderHandle>, bool) =
+            match camera.and_then(|camera| camera.target.as_image()) {
+                Some(image) => match captures.iter().find(|c| c.src_image() == image) {
+                    Some(capture) => (Some(capture.encoder()), capture.capturing()),
+                    None => (None, false),
+                },
+                None => (audio_only.map(|streamer| &streamer.encoder), false),
+            };
+        let pipeline_state = match (encoder, pre_encoded) {
+            (Some(encoder), _) => encoder.pipeline_state(),
+            (None, Some(streamer)) => streamer.encoder.pipeline_state(),
+            (None, None) => None,
+        };
+        let frames_pushed = encoder
+            .and_then(|encoder| encoder.stats())
+            .map(|stats| stats.frames_pushed);
+
+        let Some(mut health) = health else {
+            commands.entity(entity).insert(StreamHealth::new(now));
+            continue;
+        };
+
+        let mut issues = Vec::new();
+        // The pipelines in standby are not playing
+        let standby = standby.is_some_and(StandbyPolicy::is_standby);
+        if let Some(state) =
+            pipeline_state.filter(|state| *state != gst::State::Playing && !standby)
+        {
+            issues.push(HealthIssue::PipelineNotPlaying(state));
+        }
+        if connection.is_some_and(|connection| !connection.ready) {
+            issues.push(HealthIssue::SignallingNotReady);
+        }
+        match frames_pushed {
+            Some(frames_pushed) if frames_pushed != health.frames_pushed || !capturing => {
+                health.frames_pushed = frames_pushed;
+                health.last_frame = now;
+            }
+            Some(_) if now.duration_since(health.last_frame) >= settings.stall_after => {
+                issues.push(HealthIssue::FramesStalled);
+            }
+            _ => {}
+        }
+
+        let observed = issues
+            .iter()
+            .map(HealthIssue::state)
+            .max()
+            .unwrap_or(HealthState::Healthy);
+        let previous = health.observe(observed, now, &settings);
+        health.issues = issues;
+
+        let Some(previous) = previous else {
+            continue;
+        };
+        let current = health.state;
+        if current > previous && previous != HealthState::Starting {
+            warn!(stream = %labels.name, "Stream {:?}: {:?}", current, health.issues);
+        } else {
+            info!(stream = %labels.name, "Stream {:?}", current);
+        }
+        changed_events.write(StreamHealthChanged {
+            entity,
+            stream: labels.name.clone(),
+            previous,
+            current,
+            issues: health.issues.clone(),
+        });
+    }
+}
@@ -2,28 +2,231 @@ use bevy_asset::prelude::*;
 use bevy_ecs::{prelude::*, system::SystemParam};
 use bevy_image::prelude::*;
 use bevy_log::prelude::*;
-use bevy_render::{prelude::*, renderer::RenderDevice};
+use bevy_render::{camera::RenderTarget, prelude::*, renderer::RenderDevice};
 use gst::prelude::*;
 use gstrswebrtc::webrtcsink;
 use std::{marker::PhantomData, sync::Arc};
 
 use crate::{
-    capture::setup_render_target, encoder::StreamEncoder, gst_webrtc_encoder::GstWebRtcEncoder, ControllerState, GstWebRtcSettings
+    AudioOnlyStreamer, ConnectionInfo, ControllerState, DataChannelTransport, EncodedCodec,
+    GpuMemoryBudget, GpuMemoryBudgetExceeded, GstWebRtcSettings, PeerLatency, PeerMetadata,
+    PeerVideoPause, PendingStreamer, PreEncodedStreamer, StandbyPolicy, StreamLabels, ViewerCount,
+    budget::BudgetedSize,
+    callback::{CallbackCamera, CallbackEncoder, CallbackSettings},
+    capture::{PendingEncoders, placeholder_render_target, setup_render_target},
+    connection::ConnectionInfoSource,
+    custom_pipeline::{CustomPipelineEncoder, CustomPipelineSettings},
+    encoder::{DeferredEncoder, EncoderHandle, StreamEncoder},
+    gst_webrtc_encoder::GstWebRtcEncoder,
+    latency::PeerLatencyTracker,
+    peers::PeerMetadataTracker,
+    record::{RecordEncoder, RecordSettings, RecordingOutput},
+    rtmp::{RtmpEncoder, RtmpSettings},
+    rtp_udp::{RtpUdpEncoder, RtpUdpSettings},
+    viewers::ViewerTracker,
 };
+#[cfg(unix)]
+use crate::isolated::{IsolatedEncoder, IsolatedSettings};
 #[cfg(feature = "livekit")]
 use crate::livekit::{LiveKitSettings, LiveKitEncoder};
+#[cfg(feature = "moq")]
+use crate::moq::{MoqEncoder, MoqSettings};
+#[cfg(feature = "ndi")]
+use crate::ndi::{NdiEncoder, NdiSettings};
+#[cfg(feature = "rtsp")]
+use crate::rtsp::{RtspServerEncoder, RtspServerSettings};
+#[cfg(unix)]
+use crate::shm::{ShmEncoder, ShmSettings};
+#[cfg(target_os = "linux")]
+use crate::v4l2::{V4l2Encoder, V4l2Settings};
+#[cfg(feature = "window-mirror")]
+use crate::WindowMirror;
+#[cfg(feature = "streamed-ui")]
+use crate::UiPointer;
+#[cfg(feature = "texture-sharing")]
+use crate::{SharedTexture, capture::render_target_image};
 
 #[cfg(feature = "pixelstreaming")]
-use crate::pixelstreaming::{controller::PSControllerState, handler::PSMessageHandler};
+use crate::pixelstreaming::{
+    controller::PSControllerState, handler::PSMessageHandler, load::LoadReporter,
+};
 
 #[derive(SystemParam)]
 pub struct StreamerHelper<'w, 's, E: StreamEncoder + 'static> {
     commands: Commands<'w, 's>,
     images: ResMut<'w, Assets<Image>>,
     render_device: Res<'w, RenderDevice>,
+    budget: Option<Res<'w, GpuMemoryBudget>>,
+    budget_exceeded: EventWriter<'w, GpuMemoryBudgetExceeded>,
     _phantom_encoder: PhantomData<E>
 }
 
+impl<'w, 's, E: StreamEncoder> StreamerHelper<'w, 's, E> {
+    /// Creates a streamer camera for an already started encoder, e.g. created from the
+    /// `EncoderRegistry`
+    pub fn new_streamer_camera_with_encoder(
+        &mut self,
+        width: u32,
+        height: u32,
+        encoder: EncoderHandle,
+    ) -> impl Bundle {
+        let mut size = self.fit_in_budget("", width, height);
+        if !size.refused && (size.width, size.height) != (width, height) {
+            if let Err(e) = encoder.resize(size.width, size.height) {
+                warn!(
+                    "Unable to downscale the encoder, the camera is refused: {:?}",
+                    e
+                );
+                size = BudgetedSize::refused(width, height);
+            }
+        }
+        let render_target = self.render_target(size, encoder);
+
+        (
+            Camera {
+                target: render_target,
+                ..Default::default()
+            },
+            ControllerState::None,
+        )
+    }
+
+    /// Pushes the frames of the streamer camera `camera` to another encoder too, e.g. to
+    /// record a camera streamed to LiveKit or to feed a thumbnail pipeline. The encoder is
+    /// started, and resized to the captured frames.
+    ///
+    /// The statistics and health of the stream remain the ones of its first encoder. The
+    /// `TestPattern` is pushed to the encoders added before it.
+    pub fn add_encoder(&mut self, camera: Entity, encoder: EncoderHandle) {
+        if let Err(e) = encoder.start() {
+            error!("Unable to start the encoder added to {}: {:?}", camera, e);
+            return;
+        }
+
+        self.commands
+            .entity(camera)
+            .queue(move |mut entity: EntityWorldMut| {
+                if let Some(mut pending) = entity.get_mut::<PendingEncoders>() {
+                    pending.0.push(encoder);
+                    return;
+                }
+                entity.insert(PendingEncoders(vec![encoder]));
+            });
+    }
+
+    /// Creates a streamer camera broadcasting what `window` shows, see `WindowMirror`
+    #[cfg(feature = "window-mirror")]
+    pub fn new_window_mirror<S>(
+        &mut self,
+        window: bevy_window::WindowRef,
+        settings: S,
+    ) -> impl Bundle
+    where
+        Self: StreamerCameraBuilder<E, S>,
+    {
+        (self.new_streamer_camera(settings), WindowMirror { window })
+    }
+
+    /// Creates a camera shared with the other apps of the machine, see `SharedTexture`. Its
+    /// frames are neither copied to the CPU nor streamed.
+    #[cfg(feature = "texture-sharing")]
+    pub fn new_shared_texture_camera(
+        &mut self,
+        name: &str,
+        width: u32,
+        height: u32,
+    ) -> impl Bundle {
+        let size = bevy_render::render_resource::Extent3d {
+            width,
+            height,
+            ..Default::default()
+        };
+        let image = render_target_image(&mut self.images, size);
+
+        (
+            Camera {
+                target: RenderTarget::Image(image.into()),
+                ..Default::default()
+            },
+            SharedTexture {
+                name: name.to_string(),
+            },
+        )
+    }
+
+    /// Creates a streamer camera rendering only UI, e.g. a streamed dashboard or tool, see
+    /// `UiPointer`.
+    ///
+    /// The camera renders the `bevy_ui` nodes with `UiTargetCamera` set to it, or the egui
+    /// context added to it by bevy_egui. Enable the controller so that the peers can use the UI.
+    #[cfg(feature = "streamed-ui")]
+    pub fn new_ui_streamer_camera<S>(&mut self, settings: S) -> impl Bundle
+    where
+        Self: StreamerCameraBuilder<E, S>,
+    {
+        (
+            self.new_streamer_camera(settings),
+            bevy_core_pipeline::core_2d::Camera2d,
+            UiPointer::default(),
+        )
+    }
+
+    /// Fits a new stream in the `GpuMemoryBudget`, if any
+    fn fit_in_budget(&mut self, stream: &str, width: u32, height: u32) -> BudgetedSize {
+        let Some(budget) = &self.budget else {
+            return BudgetedSize::unlimited(width, height);
+        };
+
+        let size = budget.reserve(width, height);
+        if size.refused {
+            error!(
+                %stream,
+                "Not enough GPU memory for a {}x{} stream ({} of {} bytes used), it is refused",
+                width,
+                height,
+                budget.used_bytes(),
+                budget.max_bytes
+            );
+        } else if (size.width, size.height) != (width, height) {
+            warn!(
+                %stream,
+                "Not enough GPU memory for a {}x{} stream, it is downscaled to {}x{}",
+                width,
+                height,
+                size.width,
+                size.height
+            );
+        } else {
+            return size;
+        }
+
+        self.budget_exceeded.write(GpuMemoryBudgetExceeded {
+            stream: stream.to_string(),
+            requested_size: (width, height),
+            granted_size: (!size.refused).then_some((size.width, size.height)),
+        });
+        size
+    }
+
+    /// Creates the render target of a stream, a placeholder which is not captured if the
+    /// stream is refused
+    fn render_target(&mut self, size: BudgetedSize, encoder: EncoderHandle) -> RenderTarget {
+        if size.refused {
+            return placeholder_render_target(&mut self.images);
+        }
+
+        setup_render_target(
+            &mut self.commands,
+            &mut self.images,
+            &self.render_device,
+            size.width,
+            size.height,
+            encoder,
+            size.reservation,
+        )
+    }
+}
+
 pub trait StreamerCameraBuilder<E: StreamEncoder, S> {
     fn new_streamer_camera(&mut self, settings: S) -> impl Bundle;
 }
@@ -32,15 +235,324 @@ impl<'w, 's> StreamerCameraBuilder<GstWebRtcEncoder, GstWebRtcSettings>
 for StreamerHelper<'w, 's, GstWebRtcEncoder>
 {
     fn new_streamer_camera(&mut self, settings: GstWebRtcSettings) -> impl Bundle {
+        let size = self.fit_in_budget(&settings.stream_name(), settings.width, settings.height);
+        let settings = GstWebRtcSettings {
+            width: size.width,
+            height: size.height,
+            ..settings
+        };
         let encoder = GstWebRtcEncoder::with_settings(settings.clone())
             .expect("Unable to create gst encoder");
+        // A stream refused by the `GpuMemoryBudget` doesn't connect to the signalling server
+        if !size.refused {
+            encoder.start().expect("Unable to start pipeline");
+        }
+
+        self.gst_webrtc_camera(settings, encoder, size)
+    }
+}
+
+impl<'w, 's> StreamerHelper<'w, 's, GstWebRtcEncoder> {
+    /// Creates cameras streamed as separate video tracks of a single WebRTC session.
+    ///
+    /// The first bundle is the main camera, which owns the controller and the data transport.
+    /// The other bundles are the cameras for each of the `extra_tracks` sizes.
+    pub fn new_multi_track_cameras(
+        &mut self,
+        settings: GstWebRtcSettings,
+        extra_tracks: &[(u32, u32)],
+    ) -> (impl Bundle, Vec<impl Bundle>) {
+        let stream_name = settings.stream_name();
+        let size = self.fit_in_budget(&stream_name, settings.width, settings.height);
+        let track_sizes = extra_tracks
+            .iter()
+            .enumerate()
+            .map(|(i, (width, height))| {
+                self.fit_in_budget(&format!("{}-{}", stream_name, i + 1), *width, *height)
+            })
+            .collect::<Vec<_>>();
+        let extra_tracks = track_sizes
+            .iter()
+            .map(|size| (size.width, size.height))
+            .collect::<Vec<_>>();
+
+        let settings = GstWebRtcSettings {
+            width: size.width,
+            height: size.height,
+            ..settings
+        };
+        let (encoder, tracks) =
+            GstWebRtcEncoder::with_extra_tracks(settings.clone(), &extra_tracks)
+                .expect("Unable to create gst encoder");
+        if !size.refused {
+            encoder.start().expect("Unable to start pipeline");
+        }
+
+        let track_cameras = tracks
+            .into_iter()
+            .zip(track_sizes)
+            .enumerate()
+            .map(|(i, (track, size))| {
+                let render_target = self.render_target(size, Arc::new(track));
+
+                let camera = Camera {
+                    target: render_target,
+                    ..Default::default()
+                };
+
+                let labels = StreamLabels {
+                    name: format!("{}-{}", stream_name, i + 1),
+                    labels: settings.labels.clone(),
+                };
+
+                (camera, labels)
+            })
+            .collect();
+
+        (
+            self.gst_webrtc_camera(settings, encoder, size),
+            track_cameras,
+        )
+    }
+
+    /// Creates a streamer without any camera, streaming only the `audio_source` and `host_audio`
+    /// of the settings and the data channels.
+    pub fn new_audio_only_streamer(&mut self, settings: GstWebRtcSettings) -> impl Bundle {
+        let encoder =
+            GstWebRtcEncoder::audio_only(settings.clone()).expect("Unable to create gst encoder");
         encoder.start().expect("Unable to start pipeline");
-        
+
+        let transport = DataChannelTransport::default();
+        if settings.data_transport {
+            transport.connect(&encoder.webrtcsink);
+        }
+
+        let viewers = ViewerTracker::default();
+        viewers.connect(encoder.webrtcsink.upcast_ref());
+
+        let connection = ConnectionInfoSource::from_settings(&settings);
+        connection.connect(&encoder.webrtcsink);
+
+        let peers = PeerMetadataTracker::default();
+        peers.connect(&encoder.webrtcsink);
+
+        let latency = PeerLatencyTracker::default();
+        latency.connect(encoder.webrtcsink.upcast_ref());
+
+        let load_reporter = load_reporter(&settings, &encoder.webrtcsink);
+
+        let labels = StreamLabels {
+            name: settings.stream_name(),
+            labels: settings.labels,
+        };
+
+        (
+            AudioOnlyStreamer {
+                encoder: Arc::new(encoder),
+            },
+            transport,
+            labels,
+            viewers,
+            ViewerCount::default(),
+            connection,
+            ConnectionInfo::default(),
+            (peers, PeerMetadata::default()),
+            (latency, PeerLatency::default()),
+            load_reporter,
+        )
+    }
+
+    /// Creates a streamer without any camera, streaming the frames pushed to its
+    /// `PreEncodedStreamer` already encoded with `codec`, see `GstWebRtcEncoder::pre_encoded`.
+    pub fn new_pre_encoded_streamer(
+        &mut self,
+        settings: GstWebRtcSettings,
+        codec: EncodedCodec,
+    ) -> impl Bundle {
+        let encoder = GstWebRtcEncoder::pre_encoded(settings.clone(), codec)
+            .expect("Unable to create gst encoder");
+        encoder.start().expect("Unable to start pipeline");
+
+        let transport = DataChannelTransport::default();
+        if settings.data_transport {
+            transport.connect(&encoder.webrtcsink);
+        }
+
+        let viewers = ViewerTracker::default();
+        viewers.connect(encoder.webrtcsink.upcast_ref());
+
+        let connection = ConnectionInfoSource::from_settings(&settings);
+        connection.connect(&encoder.webrtcsink);
+
+        let peers = PeerMetadataTracker::default();
+        peers.connect(&encoder.webrtcsink);
+
+        let latency = PeerLatencyTracker::default();
+        latency.connect(encoder.webrtcsink.upcast_ref());
+
+        let pause = PeerVideoPause::default();
+        pause.connect(&encoder.webrtcsink);
+
+        let load_reporter = load_reporter(&settings, &encoder.webrtcsink);
+
+        let labels = StreamLabels {
+            name: settings.stream_name(),
+            labels: settings.labels,
+        };
+
+        (
+            PreEncodedStreamer {
+                encoder: Arc::new(encoder),
+            },
+            transport,
+            pause,
+            labels,
+            viewers,
+            ViewerCount::default(),
+            connection,
+            ConnectionInfo::default(),
+            (peers, PeerMetadata::default()),
+            (latency, PeerLatency::default()),
+            load_reporter,
+        )
+    }
+
+    /// Creates a streamer camera whose pipeline is created and started on a background thread,
+    /// so that spawning cameras while the game is running doesn't block the frame.
+    ///
+    /// The camera renders immediately but frames are dropped until `StreamerCameraReady` is sent.
+    pub fn new_streamer_camera_async(&mut self, settings: GstWebRtcSettings) -> impl Bundle {
+        let size = self.fit_in_budget(&settings.stream_name(), settings.width, settings.height);
+        let settings = GstWebRtcSettings {
+            width: size.width,
+            height: size.height,
+            ..settings
+        };
+        let deferred = Arc::new(DeferredEncoder::default());
+        let (ready_sender, ready_receiver) = crossbeam_channel::bounded(1);
+
+        #[cfg(feature = "pixelstreaming")]
+        let (controller_sender, controller_state) = match &settings.signalling_server {
+            crate::SignallingServer::PixelStreaming { .. } if settings.enable_controller => {
+                let (sender, receiver) = crossbeam_channel::unbounded();
+                (
+                    Some(sender),
+                    ControllerState::PSControllerState(PSControllerState::new(receiver)),
+                )
+            }
+            _ => (None, ControllerState::None),
+        };
+        #[cfg(not(feature = "pixelstreaming"))]
+        let controller_state = ControllerState::None;
+
+        let transport = DataChannelTransport::default();
+        let viewers = ViewerTracker::default();
+        let connection = ConnectionInfoSource::from_settings(&settings);
+        let peers = PeerMetadataTracker::default();
+        let latency = PeerLatencyTracker::default();
+        let pause = PeerVideoPause::default();
+        #[cfg(feature = "pixelstreaming")]
+        let load_reporter = LoadReporter::from_settings(&settings);
+        #[cfg(not(feature = "pixelstreaming"))]
+        let load_reporter = ();
+
+        // A stream refused by the `GpuMemoryBudget` doesn't connect to the signalling server
+        if size.refused {
+            let _ = ready_sender.send(Err("Refused by the GpuMemoryBudget".to_string()));
+        } else {
+            std::thread::spawn({
+                let settings = settings.clone();
+                let deferred = deferred.clone();
+                let transport = transport.clone();
+                let viewers = viewers.clone();
+                let connection = connection.clone();
+                let peers = peers.clone();
+                let latency = latency.clone();
+                let pause = pause.clone();
+                #[cfg(feature = "pixelstreaming")]
+                let load_reporter = load_reporter.clone();
+                move || {
+                    let result =
+                        GstWebRtcEncoder::with_settings(settings.clone()).and_then(|encoder| {
+                            #[cfg(feature = "pixelstreaming")]
+                            if let Some(sender) = controller_sender {
+                                connect_pixelstreaming_handlers(
+                                    &encoder.webrtcsink,
+                                    sender,
+                                    &settings.input_limits,
+                                );
+                            }
+                            if settings.data_transport {
+                                transport.connect(&encoder.webrtcsink);
+                            }
+                            viewers.connect(encoder.webrtcsink.upcast_ref());
+                            connection.connect(&encoder.webrtcsink);
+                            peers.connect(&encoder.webrtcsink);
+                            latency.connect(encoder.webrtcsink.upcast_ref());
+                            pause.connect(&encoder.webrtcsink);
+                            #[cfg(feature = "pixelstreaming")]
+                            load_reporter.connect(&encoder.webrtcsink);
+
+                            encoder.start()?;
+                            deferred.set(Arc::new(encoder));
+                            Ok(())
+                        });
+
+                    if let Err(e) = &result {
+                        error!(
+                            "Unable to create pipeline for {}: {:?}",
+                            settings.stream_name(),
+                            e
+                        );
+                    }
+                    let _ = ready_sender.send(result.map_err(|e| e.to_string()));
+                }
+            });
+        }
+
+        let render_target = self.render_target(size, deferred);
+
+        let camera = Camera {
+            target: render_target,
+            ..Default::default()
+        };
+
+        let labels = StreamLabels {
+            name: settings.stream_name(),
+            labels: settings.labels,
+        };
+
+        (
+            camera,
+            controller_state,
+            transport,
+            labels,
+            viewers,
+            ViewerCount::default(),
+            StandbyPolicy::new(settings.standby_after),
+            connection,
+            ConnectionInfo::default(),
+            (peers, PeerMetadata::default()),
+            (latency, PeerLatency::default()),
+            pause,
+            load_reporter,
+            PendingStreamer {
+                receiver: ready_receiver,
+            },
+        )
+    }
+
+    fn gst_webrtc_camera(
+        &mut self,
+        settings: GstWebRtcSettings,
+        encoder: GstWebRtcEncoder,
+        size: BudgetedSize,
+    ) -> impl Bundle {
         let controller_state = if settings.enable_controller {
             match &settings.signalling_server {
                 #[cfg(feature = "pixelstreaming")]
                 crate::SignallingServer::PixelStreaming { .. } => {
-                    create_pixelstreaming_controller(&encoder)
+                    create_pixelstreaming_controller(&encoder, &settings.input_limits)
                 }
                 _ => ControllerState::None,
             }
@@ -48,88 +560,530 @@ for StreamerHelper<'w, 's, GstWebRtcEncoder>
             ControllerState::None
         };
 
-        let render_target = setup_render_target(
-            &mut self.commands,
-            &mut self.images,
-            &self.render_device,
-            settings.width,
-            settings.height,
-            Arc::new(encoder),
-        );
+        let transport = DataChannelTransport::default();
+        if settings.data_transport {
+            transport.connect(&encoder.webrtcsink);
+        }
+
+        let viewers = ViewerTracker::default();
+        viewers.connect(encoder.webrtcsink.upcast_ref());
+
+        let connection = ConnectionInfoSource::from_settings(&settings);
+        connection.connect(&encoder.webrtcsink);
+
+        let peers = PeerMetadataTracker::default();
+        peers.connect(&encoder.webrtcsink);
+
+        let latency = PeerLatencyTracker::default();
+        latency.connect(encoder.webrtcsink.upcast_ref());
+
+        let pause = PeerVideoPause::default();
+        pause.connect(&encoder.webrtcsink);
+
+        let load_reporter = load_reporter(&settings, &encoder.webrtcsink);
+
+        let render_target = self.render_target(size, Arc::new(encoder));
 
         let camera = Camera {
             target: render_target,
             ..Default::default()
         };
 
-        (camera, controller_state)
+        let labels = StreamLabels {
+            name: settings.stream_name(),
+            labels: settings.labels.clone(),
+        };
+
+        (
+            camera,
+            controller_state,
+            transport,
+            labels,
+            viewers,
+            ViewerCount::default(),
+            StandbyPolicy::new(settings.standby_after),
+            connection,
+            ConnectionInfo::default(),
+            (peers, PeerMetadata::default()),
+            (latency, PeerLatency::default()),
+            pause,
+            load_reporter,
+        )
     }
 }
 
+/// Returns the `LoadReporter` of a streamer, only available with Pixel Streaming
+#[cfg(feature = "pixelstreaming")]
+fn load_reporter(
+    settings: &GstWebRtcSettings,
+    webrtcsink: &webrtcsink::BaseWebRTCSink,
+) -> impl Bundle {
+    let load_reporter = LoadReporter::from_settings(settings);
+    load_reporter.connect(webrtcsink);
+    load_reporter
+}
+
+#[cfg(not(feature = "pixelstreaming"))]
+fn load_reporter(
+    _settings: &GstWebRtcSettings,
+    _webrtcsink: &webrtcsink::BaseWebRTCSink,
+) -> impl Bundle {
+}
+
 #[cfg(feature = "livekit")]
 impl<'w, 's> StreamerCameraBuilder<LiveKitEncoder, LiveKitSettings> 
 for StreamerHelper<'w, 's, LiveKitEncoder>
 {
     fn new_streamer_camera(&mut self, settings: LiveKitSettings) -> impl Bundle {
-        let encoder = LiveKitEncoder::new(settings.clone())
-            .expect("Unable to create LiveKit encoder");
+        let size = self.fit_in_budget(&settings.stream_name(), settings.width, settings.height);
+        let settings = LiveKitSettings {
+            width: size.width,
+            height: size.height,
+            ..settings
+        };
+        let encoder =
+            LiveKitEncoder::new(settings.clone()).expect("Unable to create LiveKit encoder");
+        // The pipeline is started when it is created
+        if size.refused {
+            if let Err(e) = encoder.stop() {
+                warn!("Unable to stop the refused LiveKit stream: {:?}", e);
+            }
+        }
 
-        let render_target = setup_render_target(
-            &mut self.commands,
-            &mut self.images,
-            &self.render_device,
-            settings.width,
-            settings.height,
-            encoder,
-        );
+        let viewers = ViewerTracker::default();
+        let latency = PeerLatencyTracker::default();
+        for sink in encoder.sinks() {
+            viewers.connect(&sink);
+            latency.connect(&sink);
+        }
+        let destinations = encoder.destinations();
+
+        let connection = ConnectionInfoSource::from_livekit_settings(&settings);
+
+        let render_target = self.render_target(size, encoder);
 
         let camera = Camera {
             target: render_target,
             ..Default::default()
         };
 
-        (camera, ControllerState::None)
+        let labels = StreamLabels {
+            name: settings.stream_name(),
+            labels: settings.labels.clone(),
+        };
+
+        (
+            camera,
+            ControllerState::None,
+            labels,
+            viewers,
+            ViewerCount::default(),
+            connection,
+            ConnectionInfo::default(),
+            (latency, PeerLatency::default()),
+            destinations,
+        )
     }
 }
 
-#[cfg(feature = "pixelstreaming")]
-fn create_pixelstreaming_controller(encoder: &GstWebRtcEncoder) -> ControllerState {
-    use bevy_platform::collections::HashMap;
+impl<'w, 's> StreamerCameraBuilder<CustomPipelineEncoder, CustomPipelineSettings>
+    for StreamerHelper<'w, 's, CustomPipelineEncoder>
+{
+    fn new_streamer_camera(&mut self, settings: CustomPipelineSettings) -> impl Bundle {
+        let size = self.fit_in_budget(&settings.name, settings.width, settings.height);
+        let settings = CustomPipelineSettings {
+            width: size.width,
+            height: size.height,
+            ..settings
+        };
+        let encoder = CustomPipelineEncoder::new(settings.clone())
+            .expect("Unable to create custom pipeline encoder");
+        if !size.refused {
+            encoder.start().expect("Unable to start pipeline");
+        }
+
+        let render_target = self.render_target(size, encoder);
+
+        let camera = Camera {
+            target: render_target,
+            ..Default::default()
+        };
+
+        let labels = StreamLabels {
+            name: settings.name,
+            labels: settings.labels,
+        };
 
+        (camera, ControllerState::None, labels)
+    }
+}
+
+impl<'w, 's> StreamerCameraBuilder<CallbackEncoder, CallbackSettings>
+    for StreamerHelper<'w, 's, CallbackEncoder>
+{
+    fn new_streamer_camera(&mut self, settings: CallbackSettings) -> impl Bundle {
+        let size = self.fit_in_budget(&settings.name, settings.width, settings.height);
+        let settings = CallbackSettings {
+            width: size.width,
+            height: size.height,
+            ..settings
+        };
+        let encoder = CallbackEncoder::new(settings.clone());
+        if !size.refused {
+            encoder.start().expect("Unable to start callback encoder");
+        }
+
+        let render_target = self.render_target(size, encoder.clone());
+
+        let camera = Camera {
+            target: render_target,
+            ..Default::default()
+        };
+
+        let labels = StreamLabels {
+            name: settings.name,
+            labels: settings.labels,
+        };
+
+        (
+            camera,
+            ControllerState::None,
+            labels,
+            CallbackCamera(encoder),
+        )
+    }
+}
+
+impl<'w, 's> StreamerCameraBuilder<RecordEncoder, RecordSettings>
+    for StreamerHelper<'w, 's, RecordEncoder>
+{
+    fn new_streamer_camera(&mut self, settings: RecordSettings) -> impl Bundle {
+        let size = self.fit_in_budget(&settings.name, settings.width, settings.height);
+        let settings = RecordSettings {
+            width: size.width,
+            height: size.height,
+            ..settings
+        };
+        let encoder = RecordEncoder::new(settings.clone()).expect("Unable to create recorder");
+        if !size.refused {
+            encoder.start().expect("Unable to start pipeline");
+        }
+
+        let output = RecordingOutput {
+            finalized: encoder.finalized_files(),
+            limits_reached: encoder.limits_reached(),
+            next_segment: encoder.segment_counter(),
+        };
+
+        let render_target = self.render_target(size, encoder);
+
+        let camera = Camera {
+            target: render_target,
+            ..Default::default()
+        };
+
+        let labels = StreamLabels {
+            name: settings.name,
+            labels: settings.labels,
+        };
+
+        (camera, ControllerState::None, labels, output)
+    }
+}
+
+impl<'w, 's> StreamerCameraBuilder<RtmpEncoder, RtmpSettings>
+    for StreamerHelper<'w, 's, RtmpEncoder>
+{
+    fn new_streamer_camera(&mut self, settings: RtmpSettings) -> impl Bundle {
+        let size = self.fit_in_budget(&settings.name, settings.width, settings.height);
+        let settings = RtmpSettings {
+            width: size.width,
+            height: size.height,
+            ..settings
+        };
+        let encoder = RtmpEncoder::new(settings.clone()).expect("Unable to create RTMP encoder");
+        if !size.refused {
+            encoder.start().expect("Unable to start pipeline");
+        }
+
+        let render_target = self.render_target(size, encoder);
+
+        let camera = Camera {
+            target: render_target,
+            ..Default::default()
+        };
+
+        let labels = StreamLabels {
+            name: settings.name,
+            labels: settings.labels,
+        };
+
+        (camera, ControllerState::None, labels)
+    }
+}
+
+impl<'w, 's> StreamerCameraBuilder<RtpUdpEncoder, RtpUdpSettings>
+    for StreamerHelper<'w, 's, RtpUdpEncoder>
+{
+    fn new_streamer_camera(&mut self, settings: RtpUdpSettings) -> impl Bundle {
+        let size = self.fit_in_budget(&settings.name, settings.width, settings.height);
+        let settings = RtpUdpSettings {
+            width: size.width,
+            height: size.height,
+            ..settings
+        };
+        let encoder = RtpUdpEncoder::new(settings.clone()).expect("Unable to create RTP encoder");
+        if !size.refused {
+            encoder.start().expect("Unable to start pipeline");
+        }
+
+        let render_target = self.render_target(size, encoder);
+
+        let camera = Camera {
+            target: render_target,
+            ..Default::default()
+        };
+
+        let labels = StreamLabels {
+            name: settings.name,
+            labels: settings.labels,
+        };
+
+        (camera, ControllerState::None, labels)
+    }
+}
+
+#[cfg(feature = "rtsp")]
+impl<'w, 's> StreamerCameraBuilder<RtspServerEncoder, RtspServerSettings>
+    for StreamerHelper<'w, 's, RtspServerEncoder>
+{
+    fn new_streamer_camera(&mut self, settings: RtspServerSettings) -> impl Bundle {
+        let size = self.fit_in_budget(&settings.name, settings.width, settings.height);
+        let settings = RtspServerSettings {
+            width: size.width,
+            height: size.height,
+            ..settings
+        };
+        let encoder =
+            RtspServerEncoder::new(settings.clone()).expect("Unable to create RTSP encoder");
+        if !size.refused {
+            encoder.start().expect("Unable to mount the RTSP stream");
+        }
+
+        let render_target = self.render_target(size, encoder);
+
+        let camera = Camera {
+            target: render_target,
+            ..Default::default()
+        };
+
+        let labels = StreamLabels {
+            name: settings.name,
+            labels: settings.labels,
+        };
+
+        (camera, ControllerState::None, labels)
+    }
+}
+
+#[cfg(feature = "moq")]
+impl<'w, 's> StreamerCameraBuilder<MoqEncoder, MoqSettings> for StreamerHelper<'w, 's, MoqEncoder> {
+    fn new_streamer_camera(&mut self, settings: MoqSettings) -> impl Bundle {
+        let size = self.fit_in_budget(&settings.name, settings.width, settings.height);
+        let settings = MoqSettings {
+            width: size.width,
+            height: size.height,
+            ..settings
+        };
+        let encoder = MoqEncoder::new(settings.clone()).expect("Unable to create MoQ encoder");
+        if !size.refused {
+            encoder.start().expect("Unable to start pipeline");
+        }
+
+        let render_target = self.render_target(size, encoder);
+
+        let camera = Camera {
+            target: render_target,
+            ..Default::default()
+        };
+
+        let labels = StreamLabels {
+            name: settings.name,
+            labels: settings.labels,
+        };
+
+        (camera, ControllerState::None, labels)
+    }
+}
+
+#[cfg(feature = "ndi")]
+impl<'w, 's> StreamerCameraBuilder<NdiEncoder, NdiSettings> for StreamerHelper<'w, 's, NdiEncoder> {
+    fn new_streamer_camera(&mut self, settings: NdiSettings) -> impl Bundle {
+        let size = self.fit_in_budget(&settings.name, settings.width, settings.height);
+        let settings = NdiSettings {
+            width: size.width,
+            height: size.height,
+            ..settings
+        };
+        let encoder = NdiEncoder::new(settings.clone()).expect("Unable to create NDI encoder");
+        if !size.refused {
+            encoder.start().expect("Unable to start pipeline");
+        }
+
+        let render_target = self.render_target(size, encoder);
+
+        let camera = Camera {
+            target: render_target,
+            ..Default::default()
+        };
+
+        let labels = StreamLabels {
+            name: settings.name,
+            labels: settings.labels,
+        };
+
+        (camera, ControllerState::None, labels)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl<'w, 's> StreamerCameraBuilder<V4l2Encoder, V4l2Settings>
+    for StreamerHelper<'w, 's, V4l2Encoder>
+{
+    fn new_streamer_camera(&mut self, settings: V4l2Settings) -> impl Bundle {
+        let size = self.fit_in_budget(&settings.name, settings.width, settings.height);
+        let settings = V4l2Settings {
+            width: size.width,
+            height: size.height,
+            ..settings
+        };
+        let encoder = V4l2Encoder::new(settings.clone()).expect("Unable to create V4L2 encoder");
+        if !size.refused {
+            encoder.start().expect("Unable to start pipeline");
+        }
+
+        let render_target = self.render_target(size, encoder);
+
+        let camera = Camera {
+            target: render_target,
+            ..Default::default()
+        };
+
+        let labels = StreamLabels {
+            name: settings.name,
+            labels: settings.labels,
+        };
+
+        (camera, ControllerState::None, labels)
+    }
+}
+
+#[cfg(unix)]
+impl<'w, 's> StreamerCameraBuilder<ShmEncoder, ShmSettings> for StreamerHelper<'w, 's, ShmEncoder> {
+    fn new_streamer_camera(&mut self, settings: ShmSettings) -> impl Bundle {
+        let size = self.fit_in_budget(&settings.name, settings.width, settings.height);
+        let settings = ShmSettings {
+            width: size.width,
+            height: size.height,
+            ..settings
+        };
+        let encoder =
+            ShmEncoder::new(settings.clone()).expect("Unable to create shared memory encoder");
+        if !size.refused {
+            encoder.start().expect("Unable to start pipeline");
+        }
+
+        let render_target = self.render_target(size, encoder);
+
+        let camera = Camera {
+            target: render_target,
+            ..Default::default()
+        };
+
+        let labels = StreamLabels {
+            name: settings.name,
+            labels: settings.labels,
+        };
+
+        (camera, ControllerState::None, labels)
+    }
+}
+
+#[cfg(unix)]
+impl<'w, 's> StreamerCameraBuilder<IsolatedEncoder, IsolatedSettings>
+    for StreamerHelper<'w, 's, IsolatedEncoder>
+{
+    fn new_streamer_camera(&mut self, mut settings: IsolatedSettings) -> impl Bundle {
+        let config = &mut settings.config;
+        let size = self.fit_in_budget(&settings.name, config.width, config.height);
+        config.width = size.width;
+        config.height = size.height;
+        let encoder =
+            IsolatedEncoder::new(settings.clone()).expect("Unable to create isolated encoder");
+        if !size.refused {
+            encoder.start().expect("Unable to start pipeline");
+        }
+
+        let render_target = self.render_target(size, encoder);
+
+        let camera = Camera {
+            target: render_target,
+            ..Default::default()
+        };
+
+        let labels = StreamLabels {
+            name: settings.name,
+            labels: settings.labels,
+        };
+
+        (camera, ControllerState::None, labels)
+    }
+}
+
+#[cfg(feature = "pixelstreaming")]
+fn create_pixelstreaming_controller(
+    encoder: &GstWebRtcEncoder,
+    input_limits: &crate::InputLimits,
+) -> ControllerState {
     let (sender, receiver) = crossbeam_channel::unbounded::<(String, Option<PSMessageHandler>)>();
 
-    encoder
-        .webrtcsink
-        .connect_closure("consumer-added", false, {
-            let sender = sender.clone();
-            glib::closure!(move |sink: &webrtcsink::BaseWebRTCSink,
-                                 peer_id: &str,
-                                 webrtcbin: &gst::Element| {
-                info!("New consumer: {}", peer_id);
+    connect_pixelstreaming_handlers(&encoder.webrtcsink, sender, input_limits);
 
-                let message_handler = PSMessageHandler::new(sink, webrtcbin, peer_id);
+    ControllerState::PSControllerState(PSControllerState::new(receiver))
+}
 
-                sender
-                    .send((peer_id.to_string(), Some(message_handler)))
-                    .unwrap();
-            })
-        });
+/// Creates a message handler for every new consumer of `webrtcsink`
+#[cfg(feature = "pixelstreaming")]
+fn connect_pixelstreaming_handlers(
+    webrtcsink: &webrtcsink::BaseWebRTCSink,
+    sender: crossbeam_channel::Sender<(String, Option<PSMessageHandler>)>,
+    input_limits: &crate::InputLimits,
+) {
+    webrtcsink.connect_closure("consumer-added", false, {
+        let sender = sender.clone();
+        let input_limits = input_limits.clone();
+        glib::closure!(move |sink: &webrtcsink::BaseWebRTCSink,
+                             peer_id: &str,
+                             webrtcbin: &gst::Element| {
+            info!("New consumer: {}", peer_id);
 
-    encoder
-        .webrtcsink
-        .connect_closure("consumer-removed", false, {
-            let sender = sender.clone();
-            glib::closure!(move |_sink: &webrtcsink::BaseWebRTCSink,
-                                 peer_id: &str,
-                                 _webrtcbin: &gst::Element| {
-                info!("Consumer removed: {}", peer_id);
+            let message_handler =
+                PSMessageHandler::new(sink, webrtcbin, peer_id, input_limits.clone());
 
-                sender.send((peer_id.to_string(), None)).unwrap();
-            })
-        });
+            sender
+                .send((peer_id.to_string(), Some(message_handler)))
+                .unwrap();
+        })
+    });
+
+    webrtcsink.connect_closure("consumer-removed", false, {
+        let sender = sender.clone();
+        glib::closure!(move |_sink: &webrtcsink::BaseWebRTCSink,
+                             peer_id: &str,
+                             _webrtcbin: &gst::Element| {
+            info!("Consumer removed: {}", peer_id);
 
-    ControllerState::PSControllerState(PSControllerState {
-        add_remove_handlers: receiver,
-        handlers: HashMap::new(),
-    })
+            sender.send((peer_id.to_string(), None)).unwrap();
+        })
+    });
 }
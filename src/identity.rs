@@ -0,0 +1,221 @@
+use anyhow::{Context, Result, bail};
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, atomic::Ordering},
+};
+
+#[cfg(feature = "pixelstreaming")]
+use crate::SignallingServer;
+#[cfg(feature = "livekit")]
+use crate::livekit::LiveKitSettings;
+use crate::{
+    ConnectionInfo, DtlsCertificate, GstWebRtcSettings, StreamLabels,
+    record::{RecordSettings, RecordTarget, RecordingOutput},
+};
+
+/// Identity of a stream kept across the restarts of the app, see `StreamIdentities`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamIdentity {
+    /// Streamer id committed by the Pixel Streaming signalling server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub streamer_id: Option<String>,
+    /// Participant identity generated for a LiveKit stream
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub livekit_identity: Option<String>,
+    /// File of the DTLS certificate of a WebRTC stream
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dtls_certificate: Option<PathBuf>,
+    /// Index of the next segment of a segmented recording
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_segment: Option<u32>,
+}
+
+/// Identities of the streams, by stream name, persisted to a JSON file so that an instance
+/// restarted after a crash resumes under the same identity: the viewers and dashboards
+/// following a streamer id or a LiveKit participant are not confused, and the recordings
+/// continue their numbering instead of overwriting their segments.
+///
+/// The settings are passed to the `restore_*` methods before creating the streamer cameras,
+/// which only fill in what the settings leave to be chosen, e.g. a Pixel Streaming streamer
+/// id left to the signalling server. The committed streamer ids and the segment indices
+/// are saved by `StreamerPlugin` once this resource is inserted.
+#[derive(Resource, Clone)]
+pub struct StreamIdentities {
+    path: PathBuf,
+    identities: Arc<Mutex<BTreeMap<String, StreamIdentity>>>,
+}
+
+impl StreamIdentities {
+    /// Loads the identities from `path`, created when one is first saved. The DTLS
+    /// certificates are written next to it.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let identities = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .with_context(|| format!("Invalid stream identities in {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Unable to read {}", path.display()));
+            }
+        };
+
+        Ok(Self {
+            path,
+            identities: Arc::new(Mutex::new(identities)),
+        })
+    }
+
+    /// Returns the identity of a stream, if one was saved
+    pub fn get(&self, stream: &str) -> Option<StreamIdentity> {
+        self.identities.lock().unwrap().get(stream).cloned()
+    }
+
+    /// Updates the identity of a stream, and saves the identities if it changed
+    pub fn update(&self, stream: &str, update: impl FnOnce(&mut StreamIdentity)) -> Result<()> {
+        let mut identities = self.identities.lock().unwrap();
+        let identity = identities.entry(stream.to_string()).or_default();
+        let previous = identity.clone();
+        update(identity);
+        if *identity == previous {
+            return Ok(());
+        }
+
+        save(&self.path, &identities)
+    }
+
+    /// Restores the streamer id of a Pixel Streaming stream without one, and gives the
+    /// WebRTC streams without a DTLS certificate one kept next to the identities. Such streams
+    /// need a `name`, their default name being the streamer id once it is restored.
+    pub fn restore_webrtc(&self, mut settings: GstWebRtcSettings) -> Result<GstWebRtcSettings> {
+        // The identities are saved under the name of the stream, see `save_stream_identities`
+        let Some(stream) = settings.name.clone() else {
+            bail!("The streams whose identity is restored need a name");
+        };
+        let identity = self.get(&stream).unwrap_or_default();
+
+        #[cfg(feature = "pixelstreaming")]
+        if let SignallingServer::PixelStreaming { streamer_id, .. } =
+            &mut settings.signalling_server
+        {
+            if streamer_id.is_none() {
+                *streamer_id = identity.streamer_id.clone();
+            }
+        }
+
+        if settings.dtls_certificate.is_none() {
+            let path = identity
+                .dtls_certificate
+                .clone()
+                .unwrap_or_else(|| self.certificate_path(&stream));
+            self.update(&stream, |identity| {
+                identity.dtls_certificate = Some(path.clone())
+            })?;
+            settings.dtls_certificate = Some(DtlsCertificate::File(path));
+        }
+
+        Ok(settings)
+    }
+
+    /// Gives a LiveKit stream with an empty participant identity the one generated on its
+    /// first start. Such streams need a `name`, their identity being their default name.
+    #[cfg(feature = "livekit")]
+    pub fn restore_livekit(&self, mut settings: LiveKitSettings) -> Result<LiveKitSettings> {
+        if !settings.participant_identity.is_empty() {
+            return Ok(settings);
+        }
+
+        let stream = settings.stream_name();
+        let identity = self
+            .get(&stream)
+            .and_then(|identity| identity.livekit_identity)
+            .unwrap_or_else(|| format!("bevy-{}", uuid::Uuid::new_v4()));
+        self.update(&stream, |saved| {
+            saved.livekit_identity = Some(identity.clone())
+        })?;
+        settings.participant_identity = identity;
+
+        Ok(settings)
+    }
+
+    /// Continues the numbering of the segments of a segmented recording after the last
+    /// segment opened, which may be incomplete
+    pub fn restore_record(&self, mut settings: RecordSettings) -> RecordSettings {
+        if let (RecordTarget::Segments { .. }, Some(next_segment)) = (
+            &settings.target,
+            self.get(&settings.name)
+                .and_then(|identity| identity.next_segment),
+        ) {
+            settings.first_segment = settings.first_segment.max(next_segment);
+        }
+
+        settings
+    }
+
+    /// Returns the path of the DTLS certificate of a stream, next to the identities
+    fn certificate_path(&self, stream: &str) -> PathBuf {
+        let name: String = stream
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let directory = self.path.parent().unwrap_or(Path::new("."));
+        directory.join(format!("{name}.pem"))
+    }
+}
+
+/// Writes the identities, through a temporary file so that a crash does not leave a
+/// truncated file
+fn save(path: &Path, identities: &BTreeMap<String, StreamIdentity>) -> Result<()> {
+    let data = serde_json::to_vec_pretty(identities)?;
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, data)
+        .with_context(|| format!("Unable to write {}", temporary.display()))?;
+    std::fs::rename(&temporary, path)
+        .with_context(|| format!("Unable to write {}", path.display()))?;
+
+    Ok(())
+}
+
+/// This system saves the streamer ids committed by the signalling servers and the next
+/// segments of the recordings to the `StreamIdentities`
+pub(crate) fn save_stream_identities(
+    identities: Option<Res<StreamIdentities>>,
+    connections: Query<(&StreamLabels, &ConnectionInfo), Changed<ConnectionInfo>>,
+    recordings: Query<(&StreamLabels, &RecordingOutput)>,
+) {
+    let Some(identities) = identities else {
+        return;
+    };
+
+    for (labels, info) in connections.iter() {
+        let Some(streamer_id) = info.streamer_id.clone().filter(|_| info.ready) else {
+            continue;
+        };
+        if let Err(e) = identities.update(&labels.name, |identity| {
+            identity.streamer_id = Some(streamer_id)
+        }) {
+            warn!(stream = %labels.name, "Unable to save the streamer id: {:?}", e);
+        }
+    }
+
+    for (labels, output) in recordings.iter() {
+        let Some(next_segment) = &output.next_segment else {
+            continue;
+        };
+        let next_segment = next_segment.load(Ordering::Relaxed);
+        if let Err(e) = identities.update(&labels.name, |identity| {
+            identity.next_segment = Some(next_segment)
+        }) {
+            warn!(stream = %labels.name, "Unable to save the next segment: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+
+use crate::{
+    ControllerState,
+    pixelstreaming::{controller::PSControllerState, message::PSMessage},
+};
+
+/// Injects a synthetic Pixel Streaming message into the controller of a streamer camera,
+/// as if it was received from the given peer.
+///
+/// This allows testing input mapping (key codes, coordinates scaling) without a real peer.
+#[derive(Event, Clone, Debug)]
+pub struct InjectInput {
+    pub camera: Entity,
+    pub peer_id: String,
+    pub message: PSMessage,
+}
+
+/// Returns a controller which only receives injected input, to be added to a camera
+/// rendering to an image.
+///
+/// As with regular streamer cameras, converted input targets the `StreamWindow` of the
+/// camera.
+pub fn synthetic_controller() -> impl Bundle {
+    let (_sender, receiver) = crossbeam_channel::unbounded();
+    ControllerState::PSControllerState(PSControllerState::new(receiver))
+}
+
+/// This system forwards `InjectInput` events to the controllers
+pub(crate) fn inject_inputs(
+    mut events: EventReader<InjectInput>,
+    controllers: Query<&ControllerState>,
+) {
+    for event in events.read() {
+        let Ok(ControllerState::PSControllerState(ue_controller_state)) =
+            controllers.get(event.camera)
+        else {
+            warn!("Cannot inject input: {} has no controller", event.camera);
+            continue;
+        };
+
+        let _ = ue_controller_state
+            .injected_sender
+            .send((event.peer_id.clone(), event.message.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        StreamerCommand, StreamerResolutionRequest, StreamerUiInteraction,
+        pixelstreaming::message::{KeyDown, MouseDown, MouseMove, MouseWheel},
+    };
+    use bevy_asset::{Assets, RenderAssetUsages};
+    use bevy_ecs::{event::Events, system::RunSystemOnce};
+    use bevy_image::Image;
+    use bevy_input::{
+        ButtonState,
+        keyboard::{Key, KeyCode, KeyboardInput},
+        mouse::{self, MouseButton, MouseButtonInput, MouseMotion},
+    };
+    use bevy_math::Vec2;
+    use bevy_render::{
+        camera::{Camera, RenderTarget},
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    };
+    use bevy_window::{PrimaryWindow, Window, WindowEvent};
+
+    const WIDTH: u32 = 640;
+    const HEIGHT: u32 = 480;
+
+    /// Returns a world with a primary window and a camera with a synthetic controller
+    fn setup() -> (World, Entity, Entity) {
+        let mut world = World::new();
+        world.init_resource::<Events<InjectInput>>();
+        world.init_resource::<Events<MouseMotion>>();
+        world.init_resource::<Events<MouseButtonInput>>();
+        world.init_resource::<Events<mouse::MouseWheel>>();
+        world.init_resource::<Events<WindowEvent>>();
+        world.init_resource::<Events<KeyboardInput>>();
+        world.init_resource::<Events<StreamerUiInteraction>>();
+        world.init_resource::<Events<StreamerCommand>>();
+        world.init_resource::<Events<StreamerResolutionRequest>>();
+
+        let mut images = Assets::<Image>::default();
+        let image = images.add(Image::new_fill(
+            Extent3d {
+                width: WIDTH,
+                height: HEIGHT,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[0; 4],
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        ));
+        world.insert_resource(images);
+
+        let window = world.spawn((Window::default(), PrimaryWindow)).id();
+        let camera = world
+            .spawn((
+                Camera {
+                    target: RenderTarget::Image(image.into()),
+                    ..Default::default()
+                },
+                synthetic_controller(),
+            ))
+            .id();
+
+        (world, window, camera)
+    }
+
+    /// Injects `message` and converts it to Bevy input
+    fn inject(world: &mut World, camera: Entity, message: PSMessage) {
+        world.send_event(InjectInput {
+            camera,
+            peer_id: "peer".to_string(),
+            message,
+        });
+        world.run_system_once(inject_inputs).unwrap();
+        world
+            .run_system_once(crate::handle_controller_messages)
+            .unwrap();
+    }
+
+    fn drain<E: Event>(world: &mut World) -> Vec<E> {
+        world.resource_mut::<Events<E>>().drain().collect()
+    }
+
+    #[test]
+    fn maps_keys() {
+        let (mut world, window, camera) = setup();
+
+        inject(
+            &mut world,
+            camera,
+            PSMessage::KeyDown(KeyDown {
+                key_code: 87,
+                is_repeat: 1,
+            }),
+        );
+        let events = drain::<KeyboardInput>(&mut world);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].key_code, KeyCode::KeyW);
+        assert_eq!(events[0].logical_key, Key::Character("w".into()));
+        assert_eq!(events[0].state, ButtonState::Pressed);
+        assert!(events[0].repeat);
+        assert_eq!(events[0].window, window);
+    }
+
+    #[test]
+    fn maps_mouse_buttons() {
+        let (mut world, window, camera) = setup();
+
+        inject(
+            &mut world,
+            camera,
+            PSMessage::MouseDown(MouseDown {
+                button: 2,
+                x: 0,
+                y: 0,
+            }),
+        );
+        let events = drain::<MouseButtonInput>(&mut world);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].button, MouseButton::Right);
+        assert_eq!(events[0].state, ButtonState::Pressed);
+        assert_eq!(events[0].window, window);
+    }
+
+    #[test]
+    fn scales_mouse_positions_to_the_render_target() {
+        let (mut world, window, camera) = setup();
+
+        // Positions and deltas are sent in 1/65536 of the size of the frames
+        inject(
+            &mut world,
+            camera,
+            PSMessage::MouseMove(MouseMove {
+                x: 32768,
+                y: 16384,
+                delta_x: 16384,
+                delta_y: -8192,
+            }),
+        );
+        let motions = drain::<MouseMotion>(&mut world);
+        assert_eq!(motions.len(), 1);
+        assert_eq!(motions[0].delta, Vec2::new(160.0, -60.0));
+
+        let events = drain::<WindowEvent>(&mut world);
+        let [WindowEvent::CursorMoved(cursor_moved)] = events.as_slice() else {
+            panic!("Unexpected window events {:?}", events);
+        };
+        assert_eq!(cursor_moved.window, window);
+        assert_eq!(cursor_moved.position, Vec2::new(320.0, 120.0));
+
+        inject(
+            &mut world,
+            camera,
+            PSMessage::MouseWheel(MouseWheel {
+                delta: -120,
+                x: 0,
+                y: 0,
+            }),
+        );
+        let wheels = drain::<mouse::MouseWheel>(&mut world);
+        assert_eq!(wheels.len(), 1);
+        assert_eq!(wheels[0].y, -12.0);
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{ControllerState, pixelstreaming::message::PSMessage};
+
+/// A controller message recorded from a peer
+#[derive(Clone, Debug)]
+struct InputRecord {
+    /// Time elapsed since the start of the recording
+    time: Duration,
+    peer_id: String,
+    data: Vec<u8>,
+}
+
+impl InputRecord {
+    fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_u64::<LittleEndian>(self.time.as_micros() as u64)?;
+        writer.write_u16::<LittleEndian>(self.peer_id.len() as u16)?;
+        writer.write_all(self.peer_id.as_bytes())?;
+        writer.write_u32::<LittleEndian>(self.data.len() as u32)?;
+        writer.write_all(&self.data)
+    }
+
+    fn read_from(reader: &mut impl Read) -> io::Result<Self> {
+        let time = Duration::from_micros(reader.read_u64::<LittleEndian>()?);
+        let mut peer_id = vec![0; reader.read_u16::<LittleEndian>()? as usize];
+        reader.read_exact(&mut peer_id)?;
+        // Read up to the recorded length, rather than allocating it, as it may be corrupt
+        let len = reader.read_u32::<LittleEndian>()? as u64;
+        let mut data = Vec::new();
+        reader.by_ref().take(len).read_to_end(&mut data)?;
+        if data.len() as u64 != len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Truncated input record",
+            ));
+        }
+        Ok(Self {
+            time,
+            peer_id: String::from_utf8(peer_id)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            data,
+        })
+    }
+}
+
+/// Records every controller message received by the streamer camera it is attached to,
+/// with its timestamp and peer id.
+#[derive(Component)]
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+    started_at: Instant,
+}
+
+impl InputRecorder {
+    /// Creates a recorder writing to the given file (truncated if it exists)
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            started_at: Instant::now(),
+        })
+    }
+
+    pub(crate) fn record(&mut self, peer_id: &str, message: &PSMessage) {
+        let record = InputRecord {
+            time: self.started_at.elapsed(),
+            peer_id: peer_id.to_string(),
+            data: message.into(),
+        };
+        if let Err(error) = record.write_to(&mut self.writer) {
+            error!("Unable to record input: {}", error);
+        }
+    }
+
+    /// Flushes the recorded messages to the file
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Replays a file written by an `InputRecorder` into the streamer camera it is attached to,
+/// respecting the original timing.
+#[derive(Component)]
+pub struct InputReplayer {
+    records: Vec<InputRecord>,
+    next: usize,
+    started_at: Option<Instant>,
+}
+
+impl InputReplayer {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut records = Vec::new();
+        loop {
+            match InputRecord::read_from(&mut reader) {
+                Ok(record) => records.push(record),
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(Self {
+            records,
+            next: 0,
+            started_at: None,
+        })
+    }
+
+    /// Returns true when all the recorded messages have been replayed
+    pub fn finished(&self) -> bool {
+        self.next >= self.records.len()
+    }
+}
+
+/// This system injects the recorded messages which are due into the controllers
+pub(crate) fn replay_inputs(mut replayers: Query<(&mut InputReplayer, &ControllerState)>) {
+    for (mut replayer, controller) in replayers.iter_mut() {
+        let ControllerState::PSControllerState(ue_controller_state) = controller else {
+            continue;
+        };
+
+        let elapsed = replayer
+            .started_at
+            .get_or_insert_with(Instant::now)
+            .elapsed();
+        while let Some(record) = replayer.records.get(replayer.next) {
+            if record.time > elapsed {
+                break;
+            }
+
+            match PSMessage::try_from(record.data.as_slice()) {
+                Ok(message) => {
+                    let _ = ue_controller_state
+                        .injected_sender
+                        .send((record.peer_id.clone(), message));
+                }
+                Err(error) => {
+                    warn!("Unable to decode recorded input: {}", error);
+                }
+            }
+            replayer.next += 1;
+        }
+    }
+}
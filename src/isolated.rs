@@ -0,0 +1,456 @@
+use anyhow::{Context, Result, anyhow};
+use bevy_log::prelude::*;
+use gst::prelude::*;
+use gst_video::{VideoFormat, VideoInfo};
+use std::{
+    io::{BufRead, Write},
+    path::PathBuf,
+    process::{ChildStdin, Command, Stdio},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::{
+    EncoderConfig, EncoderRegistry, PipelineLogLevel,
+    encoder::{EncoderStats, Frame, StreamEncoder, request_appsrc_keyframe, resize_appsrc},
+    pipeline_log::log_bus_message,
+};
+
+/// Environment variable holding the shared memory socket of a worker process
+const WORKER_SOCKET_ENV: &str = "BEVY_STREAMING_WORKER_SOCKET";
+
+/// Number of frames the shared memory can hold
+const SHM_FRAMES: usize = 3;
+
+/// Settings of an `IsolatedEncoder`
+#[derive(Clone)]
+pub struct IsolatedSettings {
+    /// Name of the stream
+    pub name: String,
+    /// Labels attached to the stream, see `StreamLabels`
+    pub labels: Vec<(String, String)>,
+    /// Configuration of the encoder created in the worker process, from the default backends
+    /// of `EncoderRegistry`
+    pub config: EncoderConfig,
+    /// Delay before restarting a worker which exited, it is not restarted if `None`
+    pub restart_delay: Option<Duration>,
+    /// Verbosity of the pipeline messages logs
+    pub log_level: PipelineLogLevel,
+}
+
+impl Default for IsolatedSettings {
+    fn default() -> Self {
+        Self {
+            name: "isolated".to_string(),
+            labels: Vec::new(),
+            config: EncoderConfig {
+                backend: "gstwebrtc".to_string(),
+                width: 1920,
+                height: 1080,
+                ..Default::default()
+            },
+            restart_delay: Some(Duration::from_secs(1)),
+            log_level: PipelineLogLevel::default(),
+        }
+    }
+}
+
+/// State of the worker, replayed when it is restarted
+struct WorkerState {
+    stdin: Option<ChildStdin>,
+    running: bool,
+    width: u32,
+    height: u32,
+    bitrate: Option<u32>,
+}
+
+impl WorkerState {
+    fn send(&mut self, command: &str) -> Result<()> {
+        let stdin = self
+            .stdin
+            .as_mut()
+            .ok_or_else(|| anyhow!("The worker process is not running"))?;
+        writeln!(stdin, "{command}").context("Unable to send a command to the worker")
+    }
+
+    /// Sends the commands bringing a new worker to this state, it is started with the
+    /// current size
+    fn replay(&mut self) -> Result<()> {
+        if let Some(bitrate) = self.bitrate {
+            self.send(&format!("bitrate {bitrate}"))?;
+        }
+        if !self.running {
+            self.send("stop")?;
+        }
+        Ok(())
+    }
+}
+
+/// An encoder running the pipeline of another backend in a child process, fed through shared
+/// memory, so that a crash of a GStreamer plugin or a GLib abort doesn't take down the app.
+///
+/// The child process is the current executable, which must call `run_worker_if_requested`
+/// at the start of `main`. It is restarted after `restart_delay` when it exits.
+pub struct IsolatedEncoder {
+    pipeline: gst::Pipeline,
+    appsrc: gst_app::AppSrc,
+    stats: Mutex<EncoderStats>,
+    worker: Arc<Mutex<WorkerState>>,
+    dropped: Arc<AtomicBool>,
+    /// Frames larger than the shared memory can't be sent
+    max_frame_size: usize,
+}
+
+impl IsolatedEncoder {
+    pub fn new(settings: IsolatedSettings) -> Result<Arc<Self>> {
+        gst::init()?;
+
+        let config = &settings.config;
+        let socket_path = std::env::temp_dir().join(format!(
+            "bevy-streaming-{}-{}.sock",
+            std::process::id(),
+            settings.name
+        ));
+        let _ = std::fs::remove_file(&socket_path);
+        let max_frame_size = config.width as usize * config.height as usize * 4;
+
+        let description = format!(
+            "appsrc name=src format=time is-live=true do-timestamp=true ! \
+            shmsink socket-path=\"{}\" shm-size={} wait-for-connection=false sync=false",
+            socket_path.display(),
+            max_frame_size * SHM_FRAMES
+        );
+        let pipeline = gst::parse::launch(&description)
+            .context("Unable to create the shared memory pipeline")?
+            .downcast::<gst::Pipeline>()
+            .map_err(|_| anyhow!("Failed to cast to pipeline"))?;
+        pipeline.set_property("name", &settings.name);
+
+        let appsrc = pipeline
+            .by_name("src")
+            .ok_or_else(|| anyhow!("Could not get appsrc element"))?
+            .downcast::<gst_app::AppSrc>()
+            .map_err(|_| anyhow!("Not an appsrc"))?;
+        let video_info = VideoInfo::builder(VideoFormat::Rgba, config.width, config.height)
+            .build()
+            .context("Failed to create video info")?;
+        appsrc.set_caps(Some(&video_info.to_caps()?));
+
+        let bus = pipeline
+            .bus()
+            .ok_or_else(|| anyhow!("Pipeline has no bus"))?;
+        let stream = settings.name.clone();
+        let log_level = settings.log_level;
+        std::thread::spawn(move || {
+            for msg in bus.iter_timed(gst::ClockTime::NONE) {
+                log_bus_message(&stream, log_level, &msg);
+                if let gst::MessageView::Eos(_) = msg.view() {
+                    break;
+                }
+            }
+        });
+
+        let worker = Arc::new(Mutex::new(WorkerState {
+            stdin: None,
+            running: true,
+            width: config.width,
+            height: config.height,
+            bitrate: None,
+        }));
+        let dropped = Arc::new(AtomicBool::new(false));
+        spawn_supervisor(&settings, socket_path, worker.clone(), dropped.clone());
+
+        Ok(Arc::new(Self {
+            pipeline,
+            appsrc,
+            stats: Mutex::new(EncoderStats {
+                width: config.width,
+                height: config.height,
+                ..Default::default()
+            }),
+            worker,
+            dropped,
+            max_frame_size,
+        }))
+    }
+
+    fn send(&self, command: &str) -> Result<()> {
+        self.worker.lock().unwrap().send(command)
+    }
+}
+
+/// Starts the worker process of an encoder, and restarts it whenever it exits
+fn spawn_supervisor(
+    settings: &IsolatedSettings,
+    socket_path: PathBuf,
+    worker: Arc<Mutex<WorkerState>>,
+    dropped: Arc<AtomicBool>,
+) {
+    let stream = settings.name.clone();
+    let config = settings.config.clone();
+    let restart_delay = settings.restart_delay;
+
+    std::thread::spawn(move || {
+        loop {
+            let mut command = match std::env::current_exe() {
+                Ok(exe) => Command::new(exe),
+                Err(e) => {
+                    error!(%stream, "Unable to find the worker executable: {:?}", e);
+                    return;
+                }
+            };
+            // The configuration is read by `EncoderConfig::from_env` in the worker
+            let (width, height) = {
+                let worker = worker.lock().unwrap();
+                (worker.width, worker.height)
+            };
+            for (key, _) in std::env::vars().filter(|(key, _)| key.starts_with("STREAMER_")) {
+                command.env_remove(key);
+            }
+            command
+                .env(WORKER_SOCKET_ENV, &socket_path)
+                .env("STREAMER_BACKEND", &config.backend)
+                .env("STREAMER_WIDTH", width.to_string())
+                .env("STREAMER_HEIGHT", height.to_string())
+                .envs(
+                    config.options.iter().map(|(option, value)| {
+                        (format!("STREAMER_{}", option.to_uppercase()), value)
+                    }),
+                )
+                .stdin(Stdio::piped());
+
+            match command.spawn() {
+                Ok(mut child) => {
+                    info!(%stream, "Worker process {} started", child.id());
+                    {
+                        let mut worker = worker.lock().unwrap();
+                        worker.stdin = child.stdin.take();
+                        if let Err(e) = worker.replay() {
+                            warn!(%stream, "Unable to configure the worker: {:?}", e);
+                        }
+                    }
+
+                    let status = child.wait();
+                    worker.lock().unwrap().stdin = None;
+                    if dropped.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    error!(%stream, "Worker process exited: {:?}", status);
+                }
+                Err(e) => error!(%stream, "Unable to start the worker process: {:?}", e),
+            }
+
+            let Some(delay) = restart_delay else {
+                break;
+            };
+            std::thread::sleep(delay);
+            if dropped.load(Ordering::Relaxed) {
+                break;
+            }
+        }
+
+        let _ = std::fs::remove_file(&socket_path);
+    });
+}
+
+impl Drop for IsolatedEncoder {
+    fn drop(&mut self) {
+        // Closing its stdin makes the worker exit
+        self.dropped.store(true, Ordering::Relaxed);
+        self.worker.lock().unwrap().stdin = None;
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
+
+impl StreamEncoder for IsolatedEncoder {
+    fn push_frame(&self, frame: &Frame) -> Result<()> {
+        // The worker reads tightly packed rows from the shared memory, and timestamps them
+        let buffer = gst::Buffer::from_slice(frame.packed_data().into_owned());
+        self.appsrc
+            .push_buffer(buffer)
+            .map_err(|e| anyhow!("Failed to push buffer: {:?}", e))?;
+        self.stats.lock().unwrap().frames_pushed += 1;
+
+        Ok(())
+    }
+
+    fn start(&self) -> Result<()> {
+        info!(stream = %self.pipeline.name(), "Start pipeline");
+        self.pipeline.set_state(gst::State::Playing)?;
+
+        let mut worker = self.worker.lock().unwrap();
+        worker.running = true;
+        // The worker is started with its encoder running
+        let _ = worker.send("start");
+
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<()> {
+        info!(stream = %self.pipeline.name(), "Stop pipeline");
+        self.pipeline.set_state(gst::State::Null)?;
+
+        let mut worker = self.worker.lock().unwrap();
+        worker.running = false;
+        let _ = worker.send("stop");
+
+        Ok(())
+    }
+
+    fn resize(&self, width: u32, height: u32) -> Result<()> {
+        if width as usize * height as usize * 4 > self.max_frame_size {
+            return Err(anyhow!(
+                "An isolated encoder can't be resized above its initial size"
+            ));
+        }
+        resize_appsrc(&self.appsrc, width, height)?;
+
+        let mut worker = self.worker.lock().unwrap();
+        worker.width = width;
+        worker.height = height;
+        // A restarting worker is started with the new size
+        let _ = worker.send(&format!("resize {width} {height}"));
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.width = width;
+        stats.height = height;
+
+        Ok(())
+    }
+
+    fn set_bitrate(&self, bitrate: u32) -> Result<()> {
+        let mut worker = self.worker.lock().unwrap();
+        worker.bitrate = Some(bitrate);
+        let _ = worker.send(&format!("bitrate {bitrate}"));
+
+        self.stats.lock().unwrap().bitrate = Some(bitrate);
+
+        Ok(())
+    }
+
+    fn request_keyframe(&self) -> Result<()> {
+        request_appsrc_keyframe(&self.appsrc)?;
+        self.send("keyframe")
+    }
+
+    fn stats(&self) -> Option<EncoderStats> {
+        Some(self.stats.lock().unwrap().clone())
+    }
+
+    /// The state of the pipeline feeding the worker, the worker restarts on its own
+    fn pipeline_state(&self) -> Option<gst::State> {
+        Some(self.pipeline.current_state())
+    }
+}
+
+/// Runs the encoder of an `IsolatedEncoder` and exits if the process is one of its workers,
+/// returns otherwise.
+///
+/// It must be called at the start of `main` by the apps using isolated encoders, before
+/// creating the Bevy app.
+pub fn run_worker_if_requested() {
+    let Ok(socket_path) = std::env::var(WORKER_SOCKET_ENV) else {
+        return;
+    };
+
+    let code = match run_worker(&socket_path) {
+        Ok(()) => 0,
+        Err(e) => {
+            // The worker has no logger, stderr is inherited from the app
+            eprintln!("Isolated encoder worker failed: {e:?}");
+            1
+        }
+    };
+    std::process::exit(code);
+}
+
+/// Pushes the frames read from the shared memory to an encoder, and applies the commands
+/// read from stdin until it is closed
+fn run_worker(socket_path: &str) -> Result<()> {
+    gst::init()?;
+
+    let config = EncoderConfig::from_env()?;
+    let encoder = EncoderRegistry::with_default_backends().create(&config)?;
+
+    let caps = |width: u32, height: u32| -> Result<gst::Caps> {
+        Ok(VideoInfo::builder(VideoFormat::Rgba, width, height)
+            .build()
+            .context("Failed to create video info")?
+            .to_caps()?)
+    };
+    let description = format!(
+        "shmsrc socket-path=\"{socket_path}\" is-live=true do-timestamp=true ! \
+        capsfilter name=caps ! appsink name=sink sync=false drop=true max-buffers=2"
+    );
+    let pipeline = gst::parse::launch(&description)
+        .context("Unable to create the shared memory pipeline")?
+        .downcast::<gst::Pipeline>()
+        .map_err(|_| anyhow!("Failed to cast to pipeline"))?;
+    let capsfilter = pipeline
+        .by_name("caps")
+        .ok_or_else(|| anyhow!("Could not get capsfilter element"))?;
+    capsfilter.set_property("caps", caps(config.width, config.height)?);
+
+    let appsink = pipeline
+        .by_name("sink")
+        .ok_or_else(|| anyhow!("Could not get appsink element"))?
+        .downcast::<gst_app::AppSink>()
+        .map_err(|_| anyhow!("Not an appsink"))?;
+    appsink.set_callbacks(
+        gst_app::AppSinkCallbacks::builder()
+            .new_sample({
+                let encoder = encoder.clone();
+                let mut frame_id = 0;
+                move |appsink| {
+                    let sample = appsink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                    let buffer = sample.buffer().ok_or(gst::FlowError::Error)?;
+                    let info = sample
+                        .caps()
+                        .and_then(|caps| VideoInfo::from_caps(caps).ok())
+                        .ok_or(gst::FlowError::NotNegotiated)?;
+                    let map = buffer.map_readable().map_err(|_| gst::FlowError::Error)?;
+                    let pts = buffer.pts().unwrap_or_default();
+                    let frame = Frame::packed(
+                        &map,
+                        info.width(),
+                        info.height(),
+                        Duration::from_nanos(pts.nseconds()),
+                        frame_id,
+                    );
+                    frame_id += 1;
+                    // Frames are dropped while the encoder is stopped
+                    let _ = encoder.push_frame(&frame);
+                    Ok(gst::FlowSuccess::Ok)
+                }
+            })
+            .build(),
+    );
+    pipeline.set_state(gst::State::Playing)?;
+
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        let mut args = line.split_whitespace();
+        let result = match (args.next(), args.next(), args.next()) {
+            (Some("start"), ..) => encoder.start(),
+            (Some("stop"), ..) => encoder.stop(),
+            (Some("resize"), Some(width), Some(height)) => {
+                let (width, height) = (width.parse()?, height.parse()?);
+                capsfilter.set_property("caps", caps(width, height)?);
+                encoder.resize(width, height)
+            }
+            (Some("bitrate"), Some(bitrate), _) => encoder.set_bitrate(bitrate.parse()?),
+            (Some("keyframe"), ..) => encoder.request_keyframe(),
+            _ => Err(anyhow!("Unknown command {}", line)),
+        };
+        if let Err(e) = result {
+            eprintln!("Isolated encoder worker command {line} failed: {e:?}");
+        }
+    }
+
+    // The app closed stdin, or exited
+    let _ = pipeline.set_state(gst::State::Null);
+    encoder.stop()
+}
@@ -0,0 +1,74 @@
+use bevy_ecs::prelude::*;
+use bevy_platform::collections::HashMap;
+use gst::prelude::*;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::PeerLatency;
+
+/// Interval between two reads of the sink stats
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Returns the round-trip time found in the stats of a consumer, reported by RTCP
+fn find_round_trip_time(structure: &gst::StructureRef) -> Option<f64> {
+    structure.iter().find_map(|(field, value)| {
+        if field == "round-trip-time" {
+            value.get::<f64>().ok().filter(|rtt| *rtt > 0.)
+        } else {
+            value
+                .get::<gst::Structure>()
+                .ok()
+                .and_then(|structure| find_round_trip_time(&structure))
+        }
+    })
+}
+
+/// Tracks the round-trip time of the peers of a streamer camera, from the stats of its sink
+#[derive(Component, Clone, Default)]
+pub(crate) struct PeerLatencyTracker {
+    rtts: Arc<Mutex<HashMap<String, Duration>>>,
+}
+
+impl PeerLatencyTracker {
+    /// Reads the stats of `sink`, a `webrtcsink` or one of its variants, until it is disposed
+    pub(crate) fn connect(&self, sink: &gst::Element) {
+        if sink.find_property("stats").is_none() {
+            return;
+        }
+
+        let sink = sink.downgrade();
+        let rtts = Arc::downgrade(&self.rtts);
+        std::thread::spawn(move || {
+            loop {
+                std::thread::sleep(STATS_INTERVAL);
+
+                let (Some(sink), Some(rtts)) = (sink.upgrade(), rtts.upgrade()) else {
+                    break;
+                };
+
+                // One field per session
+                let stats = sink.property::<gst::Structure>("stats");
+                let current = stats
+                    .iter()
+                    .filter_map(|(session_id, value)| {
+                        let consumer_stats = value.get::<gst::Structure>().ok()?;
+                        let rtt = find_round_trip_time(&consumer_stats)?;
+                        Some((session_id.to_string(), Duration::from_secs_f64(rtt)))
+                    })
+                    .collect();
+
+                *rtts.lock().unwrap() = current;
+            }
+        });
+    }
+}
+
+/// This system copies the round-trip times collected from the sinks to `PeerLatency`
+pub(crate) fn update_peer_latencies(mut latencies: Query<(&PeerLatencyTracker, &mut PeerLatency)>) {
+    for (tracker, mut latency) in latencies.iter_mut() {
+        let current = tracker.rtts.lock().unwrap().clone();
+        latency.set_if_neq(PeerLatency(current));
+    }
+}
@@ -8,22 +8,105 @@ use bevy_picking::PickSet;
 use bevy_render::{Render, RenderApp, RenderSet, prelude::*, render_graph::RenderGraph};
 #[cfg(feature = "pixelstreaming")]
 use bevy_window::{PrimaryWindow, WindowEvent, prelude::*};
+use std::time::Instant;
 
 use capture::{
     capture_extract,
     driver::{CaptureDriver, CaptureLabel},
 };
 
+#[cfg(feature = "audit")]
+mod audit;
+mod auth;
+mod budget;
 mod capture;
+mod chapters;
+#[cfg(feature = "pixelstreaming")]
+mod chat;
+#[cfg(feature = "color-validation")]
+mod color_validation;
+mod components;
+mod connection;
+#[cfg(feature = "pixelstreaming")]
+mod console;
+mod consumer;
+#[cfg(feature = "control-api")]
+mod control;
+mod doctor;
+#[cfg(feature = "encryption")]
+mod encryption;
+mod events;
+mod gpu;
+mod health;
 mod helper;
+#[cfg(feature = "identity-store")]
+mod identity;
+#[cfg(feature = "pixelstreaming")]
+mod inject;
+#[cfg(feature = "pixelstreaming")]
+mod input_record;
+mod latency;
+mod markers;
+#[cfg(feature = "window-mirror")]
+mod mirror;
+#[cfg(feature = "cuda")]
+mod nvenc;
+mod pacing;
+mod pause;
+mod peers;
+mod pipeline_log;
+#[cfg(feature = "local-preview")]
+mod preview;
+#[cfg(any(feature = "pixelstreaming", feature = "livekit"))]
+mod proxy;
+mod registry;
+#[cfg(feature = "pixelstreaming")]
+mod replication;
+mod resolution;
+mod sdp;
+mod sessions;
 mod settings;
-
-pub mod gst_webrtc_encoder;
 #[cfg(feature = "pixelstreaming")]
-mod pixelstreaming;
+mod stream_window;
+#[cfg(feature = "otlp")]
+mod telemetry;
+mod test_pattern;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+#[cfg(feature = "texture-sharing")]
+mod texture_sharing;
+mod transport;
+#[cfg(feature = "pixelstreaming")]
+mod ui_pointer;
+#[cfg(feature = "streamed-ui")]
+mod ui_target;
+#[cfg(feature = "upload")]
+mod upload;
+mod viewers;
+
+pub mod callback;
+pub mod custom_pipeline;
 pub mod encoder;
+pub mod gst_webrtc_encoder;
+#[cfg(unix)]
+pub mod isolated;
 #[cfg(feature = "livekit")]
 pub mod livekit;
+#[cfg(feature = "moq")]
+pub mod moq;
+#[cfg(feature = "ndi")]
+pub mod ndi;
+#[cfg(feature = "pixelstreaming")]
+pub mod pixelstreaming;
+pub mod record;
+pub mod rtmp;
+pub mod rtp_udp;
+#[cfg(feature = "rtsp")]
+pub mod rtsp;
+#[cfg(unix)]
+pub mod shm;
+#[cfg(target_os = "linux")]
+pub mod v4l2;
 
 #[derive(Component)]
 enum ControllerState {
@@ -31,8 +114,109 @@ enum ControllerState {
     #[cfg(feature = "pixelstreaming")]
     PSControllerState(PSControllerState),
 }
+
+impl ControllerState {
+    /// Sends a message to every peer connected to this controller
+    #[cfg(feature = "pixelstreaming")]
+    fn broadcast(&self, message: &pixelstreaming::message::PSOutgoingMessage) {
+        match self {
+            ControllerState::None => {}
+            ControllerState::PSControllerState(ue_controller_state) => {
+                for handler in ue_controller_state.handlers.values() {
+                    handler.send(message);
+                }
+            }
+        }
+    }
+
+    /// Returns true if the input of the peers is received by this controller
+    fn receives_input(&self) -> bool {
+        !matches!(self, ControllerState::None)
+    }
+
+    /// Returns when a peer last sent a message, `None` if it sent none
+    fn last_input(&self, peer_id: &str) -> Option<Instant> {
+        match self {
+            ControllerState::None => None,
+            #[cfg(feature = "pixelstreaming")]
+            ControllerState::PSControllerState(ue_controller_state) => {
+                ue_controller_state.last_input.get(peer_id).copied()
+            }
+        }
+    }
+
+    /// Sends a message to a single peer connected to this controller
+    #[cfg(feature = "pixelstreaming")]
+    fn send_to(&self, peer_id: &str, message: &pixelstreaming::message::PSOutgoingMessage) {
+        match self {
+            ControllerState::None => {}
+            ControllerState::PSControllerState(ue_controller_state) => {
+                if let Some(handler) = ue_controller_state.handlers.get(peer_id) {
+                    handler.send(message);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "audit")]
+pub use audit::{AuditEvent, AuditLog, AuditRecord, AuditSink, JsonLinesAuditSink};
+pub use auth::*;
+pub use budget::{GpuBudgetAction, GpuMemoryBudget};
+pub use capture::grading::StreamGrading;
+#[cfg(feature = "pixelstreaming")]
+pub use chat::{Chat, ChatMessageReceived, ChatPlugin, ChatSender, SendChatMessage};
+#[cfg(feature = "color-validation")]
+pub use color_validation::{ColorIssue, ColorStage, ColorValidation, ColorValidationReport};
+pub use components::*;
+#[cfg(feature = "pixelstreaming")]
+pub use console::*;
+pub use consumer::*;
+#[cfg(feature = "control-api")]
+pub use control::ControlApiPlugin;
+pub use doctor::{CapabilityStatus, StreamingCapabilities, doctor};
+#[cfg(feature = "encryption")]
+pub use encryption::RecordEncryption;
+pub use events::*;
+pub use gpu::{GpuSelection, GpuSettings, SelectedAdapter};
+pub use health::{
+    HealthIssue, HealthState, StreamHealth, StreamHealthChanged, StreamHealthSettings,
+};
 pub use helper::*;
+#[cfg(feature = "identity-store")]
+pub use identity::{StreamIdentities, StreamIdentity};
+#[cfg(feature = "pixelstreaming")]
+pub use inject::*;
+#[cfg(feature = "pixelstreaming")]
+pub use input_record::*;
+#[cfg(feature = "window-mirror")]
+pub use mirror::WindowMirror;
+#[cfg(feature = "cuda")]
+pub use nvenc::NvencCapabilities;
+pub use pacing::{BackpressurePacingPlugin, FramePacing};
+pub use pause::*;
+pub use pipeline_log::PIPELINE_LOG_TARGET;
+#[cfg(feature = "local-preview")]
+pub use preview::LocalPreview;
+pub use registry::*;
+#[cfg(feature = "pixelstreaming")]
+pub use replication::*;
+pub use sdp::*;
 pub use settings::*;
+#[cfg(feature = "pixelstreaming")]
+pub use stream_window::{StreamWindow, StreamWindowOf};
+#[cfg(feature = "otlp")]
+pub use telemetry::{OtlpPlugin, OtlpSettings, otlp_tracing_layer};
+pub use test_pattern::*;
+#[cfg(feature = "texture-sharing")]
+pub use texture_sharing::SharedTexture;
+pub use transport::*;
+#[cfg(feature = "pixelstreaming")]
+pub use ui_pointer::UiPointer;
+#[cfg(feature = "streamed-ui")]
+pub use ui_target::DefaultUiStream;
+#[cfg(feature = "upload")]
+pub use upload::UploadSettings;
 
 #[cfg(feature = "pixelstreaming")]
 use pixelstreaming::{
@@ -44,6 +228,8 @@ use pixelstreaming::{
 use crate::capture::{
     ReleaseBufferSignal, WorkerSendBuffer,
     driver::{receive_image_from_buffer, release_mapped_buffers},
+    grading::{GradedTargets, GradingPipeline, prepare_graded_targets},
+    letterbox::{LetterboxTargets, prepare_letterbox_targets},
     spawn_worker,
 };
 
@@ -63,23 +249,156 @@ impl Plugin for StreamerPlugin {
         graph.add_node_edge(bevy_render::graph::CameraDriverLabel, CaptureLabel);
 
         render_app
+            .init_resource::<GradedTargets>()
+            .init_resource::<LetterboxTargets>()
             .add_systems(ExtractSchedule, capture_extract)
             .add_systems(
                 Render,
                 (
+                    prepare_graded_targets.in_set(RenderSet::PrepareBindGroups),
+                    prepare_letterbox_targets.in_set(RenderSet::PrepareResources),
                     receive_image_from_buffer.after(RenderSet::Render),
                     release_mapped_buffers.after(RenderSet::Render),
                 ),
             );
+        #[cfg(feature = "texture-sharing")]
+        render_app
+            .init_resource::<texture_sharing::SharedTextureSenders>()
+            .add_systems(ExtractSchedule, texture_sharing::extract_shared_textures)
+            .add_systems(
+                Render,
+                texture_sharing::publish_shared_textures
+                    .after(RenderSet::Render)
+                    .before(RenderSet::Cleanup),
+            );
 
         #[cfg(feature = "pixelstreaming")]
         {
+            app.add_event::<StreamerUiInteraction>();
+            app.add_event::<StreamerCommand>();
+            app.add_event::<InjectInput>();
+            app.init_resource::<RemoteConsole>();
             app.add_systems(
                 PreUpdate,
-                (handle_controller_messages.in_set(PickSet::Input),),
+                (
+                    handle_controller_messages.in_set(PickSet::Input),
+                    console::dispatch_remote_commands.after(handle_controller_messages),
+                    input_record::replay_inputs.before(handle_controller_messages),
+                    inject::inject_inputs.before(handle_controller_messages),
+                    stream_window::update_stream_windows.before(handle_controller_messages),
+                    stream_window::remove_stream_windows,
+                    ui_pointer::forward_ui_pointers
+                        .in_set(PickSet::Input)
+                        .after(handle_controller_messages),
+                    ui_pointer::remove_ui_pointers,
+                ),
+            );
+            app.add_systems(PostUpdate, pixelstreaming::load::report_instance_load);
+        }
+        #[cfg(feature = "cuda")]
+        {
+            if !app.world().contains_resource::<NvencCapabilities>() {
+                app.insert_resource(NvencCapabilities::detect());
+            }
+            nvenc::set_max_sessions(app.world().resource::<NvencCapabilities>().max_sessions);
+            app.add_event::<NvencSessionLimitReached>();
+            app.add_event::<EncoderFallback>();
+            app.add_systems(
+                PostUpdate,
+                (nvenc::send_limit_events, nvenc::send_fallback_events),
             );
         }
-        app.add_systems(PostUpdate, handle_controllers);
+        #[cfg(feature = "audit")]
+        app.add_systems(
+            PostUpdate,
+            (audit::record_viewer_changes, audit::record_stream_states),
+        );
+        #[cfg(feature = "window-mirror")]
+        app.add_systems(
+            PostUpdate,
+            mirror::mirror_window_cameras
+                .after(bevy_transform::TransformSystem::TransformPropagate),
+        );
+        #[cfg(feature = "color-validation")]
+        app.add_systems(PreUpdate, color_validation::apply_color_validations);
+        #[cfg(feature = "identity-store")]
+        app.add_systems(PostUpdate, identity::save_stream_identities);
+        #[cfg(feature = "local-preview")]
+        app.add_systems(
+            PostUpdate,
+            (
+                preview::update_local_previews,
+                preview::remove_local_previews,
+            ),
+        );
+        app.insert_resource(EncoderRegistry::with_default_backends());
+        app.add_systems(Startup, doctor::report_capabilities);
+        app.add_systems(Startup, gpu::log_selected_adapter);
+        app.add_event::<StreamerCameraReady>();
+        app.add_event::<RecordingFinalized>();
+        app.add_event::<RecordingLimitReached>();
+        app.add_event::<GpuMemoryBudgetExceeded>();
+        app.add_event::<StreamerStandby>();
+        app.add_event::<StreamerResumed>();
+        app.add_event::<StreamerResolutionRequest>();
+        app.add_event::<PeerSessionExpired>();
+        app.add_event::<StreamHealthChanged>();
+        app.add_event::<StreamMarker>();
+        app.add_systems(
+            PreUpdate,
+            (
+                viewers::update_viewer_counts,
+                viewers::apply_standby_policies.after(viewers::update_viewer_counts),
+                sessions::apply_session_policies,
+                capture::apply_held_frames,
+                capture::timing::update_capture_gpu_times,
+                capture::apply_frame_skip_policies,
+                capture::grading::apply_stream_grading,
+                test_pattern::apply_test_patterns,
+                connection::update_connection_infos,
+                health::update_stream_health.after(connection::update_connection_infos),
+                peers::update_peer_metadata,
+                latency::update_peer_latencies,
+            ),
+        );
+        app.add_systems(
+            PostUpdate,
+            (
+                callback::assign_callback_cameras,
+                capture::attach_pending_encoders,
+                handle_controllers,
+                poll_pending_streamers,
+                record::poll_recording_outputs,
+                markers::apply_stream_markers,
+                resolution::apply_resolution_requests,
+            ),
+        );
+    }
+
+    fn finish(&self, app: &mut bevy_app::App) {
+        // The pipeline needs the render device, which is created once the plugins are built
+        app.sub_app_mut(RenderApp)
+            .init_resource::<GradingPipeline>();
+    }
+}
+
+/// This system sends `StreamerCameraReady` once the pipelines created in the background are started
+fn poll_pending_streamers(
+    mut commands: Commands,
+    pending: Query<(Entity, &PendingStreamer)>,
+    mut ready_events: EventWriter<StreamerCameraReady>,
+) {
+    for (camera, pending) in pending.iter() {
+        let result = match pending.receiver.try_recv() {
+            Ok(result) => result,
+            Err(crossbeam_channel::TryRecvError::Empty) => continue,
+            Err(crossbeam_channel::TryRecvError::Disconnected) => {
+                Err("Pipeline creation thread exited".to_string())
+            }
+        };
+
+        commands.entity(camera).remove::<PendingStreamer>();
+        ready_events.write(StreamerCameraReady { camera, result });
     }
 }
 
@@ -96,7 +415,10 @@ fn handle_controllers(mut controllers: Query<&mut ControllerState>) {
                     // add / remove handlers
                     match handler {
                         Some(handler) => ue_controller_state.handlers.insert(peer_id, handler),
-                        None => ue_controller_state.handlers.remove(&peer_id),
+                        None => {
+                            ue_controller_state.last_input.remove(&peer_id);
+                            ue_controller_state.handlers.remove(&peer_id)
+                        }
                     };
                 }
             }
@@ -107,100 +429,293 @@ fn handle_controllers(mut controllers: Query<&mut ControllerState>) {
 /// This system process controller's messages
 #[cfg(feature = "pixelstreaming")]
 fn handle_controller_messages(
-    mut controllers: Query<(&Camera, &mut ControllerState)>,
-    windows: Query<(Entity, &Window), With<PrimaryWindow>>,
+    mut controllers: Query<(
+        Entity,
+        &Camera,
+        &mut ControllerState,
+        Option<&mut InputRecorder>,
+        Option<&mut UiPointer>,
+        Option<&StreamWindow>,
+    )>,
+    primary_window: Query<Entity, With<PrimaryWindow>>,
+    mut stream_windows: Query<&mut Window, With<StreamWindowOf>>,
     #[cfg(feature = "pixelstreaming")] ps_conversions: PSConversions,
     mut mouse_motion_event: EventWriter<MouseMotion>,
     mut mouse_button_input_events: EventWriter<MouseButtonInput>,
     mut mouse_wheel_events: EventWriter<MouseWheel>,
     mut window_events: EventWriter<WindowEvent>,
     mut keyboard_input_events: EventWriter<KeyboardInput>,
+    mut ui_interaction_events: EventWriter<StreamerUiInteraction>,
+    mut command_events: EventWriter<StreamerCommand>,
+    mut resolution_events: EventWriter<StreamerResolutionRequest>,
 ) {
-    let window = windows.single().unwrap().0;
+    let primary_window = primary_window.single().ok();
 
-    for (camera, mut controller) in controllers.iter_mut() {
+    for (entity, camera, mut controller, mut recorder, mut ui_pointer, stream_window) in
+        controllers.iter_mut()
+    {
+        // The input targets the virtual window of the camera, once spawned
+        let Some(window) = stream_window
+            .map(|stream_window| stream_window.0)
+            .or(primary_window)
+        else {
+            continue;
+        };
         let controller = controller.as_mut();
         match controller {
             ControllerState::None => {}
             #[cfg(feature = "pixelstreaming")]
             ControllerState::PSControllerState(ue_controller_state) => {
-                for (_peer_id, handler) in ue_controller_state.handlers.iter() {
-                    for ue_msg in handler.message_receiver.try_iter() {
-                        match ue_msg {
-                            PSMessage::MouseMove(mouse_move) => {
-                                mouse_motion_event.write(MouseMotion {
-                                    delta: ps_conversions.from_ps_delta(
-                                        camera,
-                                        mouse_move.delta_x,
-                                        mouse_move.delta_y,
-                                    ),
-                                });
-                                window_events.write(WindowEvent::CursorMoved(CursorMoved {
-                                    window,
-                                    position: ps_conversions.from_ps_position(
-                                        camera,
-                                        mouse_move.x,
-                                        mouse_move.y,
-                                    ),
-                                    delta: Some(ps_conversions.from_ps_delta(
-                                        camera,
-                                        mouse_move.delta_x,
-                                        mouse_move.delta_y,
-                                    )),
-                                }));
+                let received = ue_controller_state
+                    .handlers
+                    .iter()
+                    .flat_map(|(peer_id, handler)| {
+                        handler
+                            .message_receiver
+                            .try_iter()
+                            .map(|ue_msg| (peer_id.clone(), ue_msg))
+                    })
+                    .chain(ue_controller_state.injected_receiver.try_iter())
+                    .collect::<Vec<_>>();
+
+                let now = Instant::now();
+                for (peer_id, ue_msg) in received {
+                    ue_controller_state.last_input.insert(peer_id.clone(), now);
+                    if let Some(recorder) = recorder.as_mut() {
+                        recorder.record(&peer_id, &ue_msg);
+                    }
+
+                    match ue_msg {
+                        PSMessage::MouseMove(mouse_move) => {
+                            let position =
+                                ps_conversions.from_ps_position(camera, mouse_move.x, mouse_move.y);
+                            let delta = ps_conversions.from_ps_delta(
+                                camera,
+                                mouse_move.delta_x,
+                                mouse_move.delta_y,
+                            );
+                            mouse_motion_event.write(MouseMotion { delta });
+                            window_events.write(WindowEvent::CursorMoved(CursorMoved {
+                                window,
+                                position,
+                                delta: Some(delta),
+                            }));
+                            if let Ok(mut stream_window) = stream_windows.get_mut(window) {
+                                stream_window
+                                    .set_physical_cursor_position(Some(position.as_dvec2()));
                             }
-                            PSMessage::MouseDown(mouse_down) => {
-                                mouse_button_input_events.write(MouseButtonInput {
-                                    button: ps_conversions.ps_to_mouse_button(mouse_down.button),
-                                    state: bevy_input::ButtonState::Pressed,
-                                    window,
-                                });
+                            if let Some(ui_pointer) = ui_pointer.as_mut() {
+                                ui_pointer.moved(position, delta);
                             }
-                            PSMessage::MouseUp(mouse_up) => {
-                                mouse_button_input_events.write(MouseButtonInput {
-                                    button: ps_conversions.ps_to_mouse_button(mouse_up.button),
-                                    state: bevy_input::ButtonState::Released,
-                                    window,
-                                });
+                        }
+                        PSMessage::MouseDown(mouse_down) => {
+                            mouse_button_input_events.write(MouseButtonInput {
+                                button: ps_conversions.ps_to_mouse_button(mouse_down.button),
+                                state: bevy_input::ButtonState::Pressed,
+                                window,
+                            });
+                            if let Some(ui_pointer) = ui_pointer.as_mut() {
+                                ui_pointer.button(
+                                    ps_conversions.ps_to_pointer_button(mouse_down.button),
+                                    true,
+                                );
                             }
-                            PSMessage::UiInteraction(_ui_interaction) => {}
-                            PSMessage::Command(_command) => {}
-                            PSMessage::KeyDown(key_down) => {
-                                keyboard_input_events.write(KeyboardInput {
-                                    key_code: PSKeyCode(key_down.key_code).into(),
-                                    logical_key: PSKeyCode(key_down.key_code).into(),
-                                    state: bevy_input::ButtonState::Pressed,
-                                    repeat: key_down.is_repeat == 1,
-                                    window,
-                                    text: None,
-                                });
+                        }
+                        PSMessage::MouseUp(mouse_up) => {
+                            mouse_button_input_events.write(MouseButtonInput {
+                                button: ps_conversions.ps_to_mouse_button(mouse_up.button),
+                                state: bevy_input::ButtonState::Released,
+                                window,
+                            });
+                            if let Some(ui_pointer) = ui_pointer.as_mut() {
+                                ui_pointer.button(
+                                    ps_conversions.ps_to_pointer_button(mouse_up.button),
+                                    false,
+                                );
                             }
-                            PSMessage::KeyUp(key_up) => {
-                                keyboard_input_events.write(KeyboardInput {
-                                    key_code: PSKeyCode(key_up.key_code).into(),
-                                    logical_key: PSKeyCode(key_up.key_code).into(),
-                                    state: bevy_input::ButtonState::Released,
-                                    repeat: false,
-                                    window,
-                                    text: None,
+                        }
+                        PSMessage::UiInteraction(ui_interaction) => {
+                            ui_interaction_events.write(StreamerUiInteraction {
+                                camera: entity,
+                                peer_id,
+                                message: ui_interaction.message,
+                            });
+                        }
+                        PSMessage::Command(command) => {
+                            if let Some((width, height)) = command.resolution() {
+                                resolution_events.write(StreamerResolutionRequest {
+                                    camera: entity,
+                                    peer_id: peer_id.clone(),
+                                    width,
+                                    height,
                                 });
                             }
-                            PSMessage::KeyPress(_key_press) => {}
-                            PSMessage::MouseEnter => {}
-                            PSMessage::MouseLeave => {}
-                            PSMessage::MouseWheel(mouse_wheel) => {
-                                mouse_wheel_events.write(MouseWheel {
-                                    unit: bevy_input::mouse::MouseScrollUnit::Pixel,
-                                    x: 0_f32,
-                                    y: mouse_wheel.delta as f32 / 10.0,
-                                    window,
-                                });
+                            command_events.write(StreamerCommand {
+                                camera: entity,
+                                peer_id,
+                                command: command.command,
+                            });
+                        }
+                        PSMessage::KeyDown(key_down) => {
+                            keyboard_input_events.write(KeyboardInput {
+                                key_code: PSKeyCode(key_down.key_code).into(),
+                                logical_key: PSKeyCode(key_down.key_code).into(),
+                                state: bevy_input::ButtonState::Pressed,
+                                repeat: key_down.is_repeat == 1,
+                                window,
+                                text: None,
+                            });
+                        }
+                        PSMessage::KeyUp(key_up) => {
+                            keyboard_input_events.write(KeyboardInput {
+                                key_code: PSKeyCode(key_up.key_code).into(),
+                                logical_key: PSKeyCode(key_up.key_code).into(),
+                                state: bevy_input::ButtonState::Released,
+                                repeat: false,
+                                window,
+                                text: None,
+                            });
+                        }
+                        PSMessage::KeyPress(_key_press) => {}
+                        PSMessage::MouseEnter => {}
+                        PSMessage::MouseLeave => {
+                            if let Ok(mut stream_window) = stream_windows.get_mut(window) {
+                                stream_window.set_physical_cursor_position(None);
                             }
-                            PSMessage::MouseDouble(_mouse_double) => {}
                         }
+                        PSMessage::MouseWheel(mouse_wheel) => {
+                            let y = mouse_wheel.delta as f32 / 10.0;
+                            mouse_wheel_events.write(MouseWheel {
+                                unit: bevy_input::mouse::MouseScrollUnit::Pixel,
+                                x: 0_f32,
+                                y,
+                                window,
+                            });
+                            if let Some(ui_pointer) = ui_pointer.as_mut() {
+                                ui_pointer.scrolled(y);
+                            }
+                        }
+                        PSMessage::MouseDouble(_mouse_double) => {}
                     }
                 }
             }
         }
     }
 }
+
+#[cfg(feature = "gst-plugin")]
+fn plugin_init(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    pixelstreaming::register(Some(plugin))
+}
+
+// The plugin is loaded from `libbevy_streaming.so`, see the README
+#[cfg(feature = "gst-plugin")]
+gst::plugin_define!(
+    bevy_streaming,
+    env!("CARGO_PKG_DESCRIPTION"),
+    plugin_init,
+    env!("CARGO_PKG_VERSION"),
+    "MIT/X11",
+    env!("CARGO_PKG_NAME"),
+    env!("CARGO_PKG_NAME"),
+    "https://github.com/rlamarche/bevy_streaming"
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::{DeferredEncoder, Frame, StreamEncoder};
+    use anyhow::Result;
+    use bevy_ecs::{event::Events, system::RunSystemOnce};
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    #[derive(Default)]
+    struct CountingEncoder {
+        frames: AtomicUsize,
+    }
+
+    impl StreamEncoder for CountingEncoder {
+        fn push_frame(&self, _frame: &Frame) -> Result<()> {
+            self.frames.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn start(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn push(encoder: &DeferredEncoder, id: u64) {
+        let data = [0; 4];
+        let frame = Frame::packed(&data, 1, 1, Duration::ZERO, id);
+        encoder.push_frame(&frame).unwrap();
+    }
+
+    fn ready_events(world: &mut World) -> Vec<StreamerCameraReady> {
+        world.run_system_once(poll_pending_streamers).unwrap();
+        world.flush();
+        world
+            .resource_mut::<Events<StreamerCameraReady>>()
+            .drain()
+            .collect()
+    }
+
+    #[test]
+    fn deferred_streamer_becomes_ready() {
+        let mut world = World::new();
+        world.init_resource::<Events<StreamerCameraReady>>();
+
+        let deferred = Arc::new(DeferredEncoder::default());
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        let camera = world.spawn(PendingStreamer { receiver }).id();
+
+        // Frames are dropped until the pipeline is created
+        push(&deferred, 0);
+        assert!(ready_events(&mut world).is_empty());
+        assert!(world.entity(camera).contains::<PendingStreamer>());
+
+        let encoder = Arc::new(CountingEncoder::default());
+        std::thread::spawn({
+            let deferred = deferred.clone();
+            let encoder = encoder.clone();
+            move || {
+                deferred.set(encoder);
+                sender.send(Ok(())).unwrap();
+            }
+        })
+        .join()
+        .unwrap();
+
+        let events = ready_events(&mut world);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].camera, camera);
+        assert_eq!(events[0].result, Ok(()));
+        assert!(!world.entity(camera).contains::<PendingStreamer>());
+
+        assert!(deferred.is_ready());
+        push(&deferred, 1);
+        assert_eq!(encoder.frames.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn deferred_streamer_fails_when_the_thread_exits() {
+        let mut world = World::new();
+        world.init_resource::<Events<StreamerCameraReady>>();
+
+        let (sender, receiver) = crossbeam_channel::bounded::<Result<(), String>>(1);
+        let camera = world.spawn(PendingStreamer { receiver }).id();
+        drop(sender);
+
+        let events = ready_events(&mut world);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].camera, camera);
+        assert!(events[0].result.is_err());
+        assert!(!world.entity(camera).contains::<PendingStreamer>());
+    }
+}
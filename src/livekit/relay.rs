@@ -0,0 +1,153 @@
+use anyhow::{Context, Result, anyhow, bail};
+use bevy_log::prelude::*;
+use gstrswebrtc::RUNTIME;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, copy_bidirectional},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+use url::Url;
+
+use crate::proxy;
+
+/// Longest websocket handshake request relayed
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// Relays the websocket connections of the signaller of `livekitwebrtcsink`, which connects
+/// directly, to a LiveKit server through a proxy.
+///
+/// The signaller connects to the local `url` in plain text, the relay connects to the server
+/// through the proxy, over TLS for a `wss://` server, and rewrites the `Host` header of the
+/// handshake. Stops relaying when dropped.
+pub(super) struct ProxyRelay {
+    url: String,
+    task: JoinHandle<()>,
+}
+
+impl ProxyRelay {
+    pub(super) fn new(server: &str, proxy: &str) -> Result<Self> {
+        let mut url =
+            Url::parse(server).with_context(|| format!("Invalid LiveKit URL {server}"))?;
+        let proxy = Url::parse(proxy).with_context(|| format!("Invalid proxy URI {proxy}"))?;
+        let host = url
+            .host_str()
+            .ok_or_else(|| anyhow!("No host in the LiveKit URL {server}"))?
+            .to_string();
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| anyhow!("No port in the LiveKit URL {server}"))?;
+        let tls = match url.scheme() {
+            "wss" => true,
+            "ws" => false,
+            scheme => bail!("Unsupported LiveKit URL scheme {scheme}"),
+        };
+        let authority = match url.port() {
+            Some(port) => format!("{host}:{port}"),
+            None => host.clone(),
+        };
+
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0))?;
+        listener.set_nonblocking(true)?;
+        let local = listener.local_addr()?;
+        let _runtime = RUNTIME.enter();
+        let listener = TcpListener::from_std(listener)?;
+        let task = RUNTIME.spawn(async move {
+            loop {
+                let client = match listener.accept().await {
+                    Ok((client, _)) => client,
+                    Err(e) => {
+                        warn!("Unable to accept the LiveKit signaller connection: {:?}", e);
+                        continue;
+                    }
+                };
+                let (proxy, host, authority) = (proxy.clone(), host.clone(), authority.clone());
+                RUNTIME.spawn(async move {
+                    if let Err(e) = relay(client, &proxy, &host, port, tls, &authority).await {
+                        warn!("LiveKit connection through the proxy failed: {:?}", e);
+                    }
+                });
+            }
+        });
+
+        url.set_scheme("ws")
+            .map_err(|_| anyhow!("Unable to relay {server}"))?;
+        url.set_host(Some(&local.ip().to_string()))?;
+        url.set_port(Some(local.port()))
+            .map_err(|_| anyhow!("Unable to relay {server}"))?;
+
+        Ok(Self {
+            url: url.to_string(),
+            task,
+        })
+    }
+
+    /// Returns the URL the signaller connects to
+    pub(super) fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+impl Drop for ProxyRelay {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Relays `client` to the server `host`:`port` through `proxy`
+async fn relay(
+    mut client: TcpStream,
+    proxy: &Url,
+    host: &str,
+    port: u16,
+    tls: bool,
+    authority: &str,
+) -> Result<()> {
+    let request = read_request(&mut client, authority).await?;
+    let stream = proxy::connect(proxy, host, port, &[]).await?;
+
+    if tls {
+        let connector = tokio_native_tls::TlsConnector::from(
+            tokio_native_tls::native_tls::TlsConnector::new()?,
+        );
+        let stream = connector.connect(host, stream).await?;
+        forward(client, stream, &request).await
+    } else {
+        forward(client, stream, &request).await
+    }
+}
+
+/// Sends the handshake `request` to the server, then copies the data both ways
+async fn forward<S: AsyncRead + AsyncWrite + Unpin>(
+    mut client: TcpStream,
+    mut server: S,
+    request: &[u8],
+) -> Result<()> {
+    server.write_all(request).await?;
+    copy_bidirectional(&mut client, &mut server).await?;
+
+    Ok(())
+}
+
+/// Reads the handshake request of the signaller, with the `Host` header of the server
+async fn read_request(client: &mut TcpStream, authority: &str) -> Result<Vec<u8>> {
+    // Read byte by byte so that nothing after the request header is consumed
+    let mut request = Vec::new();
+    while !request.ends_with(b"\r\n\r\n") {
+        if request.len() >= MAX_REQUEST_SIZE {
+            bail!("The websocket handshake request is too long");
+        }
+        request.push(client.read_u8().await?);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let rewritten = request
+        .split("\r\n")
+        .map(|line| match line.split_once(':') {
+            Some((name, _)) if name.eq_ignore_ascii_case("host") => format!("Host: {authority}"),
+            _ => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\r\n");
+
+    Ok(rewritten.into_bytes())
+}
@@ -0,0 +1,55 @@
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use bevy_render::prelude::*;
+
+#[cfg(feature = "pixelstreaming")]
+use crate::pixelstreaming::message::PSOutgoingMessage;
+use crate::{ControllerState, StreamMarker, capture::Capture};
+
+/// Sends a marker to the peers of a camera, as `{"event": "marker", "data": {...}}`
+#[cfg(feature = "pixelstreaming")]
+fn broadcast_marker(controller: &ControllerState, name: &str, frame_id: u64) {
+    let marker = serde_json::json!({
+        "event": "marker",
+        "data": {
+            "name": name,
+            "frame_id": frame_id,
+        },
+    });
+    controller.broadcast(&PSOutgoingMessage::Response(marker.to_string()));
+}
+
+#[cfg(not(feature = "pixelstreaming"))]
+fn broadcast_marker(_controller: &ControllerState, _name: &str, _frame_id: u64) {}
+
+/// This system adds the `StreamMarker`s to the frames captured by their cameras, and sends
+/// them to the peers
+pub(crate) fn apply_stream_markers(
+    mut markers: EventReader<StreamMarker>,
+    cameras: Query<(&Camera, Option<&ControllerState>)>,
+    captures: Query<&Capture>,
+) {
+    for marker in markers.read() {
+        let Ok((camera, controller)) = cameras.get(marker.camera) else {
+            warn!(
+                "Marker {:?} of an unknown camera {}",
+                marker.name, marker.camera
+            );
+            continue;
+        };
+        let Some(capture) = camera
+            .target
+            .as_image()
+            .and_then(|image| captures.iter().find(|c| c.src_image() == image))
+        else {
+            warn!("Marker {:?} of a camera which is not captured", marker.name);
+            continue;
+        };
+
+        let frame_id = capture.add_marker(marker.name.clone());
+        debug!("Marker {:?} added to frame {}", marker.name, frame_id);
+        if let Some(controller) = controller {
+            broadcast_marker(controller, &marker.name, frame_id);
+        }
+    }
+}
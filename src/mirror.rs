@@ -0,0 +1,96 @@
+use bevy_core_pipeline::{core_2d::Camera2d, core_3d::Camera3d, tonemapping::Tonemapping};
+use bevy_ecs::prelude::*;
+use bevy_render::{
+    camera::{Camera, Projection, RenderTarget},
+    view::Msaa,
+};
+use bevy_transform::prelude::*;
+use bevy_window::{PrimaryWindow, WindowRef};
+
+/// Makes a streamer camera mirror the camera rendering to a window, so that desktop tools can
+/// broadcast what one of their windows shows. See `StreamerHelper::new_window_mirror`.
+///
+/// The streamer camera follows the transform and the projection of the window camera with
+/// the lowest `order`, the cameras rendering on top of it (e.g. the UI) are not mirrored. The
+/// stream keeps its own size, use the physical size of the window for an exact copy.
+#[derive(Component, Clone, Debug)]
+pub struct WindowMirror {
+    pub window: WindowRef,
+}
+
+impl Default for WindowMirror {
+    fn default() -> Self {
+        Self {
+            window: WindowRef::Primary,
+        }
+    }
+}
+
+/// The window camera mirrored by a streamer camera
+#[derive(Component)]
+pub(crate) struct MirroredCamera(Entity);
+
+type MirrorItem<'a> = (
+    Entity,
+    &'a WindowMirror,
+    Option<&'a MirroredCamera>,
+    &'a mut Camera,
+    &'a mut Transform,
+    &'a mut GlobalTransform,
+    Option<&'a mut Projection>,
+);
+
+type SourceItem<'a> = (
+    Entity,
+    &'a Camera,
+    &'a GlobalTransform,
+    Option<Ref<'a, Projection>>,
+);
+
+/// This system copies the view of the window cameras to the streamer cameras mirroring them
+pub(crate) fn mirror_window_cameras(
+    mut commands: Commands,
+    primary_window: Query<Entity, With<PrimaryWindow>>,
+    mut mirrors: Query<MirrorItem>,
+    sources: Query<SourceItem, Without<WindowMirror>>,
+) {
+    let primary_window = primary_window.single().ok();
+
+    for (entity, mirror, mirrored, mut camera, mut transform, mut global_transform, projection) in
+        mirrors.iter_mut()
+    {
+        let Some(window) = mirror.window.normalize(primary_window) else {
+            continue;
+        };
+        let Some((source, source_camera, source_transform, source_projection)) = sources
+            .iter()
+            .filter(|(_, camera, ..)| match &camera.target {
+                RenderTarget::Window(target) => target.normalize(primary_window) == Some(window),
+                _ => false,
+            })
+            .min_by_key(|(_, camera, ..)| camera.order)
+        else {
+            continue;
+        };
+
+        let resolved = mirrored.is_some_and(|mirrored| mirrored.0 == source);
+        if !resolved {
+            // Renders like the window camera, the components missing on it are skipped. The
+            // projection is cloned too, the streamer camera may have been spawned without one
+            commands
+                .entity(source)
+                .clone_components::<(Camera2d, Camera3d, Projection, Tonemapping, Msaa)>(entity);
+            commands.entity(entity).insert(MirroredCamera(source));
+            camera.clear_color = source_camera.clear_color.clone();
+        }
+
+        transform.set_if_neq(source_transform.compute_transform());
+        global_transform.set_if_neq(*source_transform);
+        // Then the changes of the projection of the window camera are copied
+        if let (Some(mut projection), Some(source_projection)) = (projection, source_projection) {
+            if source_projection.is_changed() {
+                *projection = source_projection.clone();
+            }
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use anyhow::{Context, Result, anyhow};
+use bevy_log::prelude::*;
+use gst::prelude::*;
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::{
+    PipelineLogLevel,
+    encoder::{
+        EncoderStats, Frame, FrameTimestamps, StreamEncoder, pipeline_latency,
+        request_appsrc_keyframe, resize_appsrc,
+    },
+    pipeline_log::log_bus_message,
+    rtmp::h264_encoder_description,
+};
+
+/// Settings of a `MoqEncoder`
+#[derive(Clone)]
+pub struct MoqSettings {
+    /// Name of the stream
+    pub name: String,
+    /// Labels attached to the stream, see `StreamLabels`
+    pub labels: Vec<(String, String)>,
+    /// URL of the relay and path of the broadcast, e.g. `https://relay.example.com/bevy`
+    pub url: String,
+    /// Accepts invalid certificates of the relay, for development only
+    pub insecure_tls: bool,
+    pub width: u32,
+    pub height: u32,
+    pub framerate: u32,
+    /// Bitrate in kbit/s
+    pub bitrate: u32,
+    /// Interval between two keyframes, each group of pictures is a fragment of the broadcast
+    /// the subscribers can join at
+    pub keyframe_interval: Duration,
+    /// Duration of the chunks the fragments are sent in, the latency added by the muxer
+    pub chunk_duration: Duration,
+    /// Verbosity of the pipeline messages logs
+    pub log_level: PipelineLogLevel,
+}
+
+impl Default for MoqSettings {
+    fn default() -> Self {
+        Self {
+            name: "moq".to_string(),
+            labels: Vec::new(),
+            url: String::new(),
+            insecure_tls: false,
+            width: 1920,
+            height: 1080,
+            framerate: 30,
+            bitrate: 4000,
+            keyframe_interval: Duration::from_secs(2),
+            chunk_duration: Duration::from_millis(33),
+            log_level: PipelineLogLevel::default(),
+        }
+    }
+}
+
+/// An experimental encoder publishing the frames to a Media over QUIC relay (e.g. moq-rs)
+/// through WebTransport, with the `moqsink` element of moq-gst.
+///
+/// The frames are encoded in H264 and muxed in fragmented MP4 chunks, which the subscribers
+/// receive without a signalling server, with a lower latency than HLS.
+pub struct MoqEncoder {
+    pipeline: gst::Pipeline,
+    appsrc: gst_app::AppSrc,
+    timestamps: FrameTimestamps,
+    stats: Mutex<EncoderStats>,
+    bytes_sent: Arc<AtomicU64>,
+    /// Released when the encoder is dropped or falls back to software, see `EncoderFallback`
+    #[cfg(feature = "cuda")]
+    _nvenc: Arc<Mutex<Option<crate::nvenc::NvencSession>>>,
+}
+
+impl MoqEncoder {
+    pub fn new(settings: MoqSettings) -> Result<Arc<Self>> {
+        gst::init()?;
+
+        if settings.url.is_empty() {
+            return Err(anyhow!("The MoQ relay URL is not set"));
+        }
+
+        #[cfg(feature = "cuda")]
+        let nvenc = crate::nvenc::try_acquire(&settings.name);
+        #[cfg(feature = "cuda")]
+        if nvenc.is_none() {
+            warn!(stream = %settings.name, "No NVENC session left, streaming with x264enc");
+        }
+        #[cfg(feature = "cuda")]
+        let hardware = nvenc.is_some();
+        #[cfg(feature = "cuda")]
+        let nvenc = Arc::new(Mutex::new(nvenc));
+        #[cfg(not(feature = "cuda"))]
+        let hardware = false;
+
+        let framerate = settings.framerate.max(1);
+        let encoder = h264_encoder_description(
+            settings.bitrate,
+            framerate,
+            settings.keyframe_interval,
+            hardware,
+        );
+        let description = format!(
+            "appsrc name=src format=time is-live=true do-timestamp=true \
+                caps=\"video/x-raw,format=RGBA,width={},height={},framerate=0/1\" ! \
+            queue ! \
+            videoconvert ! \
+            videorate ! \
+            video/x-raw,format=I420,framerate={framerate}/1 ! \
+            {encoder} ! \
+            video/x-h264,profile=main ! \
+            h264parse ! \
+            isofmp4mux name=mux fragment-duration={} chunk-duration={} ! \
+            moqsink name=sink",
+            settings.width,
+            settings.height,
+            settings.keyframe_interval.as_nanos() as u64,
+            settings.chunk_duration.as_nanos() as u64,
+        );
+        debug!(stream = %settings.name, "MoQ pipeline: {}", description);
+
+        let pipeline = gst::parse::launch(&description)
+            .context(
+                "Unable to create the MoQ pipeline, are moq-gst and the fmp4 plugin installed?",
+            )?
+            .downcast::<gst::Pipeline>()
+            .map_err(|_| anyhow!("Failed to cast to pipeline"))?;
+        pipeline.set_property("name", &settings.name);
+
+        let sink = pipeline
+            .by_name("sink")
+            .ok_or_else(|| anyhow!("Could not get moqsink element"))?;
+        sink.set_property("url", &settings.url);
+        if settings.insecure_tls {
+            if sink.find_property("tls-disable-verify").is_some() {
+                sink.set_property("tls-disable-verify", true);
+            } else {
+                warn!(stream = %settings.name, "This moqsink does not support insecure TLS");
+            }
+        }
+
+        let appsrc = pipeline
+            .by_name("src")
+            .ok_or_else(|| anyhow!("Could not get appsrc element"))?
+            .downcast::<gst_app::AppSrc>()
+            .map_err(|_| anyhow!("Not an appsrc"))?;
+
+        let bytes_sent = Arc::new(AtomicU64::new(0));
+        pipeline
+            .by_name("mux")
+            .and_then(|mux| mux.static_pad("src"))
+            .ok_or_else(|| anyhow!("Could not get muxer src pad"))?
+            .add_probe(gst::PadProbeType::BUFFER, {
+                let bytes_sent = bytes_sent.clone();
+                move |_, info| {
+                    if let Some(buffer) = info.buffer() {
+                        bytes_sent.fetch_add(buffer.size() as u64, Ordering::Relaxed);
+                    }
+                    gst::PadProbeReturn::Ok
+                }
+            });
+
+        let bus = pipeline
+            .bus()
+            .ok_or_else(|| anyhow!("Pipeline has no bus"))?;
+        let stream = settings.name.clone();
+        let log_level = settings.log_level;
+        #[cfg(feature = "cuda")]
+        let (weak_pipeline, session) = (pipeline.downgrade(), nvenc.clone());
+        std::thread::spawn(move || {
+            for msg in bus.iter_timed(gst::ClockTime::NONE) {
+                log_bus_message(&stream, log_level, &msg);
+                #[cfg(feature = "cuda")]
+                if let Some(pipeline) = weak_pipeline.upgrade() {
+                    crate::nvenc::fall_back_to_software(&pipeline, &stream, &session, &msg);
+                }
+                if let gst::MessageView::Eos(_) = msg.view() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Arc::new(Self {
+            pipeline,
+            appsrc,
+            timestamps: FrameTimestamps::default(),
+            stats: Mutex::new(EncoderStats {
+                width: settings.width,
+                height: settings.height,
+                bitrate: Some(settings.bitrate * 1000),
+                ..Default::default()
+            }),
+            bytes_sent,
+            #[cfg(feature = "cuda")]
+            _nvenc: nvenc,
+        }))
+    }
+}
+
+impl Drop for MoqEncoder {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
+
+impl StreamEncoder for MoqEncoder {
+    fn push_frame(&self, frame: &Frame) -> Result<()> {
+        let buffer = self.timestamps.video_buffer(&self.appsrc, frame)?;
+        self.appsrc
+            .push_buffer(buffer)
+            .map_err(|e| anyhow!("Failed to push buffer: {:?}", e))?;
+        self.stats.lock().unwrap().frames_pushed += 1;
+
+        Ok(())
+    }
+
+    fn start(&self) -> Result<()> {
+        info!(stream = %self.pipeline.name(), "Start MoQ broadcast");
+        self.pipeline.set_state(gst::State::Playing)?;
+
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<()> {
+        info!(stream = %self.pipeline.name(), "Stop MoQ broadcast");
+        self.pipeline.set_state(gst::State::Null)?;
+
+        Ok(())
+    }
+
+    fn resize(&self, width: u32, height: u32) -> Result<()> {
+        resize_appsrc(&self.appsrc, width, height)?;
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.width = width;
+        stats.height = height;
+
+        Ok(())
+    }
+
+    fn set_bitrate(&self, bitrate: u32) -> Result<()> {
+        let encoder = self
+            .pipeline
+            .by_name("encoder")
+            .ok_or_else(|| anyhow!("Could not get encoder element"))?;
+        encoder.set_property("bitrate", (bitrate / 1000).max(1));
+
+        self.stats.lock().unwrap().bitrate = Some(bitrate);
+        Ok(())
+    }
+
+    fn request_keyframe(&self) -> Result<()> {
+        request_appsrc_keyframe(&self.appsrc)
+    }
+
+    fn stats(&self) -> Option<EncoderStats> {
+        let mut stats = self.stats.lock().unwrap().clone();
+        stats.bytes_sent = self.bytes_sent.load(Ordering::Relaxed);
+        stats.latency = pipeline_latency(&self.pipeline);
+        Some(stats)
+    }
+
+    fn pipeline_state(&self) -> Option<gst::State> {
+        Some(self.pipeline.current_state())
+    }
+}
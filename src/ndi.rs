@@ -0,0 +1,183 @@
+use anyhow::{Context, Result, anyhow};
+use bevy_log::prelude::*;
+use gst::prelude::*;
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    PipelineLogLevel,
+    encoder::{EncoderStats, Frame, FrameTimestamps, StreamEncoder, resize_appsrc},
+    pipeline_log::log_bus_message,
+};
+
+/// Settings of a `NdiEncoder`
+#[derive(Clone)]
+pub struct NdiSettings {
+    /// Name of the stream
+    pub name: String,
+    /// Labels attached to the stream, see `StreamLabels`
+    pub labels: Vec<(String, String)>,
+    /// Name of the NDI source, displayed by the receivers (prefixed by the host name)
+    pub ndi_name: String,
+    /// Comma separated NDI groups the source is announced in, the `public` group if `None`
+    pub groups: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    /// Framerate announced to the receivers, frames are duplicated or dropped whatever the
+    /// rate frames are pushed at
+    pub framerate: u32,
+    /// Verbosity of the pipeline messages logs
+    pub log_level: PipelineLogLevel,
+}
+
+impl Default for NdiSettings {
+    fn default() -> Self {
+        Self {
+            name: "ndi".to_string(),
+            labels: Vec::new(),
+            ndi_name: "Bevy".to_string(),
+            groups: None,
+            width: 1920,
+            height: 1080,
+            framerate: 60,
+            log_level: PipelineLogLevel::default(),
+        }
+    }
+}
+
+/// An encoder publishing the frames as a NDI source on the local network, e.g. for OBS, vMix
+/// or TriCaster, with the `ndisink` element of gst-plugins-rs.
+///
+/// The frames are sent uncompressed (UYVY) by the NDI SDK, which must be installed.
+pub struct NdiEncoder {
+    pipeline: gst::Pipeline,
+    appsrc: gst_app::AppSrc,
+    timestamps: FrameTimestamps,
+    stats: Mutex<EncoderStats>,
+}
+
+impl NdiEncoder {
+    pub fn new(settings: NdiSettings) -> Result<Arc<Self>> {
+        gst::init()?;
+
+        let description = format!(
+            "appsrc name=src format=time is-live=true do-timestamp=true \
+                caps=\"video/x-raw,format=RGBA,width={},height={},framerate=0/1\" ! \
+            queue ! \
+            videoconvert ! \
+            videorate ! \
+            video/x-raw,format=UYVY,framerate={}/1 ! \
+            ndisink name=sink",
+            settings.width,
+            settings.height,
+            settings.framerate.max(1),
+        );
+        debug!(stream = %settings.name, "NDI pipeline: {}", description);
+
+        let pipeline = gst::parse::launch(&description)
+            .context("Unable to create the NDI pipeline, is the ndi plugin installed?")?
+            .downcast::<gst::Pipeline>()
+            .map_err(|_| anyhow!("Failed to cast to pipeline"))?;
+        pipeline.set_property("name", &settings.name);
+
+        let sink = pipeline
+            .by_name("sink")
+            .ok_or_else(|| anyhow!("Could not get ndisink element"))?;
+        sink.set_property("ndi-name", &settings.ndi_name);
+        if let Some(groups) = &settings.groups {
+            if sink.find_property("groups").is_some() {
+                sink.set_property("groups", groups);
+            } else {
+                warn!(
+                    stream = %settings.name,
+                    "This ndisink does not support groups, set them in the NDI configuration"
+                );
+            }
+        }
+
+        let appsrc = pipeline
+            .by_name("src")
+            .ok_or_else(|| anyhow!("Could not get appsrc element"))?
+            .downcast::<gst_app::AppSrc>()
+            .map_err(|_| anyhow!("Not an appsrc"))?;
+
+        let bus = pipeline
+            .bus()
+            .ok_or_else(|| anyhow!("Pipeline has no bus"))?;
+        let stream = settings.name.clone();
+        let log_level = settings.log_level;
+        std::thread::spawn(move || {
+            for msg in bus.iter_timed(gst::ClockTime::NONE) {
+                log_bus_message(&stream, log_level, &msg);
+                if let gst::MessageView::Eos(_) = msg.view() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Arc::new(Self {
+            pipeline,
+            appsrc,
+            timestamps: FrameTimestamps::default(),
+            stats: Mutex::new(EncoderStats {
+                width: settings.width,
+                height: settings.height,
+                ..Default::default()
+            }),
+        }))
+    }
+}
+
+impl Drop for NdiEncoder {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
+
+impl StreamEncoder for NdiEncoder {
+    fn push_frame(&self, frame: &Frame) -> Result<()> {
+        let buffer = self.timestamps.video_buffer(&self.appsrc, frame)?;
+        self.appsrc
+            .push_buffer(buffer)
+            .map_err(|e| anyhow!("Failed to push buffer: {:?}", e))?;
+        self.stats.lock().unwrap().frames_pushed += 1;
+
+        Ok(())
+    }
+
+    fn start(&self) -> Result<()> {
+        info!(stream = %self.pipeline.name(), "Start NDI source");
+        self.pipeline.set_state(gst::State::Playing)?;
+
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<()> {
+        info!(stream = %self.pipeline.name(), "Stop NDI source");
+        self.pipeline.set_state(gst::State::Null)?;
+
+        Ok(())
+    }
+
+    fn resize(&self, width: u32, height: u32) -> Result<()> {
+        resize_appsrc(&self.appsrc, width, height)?;
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.width = width;
+        stats.height = height;
+
+        Ok(())
+    }
+
+    /// Every frame is a keyframe
+    fn request_keyframe(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn stats(&self) -> Option<EncoderStats> {
+        Some(self.stats.lock().unwrap().clone())
+    }
+
+    fn pipeline_state(&self) -> Option<gst::State> {
+        Some(self.pipeline.current_state())
+    }
+}
@@ -0,0 +1,388 @@
+use anyhow::{Result, anyhow};
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use bevy_platform::collections::HashMap;
+use crossbeam_channel::{Receiver, Sender};
+use gst::{glib, prelude::*};
+use std::sync::{
+    Arc, LazyLock, Mutex, Once,
+    atomic::{AtomicBool, AtomicU32, Ordering},
+};
+
+use crate::{EncoderFallback, NvencSessionLimitReached};
+
+/// The hardware encoders of the GPU, detected by `StreamerPlugin` when it is built.
+///
+/// Insert this resource before adding the plugin to override the detection, e.g. to set
+/// `max_sessions` on a GPU whose limit is not known.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct NvencCapabilities {
+    pub gpu_name: Option<String>,
+    pub driver_version: Option<String>,
+    /// Maximum number of concurrent encoding sessions, `None` if the GPU has no limit
+    pub max_sessions: Option<u32>,
+    pub h264: bool,
+    pub h265: bool,
+    pub av1: bool,
+}
+
+impl NvencCapabilities {
+    /// Reads the GPU and driver from NVML and the encoders from the GStreamer registry
+    pub fn detect() -> Self {
+        if let Err(e) = gst::init() {
+            warn!("Unable to initialize GStreamer: {}", e);
+        }
+        let has_element = |names: &[&str]| {
+            names
+                .iter()
+                .any(|name| gst::ElementFactory::find(name).is_some())
+        };
+
+        let mut capabilities = Self {
+            h264: has_element(&["nvh264enc", "nvcudah264enc"]),
+            h265: has_element(&["nvh265enc", "nvcudah265enc"]),
+            av1: has_element(&["nvav1enc"]),
+            ..Default::default()
+        };
+
+        match nvml_wrapper::Nvml::init() {
+            Ok(nvml) => {
+                capabilities.gpu_name = nvml
+                    .device_by_index(0)
+                    .and_then(|device| device.name())
+                    .ok();
+                capabilities.driver_version = nvml.sys_driver_version().ok();
+            }
+            Err(e) => warn!("Unable to detect the GPU: {}", e),
+        }
+        capabilities.max_sessions = consumer_session_limit(
+            capabilities.gpu_name.as_deref(),
+            capabilities.driver_version.as_deref(),
+        );
+
+        info!(
+            "NVENC: {} (driver {}), max sessions {:?}, h264 {}, h265 {}, av1 {}",
+            capabilities.gpu_name.as_deref().unwrap_or("unknown GPU"),
+            capabilities.driver_version.as_deref().unwrap_or("unknown"),
+            capabilities.max_sessions,
+            capabilities.h264,
+            capabilities.h265,
+            capabilities.av1,
+        );
+
+        capabilities
+    }
+}
+
+/// Returns the limit of concurrent sessions enforced by the driver on consumer GPUs,
+/// professional GPUs are not limited
+fn consumer_session_limit(gpu_name: Option<&str>, driver_version: Option<&str>) -> Option<u32> {
+    let gpu_name = gpu_name?.to_lowercase();
+    if !gpu_name.contains("geforce") && !gpu_name.contains("titan") {
+        return None;
+    }
+
+    let driver_major = driver_version
+        .and_then(|version| version.split('.').next())
+        .and_then(|major| major.parse::<u32>().ok())
+        .unwrap_or(0);
+
+    Some(match driver_major {
+        550.. => 8,
+        530.. => 5,
+        _ => 3,
+    })
+}
+
+/// Encoding sessions in use by all the streamers of the process
+struct NvencPool {
+    /// 0 when unlimited
+    max_sessions: AtomicU32,
+    active: AtomicU32,
+    limit_reached: (
+        Sender<NvencSessionLimitReached>,
+        Receiver<NvencSessionLimitReached>,
+    ),
+    fallbacks: (Sender<EncoderFallback>, Receiver<EncoderFallback>),
+}
+
+static POOL: LazyLock<NvencPool> = LazyLock::new(|| NvencPool {
+    max_sessions: AtomicU32::new(0),
+    active: AtomicU32::new(0),
+    limit_reached: crossbeam_channel::unbounded(),
+    fallbacks: crossbeam_channel::unbounded(),
+});
+
+pub(crate) fn set_max_sessions(max_sessions: Option<u32>) {
+    POOL.max_sessions
+        .store(max_sessions.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// An encoding session taken from the pool, released when dropped
+#[derive(Debug)]
+pub(crate) struct NvencSession(());
+
+impl Drop for NvencSession {
+    fn drop(&mut self) {
+        POOL.active.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Takes an encoding session for `stream`, or sends `NvencSessionLimitReached` if all the
+/// sessions of the GPU are in use
+pub(crate) fn try_acquire(stream: &str) -> Option<NvencSession> {
+    let max = POOL.max_sessions.load(Ordering::Relaxed);
+    let acquired = POOL
+        .active
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |active| {
+            (max == 0 || active < max).then_some(active + 1)
+        });
+
+    match acquired {
+        Ok(_) => Some(NvencSession(())),
+        Err(active) => {
+            warn!(%stream, "All the {} NVENC sessions of the GPU are in use", max);
+            let _ = POOL.limit_reached.0.send(NvencSessionLimitReached {
+                stream: stream.to_string(),
+                active,
+                max,
+            });
+            None
+        }
+    }
+}
+
+/// This system sends the `NvencSessionLimitReached` events of the pool
+pub(crate) fn send_limit_events(mut events: EventWriter<NvencSessionLimitReached>) {
+    events.write_batch(POOL.limit_reached.1.try_iter());
+}
+
+/// Replaces the hardware encoder named `encoder` of `pipeline` by x264enc if `msg` is an error
+/// of it, e.g. after a driver reset or when its NVENC session was taken by another process.
+///
+/// The pipeline is restarted with the software encoder, the NVENC `session` is released and
+/// `EncoderFallback` is sent. Returns true if the encoder was replaced.
+pub(crate) fn fall_back_to_software(
+    pipeline: &gst::Pipeline,
+    stream: &str,
+    session: &Mutex<Option<NvencSession>>,
+    msg: &gst::Message,
+) -> bool {
+    let gst::MessageView::Error(err) = msg.view() else {
+        return false;
+    };
+    let Some(encoder) = pipeline.by_name("encoder") else {
+        return false;
+    };
+    let Some(hardware) = encoder
+        .factory()
+        .map(|factory| factory.name().to_string())
+        .filter(|name| name.starts_with("nv"))
+    else {
+        return false;
+    };
+    let failed = msg.src().is_some_and(|src| {
+        src == encoder.upcast_ref::<gst::Object>() || src.has_as_ancestor(&encoder)
+    });
+    if !failed {
+        return false;
+    }
+
+    warn!(%stream, "The {} encoder failed, falling back to x264enc: {}", hardware, err.error());
+    if let Err(e) = replace_encoder(pipeline, &encoder) {
+        error!(%stream, "Unable to fall back to x264enc: {:?}", e);
+        return false;
+    }
+    session.lock().unwrap().take();
+    let _ = POOL.fallbacks.0.send(EncoderFallback {
+        stream: stream.to_string(),
+        hardware,
+        software: "x264enc".to_string(),
+        error: err.error().to_string(),
+    });
+    true
+}
+
+/// Restarts `pipeline` with x264enc in place of `encoder`, with the same bitrate and keyframe
+/// interval
+fn replace_encoder(pipeline: &gst::Pipeline, encoder: &gst::Element) -> Result<()> {
+    let peer = |pad: &str| {
+        encoder
+            .static_pad(pad)
+            .and_then(|pad| pad.peer())
+            .and_then(|peer| peer.parent_element())
+            .ok_or_else(|| anyhow!("The encoder {} pad is not linked", pad))
+    };
+    let (upstream, downstream) = (peer("sink")?, peer("src")?);
+
+    let bitrate = encoder.property::<u32>("bitrate");
+    let key_int_max = encoder
+        .find_property("gop-size")
+        .map(|_| encoder.property::<i32>("gop-size"))
+        .filter(|gop_size| *gop_size > 0)
+        .unwrap_or(60) as u32;
+    let software = gst::ElementFactory::make("x264enc")
+        .name("encoder")
+        .property_from_str("tune", "zerolatency")
+        .property_from_str("speed-preset", "veryfast")
+        .property("bitrate", bitrate)
+        .property("key-int-max", key_int_max)
+        .build()?;
+
+    // Stopping the pipeline flushes the errors posted after the one of the encoder
+    pipeline.set_state(gst::State::Null)?;
+    pipeline.remove(encoder)?;
+    pipeline.add(&software)?;
+    gst::Element::link_many([&upstream, &software, &downstream])?;
+    pipeline.set_state(gst::State::Playing)?;
+
+    Ok(())
+}
+
+/// An NVENC encoder created by `webrtcsink` for the session of a peer
+struct WebRtcEncoder {
+    encoder: glib::WeakRef<gst::Element>,
+    hardware: String,
+    stream: String,
+    session_id: String,
+    /// The NVENC sessions of the peers of the stream, see `SessionGate`
+    sessions: Arc<Mutex<HashMap<String, NvencSession>>>,
+}
+
+static WEBRTC_ENCODERS: Mutex<Vec<WebRtcEncoder>> = Mutex::new(Vec::new());
+/// Set once an encoder of `webrtcsink` failed, the next sessions use software encoders
+static HARDWARE_DISABLED: AtomicBool = AtomicBool::new(false);
+/// Creates the `ErrorTracer` with the first watched encoder
+static ERROR_TRACER: Once = Once::new();
+
+/// Watches the errors of `encoder`, created by `webrtcsink` for the session `session_id` of
+/// `stream`, if it is a hardware encoder.
+///
+/// `webrtcsink` creates an encoder per session and ends the session when it fails, so the
+/// pipeline can't be restarted with a software encoder as with `fall_back_to_software`. The
+/// NVENC session of the peer is released instead, the NVENC encoders are disabled so that the
+/// next sessions get a software encoder, and `EncoderFallback` is sent.
+pub(crate) fn watch_webrtc_encoder(
+    stream: &str,
+    session_id: &str,
+    encoder: &gst::Element,
+    sessions: &Arc<Mutex<HashMap<String, NvencSession>>>,
+) {
+    let Some(hardware) = encoder
+        .factory()
+        .map(|factory| factory.name().to_string())
+        .filter(|name| name.starts_with("nv"))
+    else {
+        return;
+    };
+
+    // The tracer stays registered until the process exits
+    ERROR_TRACER.call_once(|| std::mem::forget(glib::Object::new::<ErrorTracer>()));
+    let mut encoders = WEBRTC_ENCODERS.lock().unwrap();
+    encoders.retain(|watched| watched.encoder.upgrade().is_some());
+    encoders.push(WebRtcEncoder {
+        encoder: encoder.downgrade(),
+        hardware,
+        stream: stream.to_string(),
+        session_id: session_id.to_string(),
+        sessions: sessions.clone(),
+    });
+}
+
+/// Returns true once an encoder of `webrtcsink` failed, see `watch_webrtc_encoder`
+pub(crate) fn hardware_disabled() -> bool {
+    HARDWARE_DISABLED.load(Ordering::Acquire)
+}
+
+/// Falls back to software encoders if `msg` is an error of a watched `webrtcsink` encoder
+fn webrtc_encoder_failed(element: &gst::Element, msg: &gst::Message) {
+    let gst::MessageView::Error(err) = msg.view() else {
+        return;
+    };
+    let failed = {
+        let mut encoders = WEBRTC_ENCODERS.lock().unwrap();
+        let Some(index) = encoders.iter().position(|watched| {
+            watched
+                .encoder
+                .upgrade()
+                .is_some_and(|encoder| element == &encoder || element.has_as_ancestor(&encoder))
+        }) else {
+            return;
+        };
+        encoders.swap_remove(index)
+    };
+
+    warn!(
+        stream = %failed.stream,
+        "The {} encoder of the session {} failed, the next sessions use software encoders: {}",
+        failed.hardware,
+        failed.session_id,
+        err.error()
+    );
+    failed.sessions.lock().unwrap().remove(&failed.session_id);
+    if !HARDWARE_DISABLED.swap(true, Ordering::AcqRel) {
+        // webrtcsink chooses the encoders of the highest rank
+        for factory in gst::ElementFactory::factories_with_type(
+            gst::ElementFactoryType::ENCODER,
+            gst::Rank::NONE,
+        ) {
+            if factory.name().starts_with("nv") {
+                factory.set_rank(gst::Rank::NONE);
+            }
+        }
+    }
+
+    let software = if failed.hardware.contains("h265") {
+        "x265enc"
+    } else if failed.hardware.contains("av1") {
+        "av1enc"
+    } else {
+        "x264enc"
+    };
+    let _ = POOL.fallbacks.0.send(EncoderFallback {
+        stream: failed.stream,
+        hardware: failed.hardware,
+        software: software.to_string(),
+        error: err.error().to_string(),
+    });
+}
+
+mod imp {
+    use gst::{glib, subclass::prelude::*};
+
+    #[derive(Default)]
+    pub struct ErrorTracer;
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ErrorTracer {
+        const NAME: &'static str = "BevyStreamingNvencErrorTracer";
+        type Type = super::ErrorTracer;
+        type ParentType = gst::Tracer;
+    }
+
+    impl ObjectImpl for ErrorTracer {
+        fn constructed(&self) {
+            self.parent_constructed();
+            self.register_hook(TracerHook::ElementPostMessagePre);
+        }
+    }
+
+    impl GstObjectImpl for ErrorTracer {}
+
+    impl TracerImpl for ErrorTracer {
+        fn element_post_message_pre(&self, _ts: u64, element: &gst::Element, msg: &gst::Message) {
+            super::webrtc_encoder_failed(element, msg);
+        }
+    }
+}
+
+glib::wrapper! {
+    /// Tracer hooked on the messages posted by the elements, to see the errors of the
+    /// encoders of `webrtcsink`, which are posted on the buses of its sessions
+    struct ErrorTracer(ObjectSubclass<imp::ErrorTracer>) @extends gst::Tracer, gst::Object;
+}
+
+/// This system sends the `EncoderFallback` events of the failed hardware encoders
+pub(crate) fn send_fallback_events(mut events: EventWriter<EncoderFallback>) {
+    events.write_batch(POOL.fallbacks.1.try_iter());
+}
@@ -0,0 +1,114 @@
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use std::time::{Duration, Instant};
+
+use crate::capture::Capture;
+
+/// Slows down the frames of a headless app while the encoders can't keep up, and speeds them
+/// up again once they caught up, so that the frames don't pile up in front of the encoders
+/// of under-provisioned instances.
+///
+/// The backlog is the number of captured frames not pushed to their encoder yet. Use it with
+/// the `ScheduleRunnerPlugin` running at `interval`:
+///
+/// ```ignore
+/// app.add_plugins((
+///     ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1.0 / 60.0)),
+///     BackpressurePacingPlugin::default(),
+/// ));
+/// ```
+#[derive(Clone, Debug)]
+pub struct BackpressurePacingPlugin {
+    /// Interval between two frames when the encoders keep up, the interval of the
+    /// `ScheduleRunnerPlugin`
+    pub interval: Duration,
+    /// Longest interval between two frames while the encoders are behind
+    pub max_interval: Duration,
+}
+
+impl Default for BackpressurePacingPlugin {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / 60.0),
+            max_interval: Duration::from_secs_f64(1.0 / 10.0),
+        }
+    }
+}
+
+impl Plugin for BackpressurePacingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(FramePacing {
+            interval: self.interval,
+            max_interval: self.max_interval,
+            current: self.interval,
+            last_frame: None,
+        });
+        app.add_systems(Last, pace_frames);
+    }
+}
+
+/// State of the `BackpressurePacingPlugin`
+#[derive(Resource, Clone, Debug)]
+pub struct FramePacing {
+    pub interval: Duration,
+    pub max_interval: Duration,
+    current: Duration,
+    last_frame: Option<Instant>,
+}
+
+impl FramePacing {
+    /// Returns the current interval between two frames, longer than `interval` while the
+    /// encoders are behind
+    pub fn current_interval(&self) -> Duration {
+        self.current
+    }
+
+    /// Adapts the interval to the backlog of the most loaded capture
+    fn update(&mut self, backlog: usize, capacity: usize) {
+        let previous = self.current;
+        self.current = if backlog >= capacity {
+            // Every buffer is waiting for the encoder, the next frame would be skipped
+            self.current.mul_f64(1.25)
+        } else if backlog <= 1 {
+            self.current.mul_f64(0.9)
+        } else {
+            self.current
+        }
+        .clamp(self.interval, self.max_interval.max(self.interval));
+
+        if (self.current == self.max_interval) != (previous == self.max_interval) {
+            if self.current == self.max_interval {
+                warn!(
+                    "The encoders can't keep up, frames slowed down to {:?}",
+                    self.current
+                );
+            } else {
+                info!("The encoders are catching up, speeding up the frames");
+            }
+        }
+    }
+}
+
+/// This system waits at the end of each frame according to the backlog of the encoders
+fn pace_frames(mut pacing: ResMut<FramePacing>, captures: Query<&Capture>) {
+    let (backlog, capacity) = captures
+        .iter()
+        .filter(|capture| capture.capturing())
+        .map(|capture| (capture.buffers_in_use(), capture.buffer_count()))
+        .max()
+        .unwrap_or((0, 1));
+    pacing.update(backlog, capacity);
+
+    // The `ScheduleRunnerPlugin` already waits for `interval`
+    if pacing.current > pacing.interval {
+        if let Some(last_frame) = pacing.last_frame {
+            let next_frame = last_frame + pacing.current;
+            let now = Instant::now();
+            if next_frame > now {
+                std::thread::sleep(next_frame - now);
+            }
+        }
+    }
+    pacing.last_frame = Some(Instant::now());
+}
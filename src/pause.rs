@@ -0,0 +1,140 @@
+use anyhow::{Result, anyhow};
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use bevy_platform::collections::HashMap;
+use gst::prelude::*;
+use gstrswebrtc::webrtcsink::BaseWebRTCSink;
+use std::sync::{Arc, Mutex};
+
+/// Video of a peer, with the probes dropping its packets while it is paused
+struct PeerVideo {
+    webrtcbin: gst::Element,
+    probes: Vec<(gst::Pad, gst::PadProbeId)>,
+}
+
+/// Pauses the video sent to some peers of a streamer camera, e.g. for moderation or a privacy
+/// shutter, while their session and data channels stay open.
+///
+/// The RTP packets of the video are dropped while a peer is paused, its player shows the last
+/// frame received. A keyframe is requested when it is resumed.
+#[derive(Component, Clone, Default)]
+pub struct PeerVideoPause {
+    peers: Arc<Mutex<HashMap<String, PeerVideo>>>,
+}
+
+impl PeerVideoPause {
+    /// Tracks the peers of `webrtcsink`
+    pub(crate) fn connect(&self, webrtcsink: &BaseWebRTCSink) {
+        webrtcsink.connect_closure("consumer-added", false, {
+            let peers = self.peers.clone();
+            glib::closure!(move |_sink: &BaseWebRTCSink,
+                                 peer_id: &str,
+                                 webrtcbin: &gst::Element| {
+                peers.lock().unwrap().insert(
+                    peer_id.to_string(),
+                    PeerVideo {
+                        webrtcbin: webrtcbin.clone(),
+                        probes: Vec::new(),
+                    },
+                );
+            })
+        });
+
+        webrtcsink.connect_closure("consumer-removed", false, {
+            let peers = self.peers.clone();
+            glib::closure!(move |_sink: &BaseWebRTCSink,
+                                 peer_id: &str,
+                                 _webrtcbin: &gst::Element| {
+                peers.lock().unwrap().remove(peer_id);
+            })
+        });
+    }
+
+    /// Stops sending video to the peer
+    pub fn pause(&self, peer_id: &str) -> Result<()> {
+        let mut peers = self.peers.lock().unwrap();
+        let peer = peers
+            .get_mut(peer_id)
+            .ok_or_else(|| anyhow!("Unknown peer {}", peer_id))?;
+        if !peer.probes.is_empty() {
+            return Ok(());
+        }
+
+        info!("Pausing the video of {}", peer_id);
+        peer.probes = video_pads(&peer.webrtcbin)
+            .into_iter()
+            .filter_map(|pad| {
+                let probe = pad.add_probe(
+                    gst::PadProbeType::BUFFER | gst::PadProbeType::BUFFER_LIST,
+                    |_pad, _info| gst::PadProbeReturn::Drop,
+                )?;
+                Some((pad, probe))
+            })
+            .collect();
+
+        if peer.probes.is_empty() {
+            return Err(anyhow!("No video is sent to {}", peer_id));
+        }
+        Ok(())
+    }
+
+    /// Sends video to the peer again, starting with a keyframe
+    pub fn resume(&self, peer_id: &str) -> Result<()> {
+        let mut peers = self.peers.lock().unwrap();
+        let peer = peers
+            .get_mut(peer_id)
+            .ok_or_else(|| anyhow!("Unknown peer {}", peer_id))?;
+
+        if !peer.probes.is_empty() {
+            info!("Resuming the video of {}", peer_id);
+        }
+        for (pad, probe) in peer.probes.drain(..) {
+            pad.remove_probe(probe);
+
+            // Sent upstream to the encoder of the peer
+            let event = gst_video::UpstreamForceKeyUnitEvent::builder()
+                .all_headers(true)
+                .build();
+            if !pad.push_event(event) {
+                debug!("The keyframe request of {} was not handled", peer_id);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_paused(&self, peer_id: &str) -> bool {
+        self.peers
+            .lock()
+            .unwrap()
+            .get(peer_id)
+            .is_some_and(|peer| !peer.probes.is_empty())
+    }
+
+    /// Returns the ids of the paused peers
+    pub fn paused_peers(&self) -> Vec<String> {
+        self.peers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, peer)| !peer.probes.is_empty())
+            .map(|(peer_id, _)| peer_id.clone())
+            .collect()
+    }
+}
+
+/// Returns the sink pads of `webrtcbin` receiving video RTP packets
+fn video_pads(webrtcbin: &gst::Element) -> Vec<gst::Pad> {
+    webrtcbin
+        .sink_pads()
+        .into_iter()
+        .filter(|pad| {
+            pad.current_caps()
+                .and_then(|caps| {
+                    caps.structure(0)
+                        .and_then(|s| s.get::<&str>("media").ok())
+                        .map(|media| media == "video")
+                })
+                .unwrap_or(false)
+        })
+        .collect()
+}
@@ -0,0 +1,66 @@
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use bevy_platform::collections::HashMap;
+use gst::prelude::*;
+use gstrswebrtc::{signaller::Signallable, webrtcsink::BaseWebRTCSink};
+use std::sync::{Arc, Mutex};
+
+use crate::{PeerInfo, PeerMetadata};
+
+/// Collects the metadata of the peers of a streamer camera, from the `peer-metadata` signal
+/// of its signaller (only emitted by the Pixel Streaming signaller)
+#[derive(Component, Clone, Default)]
+pub(crate) struct PeerMetadataTracker {
+    peers: Arc<Mutex<HashMap<String, PeerInfo>>>,
+}
+
+impl PeerMetadataTracker {
+    pub(crate) fn connect(&self, webrtcsink: &BaseWebRTCSink) {
+        let signaller = webrtcsink.property::<Signallable>("signaller");
+        if glib::subclass::SignalId::lookup("peer-metadata", signaller.type_()).is_none() {
+            return;
+        }
+
+        signaller.connect_closure("peer-metadata", false, {
+            let peers = self.peers.clone();
+            glib::closure!(move |_signaller: &Signallable,
+                                 peer_id: &str,
+                                 structure: &gst::Structure| {
+                let mut info = PeerInfo::default();
+                for (field, value) in structure.iter() {
+                    match field.as_str() {
+                        "dataChannel" => info.data_channel = value.get().unwrap_or_default(),
+                        "sfu" => info.sfu = value.get().unwrap_or_default(),
+                        "userAgent" | "user_agent" => info.user_agent = value.get().ok(),
+                        "region" => info.region = value.get().ok(),
+                        "country" | "countryCode" => info.country = value.get().ok(),
+                        _ => {
+                            if let Ok(Ok(value)) = value.transform::<String>().map(|v| v.get()) {
+                                info.metadata.insert(field.to_string(), value);
+                            }
+                        }
+                    }
+                }
+
+                debug!("Metadata of {}: {:?}", peer_id, info);
+                peers.lock().unwrap().insert(peer_id.to_string(), info);
+            })
+        });
+
+        signaller.connect_closure("session-ended", false, {
+            let peers = self.peers.clone();
+            glib::closure!(move |_signaller: &Signallable, session_id: &str| -> bool {
+                peers.lock().unwrap().remove(session_id);
+                false
+            })
+        });
+    }
+}
+
+/// This system copies the metadata collected from the signallers to `PeerMetadata`
+pub(crate) fn update_peer_metadata(mut peers: Query<(&PeerMetadataTracker, &mut PeerMetadata)>) {
+    for (tracker, mut metadata) in peers.iter_mut() {
+        let current = tracker.peers.lock().unwrap().clone();
+        metadata.set_if_neq(PeerMetadata(current));
+    }
+}
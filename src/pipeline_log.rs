@@ -0,0 +1,96 @@
+use bevy_log::prelude::*;
+use gst::prelude::*;
+
+use crate::PipelineLogLevel;
+
+/// Log target of the GStreamer pipelines messages, each message has a `stream` field
+pub const PIPELINE_LOG_TARGET: &str = "bevy_streaming::pipeline";
+
+fn src_name(msg: &gst::Message) -> String {
+    msg.src()
+        .map(|s| s.path_string().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Logs a message received on the bus of the pipeline of `stream`, according to `level`
+pub(crate) fn log_bus_message(stream: &str, level: PipelineLogLevel, msg: &gst::Message) {
+    match msg.view() {
+        gst::MessageView::Error(err) => {
+            error!(
+                target: PIPELINE_LOG_TARGET,
+                stream,
+                "Pipeline error from {}: {} ({:?})",
+                src_name(msg),
+                err.error(),
+                err.debug()
+            );
+        }
+        gst::MessageView::Warning(warning) => {
+            warn!(
+                target: PIPELINE_LOG_TARGET,
+                stream,
+                "Pipeline warning from {}: {} ({:?})",
+                src_name(msg),
+                warning.error(),
+                warning.debug()
+            );
+        }
+        gst::MessageView::Eos(_) if level >= PipelineLogLevel::StateChanges => {
+            warn!(target: PIPELINE_LOG_TARGET, stream, "Pipeline reached end of stream");
+        }
+        gst::MessageView::StateChanged(state_changed)
+            if level >= PipelineLogLevel::StateChanges =>
+        {
+            let src_name = src_name(msg);
+
+            // Only the sink and the pipeline itself are relevant unless verbose
+            let important = src_name.contains("sink")
+                || src_name.contains("webrtcbin")
+                || msg.src().is_some_and(|s| s.is::<gst::Pipeline>());
+            if important || level >= PipelineLogLevel::Verbose {
+                info!(
+                    target: PIPELINE_LOG_TARGET,
+                    stream,
+                    "State change [{}]: {:?} -> {:?} (pending: {:?})",
+                    src_name,
+                    state_changed.old(),
+                    state_changed.current(),
+                    state_changed.pending()
+                );
+            }
+        }
+        gst::MessageView::Element(element) if level >= PipelineLogLevel::Verbose => {
+            if let Some(structure) = element.structure() {
+                if structure.name() != "GstBinForwarded" {
+                    debug!(
+                        target: PIPELINE_LOG_TARGET,
+                        stream,
+                        "Element message [{}]: {}",
+                        structure.name(),
+                        structure
+                    );
+                }
+            }
+        }
+        gst::MessageView::Info(info) if level >= PipelineLogLevel::Verbose => {
+            info!(
+                target: PIPELINE_LOG_TARGET,
+                stream,
+                "Info from {}: {} ({:?})",
+                src_name(msg),
+                info.error(),
+                info.debug()
+            );
+        }
+        gst::MessageView::StreamStatus(status) if level >= PipelineLogLevel::Verbose => {
+            debug!(
+                target: PIPELINE_LOG_TARGET,
+                stream,
+                "Stream status from {}: {:?}",
+                src_name(msg),
+                status.get().0
+            );
+        }
+        _ => {}
+    }
+}
@@ -1,9 +1,29 @@
 use bevy_platform::collections::HashMap;
-use crossbeam_channel::Receiver;
+use crossbeam_channel::{Receiver, Sender};
+use std::time::Instant;
 
-use super::handler::PSMessageHandler;
+use super::{handler::PSMessageHandler, message::PSMessage};
 
 pub struct PSControllerState {
     pub add_remove_handlers: Receiver<(String, Option<PSMessageHandler>)>,
     pub handlers: HashMap<String, PSMessageHandler>,
+    /// Messages injected by the app (replay, tests), processed as if sent by the given peer
+    pub injected_sender: Sender<(String, PSMessage)>,
+    pub injected_receiver: Receiver<(String, PSMessage)>,
+    /// When each peer last sent a message, see `SessionPolicy::idle_timeout`
+    pub last_input: HashMap<String, Instant>,
+}
+
+impl PSControllerState {
+    pub fn new(add_remove_handlers: Receiver<(String, Option<PSMessageHandler>)>) -> Self {
+        let (injected_sender, injected_receiver) = crossbeam_channel::unbounded();
+
+        Self {
+            add_remove_handlers,
+            handlers: HashMap::new(),
+            injected_sender,
+            injected_receiver,
+            last_input: HashMap::new(),
+        }
+    }
 }
@@ -1,10 +1,17 @@
+use std::sync::Mutex;
+
 use bevy_log::prelude::*;
 use crossbeam_channel::Receiver;
 use gst::glib::prelude::*;
 use gst_webrtc::WebRTCDataChannel;
 use gstrswebrtc::webrtcsink::BaseWebRTCSink;
 
-use super::message::PSMessage;
+use crate::InputLimits;
+
+use super::{
+    message::{PSMessage, PSOutgoingMessage},
+    validation::InputValidator,
+};
 
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -15,7 +22,12 @@ pub struct PSMessageHandler {
 }
 
 impl PSMessageHandler {
-    pub fn new(element: &BaseWebRTCSink, webrtcbin: &gst::Element, session_id: &str) -> Self {
+    pub fn new(
+        element: &BaseWebRTCSink,
+        webrtcbin: &gst::Element,
+        session_id: &str,
+        limits: InputLimits,
+    ) -> Self {
         info!("Creating Pixel Streaming data channel");
         let channel = webrtcbin.emit_by_name::<WebRTCDataChannel>(
             "create-data-channel",
@@ -30,6 +42,7 @@ impl PSMessageHandler {
         let session_id = session_id.to_string();
 
         let (sender, receiver) = crossbeam_channel::unbounded::<PSMessage>();
+        let validator = Mutex::new(InputValidator::new(limits));
 
         #[allow(unused)]
         Self {
@@ -41,13 +54,12 @@ impl PSMessageHandler {
                     #[strong]
                     session_id,
                     move |_channel: &WebRTCDataChannel, data: &glib::Bytes| {
-                        match PSMessage::try_from(data.get(..).unwrap()) {
-                            Ok(message) => {
-                                sender.send(message).unwrap();
-                            }
-                            Err(error) => {
-                                warn!("Unable to decode UE Message: {}", error);
-                            }
+                        let message = validator
+                            .lock()
+                            .unwrap()
+                            .validate(&session_id, data.get(..).unwrap());
+                        if let Some(message) = message {
+                            sender.send(message).unwrap();
                         }
                     }
                 )
@@ -56,4 +68,11 @@ impl PSMessageHandler {
             message_receiver: receiver,
         }
     }
+
+    /// Sends a message to the peer through the data channel
+    pub fn send(&self, message: &PSOutgoingMessage) {
+        let data: Vec<u8> = message.into();
+        self.data_channel
+            .send_data(Some(&glib::Bytes::from_owned(data)));
+    }
 }
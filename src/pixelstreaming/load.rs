@@ -0,0 +1,227 @@
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use bevy_platform::collections::HashMap;
+use bevy_render::prelude::*;
+use gst::prelude::*;
+use gstrswebrtc::{signaller::Signallable, webrtcsink::BaseWebRTCSink};
+use serde::Serialize;
+use std::{
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant},
+};
+use sysinfo::System;
+
+use crate::{GstWebRtcSettings, SignallingServer, StreamLabels, ViewerCount, capture::Capture};
+
+/// Periodically sends the load of the instance to the Pixel Streaming signalling server, in
+/// a `stats` message, so it can be used for matchmaking and autoscaling
+#[derive(Component, Clone)]
+pub(crate) struct LoadReporter {
+    enabled: bool,
+    signaller: Arc<OnceLock<Signallable>>,
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl LoadReporter {
+    pub(crate) fn from_settings(settings: &GstWebRtcSettings) -> Self {
+        let enabled = matches!(
+            (&settings.signalling_server, settings.load_report_interval),
+            (SignallingServer::PixelStreaming { .. }, Some(_))
+        );
+
+        Self {
+            enabled,
+            signaller: Default::default(),
+            interval: settings.load_report_interval.unwrap_or_default(),
+            last: None,
+        }
+    }
+
+    /// Reports the load with the signaller of `webrtcsink`, which may be created after the
+    /// reporter is spawned
+    pub(crate) fn connect(&self, webrtcsink: &BaseWebRTCSink) {
+        if self.enabled {
+            let _ = self
+                .signaller
+                .set(webrtcsink.property::<Signallable>("signaller"));
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StreamLoad {
+    name: String,
+    viewers: u32,
+    /// Frames pushed to the encoder per second
+    encode_fps: f64,
+    width: u32,
+    height: u32,
+    bitrate: Option<u32>,
+}
+
+/// The data of the `stats` message
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InstanceLoad {
+    /// Viewers of the stream the message is sent for
+    viewers: u32,
+    total_viewers: u32,
+    /// Global CPU usage in percent
+    cpu_usage: f32,
+    /// Used memory in percent
+    memory_usage: f32,
+    /// GPU and hardware encoder usage in percent, only available with NVIDIA GPUs
+    gpu_usage: Option<u32>,
+    encoder_usage: Option<u32>,
+    /// Frames rendered per second
+    fps: f64,
+    streams: Vec<StreamLoad>,
+}
+
+#[derive(Default)]
+pub(crate) struct LoadSampler {
+    system: Option<System>,
+    #[cfg(feature = "cuda")]
+    nvml: Option<nvml_wrapper::Nvml>,
+    updates: u64,
+    since: Option<Instant>,
+    frames_pushed: HashMap<String, u64>,
+}
+
+impl LoadSampler {
+    #[cfg(feature = "cuda")]
+    fn gpu_usage(&mut self) -> (Option<u32>, Option<u32>) {
+        if self.nvml.is_none() {
+            self.nvml = nvml_wrapper::Nvml::init()
+                .inspect_err(|e| warn!("Unable to get the GPU usage: {}", e))
+                .ok();
+        }
+        let Some(device) = self
+            .nvml
+            .as_ref()
+            .and_then(|nvml| nvml.device_by_index(0).ok())
+        else {
+            return (None, None);
+        };
+
+        (
+            device.utilization_rates().ok().map(|rates| rates.gpu),
+            device
+                .encoder_utilization()
+                .ok()
+                .map(|encoder| encoder.utilization),
+        )
+    }
+
+    #[cfg(not(feature = "cuda"))]
+    fn gpu_usage(&mut self) -> (Option<u32>, Option<u32>) {
+        (None, None)
+    }
+}
+
+/// This system sends the load of the instance with the `LoadReporter` of each camera
+pub(crate) fn report_instance_load(
+    mut reporters: Query<(&mut LoadReporter, Option<&ViewerCount>)>,
+    streams: Query<(&StreamLabels, &Camera, Option<&ViewerCount>)>,
+    captures: Query<&Capture>,
+    mut sampler: Local<LoadSampler>,
+) {
+    let now = Instant::now();
+    sampler.updates += 1;
+
+    let due = |reporter: &LoadReporter| {
+        reporter.signaller.get().is_some()
+            && reporter
+                .last
+                .is_none_or(|last| now.duration_since(last) >= reporter.interval)
+    };
+    if !reporters.iter().any(|(reporter, _)| due(reporter)) {
+        return;
+    }
+
+    // Rates are computed since the previous report
+    let elapsed = sampler
+        .since
+        .replace(now)
+        .map(|since| now.duration_since(since).as_secs_f64())
+        .unwrap_or_default();
+    let fps = if elapsed > 0. {
+        sampler.updates as f64 / elapsed
+    } else {
+        0.
+    };
+    sampler.updates = 0;
+
+    let system = sampler.system.get_or_insert_with(System::new);
+    system.refresh_cpu_usage();
+    system.refresh_memory();
+    let cpu_usage = system.global_cpu_usage();
+    let memory_usage = match system.total_memory() {
+        0 => 0.,
+        total => system.used_memory() as f32 / total as f32 * 100.,
+    };
+    let (gpu_usage, encoder_usage) = sampler.gpu_usage();
+
+    let mut streams_load = Vec::new();
+    for (labels, camera, viewers) in streams.iter() {
+        let stats = camera
+            .target
+            .as_image()
+            .and_then(|image| captures.iter().find(|c| c.src_image() == image))
+            .and_then(|capture| capture.encoder().stats())
+            .unwrap_or_default();
+
+        let previous = sampler
+            .frames_pushed
+            .insert(labels.name.clone(), stats.frames_pushed);
+        let encode_fps = match previous {
+            Some(previous) if elapsed > 0. => {
+                stats.frames_pushed.saturating_sub(previous) as f64 / elapsed
+            }
+            _ => 0.,
+        };
+
+        streams_load.push(StreamLoad {
+            name: labels.name.clone(),
+            viewers: viewers.map(|viewers| viewers.0).unwrap_or_default(),
+            encode_fps,
+            width: stats.width,
+            height: stats.height,
+            bitrate: stats.bitrate,
+        });
+    }
+    let total_viewers = streams_load.iter().map(|stream| stream.viewers).sum();
+
+    let mut load = InstanceLoad {
+        viewers: 0,
+        total_viewers,
+        cpu_usage,
+        memory_usage,
+        gpu_usage,
+        encoder_usage,
+        fps,
+        streams: streams_load,
+    };
+
+    for (mut reporter, viewers) in reporters.iter_mut() {
+        if !due(&reporter) {
+            continue;
+        }
+        reporter.last = Some(now);
+
+        load.viewers = viewers.map(|viewers| viewers.0).unwrap_or_default();
+        let data = match serde_json::to_string(&load) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Unable to serialize the instance load: {}", e);
+                continue;
+            }
+        };
+
+        if let Some(signaller) = reporter.signaller.get() {
+            signaller.emit_by_name::<()>("send-stats", &[&data]);
+        }
+    }
+}
@@ -1,9 +1,10 @@
 #![allow(dead_code)]
 
-use std::io::{Cursor, Read};
+use std::io::Cursor;
 
 use anyhow::anyhow;
-use byteorder::{LittleEndian, ReadBytesExt};
+use bevy_log::prelude::*;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 #[derive(Clone, Debug)]
 pub enum PSMessage {
@@ -48,6 +49,96 @@ impl TryFrom<&[u8]> for PSMessage {
     }
 }
 
+impl From<&PSMessage> for Vec<u8> {
+    fn from(value: &PSMessage) -> Self {
+        let mut data = Vec::new();
+        match value {
+            PSMessage::UiInteraction(ui_interaction) => {
+                data.push(50);
+                write_utf16_string(&mut data, &ui_interaction.message);
+            }
+            PSMessage::Command(command) => {
+                data.push(51);
+                write_utf16_string(&mut data, &command.command);
+            }
+            PSMessage::KeyDown(key_down) => {
+                data.extend_from_slice(&[60, key_down.key_code, key_down.is_repeat]);
+            }
+            PSMessage::KeyUp(key_up) => {
+                data.extend_from_slice(&[61, key_up.key_code]);
+            }
+            PSMessage::KeyPress(key_press) => {
+                data.push(62);
+                data.write_u16::<LittleEndian>(key_press.char_code).unwrap();
+            }
+            PSMessage::MouseEnter => data.push(70),
+            PSMessage::MouseLeave => data.push(71),
+            PSMessage::MouseDown(mouse_down) => {
+                data.extend_from_slice(&[72, mouse_down.button]);
+                data.write_u16::<LittleEndian>(mouse_down.x).unwrap();
+                data.write_u16::<LittleEndian>(mouse_down.y).unwrap();
+            }
+            PSMessage::MouseUp(mouse_up) => {
+                data.extend_from_slice(&[73, mouse_up.button]);
+                data.write_u16::<LittleEndian>(mouse_up.x).unwrap();
+                data.write_u16::<LittleEndian>(mouse_up.y).unwrap();
+            }
+            PSMessage::MouseMove(mouse_move) => {
+                data.push(74);
+                data.write_u16::<LittleEndian>(mouse_move.x).unwrap();
+                data.write_u16::<LittleEndian>(mouse_move.y).unwrap();
+                data.write_i16::<LittleEndian>(mouse_move.delta_x).unwrap();
+                data.write_i16::<LittleEndian>(mouse_move.delta_y).unwrap();
+            }
+            PSMessage::MouseWheel(mouse_wheel) => {
+                data.push(75);
+                data.write_i16::<LittleEndian>(mouse_wheel.delta).unwrap();
+                data.write_u16::<LittleEndian>(mouse_wheel.x).unwrap();
+                data.write_u16::<LittleEndian>(mouse_wheel.y).unwrap();
+            }
+            PSMessage::MouseDouble(mouse_double) => {
+                data.extend_from_slice(&[76, mouse_double.button]);
+                data.write_u16::<LittleEndian>(mouse_double.x).unwrap();
+                data.write_u16::<LittleEndian>(mouse_double.y).unwrap();
+            }
+        }
+        data
+    }
+}
+
+/// Reads a string sent by the frontend: its length in UTF-16 code units, then the UTF-16LE
+/// code units
+fn read_utf16_string(value: &[u8]) -> Result<String, std::io::Error> {
+    let mut rdr = Cursor::new(value);
+    let len = rdr.read_u16::<LittleEndian>()?;
+    let units = (0..len)
+        .map(|_| rdr.read_u16::<LittleEndian>())
+        .collect::<Result<Vec<_>, _>>()?;
+    String::from_utf16(&units).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// Writes a string as the frontend does, see `read_utf16_string`.
+///
+/// The length is a u16, longer strings are truncated to the last whole character that fits.
+fn write_utf16_string(data: &mut Vec<u8>, value: &str) {
+    let mut units = value.encode_utf16().collect::<Vec<_>>();
+    if units.len() > u16::MAX as usize {
+        warn!("Truncating a string of {} UTF-16 code units", units.len());
+        units.truncate(u16::MAX as usize);
+        // Don't split a surrogate pair
+        if units
+            .last()
+            .is_some_and(|unit| (0xd800..0xdc00).contains(unit))
+        {
+            units.pop();
+        }
+    }
+    data.write_u16::<LittleEndian>(units.len() as u16).unwrap();
+    for unit in units {
+        data.write_u16::<LittleEndian>(unit).unwrap();
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct UiInteraction {
     pub message: String,
@@ -57,10 +148,9 @@ impl TryFrom<&[u8]> for UiInteraction {
     type Error = std::io::Error;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        let mut rdr = Cursor::new(value);
-        let mut message = String::new();
-        rdr.read_to_string(&mut message)?;
-        Ok(Self { message })
+        Ok(Self {
+            message: read_utf16_string(value)?,
+        })
     }
 }
 
@@ -69,14 +159,27 @@ pub struct Command {
     pub command: String,
 }
 
+impl Command {
+    /// Returns the resolution requested by a
+    /// `{"Resolution.Width": 1280, "Resolution.Height": 720}` command
+    pub fn resolution(&self) -> Option<(u32, u32)> {
+        let command = serde_json::from_str::<serde_json::Value>(&self.command).ok()?;
+        let dimension = |key: &str| -> Option<u32> { command.get(key)?.as_u64()?.try_into().ok() };
+
+        Some((
+            dimension("Resolution.Width")?,
+            dimension("Resolution.Height")?,
+        ))
+    }
+}
+
 impl TryFrom<&[u8]> for Command {
     type Error = std::io::Error;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        let mut rdr = Cursor::new(value);
-        let mut command = String::new();
-        rdr.read_to_string(&mut command)?;
-        Ok(Self { command })
+        Ok(Self {
+            command: read_utf16_string(value)?,
+        })
     }
 }
 
@@ -231,3 +334,26 @@ impl TryFrom<&[u8]> for MouseDouble {
         })
     }
 }
+
+/// Messages sent from the streamer to the player
+#[derive(Clone, Debug)]
+pub enum PSOutgoingMessage {
+    Response(String),
+    Command(String),
+}
+
+impl From<&PSOutgoingMessage> for Vec<u8> {
+    fn from(value: &PSOutgoingMessage) -> Self {
+        let (id, payload) = match value {
+            PSOutgoingMessage::Response(response) => (1, response),
+            PSOutgoingMessage::Command(command) => (2, command),
+        };
+        // The frontend decodes string payloads as UTF-16
+        let mut data = Vec::with_capacity(1 + payload.len() * 2);
+        data.push(id);
+        for unit in payload.encode_utf16() {
+            data.write_u16::<LittleEndian>(unit).unwrap();
+        }
+        data
+    }
+}
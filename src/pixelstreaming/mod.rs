@@ -1,5 +1,27 @@
-pub mod controller;
-pub mod handler;
+use gst::prelude::*;
+
+pub(crate) mod controller;
+pub(crate) mod handler;
+pub(crate) mod load;
 pub mod message;
-pub mod signaller;
-pub mod utils;
+pub(crate) mod signaller;
+mod sink;
+pub(crate) mod utils;
+pub(crate) mod validation;
+
+pub use signaller::UePsSignaller;
+pub use sink::PixelStreamingSink;
+
+/// Registers the `pixelstreamingsink` element, to stream with the Pixel Streaming signaller
+/// from `gst-launch-1.0` or from applications not using Bevy.
+///
+/// Its signaller is configured through the child proxy, e.g. `signaller::uri`.
+pub fn register(plugin: Option<&gst::Plugin>) -> Result<(), glib::BoolError> {
+    UePsSignaller::static_type().mark_as_plugin_api(gst::PluginAPIFlags::empty());
+    gst::Element::register(
+        plugin,
+        "pixelstreamingsink",
+        gst::Rank::NONE,
+        PixelStreamingSink::static_type(),
+    )
+}
@@ -1,6 +1,11 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use super::protocol as p;
+use crate::{
+    SdpDirection, SdpMunger, SessionDecision, SessionRequest,
+    auth::{Admission, SessionGate, is_credential_field},
+    proxy,
+};
 use anyhow::{Error, anyhow};
 use async_tungstenite::tungstenite::Message as WsMessage;
 use async_tungstenite::tungstenite::client::IntoClientRequest;
@@ -14,6 +19,7 @@ use gstrswebrtc::RUNTIME;
 use gstrswebrtc::signaller::{Signallable, SignallableImpl};
 use gstrswebrtc::utils::gvalue_to_json;
 use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
 use std::ops::ControlFlow;
 use std::str::FromStr;
 use std::sync::LazyLock;
@@ -30,6 +36,9 @@ pub struct Settings {
     cafile: Option<String>,
     headers: Option<gst::Structure>,
     insecure_tls: bool,
+    proxy: Option<Url>,
+    /// Local addresses the connection is made from, the one of the family of the server
+    bind_addresses: Vec<IpAddr>,
 }
 
 impl Default for Settings {
@@ -40,6 +49,8 @@ impl Default for Settings {
             cafile: Default::default(),
             headers: None,
             insecure_tls: DEFAULT_INSECURE_TLS,
+            proxy: None,
+            bind_addresses: Vec::new(),
         }
     }
 }
@@ -49,6 +60,21 @@ pub struct Signaller {
     state: Mutex<State>,
     medias: Mutex<Vec<String>>,
     settings: Mutex<Settings>,
+    /// Checks applied to the players before requesting their session
+    gate: Mutex<Option<SessionGate>>,
+    /// The players sending the offer, until their session is requested
+    player_offers: Mutex<HashMap<String, PlayerOffer>>,
+    /// Rewrites the descriptions sent and received, along with the name of the stream
+    sdp_munger: Mutex<Option<(String, SdpMunger)>>,
+}
+
+/// Progress of a player sending the offer, its session is requested once it passed the gate
+/// and its offer is received
+enum PlayerOffer {
+    /// Neither admitted nor offered yet
+    Pending,
+    Admitted,
+    Received(gst_webrtc::WebRTCSessionDescription),
 }
 
 #[derive(Default)]
@@ -97,9 +123,14 @@ impl Signaller {
     }
 
     async fn connect(&self) -> Result<(), Error> {
-        let (cafile, insecure_tls) = {
+        let (cafile, insecure_tls, proxy, bind_addresses) = {
             let settings = self.settings.lock().unwrap();
-            (settings.cafile.clone(), settings.insecure_tls)
+            (
+                settings.cafile.clone(),
+                settings.insecure_tls,
+                settings.proxy.clone(),
+                settings.bind_addresses.clone(),
+            )
         };
 
         let mut connector_builder = tokio_native_tls::native_tls::TlsConnector::builder();
@@ -115,38 +146,57 @@ impl Signaller {
             gst::warning!(CAT, imp = self, "insecure tls connections are allowed");
         }
 
-        // TODO detect if uri starts with ws or wss and use this connector if wss
-        // let connector = Some(tokio_native_tls::TlsConnector::from(
-        //     connector_builder.build()?,
-        // ));
-
         let mut uri = self.uri();
         uri.set_query(None);
 
+        let connector = match uri.scheme() {
+            "wss" => Some(tokio_native_tls::TlsConnector::from(
+                connector_builder.build()?,
+            )),
+            _ => None,
+        };
+
         gst::info!(CAT, imp = self, "connecting to {}", uri.to_string());
 
+        let host = uri
+            .host_str()
+            .ok_or_else(|| anyhow!("No host in the URI {uri}"))?
+            .to_string();
+        let port = uri
+            .port_or_known_default()
+            .ok_or_else(|| anyhow!("No port in the URI {uri}"))?;
+
         let mut req = uri.into_client_request()?;
         let req_headers = req.headers_mut();
         if let Some(headers) = self.headers() {
             for (key, value) in headers {
                 req_headers.insert(
-                    HeaderName::from_bytes(key.as_bytes()).unwrap(),
-                    HeaderValue::from_bytes(value.as_bytes()).unwrap(),
+                    HeaderName::from_bytes(key.as_bytes())
+                        .map_err(|e| anyhow!("Invalid HTTP header name {key:?}: {e}"))?,
+                    HeaderValue::from_bytes(value.as_bytes())
+                        .map_err(|e| anyhow!("Invalid value of the HTTP header {key}: {e}"))?,
                 );
             }
         }
 
-        // let (ws, _) = timeout(
-        //     // FIXME: Make the timeout configurable
-        //     Duration::from_secs(20),
-        //     async_tungstenite::tokio::connect_async_with_tls_connector(req, connector),
-        // )
-        // .await??;
-
         let (ws, _) = timeout(
             // FIXME: Make the timeout configurable
             Duration::from_secs(20),
-            async_tungstenite::tokio::connect_async(req),
+            async {
+                let stream = match proxy {
+                    Some(proxy) => {
+                        gst::info!(CAT, imp = self, "connecting through the proxy {}", proxy);
+                        proxy::connect(&proxy, &host, port, &bind_addresses).await?
+                    }
+                    None => proxy::connect_direct(&host, port, &bind_addresses).await?,
+                };
+                Ok::<_, Error>(
+                    async_tungstenite::tokio::client_async_tls_with_connector(
+                        req, stream, connector,
+                    )
+                    .await?,
+                )
+            },
         )
         .await??;
 
@@ -223,6 +273,192 @@ impl Signaller {
         }));
     }
 
+    pub(super) fn set_session_gate(&self, gate: SessionGate) {
+        *self.gate.lock().unwrap() = Some(gate);
+    }
+
+    pub(super) fn set_sdp_munger(&self, stream: String, munger: SdpMunger) {
+        *self.sdp_munger.lock().unwrap() = Some((stream, munger));
+    }
+
+    /// Returns the description rewritten by the `SdpMunger`, if any
+    fn munge_sdp(
+        &self,
+        session_id: &str,
+        direction: SdpDirection,
+        description: gst_webrtc::WebRTCSessionDescription,
+    ) -> gst_webrtc::WebRTCSessionDescription {
+        match &*self.sdp_munger.lock().unwrap() {
+            Some((stream, munger)) => munger.munge(stream, session_id, direction, &description),
+            None => description,
+        }
+    }
+
+    /// Requests the session of a player, with its offer if it sends it
+    fn request_session(
+        &self,
+        player_id: &str,
+        offer: Option<&gst_webrtc::WebRTCSessionDescription>,
+    ) {
+        self.obj().emit_by_name::<()>(
+            "session-requested",
+            &[&player_id, &player_id, &offer.cloned()],
+        );
+    }
+
+    /// Requests the session of a player which passed the gate, once its offer is received if
+    /// it sends it
+    fn player_admitted(&self, player_id: &str) {
+        let mut player_offers = self.player_offers.lock().unwrap();
+        match player_offers.remove(player_id) {
+            None => {
+                drop(player_offers);
+                self.request_session(player_id, None);
+            }
+            Some(PlayerOffer::Pending | PlayerOffer::Admitted) => {
+                player_offers.insert(player_id.to_string(), PlayerOffer::Admitted);
+            }
+            Some(PlayerOffer::Received(offer)) => {
+                drop(player_offers);
+                self.request_session(player_id, Some(&offer));
+            }
+        }
+    }
+
+    /// Handles an offer sent by a player, it requests its session or renegotiates it
+    fn player_offer(&self, player_id: &str, offer: gst_webrtc::WebRTCSessionDescription) {
+        let mut player_offers = self.player_offers.lock().unwrap();
+        match player_offers.remove(player_id) {
+            Some(PlayerOffer::Pending | PlayerOffer::Received(_)) => {
+                player_offers.insert(player_id.to_string(), PlayerOffer::Received(offer));
+            }
+            Some(PlayerOffer::Admitted) => {
+                drop(player_offers);
+                self.request_session(player_id, Some(&offer));
+            }
+            None => {
+                drop(player_offers);
+                self.obj()
+                    .emit_by_name::<()>("session-description", &[&player_id, &offer]);
+            }
+        }
+    }
+
+    /// Requests the session of a player once it passes the gate, if any
+    fn player_connected(&self, player_connected: p::PlayerConnected) {
+        self.emit_peer_metadata(&player_connected);
+
+        if player_connected.send_offer == Some(false) {
+            gst::info!(
+                CAT,
+                imp = self,
+                "Waiting for the offer of {}",
+                player_connected.player_id
+            );
+            self.player_offers
+                .lock()
+                .unwrap()
+                .insert(player_connected.player_id.clone(), PlayerOffer::Pending);
+        }
+
+        let Some(gate) = self.gate.lock().unwrap().clone() else {
+            self.player_admitted(&player_connected.player_id);
+            return;
+        };
+
+        let player_id = player_connected.player_id;
+        let request = SessionRequest {
+            stream: gate.stream.clone(),
+            peer_id: player_id.clone(),
+            metadata: player_connected
+                .metadata
+                .into_iter()
+                .map(|(key, value)| match value {
+                    serde_json::Value::String(value) => (key, value),
+                    value => (key, value.to_string()),
+                })
+                .collect(),
+        };
+
+        RUNTIME.spawn(glib::clone!(
+            #[to_owned(rename_to = this)]
+            self,
+            async move {
+                let disconnect = |reason: Option<String>| {
+                    this.player_offers.lock().unwrap().remove(&player_id);
+                    this.send(p::Message::DisconnectPlayer(p::DisconnectPlayer {
+                        player_id: player_id.clone(),
+                        reason,
+                    }));
+                };
+
+                if let Some(throttler) = &gate.throttler {
+                    let mut queued = false;
+                    loop {
+                        match throttler.admit(queued) {
+                            Admission::Admitted => break,
+                            Admission::Wait(delay) => {
+                                queued = true;
+                                tokio::time::sleep(delay).await;
+                            }
+                            Admission::Rejected => {
+                                gst::warning!(
+                                    CAT,
+                                    imp = this,
+                                    "Too many sessions, rejecting {player_id}"
+                                );
+                                disconnect(Some("Too many connections".to_string()));
+                                return;
+                            }
+                        }
+                    }
+                }
+
+                if let Some(authorizer) = &gate.authorizer {
+                    if let SessionDecision::Deny { reason } = authorizer.authorize(request).await {
+                        disconnect(reason);
+                        return;
+                    }
+                }
+
+                if !gate.acquire_encoder(&player_id) {
+                    disconnect(Some("Encoder capacity reached".to_string()));
+                    return;
+                }
+
+                this.player_admitted(&player_id);
+            }
+        ));
+    }
+
+    /// Emits the metadata of a player, without its credentials, see `SessionAuthorizer`
+    fn emit_peer_metadata(&self, player_connected: &p::PlayerConnected) {
+        let authorizer = self
+            .gate
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|gate| gate.authorizer.clone());
+        let mut structure = gst::Structure::builder("peer-metadata")
+            .field("dataChannel", player_connected.data_channel)
+            .field("sfu", player_connected.sfu);
+        for (key, value) in &player_connected.metadata {
+            if is_credential_field(authorizer.as_ref(), key) {
+                continue;
+            }
+            let value = match value {
+                serde_json::Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            structure = structure.field(key.as_str(), value);
+        }
+
+        self.obj().emit_by_name::<()>(
+            "peer-metadata",
+            &[&player_connected.player_id, &structure.build()],
+        );
+    }
+
     fn headers(&self) -> Option<HashMap<String, String>> {
         self.settings
             .lock()
@@ -285,6 +521,23 @@ impl Signaller {
                             let mut state = self.state.lock().unwrap();
                             state.streamer_id = Some(endpoint_id_confirm.committed_id);
                             drop(state);
+                            self.obj().notify("streamer-id");
+                        }
+                        p::Message::StreamerIdChanged(streamer_id_changed) => {
+                            gst::info!(
+                                CAT,
+                                imp = self,
+                                "Streamer id changed to {}",
+                                streamer_id_changed.new_id
+                            );
+
+                            // The new id is kept to identify with it when reconnecting
+                            self.settings.lock().unwrap().streamer_id =
+                                Some(streamer_id_changed.new_id.clone());
+                            let mut state = self.state.lock().unwrap();
+                            state.streamer_id = Some(streamer_id_changed.new_id);
+                            drop(state);
+                            self.obj().notify("streamer-id");
                         }
                         p::Message::PlayerConnected(player_connected) => {
                             // assert!(matches!(
@@ -292,14 +545,7 @@ impl Signaller {
                             //     WebRTCSignallerRole::Producer
                             // ));
 
-                            self.obj().emit_by_name::<()>(
-                                "session-requested",
-                                &[
-                                    &player_connected.player_id,
-                                    &player_connected.player_id,
-                                    &None::<gst_webrtc::WebRTCSessionDescription>,
-                                ],
-                            );
+                            self.player_connected(player_connected);
                         }
                         p::Message::PlayerDisconnected(player_disconnected) => {
                             gst::info!(
@@ -309,6 +555,10 @@ impl Signaller {
                                 player_disconnected.player_id
                             );
 
+                            self.player_offers
+                                .lock()
+                                .unwrap()
+                                .remove(&player_disconnected.player_id);
                             self.obj().emit_by_name::<bool>(
                                 "session-ended",
                                 &[&player_disconnected.player_id],
@@ -332,8 +582,8 @@ impl Signaller {
 
                                 let desc =
                                     gst_webrtc::WebRTCSessionDescription::new(desc_type, sdp);
-                                self.obj()
-                                    .emit_by_name::<()>("session-description", &[player_id, &desc]);
+                                let desc = self.munge_sdp(player_id, SdpDirection::Incoming, desc);
+                                self.player_offer(player_id, desc);
                             }
                         }
                         p::Message::Answer(offer) => {
@@ -354,6 +604,7 @@ impl Signaller {
 
                                 let desc =
                                     gst_webrtc::WebRTCSessionDescription::new(desc_type, sdp);
+                                let desc = self.munge_sdp(player_id, SdpDirection::Incoming, desc);
                                 self.obj()
                                     .emit_by_name::<()>("session-description", &[player_id, &desc]);
                             }
@@ -422,6 +673,44 @@ impl ObjectSubclass for Signaller {
 }
 
 impl ObjectImpl for Signaller {
+    fn signals() -> &'static [glib::subclass::Signal] {
+        static SIGNALS: LazyLock<Vec<glib::subclass::Signal>> = LazyLock::new(|| {
+            vec![
+                /**
+                 * GstPixelStreamingWebRTCSignaller::send-stats:
+                 * @data: The stats, as a JSON string
+                 *
+                 * Sends a `stats` message to the signalling server.
+                 */
+                glib::subclass::Signal::builder("send-stats")
+                    .param_types([String::static_type()])
+                    .action()
+                    .class_handler(|args| {
+                        let signaller = args[0]
+                            .get::<super::UePsSignaller>()
+                            .expect("signal arg");
+                        let data = args[1].get::<String>().expect("signal arg");
+                        signaller.imp().send(p::Message::Stats(p::Stats { data }));
+
+                        None
+                    })
+                    .build(),
+                /**
+                 * GstPixelStreamingWebRTCSignaller::peer-metadata:
+                 * @peer_id: The id of the player
+                 * @metadata: The fields of the `playerConnected` message
+                 *
+                 * Emitted when a player connects, before its session is requested.
+                 */
+                glib::subclass::Signal::builder("peer-metadata")
+                    .param_types([String::static_type(), gst::Structure::static_type()])
+                    .build(),
+            ]
+        });
+
+        SIGNALS.as_ref()
+    }
+
     fn properties() -> &'static [glib::ParamSpec] {
         static PROPS: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
             vec![
@@ -451,6 +740,16 @@ impl ObjectImpl for Signaller {
                     .blurb("The streamer id transmitted to the signaller server")
                     .flags(glib::ParamFlags::READWRITE)
                     .build(),
+                glib::ParamSpecString::builder("proxy")
+                    .nick("Proxy URI")
+                    .blurb("HTTP CONNECT (http://) or SOCKS5 (socks5://) proxy of the connection")
+                    .flags(glib::ParamFlags::READWRITE)
+                    .build(),
+                glib::ParamSpecString::builder("bind-address")
+                    .nick("Bind address")
+                    .blurb("Comma separated local IP addresses the connection is made from")
+                    .flags(glib::ParamFlags::READWRITE)
+                    .build(),
                 glib::ParamSpecBoxed::builder::<gst::Structure>("headers")
                     .nick("HTTP headers")
                     .blurb("HTTP headers sent during the connection handshake")
@@ -497,6 +796,35 @@ impl ObjectImpl for Signaller {
                     .get::<Option<gst::Structure>>()
                     .expect("type checked upstream")
             }
+            "proxy" => {
+                let proxy = value
+                    .get::<Option<&str>>()
+                    .expect("type checked upstream")
+                    .map(Url::from_str)
+                    .transpose();
+                match proxy {
+                    Ok(proxy) => self.settings.lock().unwrap().proxy = proxy,
+                    Err(e) => gst::error!(CAT, "Couldn't set proxy: {e:?}"),
+                }
+            }
+            "bind-address" => {
+                let addresses = value
+                    .get::<Option<&str>>()
+                    .expect("type checked upstream")
+                    .map(|addresses| {
+                        addresses
+                            .split(',')
+                            .map(|address| IpAddr::from_str(address.trim()))
+                            .collect::<Result<Vec<_>, _>>()
+                    })
+                    .transpose();
+                match addresses {
+                    Ok(addresses) => {
+                        self.settings.lock().unwrap().bind_addresses = addresses.unwrap_or_default()
+                    }
+                    Err(e) => gst::error!(CAT, "Couldn't set bind-address: {e:?}"),
+                }
+            }
             "insecure-tls" => {
                 self.settings.lock().unwrap().insecure_tls =
                     value.get::<bool>().expect("type checked upstream")
@@ -514,6 +842,17 @@ impl ObjectImpl for Signaller {
             "cafile" => settings.cafile.to_value(),
             "headers" => settings.headers.to_value(),
             "insecure-tls" => settings.insecure_tls.to_value(),
+            "proxy" => settings.proxy.as_ref().map(Url::as_str).to_value(),
+            "bind-address" => (!settings.bind_addresses.is_empty())
+                .then(|| {
+                    settings
+                        .bind_addresses
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(",")
+                })
+                .to_value(),
             _ => unimplemented!(),
         }
     }
@@ -575,6 +914,7 @@ impl SignallableImpl for Signaller {
     }
 
     fn send_sdp(&self, session_id: &str, sdp: &gst_webrtc::WebRTCSessionDescription) {
+        let sdp = &self.munge_sdp(session_id, SdpDirection::Outgoing, sdp.clone());
         gst::debug!(CAT, imp = self, "Sending SDP {sdp:#?}");
 
         // store medias "mid" for each medias (or "" if no mid)
@@ -1,11 +1,13 @@
 // SPDX-License-Identifier: MPL-2.0
 #![allow(clippy::non_send_fields_in_send_ty, unused_doc_comments)]
 
-use gst::glib;
+use gst::{glib, subclass::prelude::*};
 use gstrswebrtc::signaller::Signallable;
 
+use crate::{SdpMunger, auth::SessionGate};
+
 mod imp;
-mod protocol;
+pub(crate) mod protocol;
 
 glib::wrapper! {
     pub struct UePsSignaller(ObjectSubclass<imp::Signaller>) @implements Signallable;
@@ -16,3 +18,15 @@ impl Default for UePsSignaller {
         glib::Object::new()
     }
 }
+
+impl UePsSignaller {
+    /// Sets the checks applied when a player connects, before its session is requested
+    pub(crate) fn set_session_gate(&self, gate: SessionGate) {
+        self.imp().set_session_gate(gate);
+    }
+
+    /// Sets the callback rewriting the descriptions sent to and received from the players
+    pub(crate) fn set_sdp_munger(&self, stream: String, munger: SdpMunger) {
+        self.imp().set_sdp_munger(stream, munger);
+    }
+}
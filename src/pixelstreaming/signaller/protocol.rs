@@ -193,6 +193,11 @@ pub struct PlayerConnected {
     pub sfu: bool,
     /// The ID of the player that connected.
     pub player_id: String,
+    /// False if the player sends the offer (offer to receive mode), the streamer then answers.
+    pub send_offer: Option<bool>,
+    /// Any other field added by the signalling server, e.g. an authentication token.
+    #[serde(flatten)]
+    pub metadata: serde_json::Map<String, serde_json::Value>,
 }
 /// *
 /// Message is used to notify a streamer that a player has
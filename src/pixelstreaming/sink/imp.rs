@@ -0,0 +1,54 @@
+use gst::glib;
+use gst::prelude::*;
+use gst::subclass::prelude::*;
+use gstrswebrtc::webrtcsink::{BaseWebRTCSink, BaseWebRTCSinkImpl};
+use std::sync::LazyLock;
+
+use crate::pixelstreaming::signaller::UePsSignaller;
+
+#[derive(Default)]
+pub struct PixelStreamingSink {}
+
+#[glib::object_subclass]
+impl ObjectSubclass for PixelStreamingSink {
+    const NAME: &'static str = "GstPixelStreamingWebRTCSink";
+    type Type = super::PixelStreamingSink;
+    type ParentType = BaseWebRTCSink;
+}
+
+impl ObjectImpl for PixelStreamingSink {
+    fn constructed(&self) {
+        self.parent_constructed();
+
+        let sink = self.obj();
+        let base = sink.upcast_ref::<BaseWebRTCSink>().imp();
+        if let Err(err) = base.set_signaller(UePsSignaller::default().upcast()) {
+            gst::error!(
+                gst::CAT_RUST,
+                obj = sink,
+                "Unable to set the signaller: {err}"
+            );
+        }
+    }
+}
+
+impl GstObjectImpl for PixelStreamingSink {}
+
+impl ElementImpl for PixelStreamingSink {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "PixelStreamingWebRTCSink",
+                "Sink/Network/WebRTC",
+                "WebRTC sink with the Unreal Engine Pixel Streaming signaller",
+                "Romain Lamarche",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+}
+
+impl BinImpl for PixelStreamingSink {}
+
+impl BaseWebRTCSinkImpl for PixelStreamingSink {}
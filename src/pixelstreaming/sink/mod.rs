@@ -0,0 +1,11 @@
+use gst::glib;
+use gstrswebrtc::webrtcsink::BaseWebRTCSink;
+
+mod imp;
+
+glib::wrapper! {
+    /// A `webrtcsink` using the Pixel Streaming signaller, registered as `pixelstreamingsink`
+    pub struct PixelStreamingSink(ObjectSubclass<imp::PixelStreamingSink>)
+        @extends BaseWebRTCSink, gst::Bin, gst::Element, gst::Object,
+        @implements gst::ChildProxy, gst_video::Navigation;
+}
@@ -10,36 +10,48 @@ use bevy_math::prelude::*;
 use bevy_picking::{pointer::Location, prelude::*};
 use bevy_render::prelude::*;
 
+use crate::capture::Capture;
+
 pub const SCALE: f32 = 65536.0;
 
 #[derive(SystemParam)]
-pub struct PSConversions<'w> {
+pub struct PSConversions<'w, 's> {
     images: Res<'w, Assets<Image>>,
+    captures: Query<'w, 's, &'static Capture>,
 }
 
-impl<'w> PSConversions<'w> {
+impl<'w, 's> PSConversions<'w, 's> {
+    /// Returns the size of the render target of a camera, and of its frames, larger when it is
+    /// letterboxed, see `AspectPolicy::Letterbox`
+    fn sizes(&self, camera: &Camera) -> (Vec2, Vec2) {
+        let image = camera.target.as_image().unwrap();
+        let size = self.images.get(image).unwrap().size().as_vec2();
+        let frame_size = self
+            .captures
+            .iter()
+            .find(|capture| capture.src_image() == image)
+            .map_or(size, |capture| UVec2::from(capture.size()).as_vec2());
+        (size, frame_size)
+    }
+
     pub fn from_ps_position<T>(&self, camera: &Camera, x: T, y: T) -> Vec2
     where
         T: Into<f32>,
     {
-        let image = camera.target.as_image().unwrap();
-        let image = self.images.get(image).unwrap();
-        let w = image.size().x as f32;
-        let h = image.size().y as f32;
+        let (size, frame_size) = self.sizes(camera);
 
-        let x = Into::<f32>::into(x);
-        let y = Into::<f32>::into(y);
-        Vec2 {
-            x: w * x as f32 / SCALE,
-            y: h * y as f32 / SCALE,
-        }
+        // The positions on the bars are clamped to the render target
+        let position = frame_size * Vec2::new(x.into(), y.into()) / SCALE;
+        (position - (frame_size - size) / 2.0).clamp(Vec2::ZERO, size)
     }
 
     pub fn from_ps_delta<T>(&self, camera: &Camera, x: T, y: T) -> Vec2
     where
         T: Into<f32>,
     {
-        self.from_ps_position(camera, x, y)
+        let (_, frame_size) = self.sizes(camera);
+
+        frame_size * Vec2::new(x.into(), y.into()) / SCALE
     }
 
     #[allow(dead_code)]
@@ -56,7 +68,6 @@ impl<'w> PSConversions<'w> {
         }
     }
 
-    #[allow(dead_code)]
     pub fn ps_to_pointer_button(&self, button: u8) -> PointerButton {
         match button {
             0 => PointerButton::Primary,
@@ -0,0 +1,248 @@
+use std::{ops::RangeInclusive, time::Instant};
+
+use bevy_log::prelude::*;
+
+use crate::InputLimits;
+
+use super::message::PSMessage;
+
+/// Key codes of the browser keyboard events, 0 and 255 are not keys
+const KEY_CODES: RangeInclusive<u8> = 1..=254;
+
+/// Validates and sanitizes the messages received from a single peer
+pub struct InputValidator {
+    limits: InputLimits,
+    tokens: f32,
+    key_repeat_tokens: f32,
+    last_refill: Instant,
+    dropped: u64,
+}
+
+impl InputValidator {
+    pub fn new(limits: InputLimits) -> Self {
+        Self {
+            tokens: limits.max_messages_per_second as f32,
+            key_repeat_tokens: limits.max_key_repeats_per_second as f32,
+            last_refill: Instant::now(),
+            dropped: 0,
+            limits,
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f32();
+        self.last_refill = now;
+
+        let max = self.limits.max_messages_per_second as f32;
+        self.tokens = (self.tokens + elapsed * max).min(max);
+        let max = self.limits.max_key_repeats_per_second as f32;
+        self.key_repeat_tokens = (self.key_repeat_tokens + elapsed * max).min(max);
+    }
+
+    fn drop_message(&mut self, peer_id: &str, reason: &str) -> Option<PSMessage> {
+        self.dropped += 1;
+        // Avoid flooding the logs as well
+        if self.dropped.is_power_of_two() {
+            warn!(
+                "Dropping input from {}: {} ({} dropped so far)",
+                peer_id, reason, self.dropped
+            );
+        }
+        None
+    }
+
+    /// Returns the decoded message if it is within the limits
+    pub fn validate(&mut self, peer_id: &str, data: &[u8]) -> Option<PSMessage> {
+        if data.len() > self.limits.max_message_size {
+            return self.drop_message(peer_id, "message too large");
+        }
+
+        self.refill();
+        if self.tokens < 1.0 {
+            return self.drop_message(peer_id, "rate limit exceeded");
+        }
+        self.tokens -= 1.0;
+
+        let message = match PSMessage::try_from(data) {
+            Ok(message) => message,
+            Err(error) => {
+                return self.drop_message(peer_id, &format!("unable to decode: {}", error));
+            }
+        };
+
+        self.sanitize(peer_id, message)
+    }
+
+    fn sanitize(&mut self, peer_id: &str, message: PSMessage) -> Option<PSMessage> {
+        let max_delta = self.limits.max_mouse_delta;
+        let max_wheel = self.limits.max_wheel_delta;
+        match message {
+            PSMessage::MouseMove(mut mouse_move) => {
+                mouse_move.delta_x = clamp_abs(mouse_move.delta_x, max_delta);
+                mouse_move.delta_y = clamp_abs(mouse_move.delta_y, max_delta);
+                Some(PSMessage::MouseMove(mouse_move))
+            }
+            PSMessage::MouseWheel(mut mouse_wheel) => {
+                mouse_wheel.delta = clamp_abs(mouse_wheel.delta, max_wheel);
+                Some(PSMessage::MouseWheel(mouse_wheel))
+            }
+            PSMessage::MouseDown(ref mouse_down) if mouse_down.button > 4 => {
+                self.drop_message(peer_id, "invalid mouse button")
+            }
+            PSMessage::MouseUp(ref mouse_up) if mouse_up.button > 4 => {
+                self.drop_message(peer_id, "invalid mouse button")
+            }
+            PSMessage::KeyDown(ref key_down) if !KEY_CODES.contains(&key_down.key_code) => {
+                self.drop_message(peer_id, "invalid key code")
+            }
+            PSMessage::KeyUp(ref key_up) if !KEY_CODES.contains(&key_up.key_code) => {
+                self.drop_message(peer_id, "invalid key code")
+            }
+            PSMessage::KeyPress(ref key_press)
+                if char::from_u32(key_press.char_code.into()).is_none() =>
+            {
+                self.drop_message(peer_id, "invalid character code")
+            }
+            PSMessage::KeyDown(key_down) if key_down.is_repeat != 0 => {
+                if self.key_repeat_tokens < 1.0 {
+                    return self.drop_message(peer_id, "key repeat rate exceeded");
+                }
+                self.key_repeat_tokens -= 1.0;
+                Some(PSMessage::KeyDown(key_down))
+            }
+            message => Some(message),
+        }
+    }
+}
+
+/// Clamps `value` to `max` in absolute value
+fn clamp_abs(value: i16, max: u16) -> i16 {
+    let max = max.min(i16::MAX as u16) as i16;
+    value.clamp(-max, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixelstreaming::message::{KeyDown, KeyPress, KeyUp, MouseMove, MouseWheel};
+
+    fn validate(validator: &mut InputValidator, message: PSMessage) -> Option<PSMessage> {
+        validator.validate("peer", &Vec::<u8>::from(&message))
+    }
+
+    fn mouse_move(delta_x: i16, delta_y: i16) -> PSMessage {
+        PSMessage::MouseMove(MouseMove {
+            x: 0,
+            y: 0,
+            delta_x,
+            delta_y,
+        })
+    }
+
+    fn key_down(key_code: u8, is_repeat: u8) -> PSMessage {
+        PSMessage::KeyDown(KeyDown {
+            key_code,
+            is_repeat,
+        })
+    }
+
+    #[test]
+    fn clamps_deltas() {
+        let mut validator = InputValidator::new(InputLimits {
+            max_mouse_delta: 100,
+            max_wheel_delta: 10,
+            ..Default::default()
+        });
+
+        match validate(&mut validator, mouse_move(500, i16::MIN)) {
+            Some(PSMessage::MouseMove(mouse_move)) => {
+                assert_eq!((mouse_move.delta_x, mouse_move.delta_y), (100, -100));
+            }
+            message => panic!("Unexpected message {:?}", message),
+        }
+
+        let wheel = PSMessage::MouseWheel(MouseWheel {
+            delta: -120,
+            x: 0,
+            y: 0,
+        });
+        match validate(&mut validator, wheel) {
+            Some(PSMessage::MouseWheel(mouse_wheel)) => assert_eq!(mouse_wheel.delta, -10),
+            message => panic!("Unexpected message {:?}", message),
+        }
+    }
+
+    #[test]
+    fn clamps_deltas_to_the_largest_limit() {
+        let mut validator = InputValidator::new(InputLimits {
+            max_mouse_delta: u16::MAX,
+            ..Default::default()
+        });
+
+        match validate(&mut validator, mouse_move(i16::MIN, i16::MAX)) {
+            Some(PSMessage::MouseMove(mouse_move)) => {
+                assert_eq!(
+                    (mouse_move.delta_x, mouse_move.delta_y),
+                    (-i16::MAX, i16::MAX)
+                );
+            }
+            message => panic!("Unexpected message {:?}", message),
+        }
+    }
+
+    #[test]
+    fn limits_the_message_rate() {
+        let mut validator = InputValidator::new(InputLimits {
+            max_messages_per_second: 3,
+            ..Default::default()
+        });
+
+        for _ in 0..3 {
+            assert!(validate(&mut validator, PSMessage::MouseEnter).is_some());
+        }
+        assert!(validate(&mut validator, PSMessage::MouseEnter).is_none());
+    }
+
+    #[test]
+    fn limits_the_key_repeat_rate() {
+        let mut validator = InputValidator::new(InputLimits {
+            max_key_repeats_per_second: 2,
+            ..Default::default()
+        });
+
+        for _ in 0..2 {
+            assert!(validate(&mut validator, key_down(65, 1)).is_some());
+        }
+        assert!(validate(&mut validator, key_down(65, 1)).is_none());
+        // Only the repeats are limited
+        assert!(validate(&mut validator, key_down(65, 0)).is_some());
+    }
+
+    #[test]
+    fn rejects_invalid_key_codes() {
+        let mut validator = InputValidator::new(InputLimits::default());
+
+        assert!(validate(&mut validator, key_down(65, 0)).is_some());
+        assert!(validate(&mut validator, key_down(0, 0)).is_none());
+        assert!(validate(&mut validator, key_down(255, 0)).is_none());
+        assert!(validate(&mut validator, PSMessage::KeyUp(KeyUp { key_code: 0 })).is_none());
+
+        let key_press = |char_code| PSMessage::KeyPress(KeyPress { char_code });
+        assert!(validate(&mut validator, key_press(u16::from(b'a'))).is_some());
+        assert!(validate(&mut validator, key_press(0xd800)).is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_messages() {
+        let mut validator = InputValidator::new(InputLimits {
+            max_message_size: 8,
+            ..Default::default()
+        });
+
+        assert!(validator.validate("peer", &[0; 9]).is_none());
+        assert!(validator.validate("peer", &[255]).is_none());
+        // Truncated mouse move
+        assert!(validator.validate("peer", &[74, 0]).is_none());
+    }
+}
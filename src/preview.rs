@@ -0,0 +1,102 @@
+use bevy_core_pipeline::core_2d::Camera2d;
+use bevy_ecs::prelude::*;
+use bevy_render::camera::{Camera, RenderTarget};
+use bevy_ui::{Node, UiTargetCamera, Val, widget::ImageNode};
+use bevy_window::WindowRef;
+
+/// Shows the stream of a streamer camera in a window, so that developers see locally what the
+/// viewers see.
+///
+/// The `WinitPlugin` must stay enabled. The render target of the camera is displayed by a UI
+/// camera rendering to `window`, so the streamed frames are not affected.
+#[derive(Component, Clone, Debug)]
+pub struct LocalPreview {
+    pub window: WindowRef,
+}
+
+impl Default for LocalPreview {
+    fn default() -> Self {
+        Self {
+            window: WindowRef::Primary,
+        }
+    }
+}
+
+/// Entities displaying the `LocalPreview` of a camera
+#[derive(Component)]
+pub(crate) struct PreviewEntities {
+    node: Entity,
+}
+
+/// Marks the entities displaying the `LocalPreview` of a camera
+#[derive(Component)]
+pub(crate) struct PreviewOf(Entity);
+
+/// This system displays the `LocalPreview` of the cameras
+pub(crate) fn update_local_previews(
+    mut commands: Commands,
+    cameras: Query<(Entity, &Camera, &LocalPreview, Option<&PreviewEntities>)>,
+    mut nodes: Query<&mut ImageNode, With<PreviewOf>>,
+) {
+    for (entity, camera, preview, entities) in cameras.iter() {
+        let Some(image) = camera.target.as_image() else {
+            continue;
+        };
+
+        match entities {
+            // The render target is replaced when the stream is resized
+            Some(entities) => {
+                if let Ok(mut node) = nodes.get_mut(entities.node) {
+                    if node.image != *image {
+                        node.image = image.clone();
+                    }
+                }
+            }
+            None => {
+                let preview_camera = commands
+                    .spawn((
+                        Camera2d,
+                        Camera {
+                            target: RenderTarget::Window(preview.window),
+                            // Rendered after the streamer cameras
+                            order: 1,
+                            ..Default::default()
+                        },
+                        PreviewOf(entity),
+                    ))
+                    .id();
+                let node = commands
+                    .spawn((
+                        Node {
+                            width: Val::Percent(100.0),
+                            height: Val::Percent(100.0),
+                            ..Default::default()
+                        },
+                        ImageNode::new(image.clone()),
+                        UiTargetCamera(preview_camera),
+                        PreviewOf(entity),
+                    ))
+                    .id();
+                commands.entity(entity).insert(PreviewEntities { node });
+            }
+        }
+    }
+}
+
+/// This system despawns the previews of the cameras whose `LocalPreview` is removed
+pub(crate) fn remove_local_previews(
+    mut commands: Commands,
+    previews: Query<(Entity, &PreviewOf)>,
+    cameras: Query<(), With<LocalPreview>>,
+) {
+    for (entity, preview_of) in previews.iter() {
+        if cameras.contains(preview_of.0) {
+            continue;
+        }
+        commands.entity(entity).despawn();
+        // The camera may be despawned
+        if let Ok(mut camera) = commands.get_entity(preview_of.0) {
+            camera.try_remove::<PreviewEntities>();
+        }
+    }
+}
@@ -0,0 +1,199 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use anyhow::{Error, anyhow, bail};
+use std::net::{IpAddr, SocketAddr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpSocket, TcpStream, lookup_host};
+use url::Url;
+
+/// Longest response header accepted from an HTTP proxy
+const MAX_RESPONSE_SIZE: usize = 8192;
+
+/// Opens a TCP connection to `host`:`port`, from the first of the `bind_addresses` of the
+/// family of an address of the host, if any
+pub(crate) async fn connect_direct(
+    host: &str,
+    port: u16,
+    bind_addresses: &[IpAddr],
+) -> Result<TcpStream, Error> {
+    if bind_addresses.is_empty() {
+        return Ok(TcpStream::connect((host, port)).await?);
+    }
+
+    let mut last_error = None;
+    for address in lookup_host((host, port)).await? {
+        let Some(local) = bind_addresses
+            .iter()
+            .find(|local| local.is_ipv4() == address.is_ipv4())
+        else {
+            continue;
+        };
+        let socket = match address {
+            SocketAddr::V4(_) => TcpSocket::new_v4()?,
+            SocketAddr::V6(_) => TcpSocket::new_v6()?,
+        };
+        socket.bind(SocketAddr::new(*local, 0))?;
+        match socket.connect(address).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    match last_error {
+        Some(e) => Err(e.into()),
+        None => bail!("No address of {host} reachable from {bind_addresses:?}"),
+    }
+}
+
+/// Opens a TCP connection to `host`:`port` through `proxy`, either
+/// `http://[user:password@]host:port` for an HTTP CONNECT proxy or
+/// `socks5://[user:password@]host:port` for a SOCKS5 one. The connection to the proxy is made
+/// from the `bind_addresses`, see `connect_direct`.
+pub(crate) async fn connect(
+    proxy: &Url,
+    host: &str,
+    port: u16,
+    bind_addresses: &[IpAddr],
+) -> Result<TcpStream, Error> {
+    let proxy_host = proxy
+        .host_str()
+        .ok_or_else(|| anyhow!("No host in the proxy URI {proxy}"))?;
+    let proxy_port = proxy
+        .port_or_known_default()
+        .or(match proxy.scheme() {
+            "socks5" | "socks5h" => Some(1080),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow!("No port in the proxy URI {proxy}"))?;
+    let credentials = (!proxy.username().is_empty())
+        .then(|| (proxy.username(), proxy.password().unwrap_or_default()));
+
+    let mut stream = connect_direct(proxy_host, proxy_port, bind_addresses).await?;
+    match proxy.scheme() {
+        "http" => http_connect(&mut stream, host, port, credentials).await?,
+        "socks5" | "socks5h" => socks5_connect(&mut stream, host, port, credentials).await?,
+        scheme => bail!("Unsupported proxy scheme {scheme}"),
+    }
+
+    Ok(stream)
+}
+
+async fn http_connect(
+    stream: &mut TcpStream,
+    host: &str,
+    port: u16,
+    credentials: Option<(&str, &str)>,
+) -> Result<(), Error> {
+    let mut request = format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n");
+    if let Some((user, password)) = credentials {
+        let token = base64(format!("{user}:{password}").as_bytes());
+        request.push_str(&format!("Proxy-Authorization: Basic {token}\r\n"));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read byte by byte so that nothing after the response header is consumed
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_RESPONSE_SIZE {
+            bail!("The proxy response is too long");
+        }
+        response.push(stream.read_u8().await?);
+    }
+
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        _ => Err(anyhow!("The proxy refused the connection: {status_line}")),
+    }
+}
+
+async fn socks5_connect(
+    stream: &mut TcpStream,
+    host: &str,
+    port: u16,
+    credentials: Option<(&str, &str)>,
+) -> Result<(), Error> {
+    // Methods: no authentication, and username/password if there are credentials
+    let greeting: &[u8] = match credentials {
+        Some(_) => &[5, 2, 0, 2],
+        None => &[5, 1, 0],
+    };
+    stream.write_all(greeting).await?;
+
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    match (reply, credentials) {
+        ([5, 0], _) => {}
+        ([5, 2], Some((user, password))) => {
+            if user.len() > 255 || password.len() > 255 {
+                bail!("The SOCKS5 proxy credentials are too long");
+            }
+            let mut request = vec![1, user.len() as u8];
+            request.extend_from_slice(user.as_bytes());
+            request.push(password.len() as u8);
+            request.extend_from_slice(password.as_bytes());
+            stream.write_all(&request).await?;
+
+            stream.read_exact(&mut reply).await?;
+            if reply[1] != 0 {
+                bail!("The SOCKS5 proxy rejected the credentials");
+            }
+        }
+        _ => bail!("No SOCKS5 authentication method accepted by the proxy"),
+    }
+
+    // The host name is resolved by the proxy
+    if host.len() > 255 {
+        bail!("The host name {host} is too long for SOCKS5");
+    }
+    let mut request = vec![5, 1, 0, 3, host.len() as u8];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0 {
+        bail!(
+            "The SOCKS5 proxy refused the connection (error {})",
+            reply[1]
+        );
+    }
+
+    // Skip the bound address and port
+    let address_size = match reply[3] {
+        1 => 4,
+        3 => stream.read_u8().await? as usize,
+        4 => 16,
+        address_type => bail!("Unknown SOCKS5 address type {address_type}"),
+    };
+    let mut bound = vec![0u8; address_size + 2];
+    stream.read_exact(&mut bound).await?;
+
+    Ok(())
+}
+
+/// Standard base64 with padding, for the basic authentication of HTTP proxies
+fn base64(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut output = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let triple = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(ALPHABET[((triple >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
@@ -0,0 +1,771 @@
+use anyhow::{Context, Result, anyhow};
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use crossbeam_channel::{Receiver, Sender};
+use gst::prelude::*;
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "encryption")]
+use crate::encryption::{RecordEncryption, encrypt_file};
+#[cfg(feature = "upload")]
+use crate::upload::{UploadSettings, upload_file};
+use crate::{
+    PipelineLogLevel, RecordingFinalized, RecordingLimitReached, chapters,
+    encoder::{
+        EncoderStats, Frame, FrameTimestamps, StreamEncoder, pipeline_latency,
+        request_appsrc_keyframe,
+    },
+    pipeline_log::log_bus_message,
+};
+
+/// Where a `RecordEncoder` sends the encoded stream
+#[derive(Clone, Debug)]
+pub enum RecordTarget {
+    /// A MP4 file, or a Matroska file if the extension is `mkv`. The `StreamMarker`s are
+    /// written as its chapters.
+    File { path: PathBuf },
+    /// A RTMP server, e.g. `rtmp://live.example.com/app/stream-key`
+    Rtmp { url: String },
+    /// Files split when reaching a duration or a size (whichever comes first), so a crash
+    /// only loses the current segment.
+    ///
+    /// `location` is a pattern formatted with the index of the segment, e.g. `rec-%05d.mp4`
+    Segments {
+        location: String,
+        max_duration: Option<Duration>,
+        max_size: Option<u64>,
+    },
+}
+
+/// What a `RecordEncoder` does when the maximum duration or size of `RecordLimits` is reached
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecordLimitAction {
+    /// Ends the recording
+    #[default]
+    Stop,
+    /// Starts a new segment, the limits then apply to each segment.
+    ///
+    /// Only segmented recordings can be rotated, the others are stopped.
+    Rotate,
+}
+
+/// Limits guarding a recording, so a forgotten recorder does not fill the disk
+#[derive(Clone, Debug, Default)]
+pub struct RecordLimits {
+    pub max_duration: Option<Duration>,
+    /// Maximum size in bytes of the encoded stream
+    pub max_size: Option<u64>,
+    /// Minimum free space in bytes of the disk the recording is written to. The recording
+    /// is always stopped when it is reached.
+    pub min_free_space: Option<u64>,
+    pub action: RecordLimitAction,
+}
+
+impl RecordLimits {
+    fn is_empty(&self) -> bool {
+        self.max_duration.is_none() && self.max_size.is_none() && self.min_free_space.is_none()
+    }
+}
+
+/// A limit of `RecordLimits` reached by a recording
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordingLimit {
+    Duration,
+    Size,
+    FreeSpace,
+}
+
+/// Interval between two checks of the limits
+const LIMITS_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A function called with the path of each finalized file, e.g. to post-process it
+pub type FinalizedHook = Arc<dyn Fn(&Path) + Send + Sync>;
+
+/// Settings of a `RecordEncoder`
+#[derive(Clone)]
+pub struct RecordSettings {
+    /// Name of the stream
+    pub name: String,
+    /// Labels attached to the stream, see `StreamLabels`
+    pub labels: Vec<(String, String)>,
+    pub target: RecordTarget,
+    pub width: u32,
+    pub height: u32,
+    /// Output framerate: frames are duplicated or dropped to produce a constant framerate,
+    /// whatever the rate frames are pushed at. The framerate is variable if not set.
+    pub framerate: Option<u32>,
+    /// Bitrate in kbit/s
+    pub bitrate: u32,
+    /// Verbosity of the pipeline messages logs
+    pub log_level: PipelineLogLevel,
+    pub limits: RecordLimits,
+    /// Index of the first segment of a segmented recording, e.g. to continue the numbering
+    /// after a restart
+    pub first_segment: u32,
+    /// Encrypts each finalized file, before it is passed to `on_finalized` and uploaded
+    #[cfg(feature = "encryption")]
+    pub encryption: Option<RecordEncryption>,
+    /// Called on a background thread with each finalized file, before it is uploaded
+    pub on_finalized: Option<FinalizedHook>,
+    /// Uploads each finalized file to an object storage
+    #[cfg(feature = "upload")]
+    pub upload: Option<UploadSettings>,
+}
+
+impl Default for RecordSettings {
+    fn default() -> Self {
+        Self {
+            name: "record".to_string(),
+            labels: Vec::new(),
+            target: RecordTarget::File {
+                path: PathBuf::from("recording.mp4"),
+            },
+            width: 1920,
+            height: 1080,
+            framerate: Some(60),
+            bitrate: 8000,
+            log_level: PipelineLogLevel::default(),
+            limits: RecordLimits::default(),
+            first_segment: 0,
+            #[cfg(feature = "encryption")]
+            encryption: None,
+            on_finalized: None,
+            #[cfg(feature = "upload")]
+            upload: None,
+        }
+    }
+}
+
+/// Returns the gst-launch description of the recording pipeline, `hardware` selects NVENC
+fn pipeline_description(settings: &RecordSettings, hardware: bool) -> String {
+    let rate = match settings.framerate {
+        // videorate duplicates or drops frames according to their timestamps
+        Some(framerate) => format!("videorate ! video/x-raw,framerate={framerate}/1 ! "),
+        None => String::new(),
+    };
+
+    let key_int_max = settings.framerate.unwrap_or(60) * 2;
+    let encoder = if hardware {
+        format!(
+            "nvh264enc name=encoder bitrate={} gop-size={}",
+            settings.bitrate, key_int_max
+        )
+    } else {
+        format!(
+            "x264enc name=encoder tune=zerolatency speed-preset=veryfast bitrate={} key-int-max={}",
+            settings.bitrate, key_int_max
+        )
+    };
+
+    let sink = match &settings.target {
+        RecordTarget::File { path } => {
+            let muxer = if is_matroska(path) {
+                "matroskamux"
+            } else {
+                "mp4mux"
+            };
+            format!(
+                "{muxer} name=mux ! filesink location=\"{}\"",
+                path.display()
+            )
+        }
+        RecordTarget::Rtmp { url } => {
+            format!("flvmux streamable=true ! rtmpsink location=\"{url} live=1\"")
+        }
+        RecordTarget::Segments {
+            location,
+            max_duration,
+            max_size,
+        } => {
+            let muxer = if location.ends_with(".mkv") {
+                "matroskamux"
+            } else {
+                "mp4mux"
+            };
+            format!(
+                "splitmuxsink name=splitmux location=\"{location}\" muxer-factory={muxer} \
+                max-size-time={} max-size-bytes={} start-index={}",
+                max_duration.map(|d| d.as_nanos() as u64).unwrap_or(0),
+                max_size.unwrap_or(0),
+                settings.first_segment,
+            )
+        }
+    };
+
+    format!(
+        "appsrc name=src format=time is-live=true do-timestamp=true \
+            caps=\"video/x-raw,format=RGBA,width={},height={},framerate=0/1\" ! \
+        queue ! \
+        videoconvert ! \
+        {rate}\
+        video/x-raw,format=I420 ! \
+        {encoder} ! \
+        h264parse name=parse ! \
+        queue ! \
+        {sink}",
+        settings.width, settings.height,
+    )
+}
+
+/// Spawns the thread encrypting, calling the hook and uploading the finalized files, if any
+/// is configured. The files are sent to `finalized` once encrypted.
+fn spawn_post_processing(
+    settings: &RecordSettings,
+    finalized: Sender<PathBuf>,
+) -> Result<Option<Sender<PathBuf>>> {
+    let on_finalized = settings.on_finalized.clone();
+    #[cfg(feature = "encryption")]
+    let encryption = settings.encryption.clone();
+    #[cfg(feature = "encryption")]
+    if let Some(encryption) = &encryption {
+        encryption.validate()?;
+    }
+    #[cfg(feature = "upload")]
+    let upload = settings.upload.clone();
+
+    let enabled = on_finalized.is_some();
+    #[cfg(feature = "encryption")]
+    let enabled = enabled || encryption.is_some();
+    #[cfg(feature = "upload")]
+    let enabled = enabled || upload.is_some();
+    if !enabled {
+        return Ok(None);
+    }
+
+    let (sender, receiver) = crossbeam_channel::unbounded::<PathBuf>();
+    std::thread::spawn(move || {
+        for path in receiver.iter() {
+            // The plaintext file is neither reported nor uploaded
+            #[cfg(feature = "encryption")]
+            let path = match &encryption {
+                Some(encryption) => match encrypt_file(encryption, &path) {
+                    Ok(encrypted) => encrypted,
+                    Err(e) => {
+                        error!("Unable to encrypt {}: {:?}", path.display(), e);
+                        continue;
+                    }
+                },
+                None => path,
+            };
+            let _ = finalized.send(path.clone());
+
+            if let Some(on_finalized) = &on_finalized {
+                on_finalized(&path);
+            }
+
+            #[cfg(feature = "upload")]
+            if let Some(upload) = &upload {
+                if let Err(e) = upload_file(upload, &path) {
+                    error!("Unable to upload {}: {:?}", path.display(), e);
+                }
+            }
+        }
+    });
+
+    Ok(Some(sender))
+}
+
+/// Returns the directory the recording is written to, if written to the disk
+fn output_directory(target: &RecordTarget) -> Option<PathBuf> {
+    let path = match target {
+        RecordTarget::File { path } => path.as_path(),
+        RecordTarget::Segments { location, .. } => Path::new(location),
+        RecordTarget::Rtmp { .. } => return None,
+    };
+
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => Some(parent.to_path_buf()),
+        _ => Some(PathBuf::from(".")),
+    }
+}
+
+/// Returns true if the file is written by matroskamux, which writes the chapters itself
+fn is_matroska(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()) == Some("mkv")
+}
+
+/// The markers of a recording to a file, written as its chapters
+#[derive(Default)]
+struct RecordChapters {
+    /// Markers of the next pushed frame
+    pending: Vec<String>,
+    /// Pts of the first frame, the start of the file
+    start: Option<gst::ClockTime>,
+    chapters: Vec<(gst::ClockTime, String)>,
+}
+
+impl RecordChapters {
+    /// Returns the chapters, starting from the start of the file
+    fn relative(&self) -> Vec<(Duration, String)> {
+        let start = self.start.unwrap_or(gst::ClockTime::ZERO);
+        self.chapters
+            .iter()
+            .map(|(pts, name)| (pts.saturating_sub(start).into(), name.clone()))
+            .collect()
+    }
+}
+
+/// Checks the limits of a recording until it is dropped, stopping or rotating it when one
+/// is reached
+struct LimitsGuard {
+    limits: RecordLimits,
+    directory: Option<PathBuf>,
+    appsrc: gst_app::AppSrc,
+    splitmux: Option<gst::Element>,
+    bytes: Arc<AtomicU64>,
+    limit_stopped: Arc<AtomicBool>,
+    reached: Sender<RecordingLimit>,
+}
+
+impl LimitsGuard {
+    fn run(self, stop: Receiver<()>) {
+        let mut started = Instant::now();
+
+        // Stops when the encoder is dropped
+        while let Err(crossbeam_channel::RecvTimeoutError::Timeout) =
+            stop.recv_timeout(LIMITS_CHECK_INTERVAL)
+        {
+            let Some(limit) = self.check(started) else {
+                continue;
+            };
+            let _ = self.reached.send(limit);
+
+            let rotate = limit != RecordingLimit::FreeSpace
+                && self.limits.action == RecordLimitAction::Rotate;
+            match (&self.splitmux, rotate) {
+                (Some(splitmux), true) => {
+                    warn!(
+                        "Recording limit {:?} reached, starting a new segment",
+                        limit
+                    );
+                    splitmux.emit_by_name::<()>("split-now", &[]);
+                    self.bytes.store(0, Ordering::Relaxed);
+                    started = Instant::now();
+                }
+                _ => {
+                    warn!(
+                        "Recording limit {:?} reached, stopping the recording",
+                        limit
+                    );
+                    self.limit_stopped.store(true, Ordering::Release);
+                    let _ = self.appsrc.end_of_stream();
+                    break;
+                }
+            }
+        }
+    }
+
+    fn check(&self, started: Instant) -> Option<RecordingLimit> {
+        if let (Some(min_free_space), Some(directory)) =
+            (self.limits.min_free_space, &self.directory)
+        {
+            match fs4::available_space(directory) {
+                Ok(available) if available < min_free_space => {
+                    return Some(RecordingLimit::FreeSpace);
+                }
+                Ok(_) => {}
+                Err(e) => warn!(
+                    "Unable to get the free space of {}: {}",
+                    directory.display(),
+                    e
+                ),
+            }
+        }
+
+        if self
+            .limits
+            .max_duration
+            .is_some_and(|max_duration| started.elapsed() >= max_duration)
+        {
+            return Some(RecordingLimit::Duration);
+        }
+
+        if self
+            .limits
+            .max_size
+            .is_some_and(|max_size| self.bytes.load(Ordering::Relaxed) >= max_size)
+        {
+            return Some(RecordingLimit::Size);
+        }
+
+        None
+    }
+}
+
+/// An encoder recording the frames to a file or sending them to a RTMP server
+pub struct RecordEncoder {
+    pipeline: gst::Pipeline,
+    appsrc: gst_app::AppSrc,
+    timestamps: FrameTimestamps,
+    stats: Mutex<EncoderStats>,
+    finalized: Receiver<PathBuf>,
+    eos: Receiver<()>,
+    limits: RecordLimits,
+    directory: Option<PathBuf>,
+    bytes: Arc<AtomicU64>,
+    limit_stopped: Arc<AtomicBool>,
+    limits_reached: (Sender<RecordingLimit>, Receiver<RecordingLimit>),
+    guard_stop: Mutex<Option<Sender<()>>>,
+    /// Only for the recordings to a file
+    chapters: Option<Arc<Mutex<RecordChapters>>>,
+    /// Index of the next segment opened, only for the segmented recordings
+    next_segment: Option<Arc<AtomicU32>>,
+    /// Released when the encoder is dropped or falls back to software, see `EncoderFallback`
+    #[cfg(feature = "cuda")]
+    _nvenc: Arc<Mutex<Option<crate::nvenc::NvencSession>>>,
+}
+
+impl RecordEncoder {
+    pub fn new(settings: RecordSettings) -> Result<Arc<Self>> {
+        gst::init()?;
+
+        #[cfg(feature = "cuda")]
+        let nvenc = crate::nvenc::try_acquire(&settings.name);
+        #[cfg(feature = "cuda")]
+        if nvenc.is_none() {
+            warn!(stream = %settings.name, "No NVENC session left, recording with x264enc");
+        }
+        #[cfg(feature = "cuda")]
+        let hardware = nvenc.is_some();
+        #[cfg(feature = "cuda")]
+        let nvenc = Arc::new(Mutex::new(nvenc));
+        #[cfg(not(feature = "cuda"))]
+        let hardware = false;
+
+        let description = pipeline_description(&settings, hardware);
+        debug!(stream = %settings.name, "Recording pipeline: {}", description);
+
+        let pipeline = gst::parse::launch(&description)
+            .context("Unable to create the recording pipeline")?
+            .downcast::<gst::Pipeline>()
+            .map_err(|_| anyhow!("Failed to cast to pipeline"))?;
+        pipeline.set_property("name", &settings.name);
+
+        let appsrc = pipeline
+            .by_name("src")
+            .ok_or_else(|| anyhow!("Could not get appsrc element"))?
+            .downcast::<gst_app::AppSrc>()
+            .map_err(|_| anyhow!("Not an appsrc"))?;
+
+        // Counts the size of the encoded stream, checked by the limits guard
+        let bytes = Arc::new(AtomicU64::new(0));
+        pipeline
+            .by_name("parse")
+            .and_then(|parse| parse.static_pad("src"))
+            .ok_or_else(|| anyhow!("Could not get parser src pad"))?
+            .add_probe(gst::PadProbeType::BUFFER, {
+                let bytes = bytes.clone();
+                move |_, info| {
+                    if let Some(buffer) = info.buffer() {
+                        bytes.fetch_add(buffer.size() as u64, Ordering::Relaxed);
+                    }
+                    gst::PadProbeReturn::Ok
+                }
+            });
+
+        let bus = pipeline
+            .bus()
+            .ok_or_else(|| anyhow!("Pipeline has no bus"))?;
+        let stream = settings.name.clone();
+        let log_level = settings.log_level;
+        let target = settings.target.clone();
+        let chapters = matches!(settings.target, RecordTarget::File { .. })
+            .then(|| Arc::new(Mutex::new(RecordChapters::default())));
+        let mp4_chapters = chapters.clone();
+        let next_segment = matches!(settings.target, RecordTarget::Segments { .. })
+            .then(|| Arc::new(AtomicU32::new(settings.first_segment)));
+        let opened_segments = next_segment.clone();
+        let (finalized_sender, finalized) = crossbeam_channel::unbounded();
+        let (eos_sender, eos) = crossbeam_channel::bounded(1);
+        let post_process_sender = spawn_post_processing(&settings, finalized_sender.clone())?;
+        let finalize_file = move |path: PathBuf| match &post_process_sender {
+            Some(sender) => {
+                let _ = sender.send(path);
+            }
+            None => {
+                let _ = finalized_sender.send(path);
+            }
+        };
+        // Restarting the pipeline would overwrite the files, only the streams fall back
+        #[cfg(feature = "cuda")]
+        let fallback = matches!(settings.target, RecordTarget::Rtmp { .. })
+            .then(|| (pipeline.downgrade(), nvenc.clone()));
+        std::thread::spawn(move || {
+            for msg in bus.iter_timed(gst::ClockTime::NONE) {
+                log_bus_message(&stream, log_level, &msg);
+                #[cfg(feature = "cuda")]
+                if let Some((pipeline, session)) = &fallback {
+                    if let Some(pipeline) = pipeline.upgrade() {
+                        crate::nvenc::fall_back_to_software(&pipeline, &stream, session, &msg);
+                    }
+                }
+                match msg.view() {
+                    gst::MessageView::Element(element) => {
+                        let Some(structure) = element.structure() else {
+                            continue;
+                        };
+                        match structure.name().as_str() {
+                            "splitmuxsink-fragment-opened" => {
+                                if let Some(next_segment) = &opened_segments {
+                                    next_segment.fetch_add(1, Ordering::Relaxed);
+                                }
+                            }
+                            "splitmuxsink-fragment-closed" => {
+                                if let Ok(location) = structure.get::<String>("location") {
+                                    finalize_file(PathBuf::from(location));
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    gst::MessageView::Eos(_) => {
+                        if let RecordTarget::File { path } = &target {
+                            let markers = mp4_chapters
+                                .as_ref()
+                                .map(|chapters| chapters.lock().unwrap().relative())
+                                .unwrap_or_default();
+                            if !is_matroska(path) && !markers.is_empty() {
+                                if let Err(e) = chapters::write_mp4_chapters(path, &markers) {
+                                    warn!(
+                                        stream = %stream,
+                                        "Unable to write the chapters of {}: {:?}",
+                                        path.display(),
+                                        e
+                                    );
+                                }
+                            }
+                            finalize_file(path.clone());
+                        }
+                        let _ = eos_sender.send(());
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        Ok(Arc::new(Self {
+            pipeline,
+            appsrc,
+            timestamps: FrameTimestamps::default(),
+            stats: Mutex::new(EncoderStats {
+                width: settings.width,
+                height: settings.height,
+                bitrate: Some(settings.bitrate * 1000),
+                ..Default::default()
+            }),
+            finalized,
+            eos,
+            directory: output_directory(&settings.target),
+            limits: settings.limits,
+            bytes,
+            limit_stopped: Arc::new(AtomicBool::new(false)),
+            limits_reached: crossbeam_channel::unbounded(),
+            guard_stop: Mutex::new(None),
+            chapters,
+            next_segment,
+            #[cfg(feature = "cuda")]
+            _nvenc: nvenc,
+        }))
+    }
+
+    /// Returns a receiver of the paths of the files (or segments) once they are finalized
+    pub fn finalized_files(&self) -> Receiver<PathBuf> {
+        self.finalized.clone()
+    }
+
+    /// Returns a receiver of the limits reached by the recording
+    pub fn limits_reached(&self) -> Receiver<RecordingLimit> {
+        self.limits_reached.1.clone()
+    }
+
+    /// Returns the index of the next segment opened by a segmented recording, to continue
+    /// the numbering after a restart with `RecordSettings::first_segment`
+    pub fn next_segment(&self) -> Option<u32> {
+        self.next_segment
+            .as_ref()
+            .map(|next_segment| next_segment.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn segment_counter(&self) -> Option<Arc<AtomicU32>> {
+        self.next_segment.clone()
+    }
+
+    fn spawn_limits_guard(&self) {
+        if self.limits.is_empty() {
+            return;
+        }
+
+        let guard = LimitsGuard {
+            limits: self.limits.clone(),
+            directory: self.directory.clone(),
+            appsrc: self.appsrc.clone(),
+            splitmux: self.pipeline.by_name("splitmux"),
+            bytes: self.bytes.clone(),
+            limit_stopped: self.limit_stopped.clone(),
+            reached: self.limits_reached.0.clone(),
+        };
+        let (stop_sender, stop) = crossbeam_channel::bounded(1);
+        std::thread::spawn(move || guard.run(stop));
+
+        *self.guard_stop.lock().unwrap() = Some(stop_sender);
+    }
+
+    fn push_frame_buffer(&self, frame: &Frame) -> Result<()> {
+        // The frames are dropped once a limit stopped the recording
+        if self.limit_stopped.load(Ordering::Acquire) {
+            return Ok(());
+        }
+
+        let buffer = self.timestamps.video_buffer(&self.appsrc, frame)?;
+        if let (Some(chapters), Some(pts)) = (&self.chapters, buffer.pts()) {
+            self.add_chapters(&mut chapters.lock().unwrap(), pts);
+        }
+        self.appsrc
+            .push_buffer(buffer)
+            .map_err(|e| anyhow!("Failed to push buffer: {:?}", e))?;
+        self.stats.lock().unwrap().frames_pushed += 1;
+
+        Ok(())
+    }
+
+    /// Adds the pending markers as chapters starting at `pts`
+    fn add_chapters(&self, chapters: &mut RecordChapters, pts: gst::ClockTime) {
+        chapters.start.get_or_insert(pts);
+        if chapters.pending.is_empty() {
+            return;
+        }
+        for name in std::mem::take(&mut chapters.pending) {
+            chapters.chapters.push((pts, name));
+        }
+
+        // The table of contents is written by matroskamux when the file is finalized, the
+        // MP4 chapters are added to the file afterwards
+        let mux = self.pipeline.by_name("mux");
+        if let Some(toc_setter) = mux
+            .as_ref()
+            .and_then(|mux| mux.dynamic_cast_ref::<gst::TocSetter>())
+        {
+            toc_setter.set_toc(Some(&chapters::matroska_toc(&chapters.chapters)));
+        }
+    }
+
+    /// Ends the stream and waits for the muxer to finalize the file
+    fn finalize(&self) -> Result<()> {
+        // Dropping the sender stops the limits guard
+        self.guard_stop.lock().unwrap().take();
+
+        if self.pipeline.current_state() != gst::State::Playing {
+            return Ok(());
+        }
+
+        let _ = self.appsrc.end_of_stream();
+
+        // Signaled by the bus thread once the EOS went through the whole pipeline
+        if self.eos.recv_timeout(Duration::from_secs(5)).is_err() {
+            warn!("Timeout while finalizing the recording");
+        }
+
+        self.pipeline.set_state(gst::State::Null)?;
+
+        Ok(())
+    }
+}
+
+impl Drop for RecordEncoder {
+    fn drop(&mut self) {
+        if let Err(e) = self.finalize() {
+            error!("Unable to finalize recording: {:?}", e);
+        }
+    }
+}
+
+impl StreamEncoder for RecordEncoder {
+    fn push_frame(&self, frame: &Frame) -> Result<()> {
+        self.push_frame_buffer(frame)
+    }
+
+    fn start(&self) -> Result<()> {
+        info!(stream = %self.pipeline.name(), "Start recording");
+        self.pipeline.set_state(gst::State::Playing)?;
+        self.spawn_limits_guard();
+
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<()> {
+        info!(stream = %self.pipeline.name(), "Stop recording");
+        self.finalize()
+    }
+
+    fn set_bitrate(&self, bitrate: u32) -> Result<()> {
+        let encoder = self
+            .pipeline
+            .by_name("encoder")
+            .ok_or_else(|| anyhow!("Could not get encoder element"))?;
+        encoder.set_property("bitrate", (bitrate / 1000).max(1));
+
+        self.stats.lock().unwrap().bitrate = Some(bitrate);
+        Ok(())
+    }
+
+    fn request_keyframe(&self) -> Result<()> {
+        request_appsrc_keyframe(&self.appsrc)
+    }
+
+    /// The markers are written as the chapters of the files, the segments and the RTMP
+    /// streams do not keep them
+    fn add_marker(&self, name: &str, _frame: &Frame) -> Result<()> {
+        let chapters = self
+            .chapters
+            .as_ref()
+            .ok_or_else(|| anyhow!("Only the recordings to a file keep the markers"))?;
+        chapters.lock().unwrap().pending.push(name.to_string());
+
+        Ok(())
+    }
+
+    fn stats(&self) -> Option<EncoderStats> {
+        let mut stats = self.stats.lock().unwrap().clone();
+        stats.latency = pipeline_latency(&self.pipeline);
+        Some(stats)
+    }
+
+    fn pipeline_state(&self) -> Option<gst::State> {
+        Some(self.pipeline.current_state())
+    }
+}
+
+/// Receives the files finalized and the limits reached by the `RecordEncoder` of a camera
+#[derive(Component)]
+pub(crate) struct RecordingOutput {
+    pub(crate) finalized: Receiver<PathBuf>,
+    pub(crate) limits_reached: Receiver<RecordingLimit>,
+    /// See `RecordEncoder::next_segment`
+    pub(crate) next_segment: Option<Arc<AtomicU32>>,
+}
+
+/// This system sends `RecordingFinalized` for the files finalized by the recorders, and
+/// `RecordingLimitReached` for the limits they reached
+pub(crate) fn poll_recording_outputs(
+    outputs: Query<(Entity, &RecordingOutput)>,
+    mut finalized_events: EventWriter<RecordingFinalized>,
+    mut limit_events: EventWriter<RecordingLimitReached>,
+) {
+    for (camera, output) in outputs.iter() {
+        for limit in output.limits_reached.try_iter() {
+            limit_events.write(RecordingLimitReached { camera, limit });
+        }
+        for path in output.finalized.try_iter() {
+            info!("Recording finalized: {}", path.display());
+            finalized_events.write(RecordingFinalized { camera, path });
+        }
+    }
+}
@@ -0,0 +1,520 @@
+use anyhow::{Context, Result, anyhow};
+use bevy_ecs::prelude::*;
+use bevy_platform::collections::HashMap;
+use std::{sync::Arc, time::Duration};
+
+#[cfg(unix)]
+use crate::isolated::{IsolatedEncoder, IsolatedSettings};
+#[cfg(feature = "livekit")]
+use crate::livekit::{LiveKitEncoder, LiveKitSettings};
+#[cfg(feature = "moq")]
+use crate::moq::{MoqEncoder, MoqSettings};
+#[cfg(feature = "ndi")]
+use crate::ndi::{NdiEncoder, NdiSettings};
+#[cfg(feature = "rtsp")]
+use crate::rtsp::{RtspServerEncoder, RtspServerSettings};
+#[cfg(unix)]
+use crate::shm::{ShmEncoder, ShmSettings};
+#[cfg(target_os = "linux")]
+use crate::v4l2::{V4l2Encoder, V4l2Format, V4l2Settings};
+use crate::{
+    DtlsCertificate, GstWebRtcSettings, SignallingServer,
+    custom_pipeline::{CustomPipelineEncoder, CustomPipelineSettings},
+    encoder::EncoderHandle,
+    gst_webrtc_encoder::GstWebRtcEncoder,
+    record::{RecordEncoder, RecordSettings, RecordTarget},
+    rtmp::{RtmpEncoder, RtmpSettings},
+    rtp_udp::{RtpUdpEncoder, RtpUdpSettings},
+};
+
+type EncoderFactory = Arc<dyn Fn(&EncoderConfig) -> Result<EncoderHandle> + Send + Sync>;
+
+/// Backend-agnostic configuration of an encoder, read by the factories of `EncoderRegistry`
+#[derive(Clone, Debug, Default)]
+pub struct EncoderConfig {
+    /// Name of the backend, as registered in `EncoderRegistry`
+    pub backend: String,
+    pub width: u32,
+    pub height: u32,
+    /// Backend specific options, e.g. `uri`, `streamer_id`, `room_name`
+    pub options: HashMap<String, String>,
+}
+
+impl EncoderConfig {
+    /// Reads the configuration from the environment.
+    ///
+    /// The backend is read from `STREAMER_BACKEND`, the size from `STREAMER_WIDTH` and
+    /// `STREAMER_HEIGHT` and every other `STREAMER_*` variable is an option, e.g.
+    /// `STREAMER_URI` is the `uri` option.
+    pub fn from_env() -> Result<Self> {
+        let mut config = Self {
+            backend: std::env::var("STREAMER_BACKEND")
+                .context("STREAMER_BACKEND environment variable must be set")?,
+            width: 1920,
+            height: 1080,
+            options: HashMap::new(),
+        };
+
+        for (key, value) in std::env::vars() {
+            let Some(option) = key.strip_prefix("STREAMER_") else {
+                continue;
+            };
+            match option {
+                "BACKEND" => {}
+                "WIDTH" => config.width = value.parse().context("Invalid STREAMER_WIDTH")?,
+                "HEIGHT" => config.height = value.parse().context("Invalid STREAMER_HEIGHT")?,
+                _ => {
+                    config.options.insert(option.to_lowercase(), value);
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    /// Returns a boolean option, false if it is not set
+    pub fn flag(&self, name: &str) -> Result<bool> {
+        self.option(name)
+            .map_or(Ok(false), str::parse)
+            .with_context(|| format!("Invalid {}", name))
+    }
+
+    /// Returns the HTTP headers of the signalling connection, from the `header_*` options,
+    /// e.g. `STREAMER_HEADER_X_API_KEY` is the `x-api-key` header
+    #[cfg(feature = "pixelstreaming")]
+    pub fn headers(&self) -> Result<HashMap<String, String>> {
+        let headers = self
+            .options
+            .iter()
+            .filter_map(|(name, value)| {
+                let header = name.strip_prefix("header_")?.replace('_', "-");
+                Some((header, value.clone()))
+            })
+            .collect();
+        crate::settings::check_headers(&headers)?;
+        Ok(headers)
+    }
+
+    pub fn required_option(&self, name: &str) -> Result<&str> {
+        self.option(name).ok_or_else(|| {
+            anyhow!(
+                "Option {} is required by the {} backend",
+                name,
+                self.backend
+            )
+        })
+    }
+}
+
+/// Factories of encoders by backend name, to choose the backend at runtime from a config
+/// or the environment.
+///
+/// The `gstwebrtc`, `pixelstreaming`, `janus`, `whip`, `livekit`, `custom`, `record`, `rtmp`, `rtp`, `rtsp`, `moq`, `ndi`, `v4l2`, `shm` and `isolated` backends are
+/// registered by `StreamerPlugin` (depending on the enabled features and the platform). Encoders created from the registry
+/// are used with `StreamerHelper::new_streamer_camera_with_encoder`, without controller.
+#[derive(Resource, Clone, Default)]
+pub struct EncoderRegistry {
+    factories: HashMap<String, EncoderFactory>,
+}
+
+impl EncoderRegistry {
+    /// Registers a factory for `backend`, replacing any existing one
+    pub fn register(
+        &mut self,
+        backend: impl Into<String>,
+        factory: impl Fn(&EncoderConfig) -> Result<EncoderHandle> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.factories.insert(backend.into(), Arc::new(factory));
+        self
+    }
+
+    /// Returns the names of the registered backends
+    pub fn backends(&self) -> impl Iterator<Item = &str> {
+        self.factories.keys().map(String::as_str)
+    }
+
+    /// Creates and starts an encoder for the backend of `config`
+    pub fn create(&self, config: &EncoderConfig) -> Result<EncoderHandle> {
+        let factory = self
+            .factories
+            .get(&config.backend)
+            .ok_or_else(|| anyhow!("Unknown encoder backend {}", config.backend))?;
+
+        let encoder = factory(config)?;
+        encoder.start()?;
+
+        Ok(encoder)
+    }
+
+    pub(crate) fn with_default_backends() -> Self {
+        let mut registry = Self::default();
+
+        registry.register("gstwebrtc", |config| {
+            let settings = GstWebRtcSettings {
+                signalling_server: SignallingServer::GstWebRtc {
+                    uri: config
+                        .option("uri")
+                        .unwrap_or("ws://127.0.0.1:8443")
+                        .to_string(),
+                    peer_id: config.option("peer_id").map(str::to_string),
+                    cafile: config.option("cafile").map(str::to_string),
+                    insecure_tls: config.flag("insecure_tls")?,
+                },
+                ..webrtc_settings(config)?
+            };
+            Ok(Arc::new(GstWebRtcEncoder::with_settings(settings)?))
+        });
+
+        #[cfg(feature = "pixelstreaming")]
+        registry.register("pixelstreaming", |config| {
+            let settings = GstWebRtcSettings {
+                signalling_server: SignallingServer::PixelStreaming {
+                    uri: config
+                        .option("uri")
+                        .unwrap_or("ws://127.0.0.1:8888")
+                        .to_string(),
+                    streamer_id: config.option("streamer_id").map(str::to_string),
+                    proxy: config.option("proxy").map(str::to_string),
+                    headers: config.headers()?,
+                    cafile: config.option("cafile").map(str::to_string),
+                    insecure_tls: config.flag("insecure_tls")?,
+                },
+                ..webrtc_settings(config)?
+            };
+            Ok(Arc::new(GstWebRtcEncoder::with_settings(settings)?))
+        });
+
+        #[cfg(feature = "janus")]
+        registry.register("janus", |config| {
+            let settings = GstWebRtcSettings {
+                signalling_server: SignallingServer::Janus {
+                    uri: config
+                        .option("uri")
+                        .unwrap_or("ws://127.0.0.1:8188")
+                        .to_string(),
+                    room_id: config
+                        .required_option("room_id")?
+                        .parse()
+                        .context("Invalid room_id")?,
+                    feed_id: config
+                        .option("feed_id")
+                        .map(str::parse)
+                        .transpose()
+                        .context("Invalid feed_id")?,
+                    display_name: config.option("display_name").map(str::to_string),
+                    secret_key: config.option("secret_key").map(str::to_string),
+                },
+                ..webrtc_settings(config)?
+            };
+            Ok(Arc::new(GstWebRtcEncoder::with_settings(settings)?))
+        });
+
+        #[cfg(feature = "whip")]
+        registry.register("whip", |config| {
+            let settings = GstWebRtcSettings {
+                signalling_server: SignallingServer::Whip {
+                    endpoint: config.required_option("endpoint")?.to_string(),
+                    token: config.option("token").map(str::to_string),
+                },
+                ..webrtc_settings(config)?
+            };
+            Ok(Arc::new(GstWebRtcEncoder::with_settings(settings)?))
+        });
+
+        #[cfg(feature = "livekit")]
+        registry.register("livekit", |config| {
+            let defaults = LiveKitSettings::default();
+            let option =
+                |name: &str, default: &str| config.option(name).unwrap_or(default).to_string();
+            let settings = LiveKitSettings {
+                url: config.required_option("url")?.to_string(),
+                api_key: config.required_option("api_key")?.to_string(),
+                api_secret: config.required_option("api_secret")?.to_string(),
+                room_name: option("room_name", &defaults.room_name),
+                participant_identity: option(
+                    "participant_identity",
+                    &defaults.participant_identity,
+                ),
+                participant_name: option("participant_name", &defaults.participant_name),
+                proxy: config.option("proxy").map(str::to_string),
+                width: config.width,
+                height: config.height,
+                ..defaults.clone()
+            };
+            Ok(LiveKitEncoder::new(settings)?)
+        });
+
+        registry.register("custom", |config| {
+            let defaults = CustomPipelineSettings::default();
+            let settings = CustomPipelineSettings {
+                pipeline: config.required_option("pipeline")?.to_string(),
+                appsrc_name: config
+                    .option("appsrc_name")
+                    .unwrap_or(&defaults.appsrc_name)
+                    .to_string(),
+                width: config.width,
+                height: config.height,
+                ..defaults.clone()
+            };
+            Ok(CustomPipelineEncoder::new(settings)?)
+        });
+
+        registry.register("record", |config| {
+            let defaults = RecordSettings::default();
+            let target = match (config.option("path"), config.option("url")) {
+                (_, Some(url)) => RecordTarget::Rtmp {
+                    url: url.to_string(),
+                },
+                (Some(path), None) if config.option("segment_duration").is_some() => {
+                    RecordTarget::Segments {
+                        location: path.to_string(),
+                        max_duration: Some(Duration::from_secs(
+                            config
+                                .required_option("segment_duration")?
+                                .parse()
+                                .context("Invalid segment_duration")?,
+                        )),
+                        max_size: None,
+                    }
+                }
+                (Some(path), None) => RecordTarget::File { path: path.into() },
+                (None, None) => defaults.target.clone(),
+            };
+            let settings = RecordSettings {
+                target,
+                width: config.width,
+                height: config.height,
+                framerate: match config.option("framerate") {
+                    Some("vfr") => None,
+                    Some(framerate) => Some(framerate.parse().context("Invalid framerate")?),
+                    None => defaults.framerate,
+                },
+                bitrate: match config.option("bitrate") {
+                    Some(bitrate) => bitrate.parse().context("Invalid bitrate")?,
+                    None => defaults.bitrate,
+                },
+                #[cfg(feature = "encryption")]
+                encryption: config.option("age_recipients").map(|recipients| {
+                    crate::RecordEncryption::Recipients(
+                        recipients
+                            .split(',')
+                            .map(|r| r.trim().to_string())
+                            .collect(),
+                    )
+                }),
+                #[cfg(feature = "upload")]
+                upload: config
+                    .option("upload_url")
+                    .map(|url| crate::UploadSettings {
+                        url: url.to_string(),
+                        delete_after_upload: config.option("delete_after_upload") == Some("true"),
+                    }),
+                ..defaults
+            };
+            Ok(RecordEncoder::new(settings)?)
+        });
+
+        registry.register("rtmp", |config| {
+            let defaults = RtmpSettings::default();
+            let settings = RtmpSettings {
+                url: config.required_option("url")?.to_string(),
+                stream_key: config.option("stream_key").unwrap_or_default().to_string(),
+                width: config.width,
+                height: config.height,
+                framerate: match config.option("framerate") {
+                    Some(framerate) => framerate.parse().context("Invalid framerate")?,
+                    None => defaults.framerate,
+                },
+                bitrate: match config.option("bitrate") {
+                    Some(bitrate) => bitrate.parse().context("Invalid bitrate")?,
+                    None => defaults.bitrate,
+                },
+                keyframe_interval: match config.option("keyframe_interval") {
+                    Some(interval) => Duration::from_secs_f64(
+                        interval.parse().context("Invalid keyframe_interval")?,
+                    ),
+                    None => defaults.keyframe_interval,
+                },
+                ..defaults
+            };
+            Ok(RtmpEncoder::new(settings)?)
+        });
+
+        registry.register("rtp", |config| {
+            let defaults = RtpUdpSettings::default();
+            let settings = RtpUdpSettings {
+                host: config.required_option("host")?.to_string(),
+                port: match config.option("port") {
+                    Some(port) => port.parse().context("Invalid port")?,
+                    None => defaults.port,
+                },
+                codec: match config.option("codec") {
+                    Some(codec) => codec.parse()?,
+                    None => defaults.codec,
+                },
+                rtcp: config.flag("rtcp")?,
+                sdp_path: config.option("sdp_path").map(Into::into),
+                width: config.width,
+                height: config.height,
+                bitrate: match config.option("bitrate") {
+                    Some(bitrate) => bitrate.parse().context("Invalid bitrate")?,
+                    None => defaults.bitrate,
+                },
+                ..defaults
+            };
+            Ok(RtpUdpEncoder::new(settings)?)
+        });
+
+        #[cfg(feature = "rtsp")]
+        registry.register("rtsp", |config| {
+            let defaults = RtspServerSettings::default();
+            let settings = RtspServerSettings {
+                address: config
+                    .option("address")
+                    .unwrap_or(&defaults.address)
+                    .to_string(),
+                port: match config.option("port") {
+                    Some(port) => port.parse().context("Invalid port")?,
+                    None => defaults.port,
+                },
+                mount: config.required_option("mount")?.to_string(),
+                width: config.width,
+                height: config.height,
+                framerate: match config.option("framerate") {
+                    Some(framerate) => framerate.parse().context("Invalid framerate")?,
+                    None => defaults.framerate,
+                },
+                bitrate: match config.option("bitrate") {
+                    Some(bitrate) => bitrate.parse().context("Invalid bitrate")?,
+                    None => defaults.bitrate,
+                },
+                ..defaults
+            };
+            Ok(RtspServerEncoder::new(settings)?)
+        });
+
+        #[cfg(feature = "moq")]
+        registry.register("moq", |config| {
+            let defaults = MoqSettings::default();
+            let settings = MoqSettings {
+                url: config.required_option("url")?.to_string(),
+                insecure_tls: config.flag("insecure_tls")?,
+                width: config.width,
+                height: config.height,
+                framerate: match config.option("framerate") {
+                    Some(framerate) => framerate.parse().context("Invalid framerate")?,
+                    None => defaults.framerate,
+                },
+                bitrate: match config.option("bitrate") {
+                    Some(bitrate) => bitrate.parse().context("Invalid bitrate")?,
+                    None => defaults.bitrate,
+                },
+                ..defaults
+            };
+            Ok(MoqEncoder::new(settings)?)
+        });
+
+        #[cfg(feature = "ndi")]
+        registry.register("ndi", |config| {
+            let defaults = NdiSettings::default();
+            let settings = NdiSettings {
+                ndi_name: config.required_option("ndi_name")?.to_string(),
+                groups: config.option("groups").map(str::to_string),
+                width: config.width,
+                height: config.height,
+                framerate: match config.option("framerate") {
+                    Some(framerate) => framerate.parse().context("Invalid framerate")?,
+                    None => defaults.framerate,
+                },
+                ..defaults
+            };
+            Ok(NdiEncoder::new(settings)?)
+        });
+
+        #[cfg(target_os = "linux")]
+        registry.register("v4l2", |config| {
+            let defaults = V4l2Settings::default();
+            let settings = V4l2Settings {
+                device: config.option("device").map(str::to_string),
+                format: match config.option("format") {
+                    Some("nv12") => V4l2Format::Nv12,
+                    Some("rgba") => V4l2Format::Rgba,
+                    Some(format) => return Err(anyhow!("Unknown V4L2 format {}", format)),
+                    None => defaults.format,
+                },
+                width: config.width,
+                height: config.height,
+                framerate: match config.option("framerate") {
+                    Some(framerate) => framerate.parse().context("Invalid framerate")?,
+                    None => defaults.framerate,
+                },
+                ..defaults
+            };
+            Ok(V4l2Encoder::new(settings)?)
+        });
+
+        #[cfg(unix)]
+        registry.register("shm", |config| {
+            let defaults = ShmSettings::default();
+            let settings = ShmSettings {
+                socket_path: match config.option("socket_path") {
+                    Some(socket_path) => socket_path.into(),
+                    None => defaults.socket_path.clone(),
+                },
+                width: config.width,
+                height: config.height,
+                buffers: match config.option("buffers") {
+                    Some(buffers) => buffers.parse().context("Invalid buffers")?,
+                    None => defaults.buffers,
+                },
+                wait_for_connection: config.flag("wait_for_connection")?,
+                ..defaults
+            };
+            Ok(ShmEncoder::new(settings)?)
+        });
+
+        // Runs the `worker_backend` in a child process, with the other options
+        #[cfg(unix)]
+        registry.register("isolated", |config| {
+            let defaults = IsolatedSettings::default();
+            let mut worker = config.clone();
+            worker.backend = config.required_option("worker_backend")?.to_string();
+            worker.options.remove("worker_backend");
+            let settings = IsolatedSettings {
+                name: config.option("name").unwrap_or(&defaults.name).to_string(),
+                config: worker,
+                ..defaults.clone()
+            };
+            Ok(IsolatedEncoder::new(settings)?)
+        });
+
+        registry
+    }
+}
+
+/// Returns the `GstWebRtcSettings` options shared by the WebRTC backends, to complete with
+/// their signalling server
+fn webrtc_settings(config: &EncoderConfig) -> Result<GstWebRtcSettings> {
+    Ok(GstWebRtcSettings {
+        width: config.width,
+        height: config.height,
+        video_caps: config
+            .option("video_caps")
+            .map(str::parse)
+            .transpose()
+            .context("Invalid video_caps")?,
+        bind_address: config
+            .option("bind_address")
+            .map(str::parse)
+            .transpose()
+            .context("Invalid bind_address")?,
+        dtls_certificate: config
+            .option("dtls_certificate")
+            .map(|path| DtlsCertificate::File(path.into())),
+        ..Default::default()
+    })
+}
@@ -0,0 +1,109 @@
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{ControllerState, StreamerUiInteraction, pixelstreaming::message::PSOutgoingMessage};
+
+/// Envelope used to send events over the data channel.
+///
+/// Outgoing events are sent as Pixel Streaming `Response` messages, incoming events are
+/// expected in `UiInteraction` messages (`emitUIInteraction` in the Pixel Streaming frontend).
+#[derive(Serialize)]
+struct OutgoingEnvelope<'a, E> {
+    event: &'a str,
+    data: &'a E,
+}
+
+#[derive(Deserialize)]
+struct IncomingEnvelope {
+    event: String,
+    data: serde_json::Value,
+}
+
+/// Replicates Bevy events between the app and the peers connected to streamer cameras
+/// with `enable_controller` set.
+pub trait ReplicateEventsAppExt {
+    /// Broadcasts every `E` sent in the app to all connected peers
+    fn replicate_event_to_peers<E>(&mut self, name: &'static str) -> &mut Self
+    where
+        E: Event + Serialize;
+
+    /// Sends an `E` in the app for every matching event received from a peer
+    fn replicate_event_from_peers<E>(&mut self, name: &'static str) -> &mut Self
+    where
+        E: Event + DeserializeOwned;
+
+    /// Replicates `E` in both directions
+    fn replicate_event<E>(&mut self, name: &'static str) -> &mut Self
+    where
+        E: Event + Serialize + DeserializeOwned,
+    {
+        self.replicate_event_to_peers::<E>(name)
+            .replicate_event_from_peers::<E>(name)
+    }
+}
+
+impl ReplicateEventsAppExt for App {
+    fn replicate_event_to_peers<E>(&mut self, name: &'static str) -> &mut Self
+    where
+        E: Event + Serialize,
+    {
+        self.add_event::<E>().add_systems(
+            PostUpdate,
+            move |mut events: EventReader<E>, controllers: Query<&ControllerState>| {
+                for event in events.read() {
+                    let message = match serde_json::to_string(&OutgoingEnvelope {
+                        event: name,
+                        data: event,
+                    }) {
+                        Ok(message) => PSOutgoingMessage::Response(message),
+                        Err(error) => {
+                            warn!("Unable to serialize replicated event {}: {}", name, error);
+                            continue;
+                        }
+                    };
+
+                    for controller in controllers.iter() {
+                        controller.broadcast(&message);
+                    }
+                }
+            },
+        )
+    }
+
+    fn replicate_event_from_peers<E>(&mut self, name: &'static str) -> &mut Self
+    where
+        E: Event + DeserializeOwned,
+    {
+        self.add_event::<E>().add_systems(
+            PreUpdate,
+            (move |mut interactions: EventReader<StreamerUiInteraction>,
+                   mut events: EventWriter<E>| {
+                for interaction in interactions.read() {
+                    let Ok(envelope) =
+                        serde_json::from_str::<IncomingEnvelope>(&interaction.message)
+                    else {
+                        continue;
+                    };
+                    if envelope.event != name {
+                        continue;
+                    }
+
+                    match serde_json::from_value::<E>(envelope.data) {
+                        Ok(event) => {
+                            events.write(event);
+                        }
+                        Err(error) => {
+                            warn!(
+                                "Unable to decode replicated event {} from {}: {}",
+                                name, interaction.peer_id, error
+                            );
+                        }
+                    }
+                }
+            })
+            .after(crate::handle_controller_messages),
+        )
+    }
+}
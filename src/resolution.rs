@@ -0,0 +1,164 @@
+use anyhow::{Result, anyhow};
+use bevy_asset::prelude::*;
+use bevy_ecs::{prelude::*, system::SystemParam};
+use bevy_image::prelude::*;
+use bevy_log::prelude::*;
+use bevy_platform::collections::HashMap;
+use bevy_render::{camera::Camera, render_resource::Extent3d, renderer::RenderDevice};
+
+use crate::{
+    AspectPolicy, GpuMemoryBudget, ResolutionPolicy, StreamerResolutionRequest, capture::Capture,
+};
+
+/// Resizes the streamer cameras: their render target, capture and encoder
+#[derive(SystemParam)]
+pub(crate) struct CaptureResizer<'w, 's> {
+    commands: Commands<'w, 's>,
+    images: ResMut<'w, Assets<Image>>,
+    render_device: Res<'w, RenderDevice>,
+    budget: Option<Res<'w, GpuMemoryBudget>>,
+}
+
+impl CaptureResizer<'_, '_> {
+    /// Resizes the capture held by `entity` within the `GpuMemoryBudget`, if any, and its
+    /// render target according to the `AspectPolicy` of the camera.
+    ///
+    /// A keyframe is requested so that the peers receive the new resolution immediately.
+    pub(crate) fn resize(
+        &mut self,
+        entity: Entity,
+        capture: &Capture,
+        width: u32,
+        height: u32,
+        aspect: AspectPolicy,
+    ) -> Result<()> {
+        // A larger render target can't be created, and would take the app down
+        let max_dimension = self.render_device.limits().max_texture_dimension_2d;
+        if width > max_dimension || height > max_dimension {
+            return Err(anyhow!(
+                "{}x{} exceeds the maximum texture size {}",
+                width,
+                height,
+                max_dimension
+            ));
+        }
+
+        let encoder = capture.encoder();
+        let reservation = capture.reservation();
+        let previous = capture.size();
+        if let (Some(budget), Some(reservation)) = (&self.budget, reservation) {
+            if !budget.resize(reservation, width, height) {
+                return Err(anyhow!(
+                    "Not enough GPU memory to resize to {}x{}",
+                    width,
+                    height
+                ));
+            }
+        }
+
+        // The previous size fit, the reservation is restored if the encoder fails
+        if let Err(e) = encoder.resize(width, height) {
+            if let (Some(budget), Some(reservation)) = (&self.budget, reservation) {
+                budget.resize(reservation, previous.0, previous.1);
+            }
+            return Err(e);
+        }
+        let encoders = capture.encoders();
+        for encoder in encoders.iter().skip(1) {
+            if let Err(e) = encoder.resize(width, height) {
+                warn!("Unable to resize an added encoder: {:?}", e);
+            }
+        }
+
+        let size = Extent3d {
+            width,
+            height,
+            ..Default::default()
+        };
+        let (render_width, render_height) = aspect.render_size(capture.aspect(), width, height);
+        let image = capture.src_image().clone();
+        if let Some(image) = self.images.get_mut(&image) {
+            image.resize(Extent3d {
+                width: render_width,
+                height: render_height,
+                ..Default::default()
+            });
+        }
+
+        // The capture buffers are sized for the frames, replace them
+        let resized = Capture::new(image, size, &self.render_device, encoder.clone())
+            .with_encoders_of(capture)
+            .with_aspect_of(capture)
+            .with_reservation(reservation.cloned());
+        resized.set_enabled(capture.enabled());
+        self.commands.entity(entity).despawn();
+        self.commands.spawn(resized);
+
+        for encoder in &encoders {
+            if let Err(e) = encoder.request_keyframe() {
+                debug!("Unable to request a keyframe after resizing: {:?}", e);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// This system applies the resolutions requested by the peers, according to the
+/// `ResolutionPolicy` of their camera
+pub(crate) fn apply_resolution_requests(
+    mut requests: EventReader<StreamerResolutionRequest>,
+    cameras: Query<(&Camera, Option<&ResolutionPolicy>, Option<&AspectPolicy>)>,
+    captures: Query<(Entity, &Capture)>,
+    mut resizer: CaptureResizer,
+) {
+    // Only the last request received for each camera is applied
+    let latest = requests
+        .read()
+        .map(|request| (request.camera, request))
+        .collect::<HashMap<_, _>>();
+
+    for (camera, request) in latest {
+        let Ok((camera, policy, aspect)) = cameras.get(camera) else {
+            continue;
+        };
+        if request.width == 0 || request.height == 0 {
+            warn!(
+                "Invalid resolution {}x{} requested by {}",
+                request.width, request.height, request.peer_id
+            );
+            continue;
+        }
+        let Some((width, height)) =
+            policy.and_then(|policy| policy.resolve(request.width, request.height))
+        else {
+            debug!(
+                "Ignoring the resolution {}x{} requested by {}",
+                request.width, request.height, request.peer_id
+            );
+            continue;
+        };
+
+        let Some(image) = camera.target.as_image() else {
+            continue;
+        };
+        let Some((entity, capture)) = captures.iter().find(|(_, c)| c.src_image() == image) else {
+            continue;
+        };
+        if capture.size() == (width, height) {
+            continue;
+        }
+
+        info!(
+            "Resizing to {}x{}, {}x{} was requested by {}",
+            width, height, request.width, request.height, request.peer_id
+        );
+        let aspect = aspect.copied().unwrap_or_default();
+        if let Err(e) = resizer.resize(entity, capture, width, height, aspect) {
+            warn!(
+                "Unable to apply the resolution requested by {}: {:?}",
+                request.peer_id, e
+            );
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use anyhow::{Context, Result, anyhow};
+use bevy_log::prelude::*;
+use gst::prelude::*;
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::{
+    PipelineLogLevel,
+    encoder::{
+        EncoderStats, Frame, FrameTimestamps, StreamEncoder, pipeline_latency,
+        request_appsrc_keyframe, resize_appsrc,
+    },
+    pipeline_log::log_bus_message,
+};
+
+/// Settings of a `RtmpEncoder`
+#[derive(Clone)]
+pub struct RtmpSettings {
+    /// Name of the stream
+    pub name: String,
+    /// Labels attached to the stream, see `StreamLabels`
+    pub labels: Vec<(String, String)>,
+    /// Ingest URL, e.g. `rtmp://live.twitch.tv/app` or `rtmp://a.rtmp.youtube.com/live2`
+    pub url: String,
+    /// Stream key appended to the ingest URL, it is not logged
+    pub stream_key: String,
+    pub width: u32,
+    pub height: u32,
+    /// Output framerate, the ingest servers expect a constant framerate: frames are
+    /// duplicated or dropped whatever the rate frames are pushed at
+    pub framerate: u32,
+    /// Bitrate in kbit/s
+    pub bitrate: u32,
+    /// Interval between two keyframes, most ingest servers require 2 seconds
+    pub keyframe_interval: Duration,
+    /// Verbosity of the pipeline messages logs
+    pub log_level: PipelineLogLevel,
+}
+
+impl Default for RtmpSettings {
+    fn default() -> Self {
+        Self {
+            name: "rtmp".to_string(),
+            labels: Vec::new(),
+            url: String::new(),
+            stream_key: String::new(),
+            width: 1920,
+            height: 1080,
+            framerate: 30,
+            bitrate: 6000,
+            keyframe_interval: Duration::from_secs(2),
+            log_level: PipelineLogLevel::default(),
+        }
+    }
+}
+
+impl RtmpSettings {
+    /// Returns the URL of the stream, with the stream key
+    fn location(&self) -> String {
+        if self.stream_key.is_empty() {
+            return self.url.clone();
+        }
+        format!("{}/{}", self.url.trim_end_matches('/'), self.stream_key)
+    }
+}
+
+/// Returns the gst-launch description of a H264 encoder named `encoder`, with a bitrate in
+/// kbit/s and a keyframe every `keyframe_interval`. `hardware` selects NVENC.
+pub(crate) fn h264_encoder_description(
+    bitrate: u32,
+    framerate: u32,
+    keyframe_interval: Duration,
+    hardware: bool,
+) -> String {
+    let key_int_max =
+        ((keyframe_interval.as_secs_f64() * framerate.max(1) as f64).round() as u32).max(1);
+    if hardware {
+        format!("nvh264enc name=encoder rc-mode=cbr bitrate={bitrate} gop-size={key_int_max}")
+    } else {
+        format!(
+            "x264enc name=encoder tune=zerolatency speed-preset=veryfast bitrate={bitrate} \
+            key-int-max={key_int_max}"
+        )
+    }
+}
+
+/// Returns the gst-launch description of the pipeline, without the location of the sink.
+/// `hardware` selects NVENC.
+fn pipeline_description(settings: &RtmpSettings, hardware: bool) -> String {
+    let framerate = settings.framerate.max(1);
+    let encoder = h264_encoder_description(
+        settings.bitrate,
+        framerate,
+        settings.keyframe_interval,
+        hardware,
+    );
+
+    format!(
+        "appsrc name=src format=time is-live=true do-timestamp=true \
+            caps=\"video/x-raw,format=RGBA,width={},height={},framerate=0/1\" ! \
+        queue ! \
+        videoconvert ! \
+        videorate ! \
+        video/x-raw,format=I420,framerate={framerate}/1 ! \
+        {encoder} ! \
+        video/x-h264,profile=main ! \
+        h264parse ! \
+        queue ! \
+        flvmux name=mux streamable=true ! \
+        rtmp2sink name=sink async-connect=true",
+        settings.width, settings.height,
+    )
+}
+
+/// An encoder streaming the frames to a RTMP ingest server, e.g. Twitch or YouTube Live.
+///
+/// The frames are encoded in H264 at a constant framerate and sent without audio in FLV by
+/// `rtmp2sink`. `RecordEncoder` also sends to a RTMP server, without the stream settings of
+/// the ingest servers.
+pub struct RtmpEncoder {
+    pipeline: gst::Pipeline,
+    appsrc: gst_app::AppSrc,
+    timestamps: FrameTimestamps,
+    stats: Mutex<EncoderStats>,
+    bytes_sent: Arc<AtomicU64>,
+    /// Released when the encoder is dropped or falls back to software, see `EncoderFallback`
+    #[cfg(feature = "cuda")]
+    _nvenc: Arc<Mutex<Option<crate::nvenc::NvencSession>>>,
+}
+
+impl RtmpEncoder {
+    pub fn new(settings: RtmpSettings) -> Result<Arc<Self>> {
+        gst::init()?;
+
+        if settings.url.is_empty() {
+            return Err(anyhow!("The RTMP ingest URL is not set"));
+        }
+
+        #[cfg(feature = "cuda")]
+        let nvenc = crate::nvenc::try_acquire(&settings.name);
+        #[cfg(feature = "cuda")]
+        if nvenc.is_none() {
+            warn!(stream = %settings.name, "No NVENC session left, streaming with x264enc");
+        }
+        #[cfg(feature = "cuda")]
+        let hardware = nvenc.is_some();
+        #[cfg(feature = "cuda")]
+        let nvenc = Arc::new(Mutex::new(nvenc));
+        #[cfg(not(feature = "cuda"))]
+        let hardware = false;
+
+        // The location is set afterwards, so that the stream key is not in the logs
+        let description = pipeline_description(&settings, hardware);
+        debug!(stream = %settings.name, "RTMP pipeline: {}", description);
+
+        let pipeline = gst::parse::launch(&description)
+            .context("Unable to create the RTMP pipeline")?
+            .downcast::<gst::Pipeline>()
+            .map_err(|_| anyhow!("Failed to cast to pipeline"))?;
+        pipeline.set_property("name", &settings.name);
+
+        pipeline
+            .by_name("sink")
+            .ok_or_else(|| anyhow!("Could not get rtmp2sink element"))?
+            .set_property("location", settings.location());
+
+        let appsrc = pipeline
+            .by_name("src")
+            .ok_or_else(|| anyhow!("Could not get appsrc element"))?
+            .downcast::<gst_app::AppSrc>()
+            .map_err(|_| anyhow!("Not an appsrc"))?;
+
+        let bytes_sent = Arc::new(AtomicU64::new(0));
+        pipeline
+            .by_name("mux")
+            .and_then(|mux| mux.static_pad("src"))
+            .ok_or_else(|| anyhow!("Could not get muxer src pad"))?
+            .add_probe(gst::PadProbeType::BUFFER, {
+                let bytes_sent = bytes_sent.clone();
+                move |_, info| {
+                    if let Some(buffer) = info.buffer() {
+                        bytes_sent.fetch_add(buffer.size() as u64, Ordering::Relaxed);
+                    }
+                    gst::PadProbeReturn::Ok
+                }
+            });
+
+        let bus = pipeline
+            .bus()
+            .ok_or_else(|| anyhow!("Pipeline has no bus"))?;
+        let stream = settings.name.clone();
+        let log_level = settings.log_level;
+        #[cfg(feature = "cuda")]
+        let (weak_pipeline, session) = (pipeline.downgrade(), nvenc.clone());
+        std::thread::spawn(move || {
+            for msg in bus.iter_timed(gst::ClockTime::NONE) {
+                log_bus_message(&stream, log_level, &msg);
+                #[cfg(feature = "cuda")]
+                if let Some(pipeline) = weak_pipeline.upgrade() {
+                    crate::nvenc::fall_back_to_software(&pipeline, &stream, &session, &msg);
+                }
+                if let gst::MessageView::Eos(_) = msg.view() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Arc::new(Self {
+            pipeline,
+            appsrc,
+            timestamps: FrameTimestamps::default(),
+            stats: Mutex::new(EncoderStats {
+                width: settings.width,
+                height: settings.height,
+                bitrate: Some(settings.bitrate * 1000),
+                ..Default::default()
+            }),
+            bytes_sent,
+            #[cfg(feature = "cuda")]
+            _nvenc: nvenc,
+        }))
+    }
+}
+
+impl Drop for RtmpEncoder {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
+
+impl StreamEncoder for RtmpEncoder {
+    fn push_frame(&self, frame: &Frame) -> Result<()> {
+        let buffer = self.timestamps.video_buffer(&self.appsrc, frame)?;
+        self.appsrc
+            .push_buffer(buffer)
+            .map_err(|e| anyhow!("Failed to push buffer: {:?}", e))?;
+        self.stats.lock().unwrap().frames_pushed += 1;
+
+        Ok(())
+    }
+
+    fn start(&self) -> Result<()> {
+        info!(stream = %self.pipeline.name(), "Start RTMP stream");
+        self.pipeline.set_state(gst::State::Playing)?;
+
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<()> {
+        info!(stream = %self.pipeline.name(), "Stop RTMP stream");
+        self.pipeline.set_state(gst::State::Null)?;
+
+        Ok(())
+    }
+
+    fn resize(&self, width: u32, height: u32) -> Result<()> {
+        resize_appsrc(&self.appsrc, width, height)?;
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.width = width;
+        stats.height = height;
+
+        Ok(())
+    }
+
+    fn set_bitrate(&self, bitrate: u32) -> Result<()> {
+        let encoder = self
+            .pipeline
+            .by_name("encoder")
+            .ok_or_else(|| anyhow!("Could not get encoder element"))?;
+        encoder.set_property("bitrate", (bitrate / 1000).max(1));
+
+        self.stats.lock().unwrap().bitrate = Some(bitrate);
+        Ok(())
+    }
+
+    fn request_keyframe(&self) -> Result<()> {
+        request_appsrc_keyframe(&self.appsrc)
+    }
+
+    fn stats(&self) -> Option<EncoderStats> {
+        let mut stats = self.stats.lock().unwrap().clone();
+        stats.bytes_sent = self.bytes_sent.load(Ordering::Relaxed);
+        stats.latency = pipeline_latency(&self.pipeline);
+        Some(stats)
+    }
+
+    fn pipeline_state(&self) -> Option<gst::State> {
+        Some(self.pipeline.current_state())
+    }
+}
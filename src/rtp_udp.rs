@@ -0,0 +1,360 @@
+use anyhow::{Context, Result, anyhow, bail};
+use bevy_log::prelude::*;
+use gst::prelude::*;
+use std::{
+    net::IpAddr,
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::{
+    PipelineLogLevel, VideoCodec,
+    encoder::{
+        EncoderStats, Frame, FrameTimestamps, StreamEncoder, pipeline_latency,
+        request_appsrc_keyframe, resize_appsrc,
+    },
+    pipeline_log::log_bus_message,
+    rtmp::h264_encoder_description,
+};
+
+/// Payload type of the RTP packets, announced by `RtpUdpEncoder::sdp`
+const PAYLOAD_TYPE: u32 = 96;
+
+/// Settings of a `RtpUdpEncoder`
+#[derive(Clone)]
+pub struct RtpUdpSettings {
+    /// Name of the stream
+    pub name: String,
+    /// Labels attached to the stream, see `StreamLabels`
+    pub labels: Vec<(String, String)>,
+    /// Address of the receiver, or of a multicast group
+    pub host: String,
+    /// Port of the RTP packets, the RTCP packets are sent to the next one
+    pub port: u16,
+    /// H264 or VP8
+    pub codec: VideoCodec,
+    /// Sends RTCP sender reports, which the receivers use to synchronize the stream
+    pub rtcp: bool,
+    /// File the session description of the stream is written to for the receivers, see
+    /// `RtpUdpEncoder::sdp`
+    pub sdp_path: Option<PathBuf>,
+    pub width: u32,
+    pub height: u32,
+    pub framerate: u32,
+    /// Bitrate in kbit/s
+    pub bitrate: u32,
+    /// Interval between two keyframes, the receivers can only start decoding at a keyframe
+    pub keyframe_interval: Duration,
+    /// Maximum size of the RTP packets in bytes
+    pub mtu: u32,
+    /// Verbosity of the pipeline messages logs
+    pub log_level: PipelineLogLevel,
+}
+
+impl Default for RtpUdpSettings {
+    fn default() -> Self {
+        Self {
+            name: "rtp".to_string(),
+            labels: Vec::new(),
+            host: "127.0.0.1".to_string(),
+            port: 5000,
+            codec: VideoCodec::H264,
+            rtcp: false,
+            sdp_path: None,
+            width: 1920,
+            height: 1080,
+            framerate: 30,
+            bitrate: 4000,
+            keyframe_interval: Duration::from_secs(1),
+            mtu: 1200,
+            log_level: PipelineLogLevel::default(),
+        }
+    }
+}
+
+/// Returns the gst-launch description of the encoder and the payloader of `settings.codec`,
+/// `hardware` selects NVENC for H264
+fn encoder_description(settings: &RtpUdpSettings, hardware: bool) -> Result<String> {
+    let framerate = settings.framerate.max(1);
+    Ok(match settings.codec {
+        VideoCodec::H264 => format!(
+            "{} ! video/x-h264,profile=main ! h264parse ! \
+            rtph264pay name=pay pt={PAYLOAD_TYPE} mtu={} config-interval=-1",
+            h264_encoder_description(
+                settings.bitrate,
+                framerate,
+                settings.keyframe_interval,
+                hardware,
+            ),
+            settings.mtu,
+        ),
+        VideoCodec::Vp8 => {
+            let key_int_max =
+                ((settings.keyframe_interval.as_secs_f64() * framerate as f64).round() as u32)
+                    .max(1);
+            format!(
+                "vp8enc name=encoder deadline=1 cpu-used=8 end-usage=cbr target-bitrate={} \
+                keyframe-max-dist={key_int_max} ! \
+                rtpvp8pay name=pay pt={PAYLOAD_TYPE} mtu={}",
+                settings.bitrate * 1000,
+                settings.mtu,
+            )
+        }
+        codec => bail!("The {:?} codec is not supported over RTP/UDP", codec),
+    })
+}
+
+/// An encoder sending the frames as RTP packets over UDP to a host, e.g. a ffmpeg or
+/// GStreamer receiver on the LAN, without signalling.
+///
+/// The receivers need the session description returned by `RtpUdpEncoder::sdp`, logged when
+/// the stream starts and written to `RtpUdpSettings::sdp_path`, e.g.
+/// `ffplay -protocol_whitelist file,udp,rtp stream.sdp`.
+pub struct RtpUdpEncoder {
+    pipeline: gst::Pipeline,
+    appsrc: gst_app::AppSrc,
+    codec: VideoCodec,
+    sdp: String,
+    timestamps: FrameTimestamps,
+    stats: Mutex<EncoderStats>,
+    bytes_sent: Arc<AtomicU64>,
+    /// Released when the encoder is dropped or falls back to software, see `EncoderFallback`
+    #[cfg(feature = "cuda")]
+    _nvenc: Arc<Mutex<Option<crate::nvenc::NvencSession>>>,
+}
+
+impl RtpUdpEncoder {
+    pub fn new(settings: RtpUdpSettings) -> Result<Arc<Self>> {
+        gst::init()?;
+
+        if settings.rtcp && settings.port == u16::MAX {
+            bail!("RTCP is sent to the next port, the RTP port can't be 65535");
+        }
+        let sdp = session_description(&settings);
+        if let Some(path) = &settings.sdp_path {
+            std::fs::write(path, &sdp)
+                .with_context(|| format!("Unable to write the SDP to {}", path.display()))?;
+        }
+
+        #[cfg(feature = "cuda")]
+        let nvenc = (settings.codec == VideoCodec::H264)
+            .then(|| crate::nvenc::try_acquire(&settings.name))
+            .flatten();
+        #[cfg(feature = "cuda")]
+        if settings.codec == VideoCodec::H264 && nvenc.is_none() {
+            warn!(stream = %settings.name, "No NVENC session left, streaming with x264enc");
+        }
+        #[cfg(feature = "cuda")]
+        let hardware = nvenc.is_some();
+        #[cfg(feature = "cuda")]
+        let nvenc = Arc::new(Mutex::new(nvenc));
+        #[cfg(not(feature = "cuda"))]
+        let hardware = false;
+
+        let encoder = encoder_description(&settings, hardware)?;
+        let (host, port) = (&settings.host, settings.port);
+        let output = if settings.rtcp {
+            format!(
+                "rtpbin.send_rtp_sink_0 \
+                rtpbin name=rtpbin \
+                rtpbin.send_rtp_src_0 ! udpsink host=\"{host}\" port={port} \
+                rtpbin.send_rtcp_src_0 ! udpsink host=\"{host}\" port={} sync=false async=false",
+                port + 1,
+            )
+        } else {
+            format!("udpsink host=\"{host}\" port={port}")
+        };
+        let description = format!(
+            "appsrc name=src format=time is-live=true do-timestamp=true \
+                caps=\"video/x-raw,format=RGBA,width={},height={},framerate=0/1\" ! \
+            queue ! \
+            videoconvert ! \
+            videorate ! \
+            video/x-raw,format=I420,framerate={}/1 ! \
+            {encoder} ! \
+            {output}",
+            settings.width,
+            settings.height,
+            settings.framerate.max(1),
+        );
+        debug!(stream = %settings.name, "RTP pipeline: {}", description);
+
+        let pipeline = gst::parse::launch(&description)
+            .context("Unable to create the RTP pipeline")?
+            .downcast::<gst::Pipeline>()
+            .map_err(|_| anyhow!("Failed to cast to pipeline"))?;
+        pipeline.set_property("name", &settings.name);
+
+        let appsrc = pipeline
+            .by_name("src")
+            .ok_or_else(|| anyhow!("Could not get appsrc element"))?
+            .downcast::<gst_app::AppSrc>()
+            .map_err(|_| anyhow!("Not an appsrc"))?;
+
+        let bytes_sent = Arc::new(AtomicU64::new(0));
+        pipeline
+            .by_name("pay")
+            .and_then(|pay| pay.static_pad("src"))
+            .ok_or_else(|| anyhow!("Could not get payloader src pad"))?
+            .add_probe(gst::PadProbeType::BUFFER, {
+                let bytes_sent = bytes_sent.clone();
+                move |_, info| {
+                    if let Some(buffer) = info.buffer() {
+                        bytes_sent.fetch_add(buffer.size() as u64, Ordering::Relaxed);
+                    }
+                    gst::PadProbeReturn::Ok
+                }
+            });
+
+        let bus = pipeline
+            .bus()
+            .ok_or_else(|| anyhow!("Pipeline has no bus"))?;
+        let stream = settings.name.clone();
+        let log_level = settings.log_level;
+        #[cfg(feature = "cuda")]
+        let (weak_pipeline, session) = (pipeline.downgrade(), nvenc.clone());
+        std::thread::spawn(move || {
+            for msg in bus.iter_timed(gst::ClockTime::NONE) {
+                log_bus_message(&stream, log_level, &msg);
+                #[cfg(feature = "cuda")]
+                if let Some(pipeline) = weak_pipeline.upgrade() {
+                    crate::nvenc::fall_back_to_software(&pipeline, &stream, &session, &msg);
+                }
+                if let gst::MessageView::Eos(_) = msg.view() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Arc::new(Self {
+            pipeline,
+            appsrc,
+            codec: settings.codec,
+            sdp,
+            timestamps: FrameTimestamps::default(),
+            stats: Mutex::new(EncoderStats {
+                width: settings.width,
+                height: settings.height,
+                bitrate: Some(settings.bitrate * 1000),
+                ..Default::default()
+            }),
+            bytes_sent,
+            #[cfg(feature = "cuda")]
+            _nvenc: nvenc,
+        }))
+    }
+
+    /// Returns the session description of the stream, to save in a `.sdp` file read by the
+    /// receivers
+    pub fn sdp(&self) -> &str {
+        &self.sdp
+    }
+}
+
+/// Returns the session description of the RTP stream sent with `settings`
+fn session_description(settings: &RtpUdpSettings) -> String {
+    let family = match settings.host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(_)) => "IP6",
+        _ => "IP4",
+    };
+    let (encoding, fmtp) = match settings.codec {
+        VideoCodec::Vp8 => ("VP8", None),
+        _ => ("H264", Some("packetization-mode=1")),
+    };
+
+    let mut sdp = format!(
+        "v=0\r\n\
+        o=- 0 0 IN {family} {host}\r\n\
+        s={name}\r\n\
+        c=IN {family} {host}\r\n\
+        t=0 0\r\n\
+        m=video {port} RTP/AVP {PAYLOAD_TYPE}\r\n\
+        a=rtpmap:{PAYLOAD_TYPE} {encoding}/90000\r\n",
+        host = settings.host,
+        name = settings.name,
+        port = settings.port,
+    );
+    if let Some(fmtp) = fmtp {
+        sdp += &format!("a=fmtp:{PAYLOAD_TYPE} {fmtp}\r\n");
+    }
+    if settings.rtcp {
+        sdp += &format!("a=rtcp:{}\r\n", settings.port + 1);
+    }
+    sdp
+}
+
+impl Drop for RtpUdpEncoder {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
+
+impl StreamEncoder for RtpUdpEncoder {
+    fn push_frame(&self, frame: &Frame) -> Result<()> {
+        let buffer = self.timestamps.video_buffer(&self.appsrc, frame)?;
+        self.appsrc
+            .push_buffer(buffer)
+            .map_err(|e| anyhow!("Failed to push buffer: {:?}", e))?;
+        self.stats.lock().unwrap().frames_pushed += 1;
+
+        Ok(())
+    }
+
+    fn start(&self) -> Result<()> {
+        info!(stream = %self.pipeline.name(), "Start RTP stream, SDP:\n{}", self.sdp);
+        self.pipeline.set_state(gst::State::Playing)?;
+
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<()> {
+        info!(stream = %self.pipeline.name(), "Stop RTP stream");
+        self.pipeline.set_state(gst::State::Null)?;
+
+        Ok(())
+    }
+
+    fn resize(&self, width: u32, height: u32) -> Result<()> {
+        resize_appsrc(&self.appsrc, width, height)?;
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.width = width;
+        stats.height = height;
+
+        Ok(())
+    }
+
+    fn set_bitrate(&self, bitrate: u32) -> Result<()> {
+        let encoder = self
+            .pipeline
+            .by_name("encoder")
+            .ok_or_else(|| anyhow!("Could not get encoder element"))?;
+        match self.codec {
+            // vp8enc takes bit/s, x264enc and nvh264enc kbit/s
+            VideoCodec::Vp8 => encoder.set_property("target-bitrate", bitrate as i32),
+            _ => encoder.set_property("bitrate", (bitrate / 1000).max(1)),
+        }
+
+        self.stats.lock().unwrap().bitrate = Some(bitrate);
+        Ok(())
+    }
+
+    fn request_keyframe(&self) -> Result<()> {
+        request_appsrc_keyframe(&self.appsrc)
+    }
+
+    fn stats(&self) -> Option<EncoderStats> {
+        let mut stats = self.stats.lock().unwrap().clone();
+        stats.bytes_sent = self.bytes_sent.load(Ordering::Relaxed);
+        stats.latency = pipeline_latency(&self.pipeline);
+        Some(stats)
+    }
+
+    fn pipeline_state(&self) -> Option<gst::State> {
+        Some(self.pipeline.current_state())
+    }
+}
@@ -0,0 +1,411 @@
+use anyhow::{Result, anyhow};
+use bevy_log::prelude::*;
+use bevy_platform::collections::{HashMap, HashSet};
+use gst::prelude::*;
+use gst_rtsp_server::prelude::*;
+use std::{
+    sync::{
+        Arc, LazyLock, Mutex, Weak,
+        atomic::{AtomicU32, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::{
+    encoder::{
+        EncoderStats, Frame, FrameTimestamps, StreamEncoder, request_appsrc_keyframe, resize_appsrc,
+    },
+    rtmp::h264_encoder_description,
+};
+
+/// Settings of a `RtspServerEncoder`
+#[derive(Clone)]
+pub struct RtspServerSettings {
+    /// Name of the stream
+    pub name: String,
+    /// Labels attached to the stream, see `StreamLabels`
+    pub labels: Vec<(String, String)>,
+    /// Address the RTSP server listens on
+    pub address: String,
+    /// Port of the RTSP server, the streams with the same address and port share a server
+    pub port: u16,
+    /// Path of the stream on the server, e.g. `/camera0`
+    pub mount: String,
+    pub width: u32,
+    pub height: u32,
+    /// Output framerate, frames are duplicated or dropped whatever the rate frames are
+    /// pushed at
+    pub framerate: u32,
+    /// Bitrate in kbit/s
+    pub bitrate: u32,
+    /// Interval between two keyframes, the clients joining the stream wait for the next one
+    pub keyframe_interval: Duration,
+}
+
+impl Default for RtspServerSettings {
+    fn default() -> Self {
+        Self {
+            name: "rtsp".to_string(),
+            labels: Vec::new(),
+            address: "0.0.0.0".to_string(),
+            port: 8554,
+            mount: "/camera0".to_string(),
+            width: 1920,
+            height: 1080,
+            framerate: 30,
+            bitrate: 4000,
+            keyframe_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// A RTSP server shared by the encoders with the same address and port, running its own
+/// GLib main loop
+struct RtspServer {
+    server: gst_rtsp_server::RTSPServer,
+    main_loop: glib::MainLoop,
+    source: Mutex<Option<glib::SourceId>>,
+    mounts: Mutex<HashSet<String>>,
+}
+
+/// Servers by address and port, alive as long as an encoder uses them
+static SERVERS: LazyLock<Mutex<HashMap<(String, u16), Weak<RtspServer>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::default()));
+
+impl RtspServer {
+    /// Returns the server listening on `address` and `port`, starting it if needed
+    fn get_or_start(address: &str, port: u16) -> Result<Arc<Self>> {
+        let mut servers = SERVERS.lock().unwrap();
+        let key = (address.to_string(), port);
+        if let Some(server) = servers.get(&key).and_then(Weak::upgrade) {
+            return Ok(server);
+        }
+
+        let server = gst_rtsp_server::RTSPServer::new();
+        server.set_address(address);
+        server.set_service(&port.to_string());
+
+        let context = glib::MainContext::new();
+        let source = server
+            .attach(Some(&context))
+            .map_err(|e| anyhow!("Unable to start the RTSP server on {address}:{port}: {e}"))?;
+        let main_loop = glib::MainLoop::new(Some(&context), false);
+        std::thread::spawn({
+            let main_loop = main_loop.clone();
+            move || {
+                let _ = context.with_thread_default(|| main_loop.run());
+            }
+        });
+        info!("RTSP server listening on {}:{}", address, port);
+
+        let server = Arc::new(Self {
+            server,
+            main_loop,
+            source: Mutex::new(Some(source)),
+            mounts: Mutex::new(HashSet::new()),
+        });
+        servers.insert(key, Arc::downgrade(&server));
+        Ok(server)
+    }
+
+    /// Reserves a mount point, so that two encoders can't use the same one
+    fn reserve(&self, mount: &str) -> Result<()> {
+        if !self.mounts.lock().unwrap().insert(mount.to_string()) {
+            return Err(anyhow!("The RTSP mount point {} is already used", mount));
+        }
+        Ok(())
+    }
+
+    fn release(&self, mount: &str) {
+        self.mounts.lock().unwrap().remove(mount);
+    }
+
+    fn mount(&self, mount: &str, factory: &gst_rtsp_server::RTSPMediaFactory) -> Result<()> {
+        self.server
+            .mount_points()
+            .ok_or_else(|| anyhow!("The RTSP server has no mount points"))?
+            .add_factory(mount, factory.clone());
+        Ok(())
+    }
+
+    fn unmount(&self, mount: &str) {
+        if let Some(mount_points) = self.server.mount_points() {
+            mount_points.remove_factory(mount);
+        }
+    }
+}
+
+impl Drop for RtspServer {
+    fn drop(&mut self) {
+        if let Some(source) = self.source.lock().unwrap().take() {
+            source.remove();
+        }
+        self.main_loop.quit();
+    }
+}
+
+/// The media streamed to the clients of a mount point, while at least one client is
+/// connected
+struct RtspMedia {
+    element: gst::Element,
+    appsrc: gst_app::AppSrc,
+    /// Each media has its own running time
+    timestamps: FrameTimestamps,
+}
+
+/// An encoder exposing the frames as a RTSP mount point, e.g. `rtsp://host:8554/camera0`, for
+/// the clients pulling streams from IP cameras, e.g. NVRs and VLC.
+///
+/// The frames are encoded in H264 at a constant framerate, only while a client is connected:
+/// the clients of a mount point share the same encoding. The encoders with the same address
+/// and port share a server.
+pub struct RtspServerEncoder {
+    server: Arc<RtspServer>,
+    mount: String,
+    factory: gst_rtsp_server::RTSPMediaFactory,
+    media: Arc<Mutex<Option<RtspMedia>>>,
+    /// Caps of the appsrc of the next media, updated on resize
+    caps: Arc<Mutex<gst::Caps>>,
+    /// Bitrate of the next media in kbit/s
+    bitrate: Arc<AtomicU32>,
+    stats: Mutex<EncoderStats>,
+    bytes_sent: Arc<AtomicU64>,
+    /// Released when the encoder is dropped
+    #[cfg(feature = "cuda")]
+    _nvenc: Option<crate::nvenc::NvencSession>,
+}
+
+fn video_caps(width: u32, height: u32) -> gst::Caps {
+    gst::Caps::builder("video/x-raw")
+        .field("format", "RGBA")
+        .field("width", width as i32)
+        .field("height", height as i32)
+        .field("framerate", gst::Fraction::new(0, 1))
+        .build()
+}
+
+impl RtspServerEncoder {
+    pub fn new(settings: RtspServerSettings) -> Result<Arc<Self>> {
+        gst::init()?;
+
+        if !settings.mount.starts_with('/') {
+            return Err(anyhow!(
+                "The RTSP mount point {} must start with a /",
+                settings.mount
+            ));
+        }
+
+        #[cfg(feature = "cuda")]
+        let nvenc = crate::nvenc::try_acquire(&settings.name);
+        #[cfg(feature = "cuda")]
+        if nvenc.is_none() {
+            warn!(stream = %settings.name, "No NVENC session left, streaming with x264enc");
+        }
+        #[cfg(feature = "cuda")]
+        let hardware = nvenc.is_some();
+        #[cfg(not(feature = "cuda"))]
+        let hardware = false;
+
+        let framerate = settings.framerate.max(1);
+        let encoder = h264_encoder_description(
+            settings.bitrate,
+            framerate,
+            settings.keyframe_interval,
+            hardware,
+        );
+        let description = format!(
+            "( appsrc name=src format=time is-live=true do-timestamp=true ! \
+            queue ! \
+            videoconvert ! \
+            videorate ! \
+            video/x-raw,format=I420,framerate={framerate}/1 ! \
+            {encoder} ! \
+            video/x-h264,profile=main ! \
+            h264parse ! \
+            rtph264pay name=pay0 pt=96 config-interval=-1 )"
+        );
+        debug!(stream = %settings.name, "RTSP media: {}", description);
+
+        let server = RtspServer::get_or_start(&settings.address, settings.port)?;
+        server.reserve(&settings.mount)?;
+
+        let factory = gst_rtsp_server::RTSPMediaFactory::new();
+        factory.set_launch(&description);
+        factory.set_shared(true);
+
+        let media = Arc::new(Mutex::new(None));
+        let caps = Arc::new(Mutex::new(video_caps(settings.width, settings.height)));
+        let bitrate = Arc::new(AtomicU32::new(settings.bitrate));
+        let bytes_sent = Arc::new(AtomicU64::new(0));
+        factory.connect_media_configure({
+            let media = media.clone();
+            let caps = caps.clone();
+            let bitrate = bitrate.clone();
+            let bytes_sent = bytes_sent.clone();
+            let stream = settings.name.clone();
+            move |_, rtsp_media| {
+                let element = rtsp_media.element();
+                let Some(appsrc) = element
+                    .downcast_ref::<gst::Bin>()
+                    .and_then(|bin| bin.by_name("src"))
+                    .and_then(|src| src.downcast::<gst_app::AppSrc>().ok())
+                else {
+                    error!(stream = %stream, "Could not get the appsrc of the RTSP media");
+                    return;
+                };
+                appsrc.set_caps(Some(&*caps.lock().unwrap()));
+
+                let bin = element.downcast_ref::<gst::Bin>();
+                if let Some(encoder) = bin.and_then(|bin| bin.by_name("encoder")) {
+                    encoder.set_property("bitrate", bitrate.load(Ordering::Relaxed));
+                }
+                if let Some(pad) = bin
+                    .and_then(|bin| bin.by_name("pay0"))
+                    .and_then(|pay| pay.static_pad("src"))
+                {
+                    let bytes_sent = bytes_sent.clone();
+                    pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+                        if let Some(buffer) = info.buffer() {
+                            bytes_sent.fetch_add(buffer.size() as u64, Ordering::Relaxed);
+                        }
+                        gst::PadProbeReturn::Ok
+                    });
+                }
+
+                rtsp_media.connect_unprepared({
+                    let media = media.clone();
+                    let stream = stream.clone();
+                    move |_| {
+                        info!(stream = %stream, "RTSP media stopped, no client left");
+                        media.lock().unwrap().take();
+                    }
+                });
+
+                info!(stream = %stream, "RTSP media started");
+                *media.lock().unwrap() = Some(RtspMedia {
+                    element,
+                    appsrc,
+                    timestamps: FrameTimestamps::default(),
+                });
+            }
+        });
+
+        Ok(Arc::new(Self {
+            server,
+            mount: settings.mount,
+            factory,
+            media,
+            caps,
+            bitrate,
+            stats: Mutex::new(EncoderStats {
+                width: settings.width,
+                height: settings.height,
+                bitrate: Some(settings.bitrate * 1000),
+                ..Default::default()
+            }),
+            bytes_sent,
+            #[cfg(feature = "cuda")]
+            _nvenc: nvenc,
+        }))
+    }
+
+    /// Returns the path of the stream on the server
+    pub fn mount(&self) -> &str {
+        &self.mount
+    }
+
+    /// Returns true if at least one client is watching the stream
+    pub fn has_clients(&self) -> bool {
+        self.media.lock().unwrap().is_some()
+    }
+}
+
+impl Drop for RtspServerEncoder {
+    fn drop(&mut self) {
+        self.server.unmount(&self.mount);
+        self.server.release(&self.mount);
+    }
+}
+
+impl StreamEncoder for RtspServerEncoder {
+    fn push_frame(&self, frame: &Frame) -> Result<()> {
+        // Counted even without client, the frames are dropped until one connects
+        self.stats.lock().unwrap().frames_pushed += 1;
+
+        let media = self.media.lock().unwrap();
+        let Some(media) = media.as_ref() else {
+            return Ok(());
+        };
+        let buffer = media.timestamps.video_buffer(&media.appsrc, frame)?;
+        media
+            .appsrc
+            .push_buffer(buffer)
+            .map_err(|e| anyhow!("Failed to push buffer: {:?}", e))?;
+
+        Ok(())
+    }
+
+    fn start(&self) -> Result<()> {
+        info!("Mount RTSP stream {}", self.mount);
+        self.server.mount(&self.mount, &self.factory)
+    }
+
+    fn stop(&self) -> Result<()> {
+        info!("Unmount RTSP stream {}", self.mount);
+        self.server.unmount(&self.mount);
+
+        Ok(())
+    }
+
+    fn resize(&self, width: u32, height: u32) -> Result<()> {
+        *self.caps.lock().unwrap() = video_caps(width, height);
+        if let Some(media) = self.media.lock().unwrap().as_ref() {
+            resize_appsrc(&media.appsrc, width, height)?;
+        }
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.width = width;
+        stats.height = height;
+
+        Ok(())
+    }
+
+    fn set_bitrate(&self, bitrate: u32) -> Result<()> {
+        self.bitrate
+            .store((bitrate / 1000).max(1), Ordering::Relaxed);
+        if let Some(encoder) = self
+            .media
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|media| media.element.downcast_ref::<gst::Bin>()?.by_name("encoder"))
+        {
+            encoder.set_property("bitrate", (bitrate / 1000).max(1));
+        }
+
+        self.stats.lock().unwrap().bitrate = Some(bitrate);
+        Ok(())
+    }
+
+    fn request_keyframe(&self) -> Result<()> {
+        match self.media.lock().unwrap().as_ref() {
+            Some(media) => request_appsrc_keyframe(&media.appsrc),
+            None => Ok(()),
+        }
+    }
+
+    fn stats(&self) -> Option<EncoderStats> {
+        let mut stats = self.stats.lock().unwrap().clone();
+        stats.bytes_sent = self.bytes_sent.load(Ordering::Relaxed);
+        Some(stats)
+    }
+
+    /// The state of the media, `None` while no client is connected
+    fn pipeline_state(&self) -> Option<gst::State> {
+        self.media
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|media| media.element.current_state())
+    }
+}
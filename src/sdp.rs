@@ -0,0 +1,85 @@
+use gst::prelude::*;
+use gstrswebrtc::{signaller::Signallable, webrtcsink::BaseWebRTCSink};
+use std::sync::Arc;
+
+#[cfg(feature = "pixelstreaming")]
+use crate::pixelstreaming::signaller::UePsSignaller;
+
+/// Whether a session description is sent to a peer or received from it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SdpDirection {
+    Outgoing,
+    Incoming,
+}
+
+/// A session description about to be exchanged with a peer
+#[derive(Clone, Copy, Debug)]
+pub struct SdpContext<'a> {
+    /// Name of the stream, see `StreamLabels`
+    pub stream: &'a str,
+    pub session_id: &'a str,
+    pub direction: SdpDirection,
+    /// Offer or answer
+    pub sdp_type: gst_webrtc::WebRTCSDPType,
+}
+
+type MungeFn = dyn Fn(&SdpContext, &mut gst_sdp::SDPMessage) + Send + Sync;
+
+/// Rewrites the session descriptions exchanged with the peers, to work around picky decoders,
+/// e.g. by forcing a `profile-level-id`, reordering the codecs or stripping header extensions.
+///
+/// Outgoing descriptions are munged with every signaller, incoming ones only with Pixel
+/// Streaming, whose signaller is part of this crate.
+#[derive(Clone)]
+pub struct SdpMunger(Arc<MungeFn>);
+
+impl SdpMunger {
+    pub fn new(
+        munge: impl Fn(&SdpContext, &mut gst_sdp::SDPMessage) + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(munge))
+    }
+
+    /// Returns the description rewritten by the callback
+    pub(crate) fn munge(
+        &self,
+        stream: &str,
+        session_id: &str,
+        direction: SdpDirection,
+        description: &gst_webrtc::WebRTCSessionDescription,
+    ) -> gst_webrtc::WebRTCSessionDescription {
+        let context = SdpContext {
+            stream,
+            session_id,
+            direction,
+            sdp_type: description.type_(),
+        };
+        let mut sdp = description.sdp();
+        (self.0)(&context, &mut sdp);
+
+        gst_webrtc::WebRTCSessionDescription::new(description.type_(), sdp)
+    }
+
+    /// Munges the descriptions exchanged by the signaller of `webrtcsink`
+    pub(crate) fn connect(self, stream: String, webrtcsink: &BaseWebRTCSink) {
+        let signaller = webrtcsink.property::<Signallable>("signaller");
+
+        // The Pixel Streaming signaller munges the descriptions it sends and receives
+        #[cfg(feature = "pixelstreaming")]
+        if let Some(signaller) = signaller.downcast_ref::<UePsSignaller>() {
+            signaller.set_sdp_munger(stream, self);
+            return;
+        }
+
+        signaller.connect_closure(
+            "munge-session-description",
+            false,
+            glib::closure!(move |_signaller: &Signallable,
+                                 session_id: &str,
+                                 description: &gst_webrtc::WebRTCSessionDescription|
+                  -> gst_webrtc::WebRTCSessionDescription {
+                self.munge(&stream, session_id, SdpDirection::Outgoing, description)
+            }),
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use std::time::Instant;
+
+#[cfg(feature = "pixelstreaming")]
+use crate::pixelstreaming::message::PSOutgoingMessage;
+use crate::{
+    ControllerState, PeerSessionExpired, SessionExpiry, SessionPolicy, viewers::ViewerTracker,
+};
+
+impl SessionExpiry {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SessionExpiry::MaxDuration => "max_duration",
+            SessionExpiry::Idle => "idle",
+            SessionExpiry::RtcpTimeout => "rtcp_timeout",
+        }
+    }
+}
+
+/// Sends the warning of an upcoming disconnection to a peer
+#[cfg(feature = "pixelstreaming")]
+fn warn_peer(
+    controller: &ControllerState,
+    peer_id: &str,
+    reason: SessionExpiry,
+    remaining_secs: u64,
+) {
+    let warning = serde_json::json!({
+        "event": "session_warning",
+        "data": {
+            "reason": reason.as_str(),
+            "remaining_secs": remaining_secs,
+        },
+    });
+    controller.send_to(peer_id, &PSOutgoingMessage::Response(warning.to_string()));
+}
+
+#[cfg(not(feature = "pixelstreaming"))]
+fn warn_peer(
+    _controller: &ControllerState,
+    _peer_id: &str,
+    _reason: SessionExpiry,
+    _remaining_secs: u64,
+) {
+}
+
+/// This system warns and disconnects the peers according to the `SessionPolicy` of their
+/// camera
+pub(crate) fn apply_session_policies(
+    mut cameras: Query<(Entity, &ViewerTracker, &ControllerState, &mut SessionPolicy)>,
+    mut expired_events: EventWriter<PeerSessionExpired>,
+) {
+    let now = Instant::now();
+
+    for (camera, tracker, controller, mut policy) in cameras.iter_mut() {
+        let policy = policy.as_mut();
+        let peers = tracker.peers();
+        let connected = |peer_id: &String| peers.iter().any(|(peer, _)| peer == peer_id);
+        policy.warned.retain(connected);
+        policy.expired.retain(connected);
+
+        for (peer_id, joined) in &peers {
+            if policy.expired.contains(peer_id) {
+                continue;
+            }
+
+            let max_duration = policy
+                .max_duration
+                .map(|max_duration| (*joined + max_duration, SessionExpiry::MaxDuration));
+            let idle = policy
+                .idle_timeout
+                .filter(|_| controller.receives_input())
+                .map(|idle_timeout| {
+                    let last_input = controller
+                        .last_input(peer_id)
+                        .map_or(*joined, |last_input| last_input.max(*joined));
+                    (last_input + idle_timeout, SessionExpiry::Idle)
+                });
+            let rtcp_timeout = policy.rtcp_timeout.map(|rtcp_timeout| {
+                let last_rtcp = tracker
+                    .last_rtcp(peer_id)
+                    .map_or(*joined, |last_rtcp| last_rtcp.max(*joined));
+                (last_rtcp + rtcp_timeout, SessionExpiry::RtcpTimeout)
+            });
+            let Some((deadline, reason)) = max_duration
+                .into_iter()
+                .chain(idle)
+                .chain(rtcp_timeout)
+                .min_by_key(|(deadline, _)| *deadline)
+            else {
+                continue;
+            };
+
+            if now >= deadline {
+                info!("Disconnecting {}: {:?}", peer_id, reason);
+                tracker.disconnect(peer_id);
+                policy.expired.insert(peer_id.clone());
+                expired_events.write(PeerSessionExpired {
+                    camera,
+                    peer_id: peer_id.clone(),
+                    reason,
+                });
+            } else if deadline - now <= policy.warning_before {
+                if policy.warned.insert(peer_id.clone()) {
+                    let remaining_secs = (deadline - now).as_secs_f64().ceil() as u64;
+                    warn_peer(controller, peer_id, reason, remaining_secs);
+                }
+            } else {
+                // The peer sent an input or RTCP since the warning, it is warned again next time
+                policy.warned.remove(peer_id);
+            }
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use anyhow::{Context, Result, anyhow, bail};
+use bevy_log::prelude::*;
+use gst::prelude::*;
+use std::{
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    PipelineLogLevel,
+    encoder::{EncoderStats, Frame, FrameTimestamps, StreamEncoder, resize_appsrc},
+    pipeline_log::log_bus_message,
+};
+
+/// Settings of a `ShmEncoder`
+#[derive(Clone)]
+pub struct ShmSettings {
+    /// Name of the stream
+    pub name: String,
+    /// Labels attached to the stream, see `StreamLabels`
+    pub labels: Vec<(String, String)>,
+    /// Path of the control socket the consumers connect to
+    pub socket_path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    /// Number of frames the shared memory holds, the frames are dropped when the consumers
+    /// do not release them in time
+    pub buffers: u32,
+    /// Waits for a consumer before the first frame, otherwise the frames are dropped while
+    /// none is connected
+    pub wait_for_connection: bool,
+    /// Verbosity of the pipeline messages logs
+    pub log_level: PipelineLogLevel,
+}
+
+impl Default for ShmSettings {
+    fn default() -> Self {
+        Self {
+            name: "shm".to_string(),
+            labels: Vec::new(),
+            socket_path: PathBuf::from("/tmp/bevy-streaming.sock"),
+            width: 1280,
+            height: 720,
+            buffers: 4,
+            wait_for_connection: false,
+            log_level: PipelineLogLevel::default(),
+        }
+    }
+}
+
+/// An encoder writing the raw frames to shared memory with `shmsink`, for another process of
+/// the same machine, e.g. a Python ML pipeline, without encoding nor network overhead. Unix
+/// only.
+///
+/// The frames are packed RGBA, of the size of the settings: they are scaled to it when the
+/// stream is resized, since the consumers are not told about a change of format. The
+/// consumers read them with `shmsrc`, see `ShmEncoder::consumer_pipeline`.
+pub struct ShmEncoder {
+    pipeline: gst::Pipeline,
+    appsrc: gst_app::AppSrc,
+    socket_path: PathBuf,
+    width: u32,
+    height: u32,
+    timestamps: FrameTimestamps,
+    stats: Mutex<EncoderStats>,
+}
+
+impl ShmEncoder {
+    pub fn new(settings: ShmSettings) -> Result<Arc<Self>> {
+        gst::init()?;
+
+        // shmsink can't bind its socket over the one left by a previous run
+        match std::fs::symlink_metadata(&settings.socket_path) {
+            Ok(metadata) if metadata.file_type().is_socket() => {
+                std::fs::remove_file(&settings.socket_path).with_context(|| {
+                    format!("Unable to remove {}", settings.socket_path.display())
+                })?;
+            }
+            Ok(_) => bail!(
+                "{} exists and is not a socket",
+                settings.socket_path.display()
+            ),
+            Err(_) => {}
+        }
+
+        let frame_size = settings.width as u64 * settings.height as u64 * 4;
+        let shm_size = frame_size * settings.buffers.max(1) as u64;
+        if shm_size > u32::MAX as u64 {
+            bail!(
+                "{} buffers of {} bytes do not fit in the shared memory",
+                settings.buffers,
+                frame_size
+            );
+        }
+
+        let description = format!(
+            "appsrc name=src format=time is-live=true do-timestamp=true \
+                caps=\"video/x-raw,format=RGBA,width={},height={},framerate=0/1\" ! \
+            queue ! \
+            videoconvert ! \
+            videoscale ! \
+            video/x-raw,format=RGBA,width={},height={},pixel-aspect-ratio=1/1 ! \
+            shmsink name=sink sync=false",
+            settings.width, settings.height, settings.width, settings.height,
+        );
+        debug!(stream = %settings.name, "Shared memory pipeline: {}", description);
+
+        let pipeline = gst::parse::launch(&description)
+            .context("Unable to create the shared memory pipeline, is gst-plugins-bad installed?")?
+            .downcast::<gst::Pipeline>()
+            .map_err(|_| anyhow!("Failed to cast to pipeline"))?;
+        pipeline.set_property("name", &settings.name);
+
+        let sink = pipeline
+            .by_name("sink")
+            .ok_or_else(|| anyhow!("Could not get shmsink element"))?;
+        sink.set_property(
+            "socket-path",
+            settings.socket_path.to_string_lossy().as_ref(),
+        );
+        sink.set_property("shm-size", shm_size as u32);
+        sink.set_property("wait-for-connection", settings.wait_for_connection);
+
+        let appsrc = pipeline
+            .by_name("src")
+            .ok_or_else(|| anyhow!("Could not get appsrc element"))?
+            .downcast::<gst_app::AppSrc>()
+            .map_err(|_| anyhow!("Not an appsrc"))?;
+
+        let bus = pipeline
+            .bus()
+            .ok_or_else(|| anyhow!("Pipeline has no bus"))?;
+        let stream = settings.name.clone();
+        let log_level = settings.log_level;
+        std::thread::spawn(move || {
+            for msg in bus.iter_timed(gst::ClockTime::NONE) {
+                log_bus_message(&stream, log_level, &msg);
+                if let gst::MessageView::Eos(_) = msg.view() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Arc::new(Self {
+            pipeline,
+            appsrc,
+            socket_path: settings.socket_path,
+            width: settings.width,
+            height: settings.height,
+            timestamps: FrameTimestamps::default(),
+            stats: Mutex::new(EncoderStats {
+                width: settings.width,
+                height: settings.height,
+                ..Default::default()
+            }),
+        }))
+    }
+
+    /// Returns the path of the control socket the consumers connect to
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    /// Returns the gst-launch description of the start of a pipeline reading the frames, e.g.
+    /// `gst-launch-1.0 <description> ! videoconvert ! autovideosink`. `shmsrc` does not
+    /// receive the caps, they are given by this description.
+    pub fn consumer_pipeline(&self) -> String {
+        format!(
+            "shmsrc socket-path={} is-live=true do-timestamp=true ! \
+            video/x-raw,format=RGBA,width={},height={},framerate=0/1",
+            self.socket_path.display(),
+            self.width,
+            self.height
+        )
+    }
+}
+
+impl Drop for ShmEncoder {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
+
+impl StreamEncoder for ShmEncoder {
+    fn push_frame(&self, frame: &Frame) -> Result<()> {
+        let buffer = self.timestamps.video_buffer(&self.appsrc, frame)?;
+        self.appsrc
+            .push_buffer(buffer)
+            .map_err(|e| anyhow!("Failed to push buffer: {:?}", e))?;
+        self.stats.lock().unwrap().frames_pushed += 1;
+
+        Ok(())
+    }
+
+    fn start(&self) -> Result<()> {
+        info!(
+            stream = %self.pipeline.name(),
+            socket = %self.socket_path.display(),
+            "Start shared memory output"
+        );
+        self.pipeline.set_state(gst::State::Playing)?;
+
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<()> {
+        info!(
+            stream = %self.pipeline.name(),
+            socket = %self.socket_path.display(),
+            "Stop shared memory output"
+        );
+        self.pipeline.set_state(gst::State::Null)?;
+
+        Ok(())
+    }
+
+    /// The frames are scaled to the size given to the consumers
+    fn resize(&self, width: u32, height: u32) -> Result<()> {
+        resize_appsrc(&self.appsrc, width, height)?;
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.width = width;
+        stats.height = height;
+
+        Ok(())
+    }
+
+    /// Every frame is a keyframe
+    fn request_keyframe(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn stats(&self) -> Option<EncoderStats> {
+        Some(self.stats.lock().unwrap().clone())
+    }
+
+    fn pipeline_state(&self) -> Option<gst::State> {
+        Some(self.pipeline.current_state())
+    }
+}
@@ -0,0 +1,95 @@
+use bevy_asset::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_image::prelude::*;
+use bevy_render::prelude::*;
+use bevy_window::{Window, WindowResolution};
+
+use crate::{ControllerState, StreamLabels};
+
+/// The virtual window receiving the input of the peers of a streamer camera.
+///
+/// Inserted on the cameras receiving the input of the peers (see `enable_controller`), so that
+/// the injected events of each stream target their own window, sized as the stream: the cursor
+/// position of the window and the window-relative logic work per stream. The window is not
+/// rendered to, it is despawned with the camera. With the `WinitPlugin`, it is a hidden
+/// OS window.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamWindow(pub Entity);
+
+/// Marks the virtual window of a streamer camera, see `StreamWindow`
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamWindowOf(pub Entity);
+
+type StreamWindowItem<'a> = (
+    Entity,
+    &'a Camera,
+    &'a ControllerState,
+    Option<&'a StreamLabels>,
+    Option<&'a StreamWindow>,
+);
+
+/// This system spawns the virtual windows of the streamer cameras receiving input, and keeps
+/// their size in sync with the size of the streams
+pub(crate) fn update_stream_windows(
+    mut commands: Commands,
+    cameras: Query<StreamWindowItem>,
+    mut windows: Query<&mut Window, With<StreamWindowOf>>,
+    images: Res<Assets<Image>>,
+) {
+    for (entity, camera, controller, labels, stream_window) in cameras.iter() {
+        if !controller.receives_input() {
+            continue;
+        }
+        let Some(image) = camera.target.as_image().and_then(|image| images.get(image)) else {
+            continue;
+        };
+        let size = image.size();
+
+        match stream_window.and_then(|stream_window| windows.get_mut(stream_window.0).ok()) {
+            // The render target is replaced when the stream is resized
+            Some(mut window) => {
+                if (window.physical_width(), window.physical_height()) != (size.x, size.y) {
+                    window.resolution.set_physical_resolution(size.x, size.y);
+                }
+            }
+            None => {
+                let title = labels
+                    .map(|labels| labels.name.clone())
+                    .unwrap_or_else(|| format!("Stream {entity}"));
+                let window = commands
+                    .spawn((
+                        Window {
+                            title,
+                            resolution: WindowResolution::new(size.x as f32, size.y as f32)
+                                .with_scale_factor_override(1.0),
+                            visible: false,
+                            ..Default::default()
+                        },
+                        StreamWindowOf(entity),
+                    ))
+                    .id();
+                commands.entity(entity).insert(StreamWindow(window));
+            }
+        }
+    }
+}
+
+/// This system despawns the virtual windows of the cameras which are despawned or no longer
+/// receive input
+pub(crate) fn remove_stream_windows(
+    mut commands: Commands,
+    windows: Query<(Entity, &StreamWindowOf)>,
+    cameras: Query<(&ControllerState, &StreamWindow)>,
+) {
+    for (entity, window_of) in windows.iter() {
+        if let Ok((controller, stream_window)) = cameras.get(window_of.0) {
+            if stream_window.0 == entity {
+                if controller.receives_input() {
+                    continue;
+                }
+                commands.entity(window_of.0).remove::<StreamWindow>();
+            }
+        }
+        commands.entity(entity).despawn();
+    }
+}
@@ -0,0 +1,309 @@
+use anyhow::Result;
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_log::{
+    BoxedLayer, Level,
+    prelude::*,
+    tracing_subscriber::{Layer, filter::Targets},
+};
+use bevy_render::prelude::*;
+use gstrswebrtc::RUNTIME;
+use opentelemetry::{
+    KeyValue,
+    metrics::{AsyncInstrument, MeterProvider as _, ObservableCounter, ObservableGauge},
+    trace::TracerProvider as _,
+};
+use opentelemetry_otlp::{MetricExporter, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    Resource as OtelResource,
+    metrics::{PeriodicReader, SdkMeterProvider},
+    runtime,
+    trace::{Sampler, TracerProvider},
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    AudioOnlyStreamer, PreEncodedStreamer, StreamLabels, ViewerCount,
+    capture::Capture,
+    encoder::{EncoderStats, StreamEncoder},
+};
+
+/// Interval between two reads of the stats of the streams
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Export of the streaming telemetry with OTLP (OpenTelemetry over gRPC), e.g. to Grafana
+/// Tempo or Honeycomb.
+///
+/// Insert it before adding the `OtlpPlugin` and the `LogPlugin`, the defaults are used
+/// otherwise.
+#[derive(Resource, Clone, Debug)]
+pub struct OtlpSettings {
+    /// Endpoint of the collector
+    pub endpoint: String,
+    /// `service.name` of the exported telemetry
+    pub service_name: String,
+    /// Interval between two exports of the metrics
+    pub metrics_interval: Duration,
+    /// Ratio of the frames whose capture and encoding spans are exported, between 0 and 1
+    pub trace_sample_ratio: f64,
+}
+
+impl Default for OtlpSettings {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://localhost:4317".to_string(),
+            service_name: "bevy_streaming".to_string(),
+            metrics_interval: Duration::from_secs(10),
+            trace_sample_ratio: 0.01,
+        }
+    }
+}
+
+impl OtlpSettings {
+    fn from_app(app: &App) -> Self {
+        app.world()
+            .get_resource::<OtlpSettings>()
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn resource(&self) -> OtelResource {
+        OtelResource::new([KeyValue::new("service.name", self.service_name.clone())])
+    }
+}
+
+/// Exports the spans of the capture and encoding of the frames, to use as the `custom_layer`
+/// of the `LogPlugin`:
+///
+/// ```ignore
+/// app.add_plugins(DefaultPlugins.set(LogPlugin {
+///     custom_layer: bevy_streaming::otlp_tracing_layer,
+///     ..default()
+/// }));
+/// ```
+pub fn otlp_tracing_layer(app: &mut App) -> Option<BoxedLayer> {
+    let settings = OtlpSettings::from_app(app);
+
+    let provider = || -> Result<TracerProvider> {
+        // The exporter is run by the tokio runtime of the signallers
+        let _runtime = RUNTIME.enter();
+        let exporter = SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(&settings.endpoint)
+            .build()?;
+        Ok(TracerProvider::builder()
+            .with_batch_exporter(exporter, runtime::Tokio)
+            .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                settings.trace_sample_ratio,
+            ))))
+            .with_resource(settings.resource())
+            .build())
+    };
+    let provider = match provider() {
+        Ok(provider) => provider,
+        Err(e) => {
+            // The logger is not set up yet
+            eprintln!("Unable to create the OTLP span exporter: {:?}", e);
+            return None;
+        }
+    };
+
+    let tracer = provider.tracer("bevy_streaming");
+    opentelemetry::global::set_tracer_provider(provider);
+
+    Some(
+        tracing_opentelemetry::layer()
+            .with_tracer(tracer)
+            .with_filter(Targets::new().with_target("bevy_streaming", Level::TRACE))
+            .boxed(),
+    )
+}
+
+/// Telemetry of a stream, read by the instruments when the metrics are exported
+struct StreamTelemetry {
+    attributes: Vec<KeyValue>,
+    viewers: u64,
+    stats: Option<EncoderStats>,
+}
+
+type Snapshot = Arc<Mutex<Vec<StreamTelemetry>>>;
+
+/// Exports the metrics of the streams with OTLP, see `OtlpSettings`.
+///
+/// The metrics are attributed with the name and the labels of the `StreamLabels`:
+/// - `streaming.frames_pushed`: frames pushed to the encoder
+/// - `streaming.bytes_sent`: bytes sent to the peers
+/// - `streaming.viewers`: connected peers
+/// - `streaming.bitrate`: target bitrate of the encoder
+/// - `streaming.latency`: latency of the pipeline
+pub struct OtlpPlugin;
+
+impl Plugin for OtlpPlugin {
+    fn build(&self, app: &mut App) {
+        let settings = OtlpSettings::from_app(app);
+        match OtlpMetrics::new(&settings) {
+            Ok(metrics) => {
+                app.insert_resource(metrics);
+                app.add_systems(PostUpdate, update_stream_telemetry);
+            }
+            Err(e) => error!("Unable to create the OTLP metric exporter: {:?}", e),
+        }
+        app.insert_resource(settings);
+    }
+}
+
+#[derive(Resource)]
+struct OtlpMetrics {
+    snapshot: Snapshot,
+    _provider: SdkMeterProvider,
+    _counters: Vec<ObservableCounter<u64>>,
+    _gauges: Vec<ObservableGauge<u64>>,
+    _latency: ObservableGauge<f64>,
+}
+
+impl OtlpMetrics {
+    fn new(settings: &OtlpSettings) -> Result<Self> {
+        let _runtime = RUNTIME.enter();
+        let exporter = MetricExporter::builder()
+            .with_tonic()
+            .with_endpoint(&settings.endpoint)
+            .build()?;
+        let reader = PeriodicReader::builder(exporter, runtime::Tokio)
+            .with_interval(settings.metrics_interval)
+            .build();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(reader)
+            .with_resource(settings.resource())
+            .build();
+        let meter = provider.meter("bevy_streaming");
+
+        let snapshot = Snapshot::default();
+        let observe = |value: fn(&StreamTelemetry) -> Option<u64>| {
+            let snapshot = snapshot.clone();
+            move |observer: &dyn AsyncInstrument<u64>| {
+                for stream in snapshot.lock().unwrap().iter() {
+                    if let Some(value) = value(stream) {
+                        observer.observe(value, &stream.attributes);
+                    }
+                }
+            }
+        };
+
+        let counters = vec![
+            meter
+                .u64_observable_counter("streaming.frames_pushed")
+                .with_description("Frames pushed to the encoder")
+                .with_unit("{frame}")
+                .with_callback(observe(|stream| {
+                    stream.stats.as_ref().map(|stats| stats.frames_pushed)
+                }))
+                .build(),
+            meter
+                .u64_observable_counter("streaming.bytes_sent")
+                .with_description("Bytes sent to the peers")
+                .with_unit("By")
+                .with_callback(observe(|stream| {
+                    stream.stats.as_ref().map(|stats| stats.bytes_sent)
+                }))
+                .build(),
+        ];
+        let gauges = vec![
+            meter
+                .u64_observable_gauge("streaming.viewers")
+                .with_description("Connected peers")
+                .with_unit("{peer}")
+                .with_callback(observe(|stream| Some(stream.viewers)))
+                .build(),
+            meter
+                .u64_observable_gauge("streaming.bitrate")
+                .with_description("Target bitrate of the encoder")
+                .with_unit("bit/s")
+                .with_callback(observe(|stream| {
+                    stream
+                        .stats
+                        .as_ref()
+                        .and_then(|stats| stats.bitrate)
+                        .map(u64::from)
+                }))
+                .build(),
+        ];
+        let latency = meter
+            .f64_observable_gauge("streaming.latency")
+            .with_description("Latency of the pipeline")
+            .with_unit("s")
+            .with_callback({
+                let snapshot = snapshot.clone();
+                move |observer| {
+                    for stream in snapshot.lock().unwrap().iter() {
+                        if let Some(latency) = stream.stats.as_ref().and_then(|s| s.latency) {
+                            observer.observe(latency.as_secs_f64(), &stream.attributes);
+                        }
+                    }
+                }
+            })
+            .build();
+
+        Ok(Self {
+            snapshot,
+            _provider: provider,
+            _counters: counters,
+            _gauges: gauges,
+            _latency: latency,
+        })
+    }
+}
+
+type TelemetryItem<'a> = (
+    &'a StreamLabels,
+    Option<&'a Camera>,
+    Option<&'a ViewerCount>,
+    Option<&'a AudioOnlyStreamer>,
+    Option<&'a PreEncodedStreamer>,
+);
+
+/// This system copies the stats of the streams for the OTLP instruments
+fn update_stream_telemetry(
+    metrics: Res<OtlpMetrics>,
+    streams: Query<TelemetryItem>,
+    captures: Query<&Capture>,
+    mut last_update: Local<Option<Instant>>,
+) {
+    if last_update.is_some_and(|last| last.elapsed() < SNAPSHOT_INTERVAL) {
+        return;
+    }
+    *last_update = Some(Instant::now());
+
+    let snapshot = streams
+        .iter()
+        .map(|(labels, camera, viewers, audio_only, pre_encoded)| {
+            let stats = if let Some(image) = camera.and_then(|camera| camera.target.as_image()) {
+                captures
+                    .iter()
+                    .find(|capture| capture.src_image() == image)
+                    .and_then(|capture| capture.encoder().stats())
+            } else if let Some(streamer) = audio_only {
+                streamer.encoder.stats()
+            } else {
+                pre_encoded.and_then(|streamer| streamer.encoder.stats())
+            };
+
+            StreamTelemetry {
+                attributes: std::iter::once(KeyValue::new("stream", labels.name.clone()))
+                    .chain(
+                        labels
+                            .labels
+                            .iter()
+                            .map(|(key, value)| KeyValue::new(key.clone(), value.clone())),
+                    )
+                    .collect(),
+                viewers: viewers.map(|viewers| viewers.0 as u64).unwrap_or_default(),
+                stats,
+            }
+        })
+        .collect();
+
+    *metrics.snapshot.lock().unwrap() = snapshot;
+}
@@ -0,0 +1,169 @@
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use bevy_render::{camera::Camera, renderer::RenderDevice};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    capture::Capture,
+    encoder::{EncoderHandle, Frame},
+};
+
+/// Colors of the bars of the test pattern, in RGBA
+const BARS: [[u8; 4]; 7] = [
+    [192, 192, 192, 255],
+    [192, 192, 0, 255],
+    [0, 192, 192, 255],
+    [0, 192, 0, 255],
+    [192, 0, 192, 255],
+    [192, 0, 0, 255],
+    [0, 0, 192, 255],
+];
+
+/// Time taken by the moving line to cross the pattern
+const SWEEP_DURATION: Duration = Duration::from_secs(4);
+
+/// Streams generated color bars instead of the camera, to tell network and signalling issues
+/// from capture issues.
+///
+/// A white line sweeps the bars, so that frozen frames and latency can be noticed.
+#[derive(Component, Clone, Debug)]
+pub struct TestPattern {
+    pub framerate: u32,
+}
+
+impl Default for TestPattern {
+    fn default() -> Self {
+        Self { framerate: 30 }
+    }
+}
+
+/// Pushes the test pattern to an encoder from a background thread, until it is dropped.
+///
+/// It doesn't need the render world, e.g. to test an encoder created from `EncoderRegistry`.
+pub struct TestPatternGenerator {
+    stop: Arc<AtomicBool>,
+}
+
+impl TestPatternGenerator {
+    pub fn spawn(encoder: EncoderHandle, width: u32, height: u32, framerate: u32) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+
+        std::thread::spawn({
+            let stop = stop.clone();
+            move || {
+                let row_size = RenderDevice::align_copy_bytes_per_row(width as usize * 4);
+                let bars = color_bars(width, height, row_size);
+                let frame_duration = Duration::from_secs(1) / framerate.max(1);
+                let start = Instant::now();
+                let mut frame_id = 0;
+
+                while !stop.load(Ordering::Relaxed) {
+                    let mut frame = bars.clone();
+                    let sweep = start.elapsed().as_secs_f64() / SWEEP_DURATION.as_secs_f64();
+                    let x = (sweep.fract() * width as f64) as usize;
+                    for row in frame.chunks_exact_mut(row_size) {
+                        row[x * 4..x * 4 + 4].copy_from_slice(&[255; 4]);
+                    }
+
+                    let pushed = Frame {
+                        data: &frame,
+                        width,
+                        height,
+                        stride: row_size,
+                        pts: start.elapsed(),
+                        id: frame_id,
+                    };
+                    if let Err(e) = encoder.push_frame(&pushed) {
+                        debug!("Unable to push test pattern frame {}: {:?}", frame_id, e);
+                    }
+                    frame_id += 1;
+
+                    let next = start + frame_duration * frame_id as u32;
+                    std::thread::sleep(next.saturating_duration_since(Instant::now()));
+                }
+            }
+        });
+
+        Self { stop }
+    }
+}
+
+impl Drop for TestPatternGenerator {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Returns the bars with the layout of the captured frames
+fn color_bars(width: u32, height: u32, row_size: usize) -> Vec<u8> {
+    let mut frame = vec![0; row_size * height as usize];
+    for row in frame.chunks_exact_mut(row_size) {
+        for (x, pixel) in row[..width as usize * 4].chunks_exact_mut(4).enumerate() {
+            pixel.copy_from_slice(&BARS[x * BARS.len() / width as usize]);
+        }
+    }
+    frame
+}
+
+/// Generators of the test pattern of a camera, one per encoder of its capture
+#[derive(Component)]
+pub(crate) struct RunningTestPattern {
+    /// Size of the capture, the generators are replaced when it is resized
+    size: (u32, u32),
+    _generators: Vec<TestPatternGenerator>,
+}
+
+/// This system replaces the capture of the cameras having a `TestPattern`
+pub(crate) fn apply_test_patterns(
+    mut commands: Commands,
+    cameras: Query<(
+        Entity,
+        &Camera,
+        Option<&TestPattern>,
+        Option<&RunningTestPattern>,
+    )>,
+    captures: Query<&Capture>,
+) {
+    for (entity, camera, test_pattern, running) in cameras.iter() {
+        let Some(image) = camera.target.as_image() else {
+            continue;
+        };
+        let Some(capture) = captures.iter().find(|c| c.src_image() == image) else {
+            continue;
+        };
+        let resized = running.is_some_and(|running| running.size != capture.size());
+        if test_pattern.is_some() == running.is_some() && !resized {
+            continue;
+        }
+
+        capture.set_test_pattern(test_pattern.is_some());
+        match test_pattern {
+            Some(test_pattern) => {
+                if !resized {
+                    info!("Streaming the test pattern instead of the camera");
+                }
+                let (width, height) = capture.size();
+                let generators = capture
+                    .encoders()
+                    .into_iter()
+                    .map(|encoder| {
+                        TestPatternGenerator::spawn(encoder, width, height, test_pattern.framerate)
+                    })
+                    .collect();
+                commands.entity(entity).insert(RunningTestPattern {
+                    size: (width, height),
+                    _generators: generators,
+                });
+            }
+            None => {
+                commands.entity(entity).remove::<RunningTestPattern>();
+            }
+        }
+    }
+}
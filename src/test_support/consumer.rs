@@ -0,0 +1,202 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use async_tungstenite::tungstenite::Message as WsMessage;
+use futures::{channel::mpsc, prelude::*};
+use gst::prelude::*;
+use gstrswebrtc::RUNTIME;
+use tokio::task::JoinHandle;
+
+use crate::pixelstreaming::signaller::protocol as p;
+
+/// A headless WebRTC player connecting through a Pixel Streaming signalling server,
+/// counting the video buffers it receives.
+pub struct MockConsumer {
+    pipeline: gst::Pipeline,
+    video_buffers: Arc<AtomicUsize>,
+    handle: JoinHandle<()>,
+}
+
+impl MockConsumer {
+    /// Connects to `player_uri` and subscribes to the given streamer
+    pub fn connect(player_uri: &str, streamer_id: &str) -> Result<Self> {
+        gst::init()?;
+
+        let pipeline = gst::Pipeline::default();
+        let webrtcbin = gst::ElementFactory::make("webrtcbin")
+            .property_from_str("bundle-policy", "max-bundle")
+            .build()?;
+        pipeline.add(&webrtcbin)?;
+
+        let video_buffers = Arc::new(AtomicUsize::new(0));
+        webrtcbin.connect_pad_added({
+            let pipeline = pipeline.downgrade();
+            let video_buffers = video_buffers.clone();
+            move |_webrtcbin, pad| {
+                let Some(pipeline) = pipeline.upgrade() else {
+                    return;
+                };
+                let sink = gst::ElementFactory::make("fakesink")
+                    .property("sync", false)
+                    .build()
+                    .unwrap();
+                pipeline.add(&sink).unwrap();
+                sink.sync_state_with_parent().unwrap();
+                pad.link(&sink.static_pad("sink").unwrap()).unwrap();
+
+                let video_buffers = video_buffers.clone();
+                pad.add_probe(gst::PadProbeType::BUFFER, move |_pad, _info| {
+                    video_buffers.fetch_add(1, Ordering::Relaxed);
+                    gst::PadProbeReturn::Ok
+                });
+            }
+        });
+
+        let (tx, mut rx) = mpsc::unbounded::<p::Message>();
+
+        webrtcbin.connect_closure("on-ice-candidate", false, {
+            let tx = tx.clone();
+            glib::closure!(move |_webrtcbin: &gst::Element,
+                                 sdp_m_line_index: u32,
+                                 candidate: &str| {
+                let _ = tx.unbounded_send(p::Message::IceCandidate(p::IceCandidate {
+                    candidate: Some(p::IceCandidateData {
+                        candidate: candidate.to_string(),
+                        sdp_mid: "".to_string(),
+                        sdp_m_line_index: sdp_m_line_index as i32,
+                        username_fragment: None,
+                    }),
+                    player_id: None,
+                }));
+            })
+        });
+
+        pipeline.set_state(gst::State::Playing)?;
+
+        let (ws, _) = RUNTIME
+            .block_on(async_tungstenite::tokio::connect_async(player_uri))
+            .context("Unable to connect to the signalling server")?;
+        let (mut ws_sink, mut ws_stream) = ws.split();
+
+        tx.unbounded_send(p::Message::Subscribe(p::Subscribe {
+            streamer_id: streamer_id.to_string(),
+        }))?;
+
+        let handle = RUNTIME.spawn(async move {
+            let send = async {
+                while let Some(msg) = rx.next().await {
+                    let text = serde_json::to_string(&msg).unwrap();
+                    if ws_sink.send(WsMessage::Text(text.into())).await.is_err() {
+                        break;
+                    }
+                }
+            };
+
+            let receive = async {
+                while let Some(Ok(msg)) = ws_stream.next().await {
+                    let WsMessage::Text(text) = msg else {
+                        continue;
+                    };
+                    let Ok(msg) = serde_json::from_str::<p::Message>(&text) else {
+                        continue;
+                    };
+                    handle_message(&webrtcbin, &tx, msg);
+                }
+            };
+
+            futures::future::select(std::pin::pin!(send), std::pin::pin!(receive)).await;
+        });
+
+        Ok(Self {
+            pipeline,
+            video_buffers,
+            handle,
+        })
+    }
+
+    /// Number of video buffers received so far
+    pub fn received_video_buffers(&self) -> usize {
+        self.video_buffers.load(Ordering::Relaxed)
+    }
+
+    /// Waits until at least one video buffer is received, returns false on timeout
+    pub fn wait_for_video(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if self.received_video_buffers() > 0 {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        false
+    }
+}
+
+impl Drop for MockConsumer {
+    fn drop(&mut self) {
+        self.handle.abort();
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
+
+fn handle_message(
+    webrtcbin: &gst::Element,
+    tx: &mpsc::UnboundedSender<p::Message>,
+    msg: p::Message,
+) {
+    match msg {
+        p::Message::Offer(offer) => {
+            let Ok(sdp) = gst_sdp::SDPMessage::parse_buffer(offer.sdp.as_bytes()) else {
+                return;
+            };
+            let offer =
+                gst_webrtc::WebRTCSessionDescription::new(gst_webrtc::WebRTCSDPType::Offer, sdp);
+            webrtcbin
+                .emit_by_name::<()>("set-remote-description", &[&offer, &None::<gst::Promise>]);
+
+            let promise = gst::Promise::with_change_func({
+                let webrtcbin = webrtcbin.downgrade();
+                let tx = tx.clone();
+                move |reply| {
+                    let (Some(webrtcbin), Ok(Some(reply))) = (webrtcbin.upgrade(), reply) else {
+                        return;
+                    };
+                    let Ok(answer) = reply
+                        .value("answer")
+                        .map(|answer| answer.get::<gst_webrtc::WebRTCSessionDescription>())
+                    else {
+                        return;
+                    };
+                    let Ok(answer) = answer else {
+                        return;
+                    };
+
+                    webrtcbin.emit_by_name::<()>(
+                        "set-local-description",
+                        &[&answer, &None::<gst::Promise>],
+                    );
+                    let _ = tx.unbounded_send(p::Message::Answer(p::Answer {
+                        sdp: answer.sdp().as_text().unwrap(),
+                        player_id: None,
+                    }));
+                }
+            });
+            webrtcbin.emit_by_name::<()>("create-answer", &[&None::<gst::Structure>, &promise]);
+        }
+        p::Message::IceCandidate(ice_candidate) => {
+            if let Some(candidate) = ice_candidate.candidate {
+                webrtcbin.emit_by_name::<()>(
+                    "add-ice-candidate",
+                    &[&(candidate.sdp_m_line_index as u32), &candidate.candidate],
+                );
+            }
+        }
+        _ => {}
+    }
+}
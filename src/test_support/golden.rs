@@ -0,0 +1,137 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Result, bail};
+use bevy_render::renderer::RenderDevice;
+
+use crate::encoder::{Frame, StreamEncoder};
+
+/// A frame received by a `ValidatingEncoder`, without the row padding
+#[derive(Clone, Debug)]
+pub struct CapturedFrame {
+    pub frame_id: u64,
+    pub width: u32,
+    pub height: u32,
+    /// RGBA pixels, `width * 4` bytes per row
+    pub data: Vec<u8>,
+}
+
+impl CapturedFrame {
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let offset = ((y * self.width + x) * 4) as usize;
+        self.data[offset..offset + 4].try_into().unwrap()
+    }
+
+    /// Checks that the pixel at `x`, `y` is `expected`, each channel within `tolerance`
+    pub fn check_pixel(&self, x: u32, y: u32, expected: [u8; 4], tolerance: u8) -> Result<()> {
+        let actual = self.pixel(x, y);
+        let close = actual
+            .iter()
+            .zip(expected)
+            .all(|(actual, expected)| actual.abs_diff(expected) <= tolerance);
+        if !close {
+            bail!(
+                "Frame {}: pixel ({}, {}) is {:?} instead of {:?}",
+                self.frame_id,
+                x,
+                y,
+                actual,
+                expected
+            );
+        }
+        Ok(())
+    }
+}
+
+/// An encoder checking the layout of the frames pushed by `Capture` and keeping them, so that
+/// tests can render known scenes and assert the pixels of the stream.
+///
+/// Each frame must have the size of the encoder and the rows of `width * 4` bytes aligned as
+/// copied from the GPU, the padding is removed from the kept frames.
+pub struct ValidatingEncoder {
+    width: u32,
+    height: u32,
+    frames: Mutex<Vec<CapturedFrame>>,
+    errors: Mutex<Vec<String>>,
+}
+
+impl ValidatingEncoder {
+    pub fn new(width: u32, height: u32) -> Arc<Self> {
+        Arc::new(Self {
+            width,
+            height,
+            frames: Mutex::new(Vec::new()),
+            errors: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Returns the frames received so far
+    pub fn frames(&self) -> Vec<CapturedFrame> {
+        self.frames.lock().unwrap().clone()
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.lock().unwrap().len()
+    }
+
+    /// Returns the invalid frames received so far
+    pub fn errors(&self) -> Vec<String> {
+        self.errors.lock().unwrap().clone()
+    }
+
+    fn validate(&self, frame: &Frame) -> Result<CapturedFrame> {
+        if (frame.width, frame.height) != (self.width, self.height) {
+            bail!(
+                "Frame {}: {}x{} instead of {}x{}",
+                frame.id,
+                frame.width,
+                frame.height,
+                self.width,
+                self.height
+            );
+        }
+
+        let padded_row_size = RenderDevice::align_copy_bytes_per_row(self.width as usize * 4);
+        if frame.stride != padded_row_size {
+            bail!(
+                "Frame {}: rows of {} bytes instead of {}",
+                frame.id,
+                frame.stride,
+                padded_row_size
+            );
+        }
+
+        let expected_size = padded_row_size * self.height as usize;
+        if frame.data.len() != expected_size {
+            bail!(
+                "Frame {}: {} bytes instead of {} for {}x{} with rows of {} bytes",
+                frame.id,
+                frame.data.len(),
+                expected_size,
+                self.width,
+                self.height,
+                padded_row_size
+            );
+        }
+
+        Ok(CapturedFrame {
+            frame_id: frame.id,
+            width: self.width,
+            height: self.height,
+            data: frame.packed_data().into_owned(),
+        })
+    }
+}
+
+impl StreamEncoder for ValidatingEncoder {
+    fn push_frame(&self, frame: &Frame) -> Result<()> {
+        match self.validate(frame) {
+            Ok(frame) => self.frames.lock().unwrap().push(frame),
+            Err(e) => self.errors.lock().unwrap().push(e.to_string()),
+        }
+        Ok(())
+    }
+
+    fn start(&self) -> Result<()> {
+        Ok(())
+    }
+}
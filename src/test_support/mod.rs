@@ -0,0 +1,16 @@
+//! Helpers to run the whole signalling and negotiation flow in-process, without docker.
+//!
+//! Start a `MockSignallingServer`, point a `SignallingServer::PixelStreaming` at its
+//! `streamer_uri()`, then connect a `MockConsumer` to its `player_uri()` and wait for it to
+//! receive video.
+//!
+//! Capture a streamer camera to a `ValidatingEncoder` to check the pixels of the frames of
+//! known scenes.
+
+mod consumer;
+mod golden;
+mod server;
+
+pub use consumer::*;
+pub use golden::*;
+pub use server::*;
@@ -0,0 +1,286 @@
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use async_tungstenite::tungstenite::{
+    Message as WsMessage,
+    handshake::server::{Request, Response},
+};
+use futures::{channel::mpsc, prelude::*};
+use gstrswebrtc::RUNTIME;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+use crate::pixelstreaming::signaller::protocol as p;
+
+type Tx = mpsc::UnboundedSender<p::Message>;
+
+struct Player {
+    tx: Tx,
+    streamer_id: Option<String>,
+}
+
+#[derive(Default)]
+struct ServerState {
+    streamers: HashMap<String, Tx>,
+    players: HashMap<String, Player>,
+    next_player: u32,
+}
+
+impl ServerState {
+    fn send_to_player(&self, player_id: &str, msg: p::Message) {
+        if let Some(player) = self.players.get(player_id) {
+            let _ = player.tx.unbounded_send(msg);
+        }
+    }
+
+    fn send_to_streamer_of(&self, player_id: &str, msg: p::Message) {
+        let streamer = self
+            .players
+            .get(player_id)
+            .and_then(|player| player.streamer_id.as_ref())
+            .and_then(|streamer_id| self.streamers.get(streamer_id));
+        if let Some(streamer) = streamer {
+            let _ = streamer.unbounded_send(msg);
+        }
+    }
+}
+
+/// A minimal in-process Pixel Streaming signalling server.
+///
+/// Streamers connect to `streamer_uri()` and players to `player_uri()`.
+pub struct MockSignallingServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<ServerState>>,
+    handle: JoinHandle<()>,
+}
+
+impl MockSignallingServer {
+    /// Starts the server on a random local port
+    pub fn start() -> io::Result<Self> {
+        let listener = RUNTIME.block_on(TcpListener::bind("127.0.0.1:0"))?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(ServerState::default()));
+
+        let handle = RUNTIME.spawn({
+            let state = state.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(handle_connection(state.clone(), stream));
+                }
+            }
+        });
+
+        Ok(Self {
+            addr,
+            state,
+            handle,
+        })
+    }
+
+    pub fn streamer_uri(&self) -> String {
+        format!("ws://{}/", self.addr)
+    }
+
+    pub fn player_uri(&self) -> String {
+        format!("ws://{}/player", self.addr)
+    }
+
+    /// Returns the committed ids of the connected streamers
+    pub fn streamers(&self) -> Vec<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .streamers
+            .keys()
+            .cloned()
+            .collect()
+    }
+}
+
+impl Drop for MockSignallingServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn handle_connection(state: Arc<Mutex<ServerState>>, stream: TcpStream) {
+    let mut path = String::new();
+    let ws = match async_tungstenite::tokio::accept_hdr_async(
+        stream,
+        |request: &Request, response: Response| {
+            path = request.uri().path().to_string();
+            Ok(response)
+        },
+    )
+    .await
+    {
+        Ok(ws) => ws,
+        Err(_) => return,
+    };
+
+    let (mut ws_sink, ws_stream) = ws.split();
+    let (tx, mut rx) = mpsc::unbounded::<p::Message>();
+    tokio::spawn(async move {
+        while let Some(msg) = rx.next().await {
+            let text = serde_json::to_string(&msg).unwrap();
+            if ws_sink.send(WsMessage::Text(text.into())).await.is_err() {
+                break;
+            }
+        }
+        let _ = ws_sink.close().await;
+    });
+
+    let messages = ws_stream.filter_map(|msg| async move {
+        match msg {
+            Ok(WsMessage::Text(text)) => serde_json::from_str::<p::Message>(&text).ok(),
+            _ => None,
+        }
+    });
+
+    if path.starts_with("/player") {
+        handle_player(state, tx, messages).await;
+    } else {
+        handle_streamer(state, tx, messages).await;
+    }
+}
+
+async fn handle_streamer(
+    state: Arc<Mutex<ServerState>>,
+    tx: Tx,
+    messages: impl Stream<Item = p::Message>,
+) {
+    let _ = tx.unbounded_send(p::Message::Config(p::Config {
+        peer_connection_options: None,
+        protocol_version: None,
+    }));
+    let _ = tx.unbounded_send(p::Message::Identify(p::Identify {}));
+
+    let mut streamer_id = None;
+    let mut messages = std::pin::pin!(messages);
+    while let Some(msg) = messages.next().await {
+        let mut state = state.lock().unwrap();
+        match msg {
+            p::Message::EndpointId(endpoint_id) => {
+                let id = if endpoint_id.id.is_empty() {
+                    "DefaultStreamer".to_string()
+                } else {
+                    endpoint_id.id
+                };
+                state.streamers.insert(id.clone(), tx.clone());
+                let _ = tx.unbounded_send(p::Message::EndpointIdConfirm(p::EndpointIdConfirm {
+                    committed_id: id.clone(),
+                }));
+                streamer_id = Some(id);
+            }
+            p::Message::Offer(mut offer) => {
+                if let Some(player_id) = offer.player_id.take() {
+                    state.send_to_player(&player_id, p::Message::Offer(offer));
+                }
+            }
+            p::Message::Answer(mut answer) => {
+                if let Some(player_id) = answer.player_id.take() {
+                    state.send_to_player(&player_id, p::Message::Answer(answer));
+                }
+            }
+            p::Message::IceCandidate(mut ice_candidate) => {
+                if let Some(player_id) = ice_candidate.player_id.take() {
+                    state.send_to_player(&player_id, p::Message::IceCandidate(ice_candidate));
+                }
+            }
+            p::Message::DisconnectPlayer(disconnect_player) => {
+                // Dropping the sender closes the player websocket
+                state.players.remove(&disconnect_player.player_id);
+            }
+            _ => {}
+        }
+    }
+
+    let mut state = state.lock().unwrap();
+    if let Some(streamer_id) = streamer_id {
+        state.streamers.remove(&streamer_id);
+        for player in state.players.values() {
+            if player.streamer_id.as_ref() == Some(&streamer_id) {
+                let _ = player
+                    .tx
+                    .unbounded_send(p::Message::StreamerDisconnected(p::StreamerDisconnected {}));
+            }
+        }
+    }
+}
+
+async fn handle_player(
+    state: Arc<Mutex<ServerState>>,
+    tx: Tx,
+    messages: impl Stream<Item = p::Message>,
+) {
+    let player_id = {
+        let mut state = state.lock().unwrap();
+        state.next_player += 1;
+        let player_id = format!("player{}", state.next_player);
+        state.players.insert(
+            player_id.clone(),
+            Player {
+                tx: tx.clone(),
+                streamer_id: None,
+            },
+        );
+        player_id
+    };
+
+    let _ = tx.unbounded_send(p::Message::Config(p::Config {
+        peer_connection_options: None,
+        protocol_version: None,
+    }));
+
+    let mut messages = std::pin::pin!(messages);
+    while let Some(msg) = messages.next().await {
+        let mut state = state.lock().unwrap();
+        match msg {
+            p::Message::ListStreamers(_) => {
+                let ids = state.streamers.keys().cloned().collect();
+                let _ = tx.unbounded_send(p::Message::StreamerList(p::StreamerList { ids }));
+            }
+            p::Message::Subscribe(subscribe) => {
+                if let Some(player) = state.players.get_mut(&player_id) {
+                    player.streamer_id = Some(subscribe.streamer_id);
+                }
+                state.send_to_streamer_of(
+                    &player_id,
+                    p::Message::PlayerConnected(p::PlayerConnected {
+                        data_channel: true,
+                        sfu: false,
+                        player_id: player_id.clone(),
+                    }),
+                );
+            }
+            p::Message::Offer(mut offer) => {
+                offer.player_id = Some(player_id.clone());
+                state.send_to_streamer_of(&player_id, p::Message::Offer(offer));
+            }
+            p::Message::Answer(mut answer) => {
+                answer.player_id = Some(player_id.clone());
+                state.send_to_streamer_of(&player_id, p::Message::Answer(answer));
+            }
+            p::Message::IceCandidate(mut ice_candidate) => {
+                ice_candidate.player_id = Some(player_id.clone());
+                state.send_to_streamer_of(&player_id, p::Message::IceCandidate(ice_candidate));
+            }
+            _ => {}
+        }
+    }
+
+    let mut state = state.lock().unwrap();
+    state.send_to_streamer_of(
+        &player_id,
+        p::Message::PlayerDisconnected(p::PlayerDisconnected {
+            player_id: player_id.clone(),
+        }),
+    );
+    state.players.remove(&player_id);
+}
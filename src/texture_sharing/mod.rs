@@ -0,0 +1,137 @@
+use anyhow::Result;
+use bevy_asset::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_image::prelude::*;
+use bevy_log::prelude::*;
+use bevy_platform::collections::HashMap;
+use bevy_render::{
+    Extract,
+    camera::Camera,
+    render_asset::RenderAssets,
+    render_resource::Texture,
+    renderer::{RenderDevice, RenderQueue},
+    texture::GpuImage,
+};
+
+#[cfg(target_os = "windows")]
+mod spout;
+#[cfg(target_os = "macos")]
+mod syphon;
+
+/// Shares the render target of a camera with the other apps of the machine on the GPU,
+/// without copying the frames to the CPU: with Spout2 on Windows and Syphon on macOS, e.g. to
+/// pick up the camera in OBS, Resolume or TouchDesigner.
+///
+/// Added to a streamer camera, the frames are shared as they are streamed. A camera which is
+/// only shared is created with `StreamerHelper::new_shared_texture_camera`.
+///
+/// Spout needs the DX12 backend, see `GpuSettings::wgpu`, and Syphon the Syphon framework,
+/// linked with the app.
+#[derive(Component, Clone, Debug)]
+pub struct SharedTexture {
+    /// Name of the sender listed by the receivers
+    pub name: String,
+}
+
+/// Publishes a texture to the other apps
+trait TextureSender: Send + Sync {
+    /// Publishes the content of `texture`, once the commands rendering it are submitted
+    fn publish(
+        &mut self,
+        device: &RenderDevice,
+        queue: &RenderQueue,
+        texture: &Texture,
+    ) -> Result<()>;
+}
+
+#[cfg(target_os = "windows")]
+fn new_sender(
+    name: &str,
+    device: &RenderDevice,
+    queue: &RenderQueue,
+) -> Result<Box<dyn TextureSender>> {
+    Ok(Box::new(spout::SpoutSender::new(name, device, queue)?))
+}
+
+#[cfg(target_os = "macos")]
+fn new_sender(
+    name: &str,
+    device: &RenderDevice,
+    _queue: &RenderQueue,
+) -> Result<Box<dyn TextureSender>> {
+    Ok(Box::new(syphon::SyphonSender::new(name, device)?))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn new_sender(
+    _name: &str,
+    _device: &RenderDevice,
+    _queue: &RenderQueue,
+) -> Result<Box<dyn TextureSender>> {
+    anyhow::bail!("Texture sharing is only supported on Windows (Spout) and macOS (Syphon)")
+}
+
+/// `SharedTexture`s extracted into the render world, with the camera and the render target
+#[derive(Resource, Default)]
+pub(crate) struct ExtractedSharedTextures(Vec<(Entity, String, Handle<Image>)>);
+
+/// Senders of the `SharedTexture`s, by camera, `None` if it could not be created
+#[derive(Resource, Default)]
+pub(crate) struct SharedTextureSenders(HashMap<Entity, (String, Option<Box<dyn TextureSender>>)>);
+
+/// Extracts the `SharedTexture`s of the cameras rendering to an image
+pub(crate) fn extract_shared_textures(
+    mut commands: Commands,
+    cameras: Extract<Query<(Entity, &Camera, &SharedTexture)>>,
+) {
+    let shared = cameras
+        .iter()
+        .filter(|(_, camera, _)| camera.is_active)
+        .filter_map(|(entity, camera, shared)| {
+            let image = camera.target.as_image()?;
+            Some((entity, shared.name.clone(), image.clone()))
+        })
+        .collect();
+    commands.insert_resource(ExtractedSharedTextures(shared));
+}
+
+/// This system publishes the render targets of the `SharedTexture`s, once they are rendered
+pub(crate) fn publish_shared_textures(
+    shared: Res<ExtractedSharedTextures>,
+    mut senders: ResMut<SharedTextureSenders>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    device: Res<RenderDevice>,
+    queue: Res<RenderQueue>,
+) {
+    // The senders of the removed cameras stop being listed by the receivers
+    senders
+        .0
+        .retain(|camera, _| shared.0.iter().any(|(entity, ..)| entity == camera));
+
+    for (camera, name, image) in &shared.0 {
+        let Some(image) = gpu_images.get(image) else {
+            continue;
+        };
+
+        if senders.0.get(camera).map(|(current, _)| current) != Some(name) {
+            let sender = match new_sender(name, &device, &queue) {
+                Ok(sender) => {
+                    info!("Sharing the texture {}", name);
+                    Some(sender)
+                }
+                Err(e) => {
+                    error!("Unable to share the texture {}: {:?}", name, e);
+                    None
+                }
+            };
+            senders.0.insert(*camera, (name.clone(), sender));
+        }
+        let Some((_, Some(sender))) = senders.0.get_mut(camera) else {
+            continue;
+        };
+
+        if let Err(e) = sender.publish(&device, &queue, &image.texture) {
+            debug!("Unable to publish the texture {}: {:?}", name, e);
+        }
+    }
+}
@@ -0,0 +1,433 @@
+use anyhow::{Context, Result, anyhow, bail};
+use bevy_log::prelude::*;
+use bevy_render::{
+    render_resource::{
+        CommandEncoderDescriptor, Extent3d, Texture, TextureDescriptor, TextureDimension,
+        TextureFormat, TextureUsages,
+    },
+    renderer::{RenderDevice, RenderQueue},
+};
+use std::{collections::BTreeSet, ffi::CString};
+use wgpu::hal::api::Dx12;
+use windows::{
+    Win32::{
+        Foundation::{CloseHandle, HANDLE, INVALID_HANDLE_VALUE, WAIT_TIMEOUT},
+        Graphics::{
+            Direct3D11::{
+                D3D11_BIND_RENDER_TARGET, D3D11_BIND_SHADER_RESOURCE,
+                D3D11_CREATE_DEVICE_BGRA_SUPPORT, D3D11_RESOURCE_MISC_SHARED, D3D11_TEXTURE2D_DESC,
+                D3D11_USAGE_DEFAULT, ID3D11Device, ID3D11DeviceContext, ID3D11Resource,
+                ID3D11Texture2D,
+            },
+            Direct3D11on12::{D3D11_RESOURCE_FLAGS, D3D11On12CreateDevice, ID3D11On12Device},
+            Direct3D12::{
+                D3D12_RESOURCE_STATE_COPY_DEST, ID3D12CommandQueue, ID3D12Device, ID3D12Resource,
+            },
+            Dxgi::{
+                Common::{DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_SAMPLE_DESC},
+                IDXGIResource,
+            },
+        },
+        System::{
+            Memory::{
+                CreateFileMappingA, FILE_MAP_ALL_ACCESS, MEMORY_MAPPED_VIEW_ADDRESS, MapViewOfFile,
+                PAGE_READWRITE, UnmapViewOfFile,
+            },
+            Threading::{CreateMutexA, ReleaseMutex, WaitForSingleObject},
+        },
+    },
+    core::{IUnknown, Interface, PCSTR},
+};
+
+use super::TextureSender;
+
+/// Shared memory listing the names of the senders
+const SENDER_NAMES: &str = "SpoutSenderNames";
+/// Shared memory holding the name of the sender picked by default by the receivers
+const ACTIVE_SENDER: &str = "ActiveSenderName";
+/// Most senders listed, the default of Spout
+const MAX_SENDERS: usize = 64;
+/// Size of a sender name in the shared memories, null terminated
+const NAME_SIZE: usize = 256;
+/// Size of the `SharedTextureInfo` of a sender: share handle, width, height, format, usage,
+/// 128 wide chars of description and partner id
+const INFO_SIZE: usize = 280;
+/// Timeout of the mutexes of the shared memories and textures, the one of Spout
+const LOCK_TIMEOUT_MS: u32 = 67;
+
+/// A named shared memory of Spout, with its mutex
+struct SharedMemory {
+    mapping: HANDLE,
+    view: MEMORY_MAPPED_VIEW_ADDRESS,
+    mutex: HANDLE,
+    size: usize,
+}
+
+impl SharedMemory {
+    /// Opens the shared memory `name`, created if no other app did
+    fn open(name: &str, size: usize) -> Result<Self> {
+        let mapping_name = CString::new(name)?;
+        let mutex_name = CString::new(format!("{name}_mutex"))?;
+
+        unsafe {
+            let mapping = CreateFileMappingA(
+                INVALID_HANDLE_VALUE,
+                None,
+                PAGE_READWRITE,
+                0,
+                size as u32,
+                PCSTR(mapping_name.as_ptr() as _),
+            )
+            .with_context(|| format!("Unable to create the shared memory {name}"))?;
+            let view = MapViewOfFile(mapping, FILE_MAP_ALL_ACCESS, 0, 0, size);
+            if view.Value.is_null() {
+                let _ = CloseHandle(mapping);
+                bail!("Unable to map the shared memory {}", name);
+            }
+            let mutex = match CreateMutexA(None, false, PCSTR(mutex_name.as_ptr() as _)) {
+                Ok(mutex) => mutex,
+                Err(e) => {
+                    let _ = UnmapViewOfFile(view);
+                    let _ = CloseHandle(mapping);
+                    return Err(e).with_context(|| format!("Unable to create the mutex of {name}"));
+                }
+            };
+
+            Ok(Self {
+                mapping,
+                view,
+                mutex,
+                size,
+            })
+        }
+    }
+
+    /// Calls `f` with the content of the memory, locked if the other apps release it in time
+    fn with_lock<R>(&self, f: impl FnOnce(&mut [u8]) -> R) -> R {
+        unsafe {
+            let _ = WaitForSingleObject(self.mutex, LOCK_TIMEOUT_MS);
+            let data = std::slice::from_raw_parts_mut(self.view.Value as *mut u8, self.size);
+            let result = f(data);
+            let _ = ReleaseMutex(self.mutex);
+            result
+        }
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = UnmapViewOfFile(self.view);
+            let _ = CloseHandle(self.mapping);
+            let _ = CloseHandle(self.mutex);
+        }
+    }
+}
+
+/// The mutex of the texture of a sender, locked while it is written or read
+struct AccessMutex(HANDLE);
+
+impl AccessMutex {
+    fn open(sender: &str) -> Result<Self> {
+        let name = CString::new(format!("{sender}_SpoutAccessMutex"))?;
+        let mutex = unsafe { CreateMutexA(None, false, PCSTR(name.as_ptr() as _)) }
+            .with_context(|| format!("Unable to create the access mutex of {sender}"))?;
+
+        Ok(Self(mutex))
+    }
+
+    /// Calls `f` once the receivers release the texture, fails if they don't in time
+    fn with_lock<R>(&self, f: impl FnOnce() -> R) -> Result<R> {
+        unsafe {
+            if WaitForSingleObject(self.0, LOCK_TIMEOUT_MS) == WAIT_TIMEOUT {
+                bail!("The shared texture is locked by a receiver");
+            }
+            let result = f();
+            let _ = ReleaseMutex(self.0);
+            Ok(result)
+        }
+    }
+}
+
+impl Drop for AccessMutex {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CloseHandle(self.0);
+        }
+    }
+}
+
+/// Reads the null terminated names of a shared memory
+fn read_names(data: &[u8]) -> BTreeSet<String> {
+    data.chunks_exact(NAME_SIZE)
+        .map(|slot| {
+            let len = slot.iter().position(|c| *c == 0).unwrap_or(NAME_SIZE);
+            String::from_utf8_lossy(&slot[..len]).into_owned()
+        })
+        .take_while(|name| !name.is_empty())
+        .collect()
+}
+
+/// Writes the names to a shared memory, followed by an empty one
+fn write_names(data: &mut [u8], names: &BTreeSet<String>) {
+    data.fill(0);
+    for (slot, name) in data.chunks_exact_mut(NAME_SIZE).zip(names) {
+        slot[..name.len()].copy_from_slice(name.as_bytes());
+    }
+}
+
+/// The texture shared with the receivers, and the texture of the renderer it is copied from
+struct SharedTarget {
+    /// Shared with a legacy DX11 handle, the only one the receivers open
+    shared: ID3D11Texture2D,
+    /// `texture` wrapped by the D3D11On12 device
+    wrapped: ID3D11Resource,
+    texture: Texture,
+}
+
+/// A Spout2 sender: as in the DX12 path of Spout, the frames are copied on the GPU to a texture
+/// of the renderer, which the D3D11On12 device copies to a texture shared with a legacy DX11
+/// handle, and the handle is published in the shared memory of the sender.
+pub(super) struct SpoutSender {
+    name: String,
+    names: SharedMemory,
+    active: SharedMemory,
+    info: SharedMemory,
+    /// Mutex of the shared texture, locked by the receivers while they read it
+    access: AccessMutex,
+    /// D3D11On12 device over the device and the queue of the renderer, the D3D12 resources can
+    /// only be shared with NT handles, which the receivers don't open
+    device: ID3D11Device,
+    on12: ID3D11On12Device,
+    context: ID3D11DeviceContext,
+    target: Option<SharedTarget>,
+}
+
+// The handles are only used by the render world systems, one at a time
+unsafe impl Send for SpoutSender {}
+unsafe impl Sync for SpoutSender {}
+
+/// Returns the D3D12 device of the renderer
+fn d3d12_device(device: &RenderDevice) -> Result<ID3D12Device> {
+    unsafe {
+        device
+            .wgpu_device()
+            .as_hal::<Dx12, _, _>(|device| device.map(|device| device.raw_device().clone()))
+    }
+    .ok_or_else(|| anyhow!("Spout needs the DX12 backend"))
+}
+
+/// Returns the D3D12 queue of the renderer
+fn d3d12_queue(queue: &RenderQueue) -> Result<ID3D12CommandQueue> {
+    unsafe { queue.as_hal::<Dx12, _, _>(|queue| queue.map(|queue| queue.as_raw().clone())) }
+        .ok_or_else(|| anyhow!("Spout needs the DX12 backend"))
+}
+
+/// Returns the D3D12 resource of a texture of the renderer
+fn d3d12_resource(texture: &Texture) -> Result<ID3D12Resource> {
+    unsafe {
+        texture
+            .as_hal::<Dx12, _, _>(|texture| texture.map(|texture| texture.raw_resource().clone()))
+    }
+    .ok_or_else(|| anyhow!("Spout needs the DX12 backend"))
+}
+
+impl SpoutSender {
+    pub(super) fn new(name: &str, device: &RenderDevice, queue: &RenderQueue) -> Result<Self> {
+        if name.is_empty() || name.len() >= NAME_SIZE {
+            bail!("Spout sender names have 1 to {} bytes", NAME_SIZE - 1);
+        }
+
+        // The copies of the D3D11On12 device are submitted to the queue of the renderer, after
+        // the ones of the renderer
+        let (d3d11, context, on12) = unsafe {
+            let queue: IUnknown = d3d12_queue(queue)?.cast()?;
+            let mut d3d11 = None;
+            let mut context = None;
+            D3D11On12CreateDevice(
+                &d3d12_device(device)?,
+                D3D11_CREATE_DEVICE_BGRA_SUPPORT.0 as u32,
+                None,
+                Some(&[Some(queue)]),
+                0,
+                Some(&mut d3d11),
+                Some(&mut context),
+                None,
+            )
+            .context("Unable to create the D3D11On12 device")?;
+            let d3d11: ID3D11Device = d3d11.ok_or_else(|| anyhow!("No D3D11 device"))?;
+            let context = context.ok_or_else(|| anyhow!("No D3D11 device context"))?;
+            let on12: ID3D11On12Device = d3d11.cast()?;
+            (d3d11, context, on12)
+        };
+        let access = AccessMutex::open(name)?;
+
+        // Opened before registering the name, which is unregistered once the sender is dropped
+        let names = SharedMemory::open(SENDER_NAMES, MAX_SENDERS * NAME_SIZE)?;
+        let active = SharedMemory::open(ACTIVE_SENDER, NAME_SIZE)?;
+        let info = SharedMemory::open(name, INFO_SIZE)?;
+        names.with_lock(|data| {
+            let mut senders = read_names(data);
+            if senders.contains(name) {
+                bail!("A Spout sender named {} already exists", name);
+            }
+            if senders.len() >= MAX_SENDERS {
+                bail!("Too many Spout senders");
+            }
+            senders.insert(name.to_string());
+            write_names(data, &senders);
+            Ok(())
+        })?;
+        let sender = Self {
+            name: name.to_string(),
+            names,
+            active,
+            info,
+            access,
+            device: d3d11,
+            on12,
+            context,
+            target: None,
+        };
+        sender.active.with_lock(|data| {
+            data.fill(0);
+            data[..name.len()].copy_from_slice(name.as_bytes());
+        });
+
+        Ok(sender)
+    }
+
+    /// Creates the shared texture, and publishes its handle
+    fn create_target(&self, device: &RenderDevice, size: Extent3d) -> Result<SharedTarget> {
+        let desc = D3D11_TEXTURE2D_DESC {
+            Width: size.width,
+            Height: size.height,
+            MipLevels: 1,
+            ArraySize: 1,
+            Format: DXGI_FORMAT_R8G8B8A8_UNORM,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Usage: D3D11_USAGE_DEFAULT,
+            BindFlags: (D3D11_BIND_SHADER_RESOURCE.0 | D3D11_BIND_RENDER_TARGET.0) as u32,
+            CPUAccessFlags: 0,
+            MiscFlags: D3D11_RESOURCE_MISC_SHARED.0 as u32,
+        };
+
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("Spout texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8Unorm,
+            usage: TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let (shared, handle, wrapped) = unsafe {
+            let mut shared = None;
+            self.device
+                .CreateTexture2D(&desc, None, Some(&mut shared))
+                .context("Unable to create the shared texture")?;
+            let shared: ID3D11Texture2D = shared.ok_or_else(|| anyhow!("No shared texture"))?;
+            let handle = shared.cast::<IDXGIResource>()?.GetSharedHandle()?;
+
+            // The texture stays in the state of the copies of the renderer
+            let flags = D3D11_RESOURCE_FLAGS {
+                BindFlags: D3D11_BIND_SHADER_RESOURCE.0 as u32,
+                ..Default::default()
+            };
+            let mut wrapped: Option<ID3D11Resource> = None;
+            self.on12
+                .CreateWrappedResource(
+                    &d3d12_resource(&texture)?,
+                    &flags,
+                    D3D12_RESOURCE_STATE_COPY_DEST,
+                    D3D12_RESOURCE_STATE_COPY_DEST,
+                    &mut wrapped,
+                )
+                .context("Unable to wrap the texture with D3D11On12")?;
+            let wrapped = wrapped.ok_or_else(|| anyhow!("No wrapped texture"))?;
+            (shared, handle, wrapped)
+        };
+
+        // The legacy handles fit in 32 bits, the receivers can be 32 bits apps
+        self.info.with_lock(|data| {
+            data.fill(0);
+            let fields = [
+                handle.0 as usize as u32,
+                size.width,
+                size.height,
+                DXGI_FORMAT_R8G8B8A8_UNORM.0 as u32,
+            ];
+            for (field, value) in data.chunks_exact_mut(4).zip(fields) {
+                field.copy_from_slice(&value.to_le_bytes());
+            }
+        });
+        debug!(
+            "Spout sender {} shares a {}x{} texture",
+            self.name, size.width, size.height
+        );
+
+        Ok(SharedTarget {
+            shared,
+            wrapped,
+            texture,
+        })
+    }
+}
+
+impl TextureSender for SpoutSender {
+    fn publish(
+        &mut self,
+        device: &RenderDevice,
+        queue: &RenderQueue,
+        texture: &Texture,
+    ) -> Result<()> {
+        let size = texture.size();
+        if self.target.as_ref().map(|target| target.texture.size()) != Some(size) {
+            self.target = Some(self.create_target(device, size)?);
+        }
+        let Some(target) = &self.target else {
+            return Ok(());
+        };
+
+        // The copy keeps the sRGB encoded bytes, read as is by the receivers
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Spout copy"),
+        });
+        encoder.copy_texture_to_texture(
+            texture.as_image_copy(),
+            target.texture.as_image_copy(),
+            size,
+        );
+        queue.submit([encoder.finish()]);
+
+        // Submitted to the same queue, after the copy of the renderer
+        self.access.with_lock(|| unsafe {
+            let wrapped = [Some(target.wrapped.clone())];
+            self.on12.AcquireWrappedResources(&wrapped);
+            self.context.CopyResource(&target.shared, &target.wrapped);
+            self.on12.ReleaseWrappedResources(&wrapped);
+            self.context.Flush();
+        })
+    }
+}
+
+impl Drop for SpoutSender {
+    fn drop(&mut self) {
+        // The receivers stop listing the sender
+        self.names.with_lock(|data| {
+            let mut senders = read_names(data);
+            senders.remove(&self.name);
+            write_names(data, &senders);
+        });
+        self.active.with_lock(|data| {
+            if read_names(data).contains(&self.name) {
+                data.fill(0);
+            }
+        });
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::{Result, anyhow};
+use bevy_render::{
+    render_resource::Texture,
+    renderer::{RenderDevice, RenderQueue},
+};
+use metal::foreign_types::ForeignTypeRef;
+use objc2::{
+    msg_send,
+    rc::{Allocated, Retained, autoreleasepool},
+    runtime::{AnyClass, AnyObject, Bool},
+};
+use objc2_foundation::{NSPoint, NSRect, NSSize, NSString};
+use wgpu::hal::api::Metal;
+
+use super::TextureSender;
+
+// The Syphon framework is installed in /Library/Frameworks or next to the app
+#[link(name = "Syphon", kind = "framework")]
+unsafe extern "C" {}
+
+/// A `SyphonMetalServer`, publishing the textures on the command queue of the renderer so
+/// that they are drawn once rendered
+pub(super) struct SyphonSender {
+    server: Retained<AnyObject>,
+}
+
+// The server is only used by the render world systems, one at a time
+unsafe impl Send for SyphonSender {}
+unsafe impl Sync for SyphonSender {}
+
+impl SyphonSender {
+    pub(super) fn new(name: &str, device: &RenderDevice) -> Result<Self> {
+        let class = AnyClass::get(c"SyphonMetalServer")
+            .ok_or_else(|| anyhow!("SyphonMetalServer not found, is Syphon installed?"))?;
+        let name = NSString::from_str(name);
+
+        let server = unsafe {
+            device
+                .wgpu_device()
+                .as_hal::<Metal, _, _>(|device| {
+                    let device = device?.raw_device().lock().as_ptr() as *mut AnyObject;
+                    let server: Allocated<AnyObject> = msg_send![class, alloc];
+                    let server: Option<Retained<AnyObject>> = msg_send![
+                        server,
+                        initWithName: &*name,
+                        device: device,
+                        options: std::ptr::null_mut::<AnyObject>()
+                    ];
+                    server
+                })
+                .ok_or_else(|| anyhow!("Syphon needs the Metal backend"))?
+        };
+
+        Ok(Self { server })
+    }
+}
+
+impl TextureSender for SyphonSender {
+    fn publish(
+        &mut self,
+        device: &RenderDevice,
+        _queue: &RenderQueue,
+        texture: &Texture,
+    ) -> Result<()> {
+        let region = NSRect::new(
+            NSPoint::new(0.0, 0.0),
+            NSSize::new(texture.width() as f64, texture.height() as f64),
+        );
+
+        autoreleasepool(|_| unsafe {
+            let texture = texture
+                .as_hal::<Metal, _, _>(|texture| {
+                    texture.map(|texture| texture.raw_handle().as_ptr() as *mut AnyObject)
+                })
+                .ok_or_else(|| anyhow!("The texture is not a Metal texture"))?;
+
+            device.wgpu_device().as_hal::<Metal, _, _>(|device| {
+                let device = device.ok_or_else(|| anyhow!("Syphon needs the Metal backend"))?;
+                // The commands of the queue run in order, after the rendering of the texture
+                let queue = device.raw_queue().lock();
+                let command_buffer = queue.new_command_buffer();
+                let _: () = msg_send![
+                    &*self.server,
+                    publishFrameTexture: texture,
+                    onCommandBuffer: command_buffer.as_ptr() as *mut AnyObject,
+                    imageRegion: region,
+                    flipped: Bool::NO
+                ];
+                command_buffer.commit();
+                Ok(())
+            })
+        })
+    }
+}
+
+impl Drop for SyphonSender {
+    fn drop(&mut self) {
+        // Removes the server from the list of the clients
+        let _: () = unsafe { msg_send![&*self.server, stop] };
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{Result, anyhow};
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use bevy_platform::collections::HashMap;
+use crossbeam_channel::{Receiver, Sender};
+use gst::glib::prelude::*;
+use gst_webrtc::WebRTCDataChannel;
+use gstrswebrtc::webrtcsink::BaseWebRTCSink;
+use std::sync::{Arc, Mutex};
+
+/// Data channel used to carry transport packets
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TransportChannel {
+    /// Ordered and reliable delivery
+    Reliable,
+    /// Unordered delivery without retransmissions
+    Unreliable,
+}
+
+impl TransportChannel {
+    fn label(&self) -> &'static str {
+        match self {
+            TransportChannel::Reliable => "transport-reliable",
+            TransportChannel::Unreliable => "transport-unreliable",
+        }
+    }
+
+    fn config(&self) -> gst::Structure {
+        match self {
+            TransportChannel::Reliable => gst::Structure::builder("config")
+                .field("ordered", true)
+                .build(),
+            TransportChannel::Unreliable => gst::Structure::builder("config")
+                .field("ordered", false)
+                .field("max-retransmits", 0i32)
+                .build(),
+        }
+    }
+}
+
+/// A packet received from a peer
+#[derive(Clone, Debug)]
+pub struct TransportPacket {
+    pub peer_id: String,
+    pub channel: TransportChannel,
+    pub data: Vec<u8>,
+}
+
+/// A minimal byte transport, to tunnel networking crates through the peer connections
+pub trait ByteTransport: Send + Sync {
+    /// Sends `data` to the given peer
+    fn send(&self, peer_id: &str, channel: TransportChannel, data: &[u8]) -> Result<()>;
+
+    /// Returns the next received packet, if any
+    fn recv(&self) -> Option<TransportPacket>;
+
+    /// Returns the ids of the currently connected peers
+    fn peers(&self) -> Vec<String>;
+}
+
+struct PeerChannels {
+    reliable: WebRTCDataChannel,
+    unreliable: WebRTCDataChannel,
+}
+
+impl PeerChannels {
+    fn get(&self, channel: TransportChannel) -> &WebRTCDataChannel {
+        match channel {
+            TransportChannel::Reliable => &self.reliable,
+            TransportChannel::Unreliable => &self.unreliable,
+        }
+    }
+}
+
+/// Reliable and unreliable data channels opened with every peer of a streamer camera.
+///
+/// Only peers of cameras created with `data_transport` enabled have transport channels.
+#[derive(Component, Clone)]
+pub struct DataChannelTransport {
+    peers: Arc<Mutex<HashMap<String, PeerChannels>>>,
+    sender: Sender<TransportPacket>,
+    receiver: Receiver<TransportPacket>,
+}
+
+impl Default for DataChannelTransport {
+    fn default() -> Self {
+        let (sender, receiver) = crossbeam_channel::unbounded::<TransportPacket>();
+        Self {
+            peers: Default::default(),
+            sender,
+            receiver,
+        }
+    }
+}
+
+impl DataChannelTransport {
+    /// Opens the transport data channels on every new consumer of `webrtcsink`
+    pub(crate) fn connect(&self, webrtcsink: &BaseWebRTCSink) {
+        webrtcsink.connect_closure("consumer-added", false, {
+            let transport = self.clone();
+            glib::closure!(move |_sink: &BaseWebRTCSink,
+                                 peer_id: &str,
+                                 webrtcbin: &gst::Element| {
+                transport.add_peer(peer_id, webrtcbin);
+            })
+        });
+
+        webrtcsink.connect_closure("consumer-removed", false, {
+            let transport = self.clone();
+            glib::closure!(move |_sink: &BaseWebRTCSink,
+                                 peer_id: &str,
+                                 _webrtcbin: &gst::Element| {
+                transport.peers.lock().unwrap().remove(peer_id);
+            })
+        });
+    }
+
+    fn add_peer(&self, peer_id: &str, webrtcbin: &gst::Element) {
+        debug!("Creating transport data channels for {}", peer_id);
+
+        let create_channel = |channel: TransportChannel| {
+            let data_channel = webrtcbin.emit_by_name::<WebRTCDataChannel>(
+                "create-data-channel",
+                &[&channel.label(), &channel.config()],
+            );
+
+            data_channel.connect_closure("on-message-data", false, {
+                let sender = self.sender.clone();
+                let peer_id = peer_id.to_string();
+                glib::closure!(move |_channel: &WebRTCDataChannel, data: &glib::Bytes| {
+                    let _ = sender.send(TransportPacket {
+                        peer_id: peer_id.clone(),
+                        channel,
+                        data: data.to_vec(),
+                    });
+                })
+            });
+
+            data_channel
+        };
+
+        let channels = PeerChannels {
+            reliable: create_channel(TransportChannel::Reliable),
+            unreliable: create_channel(TransportChannel::Unreliable),
+        };
+
+        self.peers
+            .lock()
+            .unwrap()
+            .insert(peer_id.to_string(), channels);
+    }
+
+    /// Sends `data` to every connected peer
+    pub fn broadcast(&self, channel: TransportChannel, data: &[u8]) {
+        let bytes = glib::Bytes::from(data);
+        for channels in self.peers.lock().unwrap().values() {
+            channels.get(channel).send_data(Some(&bytes));
+        }
+    }
+}
+
+impl ByteTransport for DataChannelTransport {
+    fn send(&self, peer_id: &str, channel: TransportChannel, data: &[u8]) -> Result<()> {
+        let peers = self.peers.lock().unwrap();
+        let channels = peers
+            .get(peer_id)
+            .ok_or_else(|| anyhow!("Unknown peer {}", peer_id))?;
+        channels
+            .get(channel)
+            .send_data(Some(&glib::Bytes::from(data)));
+        Ok(())
+    }
+
+    fn recv(&self) -> Option<TransportPacket> {
+        self.receiver.try_recv().ok()
+    }
+
+    fn peers(&self) -> Vec<String> {
+        self.peers.lock().unwrap().keys().cloned().collect()
+    }
+}
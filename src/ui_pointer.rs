@@ -0,0 +1,175 @@
+use bevy_ecs::prelude::*;
+use bevy_input::mouse::MouseScrollUnit;
+use bevy_math::prelude::*;
+use bevy_picking::pointer::{
+    Location, PointerAction, PointerButton, PointerId, PointerInput, PointerInteraction,
+    PointerLocation, PointerPress,
+};
+use bevy_render::prelude::*;
+use bevy_window::PrimaryWindow;
+use uuid::Uuid;
+
+/// Drives a picking pointer with the mouse input of the peers of a streamer camera, so that
+/// the `bevy_ui` nodes (or any other pickable content) rendered by the camera can be hovered,
+/// clicked and scrolled. See `StreamerHelper::new_ui_streamer_camera`.
+///
+/// The mouse input is otherwise only injected in the `StreamWindow` of the camera, which the
+/// picking backends don't relate to the render target of the camera. The pointer is a
+/// `PointerId::Custom` located on that render target, shared by all the peers of the camera.
+/// With the `egui` feature, the pointer events are also sent to the egui context of the
+/// camera.
+///
+/// Only the cameras receiving the input of the peers are driven, see `enable_controller`.
+#[derive(Component, Clone, Debug, Default)]
+pub struct UiPointer {
+    /// Last position of the pointer, in pixels of the render target
+    position: Vec2,
+    /// Actions received since the last frame
+    pending: Vec<(Vec2, PointerAction)>,
+    pointer: Option<Entity>,
+}
+
+impl UiPointer {
+    /// Returns the pointer entity, once spawned
+    pub fn pointer(&self) -> Option<Entity> {
+        self.pointer
+    }
+
+    pub(crate) fn moved(&mut self, position: Vec2, delta: Vec2) {
+        self.position = position;
+        self.pending.push((position, PointerAction::Move { delta }));
+    }
+
+    pub(crate) fn button(&mut self, button: PointerButton, pressed: bool) {
+        let action = if pressed {
+            PointerAction::Press(button)
+        } else {
+            PointerAction::Release(button)
+        };
+        self.pending.push((self.position, action));
+    }
+
+    pub(crate) fn scrolled(&mut self, y: f32) {
+        self.pending.push((
+            self.position,
+            PointerAction::Scroll {
+                unit: MouseScrollUnit::Pixel,
+                x: 0.0,
+                y,
+            },
+        ));
+    }
+}
+
+/// Marks the pointer driven by the `UiPointer` of a camera
+#[derive(Component)]
+pub(crate) struct UiPointerOf(Entity);
+
+/// This system sends the actions of the `UiPointer`s to the picking backends, spawning their
+/// pointer if needed
+pub(crate) fn forward_ui_pointers(
+    mut commands: Commands,
+    primary_window: Query<Entity, With<PrimaryWindow>>,
+    mut cameras: Query<(Entity, &Camera, &mut UiPointer)>,
+    mut pointer_inputs: EventWriter<PointerInput>,
+    #[cfg(feature = "egui")] egui_settings: Query<&bevy_egui::EguiContextSettings>,
+    #[cfg(feature = "egui")] mut egui_inputs: EventWriter<bevy_egui::EguiInputEvent>,
+) {
+    let primary_window = primary_window.single().ok();
+
+    for (entity, camera, mut ui_pointer) in cameras.iter_mut() {
+        if ui_pointer.pending.is_empty() {
+            continue;
+        }
+        let Some(target) = camera.target.normalize(primary_window) else {
+            ui_pointer.pending.clear();
+            continue;
+        };
+
+        // Stable for the lifetime of the camera
+        let pointer_id = PointerId::Custom(Uuid::from_u128(entity.to_bits() as u128));
+        if ui_pointer.pointer.is_none() {
+            let pointer = commands
+                .spawn((
+                    pointer_id,
+                    PointerLocation::default(),
+                    PointerPress::default(),
+                    PointerInteraction::default(),
+                    UiPointerOf(entity),
+                ))
+                .id();
+            ui_pointer.pointer = Some(pointer);
+        }
+
+        #[cfg(feature = "egui")]
+        let scale_factor = egui_settings
+            .get(entity)
+            .map(|settings| settings.scale_factor)
+            .unwrap_or(1.0);
+
+        for (position, action) in ui_pointer.pending.drain(..) {
+            #[cfg(feature = "egui")]
+            egui_inputs.write(bevy_egui::EguiInputEvent {
+                context: entity,
+                event: to_egui_event(position / scale_factor, &action),
+            });
+
+            pointer_inputs.write(PointerInput::new(
+                pointer_id,
+                Location {
+                    target: target.clone(),
+                    position,
+                },
+                action,
+            ));
+        }
+    }
+}
+
+/// This system despawns the pointers of the cameras whose `UiPointer` is removed
+pub(crate) fn remove_ui_pointers(
+    mut commands: Commands,
+    pointers: Query<(Entity, &UiPointerOf)>,
+    cameras: Query<(), With<UiPointer>>,
+) {
+    for (entity, pointer_of) in pointers.iter() {
+        if !cameras.contains(pointer_of.0) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Converts a pointer action at `position` (in egui points) to an egui event
+#[cfg(feature = "egui")]
+fn to_egui_event(position: Vec2, action: &PointerAction) -> bevy_egui::egui::Event {
+    use bevy_egui::egui;
+
+    let pos = egui::pos2(position.x, position.y);
+    let button = |button: &PointerButton| match button {
+        PointerButton::Primary => egui::PointerButton::Primary,
+        PointerButton::Secondary => egui::PointerButton::Secondary,
+        PointerButton::Middle => egui::PointerButton::Middle,
+    };
+
+    match action {
+        PointerAction::Move { .. } => egui::Event::PointerMoved(pos),
+        PointerAction::Press(pressed) => egui::Event::PointerButton {
+            pos,
+            button: button(pressed),
+            pressed: true,
+            modifiers: egui::Modifiers::default(),
+        },
+        PointerAction::Release(released) => egui::Event::PointerButton {
+            pos,
+            button: button(released),
+            pressed: false,
+            modifiers: egui::Modifiers::default(),
+        },
+        PointerAction::Scroll { x, y, .. } => egui::Event::MouseWheel {
+            unit: egui::MouseWheelUnit::Point,
+            delta: egui::vec2(*x, *y),
+            modifiers: egui::Modifiers::default(),
+        },
+        PointerAction::Cancel => egui::Event::PointerGone,
+    }
+}
@@ -0,0 +1,19 @@
+use bevy_ecs::{component::HookContext, prelude::*, world::DeferredWorld};
+use bevy_ui::IsDefaultUiCamera;
+
+/// Displays the UI of the app on this streamer camera.
+///
+/// Headless apps have no window whose camera displays the UI by default, so the camera with
+/// this component is made the `IsDefaultUiCamera`, which displays the root UI nodes without a
+/// `UiTargetCamera`. Only one camera should have it, `IsDefaultUiCamera` is removed with it.
+#[derive(Component, Clone, Copy, Debug, Default)]
+#[require(IsDefaultUiCamera)]
+#[component(on_remove = remove_default_ui_camera)]
+pub struct DefaultUiStream;
+
+fn remove_default_ui_camera(mut world: DeferredWorld, context: HookContext) {
+    world
+        .commands()
+        .entity(context.entity)
+        .try_remove::<IsDefaultUiCamera>();
+}
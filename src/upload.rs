@@ -0,0 +1,76 @@
+use anyhow::{Context, Result, anyhow, bail};
+use bevy_log::prelude::*;
+use gstrswebrtc::RUNTIME;
+use object_store::{
+    ObjectStore, WriteMultipart, aws::AmazonS3Builder, gcp::GoogleCloudStorageBuilder,
+    path::Path as ObjectPath,
+};
+use std::{fs::File, io::Read, path::Path};
+use url::Url;
+
+/// Size of the parts of the multipart uploads
+const PART_SIZE: usize = 8 * 1024 * 1024;
+/// Maximum number of parts uploaded concurrently
+const MAX_CONCURRENT_PARTS: usize = 4;
+
+/// Upload of the finalized recordings to an object storage
+#[derive(Clone, Debug)]
+pub struct UploadSettings {
+    /// Destination prefix, e.g. `s3://bucket/recordings` or `gs://bucket/recordings`.
+    ///
+    /// The credentials are read from the environment (`AWS_*` or `GOOGLE_*` variables).
+    pub url: String,
+    /// Deletes the local file once uploaded
+    pub delete_after_upload: bool,
+}
+
+fn object_store(url: &Url) -> Result<Box<dyn ObjectStore>> {
+    Ok(match url.scheme() {
+        "s3" => Box::new(AmazonS3Builder::from_env().with_url(url.as_str()).build()?),
+        "gs" => Box::new(
+            GoogleCloudStorageBuilder::from_env()
+                .with_url(url.as_str())
+                .build()?,
+        ),
+        scheme => bail!("Unsupported upload scheme {}", scheme),
+    })
+}
+
+/// Uploads a file with a multipart upload, blocking until it is complete
+pub(crate) fn upload_file(settings: &UploadSettings, path: &Path) -> Result<()> {
+    let url = Url::parse(&settings.url).context("Invalid upload url")?;
+    let store = object_store(&url)?;
+
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("Invalid file name {}", path.display()))?;
+    let location = ObjectPath::parse(url.path().trim_start_matches('/'))?.child(file_name);
+
+    let mut file = File::open(path)?;
+
+    info!("Uploading {} to {}", path.display(), location);
+    RUNTIME.block_on(async {
+        let upload = store.put_multipart(&location).await?;
+        let mut writer = WriteMultipart::new_with_chunk_size(upload, PART_SIZE);
+
+        let mut buffer = vec![0; PART_SIZE];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            writer.wait_for_capacity(MAX_CONCURRENT_PARTS).await?;
+            writer.write(&buffer[..read]);
+        }
+
+        writer.finish().await?;
+        anyhow::Ok(())
+    })?;
+
+    if settings.delete_after_upload {
+        std::fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,259 @@
+use anyhow::{Context, Result, anyhow, bail};
+use bevy_log::prelude::*;
+use gst::prelude::*;
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    PipelineLogLevel,
+    encoder::{EncoderStats, Frame, FrameTimestamps, StreamEncoder, resize_appsrc},
+    pipeline_log::log_bus_message,
+};
+
+/// Directory listing the virtual video devices, the ones of v4l2loopback
+const VIRTUAL_DEVICES: &str = "/sys/devices/virtual/video4linux";
+
+/// Pixel format written to the v4l2loopback device
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum V4l2Format {
+    /// Supported by most webcam consumers, e.g. browsers, Zoom and OBS
+    #[default]
+    Nv12,
+    /// The captured frames as is, without conversion, not every consumer supports it
+    Rgba,
+}
+
+impl V4l2Format {
+    fn caps_format(&self) -> &'static str {
+        match self {
+            V4l2Format::Nv12 => "NV12",
+            V4l2Format::Rgba => "RGBA",
+        }
+    }
+}
+
+/// Settings of a `V4l2Encoder`
+#[derive(Clone)]
+pub struct V4l2Settings {
+    /// Name of the stream
+    pub name: String,
+    /// Labels attached to the stream, see `StreamLabels`
+    pub labels: Vec<(String, String)>,
+    /// Path of the v4l2loopback device, e.g. `/dev/video10`, the first v4l2loopback device
+    /// if `None`, see `v4l2loopback_devices`
+    pub device: Option<String>,
+    pub format: V4l2Format,
+    pub width: u32,
+    pub height: u32,
+    /// Framerate announced to the consumers, frames are duplicated or dropped whatever the
+    /// rate frames are pushed at
+    pub framerate: u32,
+    /// Verbosity of the pipeline messages logs
+    pub log_level: PipelineLogLevel,
+}
+
+impl Default for V4l2Settings {
+    fn default() -> Self {
+        Self {
+            name: "v4l2".to_string(),
+            labels: Vec::new(),
+            device: None,
+            format: V4l2Format::default(),
+            width: 1280,
+            height: 720,
+            framerate: 30,
+            log_level: PipelineLogLevel::default(),
+        }
+    }
+}
+
+/// Returns the paths of the v4l2loopback devices, sorted
+pub fn v4l2loopback_devices() -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(VIRTUAL_DEVICES) else {
+        return Vec::new();
+    };
+    let mut devices: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| format!("/dev/{}", entry.file_name().to_string_lossy()))
+        .filter(|device| Path::new(device).exists())
+        .collect();
+    devices.sort_by_key(|device| {
+        device
+            .trim_start_matches("/dev/video")
+            .parse::<u32>()
+            .unwrap_or(u32::MAX)
+    });
+    devices
+}
+
+/// An encoder writing the frames to a v4l2loopback device, so that the camera can be used as
+/// a webcam, e.g. in Zoom, Meet or OBS. Linux only.
+///
+/// The device is created with `modprobe v4l2loopback exclusive_caps=1`, required by the
+/// browsers to list it. Its format is negotiated once, with the size of the settings: the
+/// frames are scaled to it when the stream is resized, since the consumers do not support a
+/// change of format while they read the device.
+pub struct V4l2Encoder {
+    pipeline: gst::Pipeline,
+    appsrc: gst_app::AppSrc,
+    device: String,
+    timestamps: FrameTimestamps,
+    stats: Mutex<EncoderStats>,
+}
+
+impl V4l2Encoder {
+    pub fn new(settings: V4l2Settings) -> Result<Arc<Self>> {
+        gst::init()?;
+
+        let device = match &settings.device {
+            Some(device) => device.clone(),
+            None => v4l2loopback_devices().into_iter().next().ok_or_else(|| {
+                anyhow!(
+                    "No v4l2loopback device, load it with `modprobe v4l2loopback exclusive_caps=1`"
+                )
+            })?,
+        };
+        if !Path::new(&device).exists() {
+            bail!(
+                "The device {} does not exist, is v4l2loopback loaded?",
+                device
+            );
+        }
+
+        let description = format!(
+            "appsrc name=src format=time is-live=true do-timestamp=true \
+                caps=\"video/x-raw,format=RGBA,width={},height={},framerate=0/1\" ! \
+            queue ! \
+            videoconvert ! \
+            videoscale ! \
+            videorate ! \
+            video/x-raw,format={},width={},height={},framerate={}/1,pixel-aspect-ratio=1/1 ! \
+            v4l2sink name=sink sync=false",
+            settings.width,
+            settings.height,
+            settings.format.caps_format(),
+            settings.width,
+            settings.height,
+            settings.framerate.max(1),
+        );
+        debug!(stream = %settings.name, "V4L2 pipeline: {}", description);
+
+        let pipeline = gst::parse::launch(&description)
+            .context("Unable to create the V4L2 pipeline, is gst-plugins-good installed?")?
+            .downcast::<gst::Pipeline>()
+            .map_err(|_| anyhow!("Failed to cast to pipeline"))?;
+        pipeline.set_property("name", &settings.name);
+
+        let sink = pipeline
+            .by_name("sink")
+            .ok_or_else(|| anyhow!("Could not get v4l2sink element"))?;
+        sink.set_property("device", &device);
+
+        let appsrc = pipeline
+            .by_name("src")
+            .ok_or_else(|| anyhow!("Could not get appsrc element"))?
+            .downcast::<gst_app::AppSrc>()
+            .map_err(|_| anyhow!("Not an appsrc"))?;
+
+        let bus = pipeline
+            .bus()
+            .ok_or_else(|| anyhow!("Pipeline has no bus"))?;
+        let stream = settings.name.clone();
+        let log_level = settings.log_level;
+        let format = settings.format;
+        std::thread::spawn(move || {
+            for msg in bus.iter_timed(gst::ClockTime::NONE) {
+                log_bus_message(&stream, log_level, &msg);
+                match msg.view() {
+                    gst::MessageView::Error(err)
+                        if err.error().matches(gst::StreamError::Format) =>
+                    {
+                        warn!(
+                            stream = %stream,
+                            "The device refused the {:?} format, it may be in use with another \
+                                format, or v4l2loopback was not loaded with exclusive_caps=1",
+                            format
+                        );
+                    }
+                    gst::MessageView::Eos(_) => break,
+                    _ => {}
+                }
+            }
+        });
+
+        Ok(Arc::new(Self {
+            pipeline,
+            appsrc,
+            device,
+            timestamps: FrameTimestamps::default(),
+            stats: Mutex::new(EncoderStats {
+                width: settings.width,
+                height: settings.height,
+                ..Default::default()
+            }),
+        }))
+    }
+
+    /// Returns the path of the device written to
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+}
+
+impl Drop for V4l2Encoder {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
+
+impl StreamEncoder for V4l2Encoder {
+    fn push_frame(&self, frame: &Frame) -> Result<()> {
+        let buffer = self.timestamps.video_buffer(&self.appsrc, frame)?;
+        self.appsrc
+            .push_buffer(buffer)
+            .map_err(|e| anyhow!("Failed to push buffer: {:?}", e))?;
+        self.stats.lock().unwrap().frames_pushed += 1;
+
+        Ok(())
+    }
+
+    fn start(&self) -> Result<()> {
+        info!(stream = %self.pipeline.name(), device = %self.device, "Start V4L2 output");
+        self.pipeline.set_state(gst::State::Playing)?;
+
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<()> {
+        info!(stream = %self.pipeline.name(), device = %self.device, "Stop V4L2 output");
+        self.pipeline.set_state(gst::State::Null)?;
+
+        Ok(())
+    }
+
+    /// The frames are scaled to the size negotiated with the device
+    fn resize(&self, width: u32, height: u32) -> Result<()> {
+        resize_appsrc(&self.appsrc, width, height)?;
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.width = width;
+        stats.height = height;
+
+        Ok(())
+    }
+
+    /// Every frame is a keyframe
+    fn request_keyframe(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn stats(&self) -> Option<EncoderStats> {
+        Some(self.stats.lock().unwrap().clone())
+    }
+
+    fn pipeline_state(&self) -> Option<gst::State> {
+        Some(self.pipeline.current_state())
+    }
+}
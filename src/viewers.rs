@@ -0,0 +1,207 @@
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use bevy_platform::collections::HashMap;
+use bevy_render::prelude::*;
+use gst::prelude::*;
+use gstrswebrtc::signaller::{Signallable, SignallableExt};
+#[cfg(feature = "audit")]
+use std::time::SystemTime;
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use crate::{StandbyPolicy, StreamerResumed, StreamerStandby, ViewerCount, capture::Capture};
+
+/// A peer which joined or left, drained by the audit log
+#[cfg(feature = "audit")]
+pub(crate) struct ViewerChange {
+    pub peer_id: String,
+    pub joined: bool,
+    pub timestamp: SystemTime,
+}
+
+/// Tracks the peers connected to a streamer camera, from the consumer signals of its sink
+#[derive(Component, Clone, Default)]
+pub(crate) struct ViewerTracker {
+    /// The connected peers and when they joined
+    peers: Arc<Mutex<HashMap<String, Instant>>>,
+    sinks: Arc<Mutex<Vec<glib::WeakRef<gst::Element>>>>,
+    /// When the last RTCP packet of each peer was received
+    rtcp: Arc<Mutex<HashMap<String, Instant>>>,
+    #[cfg(feature = "audit")]
+    changes: Arc<Mutex<Vec<ViewerChange>>>,
+}
+
+impl ViewerTracker {
+    /// Tracks the consumers of `sink`, a `webrtcsink` or one of its variants (e.g. `livekitwebrtcsink`)
+    pub(crate) fn connect(&self, sink: &gst::Element) {
+        self.sinks.lock().unwrap().push(sink.downgrade());
+
+        sink.connect_closure("consumer-added", false, {
+            let tracker = self.clone();
+            glib::closure!(
+                move |_sink: &gst::Element, peer_id: &str, webrtcbin: &gst::Element| {
+                    debug!("Viewer joined: {}", peer_id);
+                    tracker
+                        .peers
+                        .lock()
+                        .unwrap()
+                        .insert(peer_id.to_string(), Instant::now());
+                    tracker.track_rtcp(peer_id, webrtcbin);
+                    #[cfg(feature = "audit")]
+                    tracker.push_change(peer_id, true);
+                }
+            )
+        });
+
+        sink.connect_closure("consumer-removed", false, {
+            let tracker = self.clone();
+            glib::closure!(
+                move |_sink: &gst::Element, peer_id: &str, _webrtcbin: &gst::Element| {
+                    debug!("Viewer left: {}", peer_id);
+                    tracker.peers.lock().unwrap().remove(peer_id);
+                    tracker.rtcp.lock().unwrap().remove(peer_id);
+                    #[cfg(feature = "audit")]
+                    tracker.push_change(peer_id, false);
+                }
+            )
+        });
+    }
+
+    /// Records the RTCP packets received from `peer_id`, by the rtpbin of its `webrtcbin`
+    fn track_rtcp(&self, peer_id: &str, webrtcbin: &gst::Element) {
+        let Some(rtpbin) = webrtcbin
+            .downcast_ref::<gst::Bin>()
+            .and_then(|webrtcbin| webrtcbin.by_name("rtpbin"))
+        else {
+            return;
+        };
+
+        // Emitted for each source which sent a RTCP packet, e.g. the receiver reports
+        let rtcp = Arc::downgrade(&self.rtcp);
+        let peer_id = peer_id.to_string();
+        rtpbin.connect("on-ssrc-active", false, move |_| {
+            if let Some(rtcp) = rtcp.upgrade() {
+                rtcp.lock().unwrap().insert(peer_id.clone(), Instant::now());
+            }
+            None
+        });
+    }
+
+    /// Returns when the last RTCP packet of a peer was received, if any
+    pub(crate) fn last_rtcp(&self, peer_id: &str) -> Option<Instant> {
+        self.rtcp.lock().unwrap().get(peer_id).copied()
+    }
+
+    pub(crate) fn count(&self) -> u32 {
+        self.peers.lock().unwrap().len() as u32
+    }
+
+    /// Returns the connected peers and when they joined
+    pub(crate) fn peers(&self) -> Vec<(String, Instant)> {
+        self.peers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(peer_id, joined)| (peer_id.clone(), *joined))
+            .collect()
+    }
+
+    /// Ends the session of a peer through the signaller of the sinks
+    pub(crate) fn disconnect(&self, peer_id: &str) {
+        let sinks = self
+            .sinks
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|sink| sink.upgrade())
+            .collect::<Vec<_>>();
+        for sink in sinks {
+            if sink.find_property("signaller").is_none() {
+                continue;
+            }
+            let signaller = sink.property::<Signallable>("signaller");
+            signaller.end_session(peer_id);
+            // Lets the sink remove the session, as when the peer leaves
+            signaller.emit_by_name::<bool>("session-ended", &[&peer_id]);
+        }
+    }
+
+    #[cfg(feature = "audit")]
+    fn push_change(&self, peer_id: &str, joined: bool) {
+        self.changes.lock().unwrap().push(ViewerChange {
+            peer_id: peer_id.to_string(),
+            joined,
+            timestamp: SystemTime::now(),
+        });
+    }
+
+    /// Returns the peers which joined or left since the last call
+    #[cfg(feature = "audit")]
+    pub(crate) fn take_changes(&self) -> Vec<ViewerChange> {
+        std::mem::take(&mut *self.changes.lock().unwrap())
+    }
+}
+
+/// This system copies the number of connected peers to the `ViewerCount` of each camera
+pub(crate) fn update_viewer_counts(mut viewers: Query<(&ViewerTracker, &mut ViewerCount)>) {
+    for (tracker, mut count) in viewers.iter_mut() {
+        count.set_if_neq(ViewerCount(tracker.count()));
+    }
+}
+
+/// This system stops and restarts the capture of cameras according to their `StandbyPolicy`
+pub(crate) fn apply_standby_policies(
+    mut cameras: Query<(Entity, &Camera, &ViewerCount, &mut StandbyPolicy)>,
+    captures: Query<&Capture>,
+    mut standby_events: EventWriter<StreamerStandby>,
+    mut resumed_events: EventWriter<StreamerResumed>,
+) {
+    let now = Instant::now();
+
+    for (entity, camera, viewer_count, mut policy) in cameras.iter_mut() {
+        let Some(after) = policy.after else {
+            continue;
+        };
+
+        let standby = if viewer_count.0 > 0 {
+            policy.idle_since = None;
+            false
+        } else {
+            let idle_since = *policy.idle_since.get_or_insert(now);
+            policy.standby || now.duration_since(idle_since) >= after
+        };
+
+        if standby == policy.standby {
+            continue;
+        }
+        policy.standby = standby;
+
+        // The encoders are put in standby once the capture stops pushing them frames, and
+        // resumed before it does again
+        if let Some(image) = camera.target.as_image() {
+            for capture in captures.iter().filter(|c| c.src_image() == image) {
+                if standby {
+                    capture.set_enabled(false);
+                }
+                for encoder in capture.encoders() {
+                    if let Err(e) = encoder.set_standby(standby) {
+                        warn!("Unable to set the standby of the encoder: {:?}", e);
+                    }
+                }
+                if !standby {
+                    capture.set_enabled(true);
+                }
+            }
+        }
+
+        if standby {
+            info!("No viewers for {:?}, going in standby", after);
+            standby_events.write(StreamerStandby { camera: entity });
+        } else {
+            info!("Viewer connected, resuming from standby");
+            resumed_events.write(StreamerResumed { camera: entity });
+        }
+    }
+}
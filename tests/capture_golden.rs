@@ -0,0 +1,167 @@
+//! Renders known scenes headlessly and checks the pixels of the captured frames, with and
+//! without multisampling.
+//!
+//! These tests need a GPU, run them with
+//! `cargo test --features test-support --test capture_golden -- --ignored`.
+
+use std::sync::Arc;
+
+use bevy::{
+    core_pipeline::tonemapping::{DebandDither, Tonemapping},
+    prelude::*,
+    render::RenderPlugin,
+    winit::WinitPlugin,
+};
+use bevy_streaming::{
+    StreamerHelper, StreamerPlugin,
+    test_support::{CapturedFrame, ValidatingEncoder},
+};
+
+/// Odd sizes, so that the rows are padded when copied from the GPU
+const WIDTH: u32 = 301;
+const HEIGHT: u32 = 167;
+
+/// Frames checked once the scene is rendered
+const CHECKED_FRAMES: usize = 3;
+const MAX_UPDATES: usize = 300;
+
+/// Difference allowed on each channel, for the sRGB conversions
+const TOLERANCE: u8 = 2;
+
+const BACKGROUND: [u8; 4] = [200, 100, 50, 255];
+/// Colors of the top left, top right, bottom left and bottom right quadrants
+const QUADRANTS: [[u8; 4]; 4] = [
+    [255, 0, 0, 255],
+    [0, 255, 0, 255],
+    [0, 0, 255, 255],
+    [255, 255, 255, 255],
+];
+
+fn srgb(color: [u8; 4]) -> Color {
+    Color::srgba_u8(color[0], color[1], color[2], color[3])
+}
+
+/// Renders the scene spawned by `setup` until enough frames are captured, and returns them
+fn capture(msaa: Msaa, setup: fn(&mut Commands)) -> Arc<ValidatingEncoder> {
+    let encoder = ValidatingEncoder::new(WIDTH, HEIGHT);
+
+    let mut app = App::new();
+    app.add_plugins((
+        DefaultPlugins
+            .build()
+            .disable::<WinitPlugin>()
+            .set(RenderPlugin {
+                synchronous_pipeline_compilation: true,
+                ..default()
+            }),
+        StreamerPlugin,
+    ));
+    app.insert_resource(ClearColor(srgb(BACKGROUND)));
+    app.add_systems(Startup, {
+        let encoder = encoder.clone();
+        move |mut commands: Commands, mut streamer: StreamerHelper<ValidatingEncoder>| {
+            commands.spawn((
+                Camera2d,
+                streamer.new_streamer_camera_with_encoder(WIDTH, HEIGHT, encoder.clone()),
+                msaa,
+                Tonemapping::None,
+                DebandDither::Disabled,
+            ));
+            setup(&mut commands);
+        }
+    });
+    // The first frames may be captured before the pipelines are ready
+    for _ in 0..MAX_UPDATES {
+        app.update();
+        if encoder.frame_count() >= CHECKED_FRAMES * 2 {
+            break;
+        }
+    }
+
+    encoder
+}
+
+/// Returns the last captured frames, after checking that every frame was valid
+fn checked_frames(encoder: &ValidatingEncoder) -> Vec<CapturedFrame> {
+    assert_eq!(encoder.errors(), Vec::<String>::new());
+    let frames = encoder.frames();
+    assert!(
+        frames.len() >= CHECKED_FRAMES,
+        "Only {} frames captured",
+        frames.len()
+    );
+    frames[frames.len() - CHECKED_FRAMES..].to_vec()
+}
+
+fn empty_scene(_commands: &mut Commands) {}
+
+/// Fills each quadrant of the view with a sprite
+fn quadrants_scene(commands: &mut Commands) {
+    let size = Vec2::new(WIDTH as f32 / 2.0, HEIGHT as f32 / 2.0);
+    let offsets = [
+        Vec2::new(-1.0, 1.0),
+        Vec2::new(1.0, 1.0),
+        Vec2::new(-1.0, -1.0),
+        Vec2::new(1.0, -1.0),
+    ];
+    for (color, offset) in QUADRANTS.into_iter().zip(offsets) {
+        commands.spawn((
+            Sprite::from_color(srgb(color), size),
+            Transform::from_translation((offset * size / 2.0).extend(0.0)),
+        ));
+    }
+}
+
+fn check_background(msaa: Msaa) {
+    let encoder = capture(msaa, empty_scene);
+    for frame in checked_frames(&encoder) {
+        for (x, y) in [
+            (0, 0),
+            (WIDTH - 1, 0),
+            (0, HEIGHT - 1),
+            (WIDTH - 1, HEIGHT - 1),
+        ] {
+            frame.check_pixel(x, y, BACKGROUND, TOLERANCE).unwrap();
+        }
+    }
+}
+
+fn check_quadrants(msaa: Msaa) {
+    let encoder = capture(msaa, quadrants_scene);
+    // Centers of the quadrants, away from the edges smoothed by multisampling
+    let points = [
+        (WIDTH / 4, HEIGHT / 4),
+        (WIDTH * 3 / 4, HEIGHT / 4),
+        (WIDTH / 4, HEIGHT * 3 / 4),
+        (WIDTH * 3 / 4, HEIGHT * 3 / 4),
+    ];
+    for frame in checked_frames(&encoder) {
+        for ((x, y), color) in points.into_iter().zip(QUADRANTS) {
+            frame.check_pixel(x, y, color, TOLERANCE).unwrap();
+        }
+    }
+}
+
+#[test]
+#[ignore = "requires a GPU"]
+fn background_without_msaa() {
+    check_background(Msaa::Off);
+}
+
+#[test]
+#[ignore = "requires a GPU"]
+fn background_with_msaa() {
+    check_background(Msaa::Sample4);
+}
+
+#[test]
+#[ignore = "requires a GPU"]
+fn quadrants_without_msaa() {
+    check_quadrants(Msaa::Off);
+}
+
+#[test]
+#[ignore = "requires a GPU"]
+fn quadrants_with_msaa() {
+    check_quadrants(Msaa::Sample4);
+}
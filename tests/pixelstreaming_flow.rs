@@ -0,0 +1,99 @@
+//! Streams frames through the whole Pixel Streaming signalling and negotiation flow, with
+//! the in-process `MockSignallingServer` and a `MockConsumer` player.
+//!
+//! These tests need the GStreamer webrtc, nice and vpx or x264 plugins, run them with
+//! `cargo test --features test-support --test pixelstreaming_flow -- --ignored`.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use bevy_streaming::{
+    GstWebRtcSettings, SignallingServer,
+    encoder::{Frame, StreamEncoder},
+    gst_webrtc_encoder::GstWebRtcEncoder,
+    test_support::{MockConsumer, MockSignallingServer},
+};
+
+const STREAMER_ID: &str = "flow";
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 240;
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Pushes frames to `encoder` until `running` is cleared
+fn push_frames(encoder: Arc<GstWebRtcEncoder>, running: Arc<AtomicBool>) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let data = vec![128; (WIDTH * HEIGHT * 4) as usize];
+        let start = Instant::now();
+        let mut id = 0;
+        while running.load(Ordering::Relaxed) {
+            let frame = Frame::packed(&data, WIDTH, HEIGHT, start.elapsed(), id);
+            encoder.push_frame(&frame).unwrap();
+            id += 1;
+            std::thread::sleep(FRAME_INTERVAL);
+        }
+    })
+}
+
+/// Waits until `condition` is true, returns false on timeout
+fn wait_until(timeout: Duration, condition: impl Fn() -> bool) -> bool {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    false
+}
+
+#[test]
+#[ignore = "requires the GStreamer webrtc plugins"]
+fn consumer_receives_video() {
+    let server = MockSignallingServer::start().unwrap();
+
+    let encoder = GstWebRtcEncoder::with_settings(GstWebRtcSettings {
+        signalling_server: SignallingServer::PixelStreaming {
+            uri: server.streamer_uri(),
+            streamer_id: Some(STREAMER_ID.to_string()),
+            proxy: None,
+            headers: Default::default(),
+            cafile: None,
+            insecure_tls: false,
+        },
+        width: WIDTH,
+        height: HEIGHT,
+        ..Default::default()
+    })
+    .unwrap();
+    let encoder = Arc::new(encoder);
+    encoder.start().unwrap();
+
+    let running = Arc::new(AtomicBool::new(true));
+    let pusher = push_frames(encoder.clone(), running.clone());
+
+    assert!(
+        wait_until(TIMEOUT, || server
+            .streamers()
+            .contains(&STREAMER_ID.to_string())),
+        "The streamer didn't register, registered: {:?}",
+        server.streamers()
+    );
+
+    let consumer = MockConsumer::connect(&server.player_uri(), STREAMER_ID).unwrap();
+    assert!(
+        consumer.wait_for_video(TIMEOUT),
+        "The consumer received no video"
+    );
+
+    running.store(false, Ordering::Relaxed);
+    pusher.join().unwrap();
+    drop(consumer);
+    encoder.stop().unwrap();
+}